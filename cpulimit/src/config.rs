@@ -0,0 +1,163 @@
+//! Read the limiting rules from a TOML configuration file.
+//!
+//! ```toml
+//! # limit each ffmpeg process to 30%, along with its children
+//! [[rule]]
+//! exe = "/usr/bin/ffmpeg"
+//! limit = 30
+//! children = true
+//!
+//! # limit each process of the user 1000 to 50%
+//! [[rule]]
+//! user = 1000
+//! limit = 50
+//! ```
+//!
+//! Each rule designates its processes with exactly one of `name`, `exe` or
+//! `user`. When several rules match a process, the first one applies.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use serde::Deserialize;
+
+use cpulimiter::{ChildrenMode, Rule, Target};
+
+/// Set when a `SIGHUP` asks to read the configuration again.
+static RELOAD: AtomicBool = AtomicBool::new(false);
+
+/// The content of a configuration file.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Config {
+    #[serde(default)]
+    rule: Vec<RuleConfig>,
+}
+
+/// A `[[rule]]` table, as written in the file.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RuleConfig {
+    name: Option<String>,
+    exe: Option<PathBuf>,
+    user: Option<u32>,
+    limit: f64,
+    #[serde(default)]
+    children: bool,
+}
+
+/// Reads the rules of the configuration file at `path`.
+pub fn load(path: &Path) -> io::Result<Vec<Rule>> {
+    let config = fs::read_to_string(path)?;
+    parse(&config).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
+
+/// Makes `SIGHUP` request a reload of the configuration, see [`reload_requested`].
+pub fn reload_on_sighup() {
+    extern "C" fn request_reload(_: libc::c_int) {
+        RELOAD.store(true, Ordering::Relaxed);
+    }
+
+    // SAFETY: the handler only stores to an atomic, which is async-signal-safe.
+    unsafe {
+        libc::signal(
+            libc::SIGHUP,
+            request_reload as extern "C" fn(libc::c_int) as libc::sighandler_t,
+        )
+    };
+}
+
+/// Indicates whether a reload was requested since the last call.
+pub fn reload_requested() -> bool {
+    RELOAD.swap(false, Ordering::Relaxed)
+}
+
+/// Parses the rules of a configuration file, or describes the first error.
+fn parse(config: &str) -> Result<Vec<Rule>, String> {
+    let config: Config = toml::from_str(config).map_err(|err| err.to_string())?;
+    config
+        .rule
+        .into_iter()
+        .enumerate()
+        .map(|(index, rule)| convert(rule).map_err(|err| format!("rule #{}: {err}", index + 1)))
+        .collect()
+}
+
+/// Checks a rule of the file, and converts it to a rule of the library.
+fn convert(rule: RuleConfig) -> Result<Rule, &'static str> {
+    let matcher = match (rule.name, rule.exe, rule.user) {
+        (Some(name), None, None) => Target::Name(name),
+        (None, Some(exe), None) => Target::Exe(exe),
+        (None, None, Some(uid)) => Target::User(uid),
+        _ => return Err("expected exactly one of `name`, `exe` or `user`"),
+    };
+    if rule.limit <= 0_f64 {
+        return Err("`limit` must be positive");
+    }
+
+    Ok(Rule {
+        matcher,
+        limit: rule.limit,
+        children: if rule.children {
+            ChildrenMode::Include
+        } else {
+            ChildrenMode::Exclude
+        },
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_rules() {
+        let config = r#"
+            [[rule]]
+            exe = "/usr/bin/ffmpeg"
+            limit = 30
+            children = true
+
+            [[rule]]
+            name = "make"
+            limit = 12.5
+
+            [[rule]]
+            user = 1000
+            limit = 50
+        "#;
+
+        assert_eq!(
+            parse(config),
+            Ok(vec![
+                Rule {
+                    matcher: Target::Exe("/usr/bin/ffmpeg".into()),
+                    limit: 30_f64,
+                    children: ChildrenMode::Include,
+                },
+                Rule {
+                    matcher: Target::Name("make".to_string()),
+                    limit: 12.5,
+                    children: ChildrenMode::Exclude,
+                },
+                Rule {
+                    matcher: Target::User(1000),
+                    limit: 50_f64,
+                    children: ChildrenMode::Exclude,
+                },
+            ])
+        );
+    }
+
+    #[test]
+    fn reject_invalid_rules() {
+        assert!(parse("limit = 10").is_err());
+        assert!(parse("[[rule]]\nname = \"a\"").is_err());
+        assert!(parse("[[rule]]\nlimit = 10").is_err());
+        assert!(parse("[[rule]]\nname = \"a\"\nuser = 1\nlimit = 10").is_err());
+        assert!(parse("[[rule]]\nname = \"a\"\nlimit = -1").is_err());
+        assert!(parse("[[rule]]\nname = \"a\"\nlimit = 10\nnice = 5").is_err());
+    }
+}
@@ -0,0 +1,83 @@
+//! Talk to a running instance over its control socket.
+//!
+//! See `cpulimiter::ControlServer` for the protocol: one command per line,
+//! answered with a line of JSON.
+
+use std::io::{self, BufRead, BufReader, Write};
+use std::os::unix::net::UnixStream;
+use std::path::Path;
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Deserializer};
+
+/// A limiter, as listed by the `LIST` command.
+#[derive(Deserialize)]
+pub struct Limiter {
+    pub pid: u32,
+    #[serde(deserialize_with = "number")]
+    pub limit: f64,
+    #[serde(deserialize_with = "number")]
+    pub usage: f64,
+}
+
+#[derive(Deserialize)]
+pub struct Limiters {
+    pub limiters: Vec<Limiter>,
+}
+
+/// The details of a limiter, as returned by the `STATS` command.
+#[cfg(feature = "tui")]
+#[derive(Deserialize)]
+pub struct Stats {
+    #[serde(deserialize_with = "number")]
+    pub working_rate: f64,
+    pub children: usize,
+    pub throttle_cycles: u64,
+    #[serde(deserialize_with = "number")]
+    pub cpu_time: f64,
+}
+
+/// Reads a number, sent as `null` by the server when it isn't finite.
+fn number<'de, D: Deserializer<'de>>(deserializer: D) -> Result<f64, D::Error> {
+    Ok(Option::<f64>::deserialize(deserializer)?.unwrap_or(f64::NAN))
+}
+
+/// A response of the server, which may be a failure.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Response<T> {
+    Error { error: String },
+    Ok(T),
+}
+
+/// A connection to the control socket of a running instance.
+pub struct Client {
+    reader: BufReader<UnixStream>,
+    writer: UnixStream,
+}
+
+impl Client {
+    pub fn connect(path: &Path) -> io::Result<Self> {
+        let writer = UnixStream::connect(path)?;
+        Ok(Self {
+            reader: BufReader::new(writer.try_clone()?),
+            writer,
+        })
+    }
+
+    /// Sends `command` and parses the response.
+    ///
+    /// A failure reported by the server is an error of kind [`io::ErrorKind::Other`].
+    pub fn request<T: DeserializeOwned>(&mut self, command: &str) -> io::Result<T> {
+        writeln!(self.writer, "{command}")?;
+        let mut line = String::new();
+        if self.reader.read_line(&mut line)? == 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+
+        match serde_json::from_str(&line)? {
+            Response::Ok(response) => Ok(response),
+            Response::Error { error } => Err(io::Error::other(error)),
+        }
+    }
+}
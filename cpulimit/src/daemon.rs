@@ -0,0 +1,57 @@
+//! Detach the program from the terminal to run in the background.
+
+use std::fs::{self, File, OpenOptions};
+use std::io;
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+use std::process::exit;
+
+/// Forks twice to run in the background, in a new session without a controlling terminal.
+///
+/// The standard input is read from `/dev/null`, the outputs are appended to
+/// `log_file` (or discarded). The PID of the daemon is written to `pid_file`.
+/// The working directory is kept, so that relative paths still work.
+///
+/// Must be called before any other thread is started.
+pub fn daemonize(log_file: Option<&Path>, pid_file: Option<&Path>) -> io::Result<()> {
+    // open the files first, so that errors are reported to the terminal
+    let null = File::open("/dev/null")?;
+    let log = match log_file {
+        Some(path) => OpenOptions::new().create(true).append(true).open(path)?,
+        None => OpenOptions::new().write(true).open("/dev/null")?,
+    };
+
+    fork_and_exit_parent()?;
+    // SAFETY: Inherently unsafe as a syscall, but the process is not a group leader.
+    if unsafe { libc::setsid() } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    // the session leader could acquire a terminal again, not its child
+    fork_and_exit_parent()?;
+
+    for (file, fd) in [
+        (&null, libc::STDIN_FILENO),
+        (&log, libc::STDOUT_FILENO),
+        (&log, libc::STDERR_FILENO),
+    ] {
+        // SAFETY: Inherently unsafe as a syscall, but both file descriptors are valid.
+        if unsafe { libc::dup2(file.as_raw_fd(), fd) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+
+    if let Some(path) = pid_file {
+        fs::write(path, format!("{}\n", std::process::id()))?;
+    }
+    Ok(())
+}
+
+/// Forks the process, the parent exits right away.
+fn fork_and_exit_parent() -> io::Result<()> {
+    // SAFETY: Inherently unsafe as a syscall, but the process has a single thread.
+    match unsafe { libc::fork() } {
+        -1 => Err(io::Error::last_os_error()),
+        0 => Ok(()),
+        _ => exit(0),
+    }
+}
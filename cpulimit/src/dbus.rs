@@ -0,0 +1,96 @@
+//! Control the running limiters over D-Bus.
+//!
+//! The `org.cpulimit.Manager` service lists the limiters, changes their limit
+//! and stops them. A limiter is designated by the label of its target, as in
+//! the messages, such as `process 1048`. Owning the name on the system bus
+//! requires the policy in `dbus/org.cpulimit.Manager.conf`.
+
+use clap::ArgEnum;
+use zbus::blocking::connection::{Builder, Connection};
+use zbus::{fdo, interface};
+
+use cpulimiter::{CpuLimit, Error};
+
+/// The well-known name of the service, and its interface.
+const NAME: &str = "org.cpulimit.Manager";
+
+/// The path of the object serving the interface.
+const PATH: &str = "/org/cpulimit/Manager";
+
+/// The bus the service is exposed on.
+#[derive(ArgEnum, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Bus {
+    System,
+    Session,
+}
+
+/// Lists the limiters along with the label of their target.
+type Limiters = Box<dyn Fn() -> Vec<(String, CpuLimit)> + Send + Sync>;
+
+struct Manager {
+    limiters: Limiters,
+}
+
+impl Manager {
+    /// Retrieves the running limiter of `target`.
+    fn find(&self, target: &str) -> fdo::Result<CpuLimit> {
+        (self.limiters)()
+            .into_iter()
+            .find(|(label, limiter)| label == target && limiter.is_running())
+            .map(|(_, limiter)| limiter)
+            .ok_or_else(|| fdo::Error::InvalidArgs(format!("no limiter for {target}")))
+    }
+}
+
+#[interface(name = "org.cpulimit.Manager")]
+impl Manager {
+    /// Lists the running limiters, as their target, their limit and the CPU
+    /// usage of the target, in percentages of one core.
+    fn list(&self) -> Vec<(String, f64, f64)> {
+        (self.limiters)()
+            .into_iter()
+            .filter(|(_, limiter)| limiter.is_running())
+            .map(|(label, limiter)| {
+                let limit = limiter.stats().limit;
+                (label, limit, limiter.cpu_usage_percent_single_core())
+            })
+            .collect()
+    }
+
+    /// Changes the limit of `target`, returning once it is in effect.
+    fn set_limit(&self, target: &str, limit: f64) -> fdo::Result<()> {
+        self.find(target)?
+            .set_limit(limit)
+            .map_err(|err| match err {
+                Error::InvalidLimit(_) => fdo::Error::InvalidArgs(err.to_string()),
+                err => fdo::Error::Failed(err.to_string()),
+            })
+    }
+
+    /// Stops limiting `target`, which is resumed.
+    fn stop(&self, target: &str) -> fdo::Result<()> {
+        self.find(target)?
+            .stop()
+            .map_err(|err| fdo::Error::Failed(err.to_string()))
+    }
+}
+
+/// Exposes the limiters returned by `limiters` on `bus`, as long as the connection is kept.
+pub fn serve<F>(bus: Bus, limiters: F) -> zbus::Result<Connection>
+where
+    F: Fn() -> Vec<(String, CpuLimit)> + Send + Sync + 'static,
+{
+    let builder = match bus {
+        Bus::System => Builder::system()?,
+        Bus::Session => Builder::session()?,
+    };
+    builder
+        .name(NAME)?
+        .serve_at(
+            PATH,
+            Manager {
+                limiters: Box::new(limiters),
+            },
+        )?
+        .build()
+}
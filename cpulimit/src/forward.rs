@@ -0,0 +1,50 @@
+//! Forward the termination signals to the spawned command.
+//!
+//! In scripts and Makefiles, `cpulimit run` stands for the command: it is
+//! terminated by the same signals, and exits with the same code.
+
+use std::os::unix::process::ExitStatusExt;
+use std::process::{Child, ExitStatus};
+use std::sync::atomic::{AtomicBool, AtomicI32, Ordering};
+
+/// The process the signals are forwarded to.
+static CHILD: AtomicI32 = AtomicI32::new(0);
+
+/// Whether a signal was forwarded since the last call to [`forwarded`].
+static FORWARDED: AtomicBool = AtomicBool::new(false);
+
+/// Makes `SIGINT` and `SIGTERM` sent to this process go to `child` instead.
+pub fn forward_to(child: &Child) {
+    extern "C" fn forward(signal: libc::c_int) {
+        let pid = CHILD.load(Ordering::Relaxed);
+        // SAFETY: `kill` is async-signal-safe, and so are the atomics.
+        unsafe { libc::kill(pid, signal) };
+        FORWARDED.store(true, Ordering::Relaxed);
+    }
+
+    CHILD.store(child.id() as i32, Ordering::Relaxed);
+    for signal in [libc::SIGINT, libc::SIGTERM] {
+        // SAFETY: the handler only calls async-signal-safe functions.
+        unsafe {
+            libc::signal(
+                signal,
+                forward as extern "C" fn(libc::c_int) as libc::sighandler_t,
+            )
+        };
+    }
+}
+
+/// Indicates whether a signal was forwarded since the last call.
+pub fn forwarded() -> bool {
+    FORWARDED.swap(false, Ordering::Relaxed)
+}
+
+/// Converts how the command exited to the code to exit with, as a shell does:
+/// `128` plus the number of the signal which terminated it, if any.
+pub fn exit_code(status: ExitStatus) -> i32 {
+    match (status.code(), status.signal()) {
+        (Some(code), _) => code,
+        (None, Some(signal)) => 128 + signal,
+        (None, None) => 1,
+    }
+}
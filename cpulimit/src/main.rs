@@ -5,59 +5,1059 @@
 //! Limit process `4562` to 10%.
 //!
 //! ```console
-//! cpulimit --pid 4562 --limit 10
+//! cpulimit attach --pid 4562 --limit 10
 //! ```
 //!
-//! Run `cpulimit --help` to list all the available options.
+//! Limit processes `4562` and `4563` to 10% each, or to 10% combined with `--shared`.
+//!
+//! ```console
+//! cpulimit attach --pid 4562 --pid 4563 --limit 10
+//! ```
+//!
+//! Limit every `ffmpeg` process to 30%, including the ones started later.
+//!
+//! ```console
+//! cpulimit watch --exe /usr/bin/ffmpeg --limit 30
+//! ```
+//!
+//! Limit all the processes of the user `1000` to 50% combined.
+//!
+//! ```console
+//! cpulimit attach --user 1000 --limit 50
+//! ```
+//!
+//! Limit the processes of a Docker container to 100% combined, without
+//! modifying its cgroup.
+//!
+//! ```console
+//! cpulimit attach --container 3f4e2a9c1b7d --limit 100
+//! ```
+//!
+//! Run `make` limited to 25%, along with the processes it spawns.
+//!
+//! ```console
+//! cpulimit run --limit 25 --include-children -- make -j4
+//! ```
+//!
+//! Keep limiting `nginx` to 50% in the background, with a log and a PID file.
+//!
+//! ```console
+//! cpulimit watch --name nginx --limit 50 --daemon --log-file /var/log/cpulimit.log --pid-file /run/cpulimit.pid
+//! ```
+//!
+//! Apply the rules of a configuration file, read again on `SIGHUP`.
+//!
+//! ```console
+//! cpulimit daemon --config /etc/cpulimit.toml
+//! ```
+//!
+//! Limit the main process of `foo.service` to 20%.
+//!
+//! ```console
+//! cpulimit attach --unit foo.service --limit 20
+//! ```
+//!
+//! List the limiters of a running instance, started with `--control-socket`,
+//! and change the limit of process `4562` through its socket.
+//!
+//! ```console
+//! cpulimit status
+//! echo "SET 4562 20" | socat - UNIX-CONNECT:/run/cpulimit.sock
+//! ```
+//!
+//! Or from another terminal, when built
+//! with the `dbus` feature and started with `--dbus session`.
+//!
+//! ```console
+//! busctl --user call org.cpulimit.Manager /org/cpulimit/Manager org.cpulimit.Manager SetLimit sd "process 4562" 20
+//! ```
+//!
+//! Run `cpulimit help` to list the subcommands, and `cpulimit help <subcommand>`
+//! for their options.
+
+mod config;
+mod control;
+mod daemon;
+#[cfg(feature = "dbus")]
+mod dbus;
+mod forward;
+mod output;
+mod systemd;
+#[cfg(feature = "tui")]
+mod tui;
 
-use std::process::exit;
+use std::error::Error;
+use std::fmt::Display;
+use std::fs;
+use std::io::{self, IsTerminal};
+use std::path::{Path, PathBuf};
+use std::process::{exit, Child, Command};
+use std::sync::{mpsc, Arc};
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
+
+use clap::{ArgGroup, Parser};
+use tracing::level_filters::LevelFilter;
+use tracing::Level;
+use tracing_subscriber::fmt::writer::MakeWriterExt;
+
+use cpulimiter::{
+    drop_privileges, ChildrenMode, ControlServer, CpuLimit, CpuLimitBuilder, DaemonEvent,
+    ExitReason, LimiterDaemon, Pid, Rule, Target,
+};
 
-use clap::Parser;
+use crate::control::{Client, Limiters};
+use crate::output::{Format, Message};
 
-use cpulimiter::{CpuLimit, Pid};
+/// How often the state of the daemon is saved to `--state-file`.
+const SAVE_INTERVAL: Duration = Duration::from_secs(10);
+
+/// How often the spawned command is checked for exiting.
+const WAIT_INTERVAL: Duration = Duration::from_millis(100);
+
+/// The control socket of an instance, unless given another path.
+const CONTROL_SOCKET: &str = "/run/cpulimit.sock";
+
+/// Where the limited processes are recorded for `cpulimit recover`, unless given another path.
+const RECOVERY_DIR: &str = "/run/cpulimit";
 
 #[derive(Parser, Debug)]
 #[clap(version, about)]
 struct Args {
+    #[clap(
+        short,
+        long,
+        arg_enum,
+        global = true,
+        default_value = "text",
+        help = "How to print the messages"
+    )]
+    output: Format,
+    #[clap(
+        long,
+        global = true,
+        default_value = "info",
+        help = "The most detailed messages to print: off, error, warn, info, debug or trace"
+    )]
+    log_level: LevelFilter,
+    #[clap(subcommand)]
+    subcommand: Subcommand,
+}
+
+#[derive(clap::Subcommand, Debug)]
+enum Subcommand {
+    #[clap(about = "Limit running processes, given by PID, user, container or systemd unit")]
+    Attach(Attach),
+    #[clap(about = "Spawn a command and limit it")]
+    Run(Run),
+    #[clap(
+        about = "Limit each process running an executable or with a name, as soon as it appears"
+    )]
+    Watch(Watch),
+    #[clap(about = "Apply the rules of a configuration file, read again on SIGHUP")]
+    Daemon(Daemon),
+    #[clap(about = "List the limiters of a running instance")]
+    Status(Status),
+    #[clap(about = "Resume the processes left suspended by an instance that was killed")]
+    Recover(Recover),
+    #[cfg(feature = "tui")]
+    #[clap(about = "Monitor the limiters of a running instance, and adjust their limits")]
+    Top(Status),
+}
+
+#[derive(clap::Args, Debug)]
+#[clap(group(ArgGroup::new("targets").required(true).args(&["pid", "user", "container", "unit"])))]
+struct Attach {
     #[clap(
         short,
         long,
         parse(try_from_str),
-        help = "The PID of the target process"
+        multiple_occurrences = true,
+        help = "The PID of a target process, may be repeated"
     )]
-    pid: Pid,
+    pid: Vec<Pid>,
+    #[clap(
+        short,
+        long,
+        conflicts_with_all = &["pid", "container", "unit"],
+        help = "The UID of the user whose processes to limit"
+    )]
+    user: Option<u32>,
+    #[clap(
+        long,
+        conflicts_with_all = &["pid", "user", "unit"],
+        help = "The ID of the container whose processes to limit"
+    )]
+    container: Option<String>,
+    #[clap(
+        long,
+        conflicts_with_all = &["user", "container"],
+        help = "Limit the main process of this systemd unit, such as foo.service"
+    )]
+    unit: Option<String>,
+    #[clap(
+        long,
+        requires = "pid",
+        help = "Share a single limit between all the PIDs instead of limiting each one"
+    )]
+    shared: bool,
+    #[clap(flatten)]
+    limit: Limit,
+    #[clap(flatten)]
+    service: Service,
+}
+
+#[derive(clap::Args, Debug)]
+struct Run {
+    #[clap(flatten)]
+    limit: Limit,
+    #[clap(flatten)]
+    service: Service,
+    #[clap(
+        required = true,
+        last = true,
+        help = "The command to spawn under the limit"
+    )]
+    command: Vec<String>,
+}
+
+#[derive(clap::Args, Debug)]
+#[clap(group(ArgGroup::new("matching").required(true).args(&["exe", "name"])))]
+struct Watch {
+    #[clap(
+        short,
+        long,
+        help = "Limit each process running the executable at this path"
+    )]
+    exe: Option<PathBuf>,
+    #[clap(short, long, help = "Limit each process with this command name")]
+    name: Option<String>,
+    #[clap(
+        long,
+        help = "Save the limits to this file, and restore them from it when starting again"
+    )]
+    state_file: Option<PathBuf>,
+    #[clap(flatten)]
+    rate: Rate,
+    #[clap(flatten)]
+    service: Service,
+}
+
+#[derive(clap::Args, Debug)]
+struct Daemon {
+    #[clap(short, long, help = "The configuration file listing the rules")]
+    config: PathBuf,
+    #[clap(
+        long,
+        help = "Save the limits to this file, and restore them from it when starting again"
+    )]
+    state_file: Option<PathBuf>,
+    #[clap(flatten)]
+    service: Service,
+}
+
+#[derive(clap::Args, Debug)]
+struct Status {
+    #[clap(
+        long,
+        value_name = "PATH",
+        default_value = CONTROL_SOCKET,
+        help = "The control socket of the instance, see --control-socket"
+    )]
+    control_socket: PathBuf,
+}
+
+#[derive(clap::Args, Debug)]
+struct Recover {
+    #[clap(
+        long,
+        value_name = "PATH",
+        default_value = RECOVERY_DIR,
+        help = "The directory the instances recorded their targets in, see --recovery-dir"
+    )]
+    recovery_dir: PathBuf,
+}
+
+/// The limit applied by the subcommands which don't read it from a configuration file.
+#[derive(clap::Args, Debug)]
+struct Rate {
     #[clap(short, long, help = "The CPU rate limit to enforce")]
     limit: f64,
     #[clap(short = 'i', long, help = "Also limit the CPU usage of the children")]
     include_children: bool,
 }
 
-fn main() {
-    let args = Args::parse();
+impl Rate {
+    fn children_mode(&self) -> ChildrenMode {
+        if self.include_children {
+            ChildrenMode::Include
+        } else {
+            ChildrenMode::Exclude
+        }
+    }
+}
 
-    let limiter = if args.include_children {
-        CpuLimit::new_with_children(args.pid, args.limit)
-    } else {
-        CpuLimit::new(args.pid, args.limit)
+/// The limit and the options of the subcommands limiting the processes
+/// found at startup, which `watch` doesn't support.
+#[derive(clap::Args, Debug)]
+struct Limit {
+    #[clap(flatten)]
+    rate: Rate,
+    #[clap(
+        long,
+        parse(try_from_str = parse_seconds),
+        help = "Lift the limit after this many seconds"
+    )]
+    timeout: Option<Duration>,
+    #[clap(
+        long,
+        value_name = "PATH",
+        min_values = 0,
+        default_missing_value = RECOVERY_DIR,
+        help = "Record the targets in this directory, for `cpulimit recover` to resume them if killed"
+    )]
+    recovery_dir: Option<PathBuf>,
+    #[clap(
+        long,
+        value_name = "UID",
+        help = "Run as this user once the targets are attached, they must belong to it"
+    )]
+    setuid: Option<u32>,
+    #[clap(
+        long,
+        value_name = "GID",
+        requires = "setuid",
+        help = "Run as this group, the primary group of the --setuid user by default"
+    )]
+    setgid: Option<u32>,
+}
+
+impl Limit {
+    /// Creates a builder with the limit, the timeout, the recovery directory
+    /// and the user to run as eventually.
+    fn builder(&self) -> CpuLimitBuilder {
+        let mut builder = CpuLimit::builder(self.rate.limit);
+        if let Some(timeout) = self.timeout {
+            builder = builder.for_duration(timeout);
+        }
+        if let Some(dir) = &self.recovery_dir {
+            builder = builder.recovery_dir(dir);
+        }
+        if let Some(uid) = self.setuid {
+            builder = builder.run_as(uid);
+        }
+        builder
     }
-    .unwrap();
 
+    /// Retrieves the user and the group to switch to once everything is set up.
+    fn run_as(&self) -> Option<(u32, u32)> {
+        let uid = self.setuid?;
+        // users missing from the database get the group of the same ID
+        let gid = self.setgid.or_else(|| primary_group(uid)).unwrap_or(uid);
+        Some((uid, gid))
+    }
+
+    fn children_mode(&self) -> ChildrenMode {
+        self.rate.children_mode()
+    }
+}
+
+/// Parses a positive number of seconds, possibly fractional.
+fn parse_seconds(value: &str) -> Result<Duration, String> {
+    let seconds = value.parse::<f64>().map_err(|err| err.to_string())?;
+    if seconds <= 0_f64 {
+        return Err("must be positive".to_string());
+    }
+    Duration::try_from_secs_f64(seconds).map_err(|err| err.to_string())
+}
+
+/// Looks up the primary group of the user `uid` in the password database.
+fn primary_group(uid: u32) -> Option<u32> {
+    // SAFETY: an all-zero `passwd` is valid, and is filled by the call.
+    let mut passwd: libc::passwd = unsafe { std::mem::zeroed() };
+    let mut buffer = vec![0; 16384];
+    let mut result = std::ptr::null_mut();
+    // SAFETY: the buffers outlive the call, which writes at most `buffer.len()` bytes.
+    unsafe {
+        libc::getpwuid_r(
+            uid,
+            &mut passwd,
+            buffer.as_mut_ptr(),
+            buffer.len(),
+            &mut result,
+        )
+    };
+    (!result.is_null()).then_some(passwd.pw_gid)
+}
+
+/// How the subcommands which limit processes run and expose their limiters.
+#[derive(clap::Args, Debug)]
+struct Service {
+    #[clap(
+        short,
+        long,
+        help = "Print the statistics of the limiters periodically"
+    )]
+    verbose: bool,
+    #[clap(
+        long,
+        requires = "verbose",
+        default_value = "1",
+        parse(try_from_str = parse_seconds),
+        help = "The interval between two statistics lines, in seconds"
+    )]
+    stats_interval: Duration,
+    #[clap(
+        short,
+        long,
+        help = "Run in the background, detached from the terminal"
+    )]
+    daemon: bool,
+    #[clap(
+        long,
+        requires = "daemon",
+        help = "The file to append the messages to in the background"
+    )]
+    log_file: Option<PathBuf>,
+    #[clap(
+        long,
+        requires = "daemon",
+        help = "The file to write the PID of the daemon to"
+    )]
+    pid_file: Option<PathBuf>,
+    #[clap(
+        long,
+        value_name = "PATH",
+        min_values = 0,
+        default_missing_value = CONTROL_SOCKET,
+        help = "Accept LIST, SET <pid> <limit>, STOP <pid> and STATS <pid> on this Unix socket"
+    )]
+    control_socket: Option<PathBuf>,
+    #[cfg(feature = "dbus")]
+    #[clap(
+        long,
+        arg_enum,
+        help = "Expose the limiters as the org.cpulimit.Manager service on this bus"
+    )]
+    dbus: Option<dbus::Bus>,
+}
+
+impl Subcommand {
+    /// Retrieves how the subcommand runs, if it limits processes.
+    fn service(&self) -> Option<&Service> {
+        match self {
+            Subcommand::Attach(attach) => Some(&attach.service),
+            Subcommand::Run(run) => Some(&run.service),
+            Subcommand::Watch(watch) => Some(&watch.service),
+            Subcommand::Daemon(daemon) => Some(&daemon.service),
+            Subcommand::Status(_) | Subcommand::Recover(_) => None,
+            #[cfg(feature = "tui")]
+            Subcommand::Top(_) => None,
+        }
+    }
+}
+
+/// What keeps the program running once the limiters started.
+enum Until {
+    /// The spawned command exits, the program exits with its code.
+    CommandExits(Child),
+    /// The only limiter stops, its processes being found again at every update.
+    LimiterStops,
+    /// The given processes exit.
+    ProcessesExit(Vec<Pid>),
+}
+
+/// Reports why the limiter couldn't start or keep running, and exits.
+fn fail(output: Format, err: impl Error) -> ! {
+    exit_with(output, chain(&err))
+}
+
+/// Reports that the program couldn't `action` because of `err`, and exits.
+fn fail_to(output: Format, action: impl Display, err: impl Error) -> ! {
+    exit_with(output, format!("couldn't {action}: {}", chain(&err)))
+}
+
+/// Prints the error `message` and exits.
+fn exit_with(output: Format, message: String) -> ! {
+    output.print(&Message::Error {
+        target: None,
+        message,
+    });
+    exit(1);
+}
+
+/// Joins the message of `err` with the ones of the errors that caused it.
+fn chain(err: &dyn Error) -> String {
+    let mut message = err.to_string();
+    let mut source = err.source();
+    while let Some(cause) = source {
+        message.push_str(": ");
+        message.push_str(&cause.to_string());
+        source = cause.source();
+    }
+    message
+}
+
+/// Prints the statistics of each limiter every `interval`, in a separate thread.
+///
+/// `limiters` lists the running limiters along with a label for each one.
+fn report<F>(output: Format, interval: Duration, limiters: F)
+where
+    F: Fn() -> Vec<(String, CpuLimit)> + Send + 'static,
+{
+    thread::spawn(move || loop {
+        thread::sleep(interval);
+        for (label, limiter) in limiters() {
+            if !limiter.is_running() {
+                continue;
+            }
+            let stats = limiter.stats();
+            output.print(&Message::Stats {
+                target: &label,
+                usage: limiter.cpu_usage_percent_single_core(),
+                working_rate: stats.working_rate * 100_f64,
+                children: stats.children,
+            });
+        }
+    });
+}
+
+/// Accepts commands for the limiters returned by `limiters` on the control socket, if requested.
+///
+/// Exits if the socket can't be set up.
+fn control<F>(service: &Service, output: Format, limiters: F) -> Option<ControlServer>
+where
+    F: Fn() -> Vec<(Pid, CpuLimit)> + Send + Sync + 'static,
+{
+    let path = service.control_socket.as_ref()?;
+    Some(ControlServer::bind(path, limiters).unwrap_or_else(|err| fail(output, err)))
+}
+
+/// Exposes the limiters returned by `limiters` on D-Bus, if requested.
+///
+/// Exits if the service can't be registered.
+#[cfg(feature = "dbus")]
+fn serve<F>(service: &Service, output: Format, limiters: F) -> Option<zbus::blocking::Connection>
+where
+    F: Fn() -> Vec<(String, CpuLimit)> + Send + Sync + 'static,
+{
+    let connection = dbus::serve(service.dbus?, limiters)
+        .unwrap_or_else(|err| fail_to(output, "register on D-Bus", err));
+    Some(connection)
+}
+
+/// Creates a daemon applying `rules`, resuming from the `state_file` if there is one.
+fn restore(rules: Vec<Rule>, state_file: Option<&Path>, output: Format) -> LimiterDaemon {
+    let Some(path) = state_file.filter(|path| path.exists()) else {
+        return LimiterDaemon::new(rules);
+    };
+    match LimiterDaemon::load_state(path) {
+        Ok(daemon) => {
+            // the rules given now take precedence over the saved ones
+            daemon.set_rules(rules);
+            daemon
+        }
+        Err(err) => {
+            output.print(&Message::Error {
+                target: None,
+                message: format!("couldn't restore {}: {}", path.display(), chain(&err)),
+            });
+            LimiterDaemon::new(rules)
+        }
+    }
+}
+
+/// Saves the state of `daemon` to the state file, if any.
+fn save(daemon: &LimiterDaemon, state_file: Option<&Path>, output: Format) {
+    let Some(path) = state_file else {
+        return;
+    };
+    if let Err(err) = daemon.save_state(path) {
+        output.print(&Message::Error {
+            target: None,
+            message: format!("couldn't save {}: {}", path.display(), chain(&err)),
+        });
+    }
+}
+
+/// Limits each process matching one of `rules` as soon as it appears, until Ctrl-C.
+///
+/// The rules are replaced by the ones of the `config` file, if any, on `SIGHUP`.
+/// The state is saved periodically, and before exiting, to the `state_file`.
+fn watch(
+    rules: Vec<Rule>,
+    service: &Service,
+    output: Format,
+    state_file: Option<&Path>,
+    config: Option<&Path>,
+) -> ! {
+    let daemon = Arc::new(restore(rules, state_file, output));
+    let events = daemon.events();
+    thread::spawn(move || {
+        for event in events {
+            match event {
+                DaemonEvent::Attached(pid) => output.print(&Message::Limiting(&label(pid))),
+                DaemonEvent::Detached(pid) => output.print(&Message::Detached(&label(pid))),
+                DaemonEvent::Failed(pid, err) => output.print(&Message::Error {
+                    target: Some(&label(pid)),
+                    message: chain(&err),
+                }),
+            }
+        }
+    });
+
+    if service.verbose {
+        let daemon = daemon.clone();
+        report(output, service.stats_interval, move || {
+            daemon
+                .limiters()
+                .into_iter()
+                .map(|(pid, limiter)| (label(pid), limiter))
+                .collect()
+        });
+    }
+
+    let _server = {
+        let daemon = daemon.clone();
+        control(service, output, move || daemon.limiters())
+    };
+    #[cfg(feature = "dbus")]
+    let _connection = {
+        let daemon = daemon.clone();
+        serve(service, output, move || {
+            daemon
+                .limiters()
+                .into_iter()
+                .map(|(pid, limiter)| (label(pid), limiter))
+                .collect()
+        })
+    };
+
+    let handle = daemon.clone();
+    let socket = service.control_socket.clone();
+    let saved = state_file.map(Path::to_path_buf);
     ctrlc::set_handler(move || {
-        println!("Stopping after receiving Ctrl-C");
-        limiter.stop().unwrap();
-        // wait for the Stop command to propagate.
-        thread::sleep(Duration::from_millis(200));
+        output.print(&Message::Interrupted);
+        // before the limiters are stopped
+        save(&handle, saved.as_deref(), output);
+        handle.stop();
+        let _ = systemd::notify("STOPPING=1");
+        // `exit` doesn't drop the server
+        if let Some(path) = &socket {
+            let _ = fs::remove_file(path);
+        }
         exit(0);
     })
-    .unwrap();
+    .unwrap_or_else(|err| fail(output, err));
+    if config.is_some() {
+        // replaces the handler of `ctrlc`, which also stops on `SIGHUP`
+        config::reload_on_sighup();
+    }
 
+    daemon.start().unwrap_or_else(|err| fail(output, err));
+    let _ = systemd::notify("READY=1");
+    systemd::spawn_watchdog();
+    let mut last_save = Instant::now();
     loop {
         thread::sleep(Duration::from_secs(1));
-        if !args.pid.alive() {
-            println!("The target process is dead");
-            break;
+        if let Some(rules) = config.and_then(|path| reload(path, output)) {
+            daemon.set_rules(rules);
+        }
+        if last_save.elapsed() >= SAVE_INTERVAL {
+            save(&daemon, state_file, output);
+            last_save = Instant::now();
+        }
+    }
+}
+
+/// Reads the rules of the `config` file again, if requested since the last call.
+fn reload(config: &Path, output: Format) -> Option<Vec<Rule>> {
+    if !config::reload_requested() {
+        return None;
+    }
+
+    match config::load(config) {
+        Ok(rules) => {
+            output.print(&Message::Reloaded);
+            Some(rules)
+        }
+        Err(err) => {
+            // the current rules stay in place
+            output.print(&Message::Error {
+                target: None,
+                message: format!("couldn't reload {}: {}", config.display(), chain(&err)),
+            });
+            None
+        }
+    }
+}
+
+/// Retrieves the main process of the systemd `unit`, or exits.
+fn unit_pid(unit: &str, output: Format) -> Pid {
+    match systemd::main_pid(unit) {
+        Ok(Some(pid)) => pid,
+        Ok(None) => exit_with(output, format!("{unit} has no running main process")),
+        Err(err) => fail_to(output, format_args!("read the main process of {unit}"), err),
+    }
+}
+
+/// Designates a target process in the messages.
+fn label(pid: Pid) -> String {
+    format!("process {pid}")
+}
+
+/// Prints the messages up to `--log-level`, the warnings and errors on the standard error.
+///
+/// Timestamps are only added in the `background`: the journal and terminals don't need them.
+fn init_logging(args: &Args, background: bool) {
+    let builder = tracing_subscriber::fmt()
+        .with_max_level(args.log_level)
+        .with_writer(io::stderr.with_max_level(Level::WARN).or_else(io::stdout))
+        .with_ansi(!background && io::stdout().is_terminal())
+        .with_target(false);
+    if background {
+        builder.init();
+    } else {
+        builder.without_time().init();
+    }
+}
+
+/// Starts limiting the processes designated by `attach`.
+fn attach(attach: &Attach, output: Format) {
+    let (limiters, until) = if let Some(uid) = attach.user {
+        let limiter = attach
+            .limit
+            .builder()
+            .target(Target::User(uid))
+            .build()
+            .unwrap_or_else(|err| fail(output, err));
+        (vec![(format!("user {uid}"), limiter)], Until::LimiterStops)
+    } else if let Some(id) = &attach.container {
+        let limiter = attach
+            .limit
+            .builder()
+            .target(Target::Container(id.clone()))
+            .build()
+            .unwrap_or_else(|err| fail(output, err));
+        (
+            vec![(format!("container {id}"), limiter)],
+            Until::LimiterStops,
+        )
+    } else {
+        let mut pids = attach.pid.clone();
+        if let Some(unit) = &attach.unit {
+            pids.push(unit_pid(unit, output));
+        }
+        let limiters = if attach.shared {
+            let limiter = attach
+                .limit
+                .builder()
+                .targets(&pids)
+                .children(attach.limit.children_mode())
+                .build()
+                .unwrap_or_else(|err| fail(output, err));
+            vec![("the shared group".to_string(), limiter)]
+        } else {
+            start_each(&pids, &attach.limit, output)
+        };
+        (limiters, Until::ProcessesExit(pids))
+    };
+    supervise(
+        limiters,
+        until,
+        &attach.service,
+        attach.limit.run_as(),
+        output,
+    );
+}
+
+/// Starts a limiter for each of `pids`, or exits.
+fn start_each(pids: &[Pid], limit: &Limit, output: Format) -> Vec<(String, CpuLimit)> {
+    // the limiters already started are dropped, thus stopped, on failure
+    pids.iter()
+        .map(|&pid| {
+            limit
+                .builder()
+                .target(pid)
+                .children(limit.children_mode())
+                .build()
+                .map(|limiter| (label(pid), limiter))
+        })
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap_or_else(|err| fail(output, err))
+}
+
+/// Spawns the command of `run` under its limit.
+fn run(run: &Run, output: Format) {
+    let mut command = Command::new(&run.command[0]);
+    command.args(&run.command[1..]);
+    // the notifications are for this process only
+    for var in ["NOTIFY_SOCKET", "WATCHDOG_PID", "WATCHDOG_USEC"] {
+        command.env_remove(var);
+    }
+
+    let (limiter, child) = run
+        .limit
+        .builder()
+        .children(run.limit.children_mode())
+        .spawn(command)
+        .unwrap_or_else(|err| fail(output, err));
+    let limiters = vec![(label(Pid::from(child.id())), limiter)];
+    let until = Until::CommandExits(child);
+    supervise(limiters, until, &run.service, run.limit.run_as(), output);
+}
+
+/// Exposes the `limiters`, switches to the user and group to `run_as` if
+/// any, and waits `until` the processes don't need them anymore.
+fn supervise(
+    limiters: Vec<(String, CpuLimit)>,
+    until: Until,
+    service: &Service,
+    run_as: Option<(u32, u32)>,
+    output: Format,
+) {
+    for (label, _) in &limiters {
+        output.print(&Message::Limiting(label));
+    }
+
+    let handles = limiters.clone();
+    if service.verbose {
+        let limiters = limiters.clone();
+        report(output, service.stats_interval, move || limiters.clone());
+    }
+    // a limiter is designated by any process it limits
+    let server = {
+        let limiters = limiters.clone();
+        control(service, output, move || {
+            limiters
+                .iter()
+                .flat_map(|(_, limiter)| {
+                    let processes = limiter.stats().processes;
+                    processes
+                        .into_iter()
+                        .map(|process| (process.pid, limiter.clone()))
+                })
+                .collect()
+        })
+    };
+    #[cfg(feature = "dbus")]
+    let _connection = {
+        let limiters = limiters.clone();
+        serve(service, output, move || limiters.clone())
+    };
+
+    let socket = service.control_socket.clone();
+    let interrupt = move || {
+        output.print(&Message::Interrupted);
+        stop(&limiters);
+        let _ = systemd::notify("STOPPING=1");
+        // `exit` doesn't drop the server
+        if let Some(path) = &socket {
+            let _ = fs::remove_file(path);
         }
+        output.print(&Message::Finished {
+            reason: "interrupted",
+            exit_code: Some(0),
+        });
+        exit(0);
+    };
+    match &until {
+        // the command decides when to exit
+        Until::CommandExits(child) => forward::forward_to(child),
+        _ => ctrlc::set_handler(interrupt).unwrap_or_else(|err| fail(output, err)),
+    }
+    // last, the control socket and the recovery files are created as root
+    if let Some((uid, gid)) = run_as {
+        if let Err(err) = drop_privileges(uid, gid) {
+            stop(&handles);
+            fail(output, err);
+        }
+    }
+    let _ = systemd::notify("READY=1");
+    systemd::spawn_watchdog();
+
+    let reason = match until {
+        Until::CommandExits(mut child) => {
+            let status = loop {
+                if forward::forwarded() {
+                    output.print(&Message::Interrupted);
+                    // a suspended command only handles the signal once resumed
+                    stop(&handles);
+                }
+                match child.try_wait() {
+                    Ok(Some(status)) => break status,
+                    Ok(None) => {}
+                    Err(err) => {
+                        stop(&handles);
+                        fail(output, err);
+                    }
+                }
+                thread::sleep(WAIT_INTERVAL);
+            };
+            // the children it left behind mustn't stay suspended
+            stop(&handles);
+            let _ = systemd::notify("STOPPING=1");
+            let exit_code = forward::exit_code(status);
+            output.print(&Message::Finished {
+                reason: "command_exited",
+                exit_code: Some(exit_code),
+            });
+            drop(server);
+            exit(exit_code);
+        }
+        Until::LimiterStops => match handles[0].1.join() {
+            Ok(ExitReason::Stopped) => "stopped",
+            Ok(ExitReason::TargetExited) => "targets_exited",
+            Ok(ExitReason::BudgetExhausted) => "budget_exhausted",
+            Ok(ExitReason::DeadlineReached) => "timed_out",
+            Err(err) => {
+                output.print(&Message::Error {
+                    target: Some(&handles[0].0),
+                    message: chain(&err),
+                });
+                "failed"
+            }
+        },
+        Until::ProcessesExit(pids) => {
+            // notified of each exit at once through a pidfd, where supported
+            let (sender, exited) = mpsc::channel();
+            for &pid in &pids {
+                let sender = sender.clone();
+                thread::spawn(move || {
+                    pid.wait_exit(None);
+                    let _ = sender.send(pid);
+                });
+            }
+            let mut remaining = pids.len();
+            // the limiters stop on their own once timed out
+            while remaining > 0 && handles.iter().any(|(_, limiter)| limiter.is_running()) {
+                if let Ok(pid) = exited.recv_timeout(Duration::from_secs(1)) {
+                    output.print(&Message::Detached(&label(pid)));
+                    remaining -= 1;
+                }
+            }
+            let timed_out = |(_, limiter): &(String, CpuLimit)| {
+                matches!(limiter.join(), Ok(ExitReason::DeadlineReached))
+            };
+            if remaining == 0 {
+                "targets_exited"
+            } else if handles.iter().all(timed_out) {
+                "timed_out"
+            } else {
+                "limiters_stopped"
+            }
+        }
+    };
+    let _ = systemd::notify("STOPPING=1");
+    output.print(&Message::Finished {
+        reason,
+        exit_code: None,
+    });
+}
+
+/// Stops the `limiters`, resuming their processes.
+fn stop(limiters: &[(String, CpuLimit)]) {
+    for (_, limiter) in limiters {
+        // fails if the limiter already stopped on its own
+        let _ = limiter.stop();
+    }
+}
+
+/// Lists the limiters of the instance listening on the control socket of `status`.
+fn status(status: &Status, output: Format) {
+    let path = &status.control_socket;
+    let listed = Client::connect(path).and_then(|mut client| client.request::<Limiters>("LIST"));
+    let limiters =
+        listed.unwrap_or_else(|err| fail_to(output, format_args!("query {}", path.display()), err));
+
+    for limiter in limiters.limiters {
+        output.print(&Message::Status {
+            target: &label(Pid::from(limiter.pid)),
+            limit: limiter.limit,
+            usage: limiter.usage,
+        });
+    }
+}
+
+fn recover(recover: &Recover, output: Format) {
+    let dir = &recover.recovery_dir;
+    let resumed = cpulimiter::recover(dir)
+        .unwrap_or_else(|err| fail_to(output, format_args!("recover from {}", dir.display()), err));
+
+    for pid in resumed {
+        output.print(&Message::Resumed(&label(pid)));
+    }
+}
+
+fn main() {
+    let args = Args::parse();
+    let output = args.output;
+    let service = args.subcommand.service();
+    let background = service.is_some_and(|service| service.daemon);
+    init_logging(&args, background);
+
+    if let Some(service) = service.filter(|_| background) {
+        let daemonized =
+            daemon::daemonize(service.log_file.as_deref(), service.pid_file.as_deref());
+        if let Err(err) = daemonized {
+            fail_to(output, "run in the background", err);
+        }
+    }
+
+    match &args.subcommand {
+        Subcommand::Attach(args) => attach(args, output),
+        Subcommand::Run(args) => run(args, output),
+        Subcommand::Watch(args) => {
+            let matcher = match (&args.exe, &args.name) {
+                (Some(exe), _) => Target::Exe(exe.clone()),
+                (_, Some(name)) => Target::Name(name.clone()),
+                // one of them is required
+                (None, None) => unreachable!(),
+            };
+            let rule = Rule {
+                matcher,
+                limit: args.rate.limit,
+                children: args.rate.children_mode(),
+            };
+            watch(
+                vec![rule],
+                &args.service,
+                output,
+                args.state_file.as_deref(),
+                None,
+            )
+        }
+        Subcommand::Daemon(args) => {
+            let rules = config::load(&args.config).unwrap_or_else(|err| {
+                fail_to(output, format_args!("read {}", args.config.display()), err)
+            });
+            watch(
+                rules,
+                &args.service,
+                output,
+                args.state_file.as_deref(),
+                Some(&args.config),
+            )
+        }
+        Subcommand::Status(args) => status(args, output),
+        Subcommand::Recover(args) => recover(args, output),
+        #[cfg(feature = "tui")]
+        Subcommand::Top(args) => {
+            if let Err(err) = tui::run(&args.control_socket) {
+                let socket = args.control_socket.display();
+                fail_to(output, format_args!("monitor {socket}"), err);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn error_chain() {
+        let err = cpulimiter::Error::Cgroup(io::Error::from(io::ErrorKind::PermissionDenied));
+        assert_eq!(chain(&err), "Couldn't set up the cgroup: permission denied");
     }
 }
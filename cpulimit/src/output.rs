@@ -0,0 +1,185 @@
+//! Report what the limiters do, for humans or for other programs.
+
+use std::fmt::Write;
+
+use clap::ArgEnum;
+
+/// How the messages are printed.
+#[derive(ArgEnum, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Format {
+    /// Sentences, one per line.
+    Text,
+    /// A JSON object per line, with an `event` field telling what happened.
+    Json,
+}
+
+/// A notable event, for one of the limiters or the whole program.
+pub enum Message<'a> {
+    /// A limiter started on `target`.
+    Limiting(&'a str),
+    /// The limiter of `target` stopped.
+    Detached(&'a str),
+    /// The statistics of the limiter of `target`.
+    Stats {
+        target: &'a str,
+        /// As a percentage of one core.
+        usage: f64,
+        /// As a percentage of each time slice.
+        working_rate: f64,
+        children: usize,
+    },
+    /// The limiter of `target` in a running instance, as reported by `cpulimit status`.
+    Status {
+        target: &'a str,
+        /// As percentages of one core.
+        limit: f64,
+        usage: f64,
+    },
+    /// `target`, left suspended by an instance that was killed, was resumed.
+    Resumed(&'a str),
+    /// The configuration file was read again.
+    Reloaded,
+    /// The program was asked to stop.
+    Interrupted,
+    /// The program is about to exit, for the given reason.
+    Finished {
+        reason: &'a str,
+        exit_code: Option<i32>,
+    },
+    /// Something went wrong, with `target` if it is specific to it.
+    Error {
+        target: Option<&'a str>,
+        message: String,
+    },
+}
+
+impl Format {
+    /// Prints `message`, errors on the standard error.
+    ///
+    /// Sentences are logged with `tracing`, along with the events of the library.
+    pub fn print(self, message: &Message) {
+        match (self, message) {
+            (Format::Text, Message::Error { .. }) => {
+                if let Some(line) = message.text() {
+                    tracing::error!("{line}");
+                }
+            }
+            (Format::Text, _) => {
+                if let Some(line) = message.text() {
+                    tracing::info!("{line}");
+                }
+            }
+            (Format::Json, Message::Error { .. }) => eprintln!("{}", message.json()),
+            (Format::Json, _) => println!("{}", message.json()),
+        }
+    }
+}
+
+impl Message<'_> {
+    /// Describes the message in a sentence, if it is worth it.
+    fn text(&self) -> Option<String> {
+        Some(match self {
+            Message::Limiting(target) => format!("Limiting {target}"),
+            Message::Detached(target) => format!("No longer limiting {target}"),
+            Message::Stats {
+                target,
+                usage,
+                working_rate,
+                children,
+            } => format!(
+                "{target}: usage {usage:.1}%, working rate {working_rate:.1}%, {children} children"
+            ),
+            Message::Status {
+                target,
+                limit,
+                usage,
+            } => format!("{target}: limit {limit}%, usage {usage:.1}%"),
+            Message::Resumed(target) => format!("Resumed {target}"),
+            Message::Reloaded => "Reloaded the configuration".to_string(),
+            Message::Interrupted => "Stopping after receiving Ctrl-C".to_string(),
+            Message::Finished { .. } => return None,
+            Message::Error {
+                target: Some(target),
+                message,
+            } => format!("Couldn't limit {target}: {message}"),
+            Message::Error {
+                target: None,
+                message,
+            } => format!("Couldn't limit the CPU usage: {message}"),
+        })
+    }
+
+    /// Describes the message as a JSON object.
+    fn json(&self) -> String {
+        let mut json = String::from("{");
+        // writing to a `String` can't fail
+        let _ = match self {
+            Message::Limiting(target) => {
+                write!(json, r#""event":"limiting","target":{}"#, quote(target))
+            }
+            Message::Detached(target) => {
+                write!(json, r#""event":"detached","target":{}"#, quote(target))
+            }
+            Message::Stats {
+                target,
+                usage,
+                working_rate,
+                children,
+            } => write!(
+                json,
+                r#""event":"stats","target":{},"usage":{usage},"working_rate":{working_rate},"children":{children}"#,
+                quote(target)
+            ),
+            Message::Status {
+                target,
+                limit,
+                usage,
+            } => write!(
+                json,
+                r#""event":"status","target":{},"limit":{limit},"usage":{usage}"#,
+                quote(target)
+            ),
+            Message::Resumed(target) => {
+                write!(json, r#""event":"resumed","target":{}"#, quote(target))
+            }
+            Message::Reloaded => write!(json, r#""event":"reloaded""#),
+            Message::Interrupted => write!(json, r#""event":"interrupted""#),
+            Message::Finished { reason, exit_code } => {
+                let exit_code = exit_code.map_or("null".to_string(), |code| code.to_string());
+                write!(
+                    json,
+                    r#""event":"finished","reason":{},"exit_code":{exit_code}"#,
+                    quote(reason)
+                )
+            }
+            Message::Error { target, message } => {
+                let target = target.map_or("null".to_string(), quote);
+                write!(
+                    json,
+                    r#""event":"error","target":{target},"message":{}"#,
+                    quote(message)
+                )
+            }
+        };
+        json.push('}');
+        json
+    }
+}
+
+/// Writes `value` as a JSON string, with the special characters escaped.
+fn quote(value: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in value.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            c if c.is_control() => {
+                let _ = write!(quoted, "\\u{:04x}", c as u32);
+            }
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
@@ -0,0 +1,75 @@
+//! Integrate with systemd: readiness notifications, the watchdog and units.
+//!
+//! With `Type=notify`, the service manager sets `NOTIFY_SOCKET`, where the
+//! state of the program is sent as `READY=1` or `STOPPING=1`. With
+//! `WatchdogSec=`, it also sets `WATCHDOG_USEC` and expects `WATCHDOG=1` more
+//! often than that. Without these variables, nothing is sent.
+
+use std::env;
+use std::io;
+use std::os::linux::net::SocketAddrExt;
+use std::os::unix::net::{SocketAddr, UnixDatagram};
+use std::process::Command;
+use std::thread;
+use std::time::Duration;
+
+use cpulimiter::Pid;
+
+/// Sends `state` to the service manager, if it expects notifications.
+pub fn notify(state: &str) -> io::Result<()> {
+    let Some(path) = env::var_os("NOTIFY_SOCKET") else {
+        return Ok(());
+    };
+    let bytes = path.as_encoded_bytes();
+    let address = match bytes.strip_prefix(b"@") {
+        Some(name) => SocketAddr::from_abstract_name(name)?,
+        None => SocketAddr::from_pathname(&path)?,
+    };
+
+    let socket = UnixDatagram::unbound()?;
+    socket.send_to_addr(state.as_bytes(), &address)?;
+    Ok(())
+}
+
+/// Sends `WATCHDOG=1` in a separate thread, twice per interval required by
+/// the service manager, if any.
+pub fn spawn_watchdog() {
+    let Some(timeout) = watchdog_timeout() else {
+        return;
+    };
+    thread::spawn(move || loop {
+        let _ = notify("WATCHDOG=1");
+        thread::sleep(timeout / 2);
+    });
+}
+
+/// Retrieves the interval after which the service manager considers the
+/// program hung, if the watchdog is enabled for this process.
+fn watchdog_timeout() -> Option<Duration> {
+    // set when the variables are inherited by another process than the service's
+    if let Some(pid) = env::var_os("WATCHDOG_PID") {
+        if pid.to_str()?.parse::<u32>().ok()? != std::process::id() {
+            return None;
+        }
+    }
+    let usec = env::var("WATCHDOG_USEC").ok()?.parse().ok()?;
+    (usec > 0).then(|| Duration::from_micros(usec))
+}
+
+/// Retrieves the main process of `unit`, or `None` if it isn't running.
+pub fn main_pid(unit: &str) -> io::Result<Option<Pid>> {
+    let output = Command::new("systemctl")
+        .args(["show", "--property", "MainPID", "--value", unit])
+        .output()?;
+    if !output.status.success() {
+        return Err(io::Error::other(
+            String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        ));
+    }
+
+    let pid: u32 = String::from_utf8_lossy(&output.stdout)
+        .trim()
+        .parse()
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "invalid MainPID"))?;
+    Ok((pid != 0).then(|| Pid::from(pid)))
+}
@@ -0,0 +1,170 @@
+//! Monitor the limiters of a running instance, and adjust their limits.
+//!
+//! The instance must be started with `--control-socket`: the monitor is a
+//! client of the control protocol, refreshing the list of limiters every
+//! second. The selected limiter is moved with the arrows, its limit changed
+//! by 5% with `+` and `-`, and `q` quits.
+
+use std::io;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Modifier, Style};
+use ratatui::widgets::{Block, Paragraph, Row, Table, TableState};
+use ratatui::{DefaultTerminal, Frame};
+
+use crate::control::{Client, Limiter, Limiters, Stats};
+
+/// How often the limiters are listed again.
+const REFRESH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How much a key press changes the limit, as a percentage.
+const LIMIT_STEP: f64 = 5_f64;
+
+/// What the monitor shows.
+struct Monitor {
+    client: Client,
+    limiters: Vec<Limiter>,
+    table: TableState,
+    /// The details of the selected limiter, if it could be retrieved.
+    stats: Option<Stats>,
+    /// The outcome of the last change of limit.
+    status: String,
+}
+
+impl Monitor {
+    /// Lists the limiters again, keeping the same process selected if possible.
+    fn refresh(&mut self) -> io::Result<()> {
+        let selected = self.selected().map(|limiter| limiter.pid);
+        self.limiters = self.client.request::<Limiters>("LIST")?.limiters;
+        self.limiters.sort_by_key(|limiter| limiter.pid);
+
+        let index = selected
+            .and_then(|pid| self.limiters.iter().position(|limiter| limiter.pid == pid))
+            .or((!self.limiters.is_empty()).then_some(0));
+        self.table.select(index);
+        self.stats = match self.selected() {
+            Some(limiter) => self.client.request(&format!("STATS {}", limiter.pid)).ok(),
+            None => None,
+        };
+        Ok(())
+    }
+
+    fn selected(&self) -> Option<&Limiter> {
+        self.table
+            .selected()
+            .and_then(|index| self.limiters.get(index))
+    }
+
+    /// Changes the limit of the selected limiter by `step`.
+    fn adjust(&mut self, step: f64) -> io::Result<()> {
+        let Some(limiter) = self.selected() else {
+            return Ok(());
+        };
+        let (pid, limit) = (limiter.pid, f64::max(limiter.limit + step, 1_f64));
+        match self
+            .client
+            .request::<serde_json::Value>(&format!("SET {pid} {limit}"))
+        {
+            Ok(_) => self.status = format!("process {pid} limited to {limit}%"),
+            Err(err) if err.kind() == io::ErrorKind::Other => self.status = err.to_string(),
+            Err(err) => return Err(err),
+        }
+        self.refresh()
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [list, details, status] = Layout::vertical([
+            Constraint::Min(3),
+            Constraint::Length(3),
+            Constraint::Length(1),
+        ])
+        .areas(frame.area());
+
+        let rows = self.limiters.iter().map(|limiter| {
+            Row::new([
+                limiter.pid.to_string(),
+                format!("{:.0}%", limiter.limit),
+                format!("{:.1}%", limiter.usage),
+            ])
+        });
+        let table = Table::new(
+            rows,
+            [
+                Constraint::Length(10),
+                Constraint::Length(10),
+                Constraint::Length(10),
+            ],
+        )
+        .header(
+            Row::new(["PID", "LIMIT", "USAGE"]).style(Style::new().add_modifier(Modifier::BOLD)),
+        )
+        .row_highlight_style(Style::new().add_modifier(Modifier::REVERSED))
+        .block(Block::bordered().title(" cpulimit "));
+        frame.render_stateful_widget(table, list, &mut self.table);
+
+        let text = match &self.stats {
+            Some(stats) => format!(
+                "working rate {:.2}, {} children, {} throttle cycles, {:.1}s of CPU time",
+                stats.working_rate, stats.children, stats.throttle_cycles, stats.cpu_time
+            ),
+            None => String::new(),
+        };
+        frame.render_widget(Paragraph::new(text).block(Block::bordered()), details);
+        let help = "↑/↓ select, +/- change the limit, q quit";
+        let line = if self.status.is_empty() {
+            help
+        } else {
+            &self.status
+        };
+        frame.render_widget(Paragraph::new(line), status);
+    }
+
+    /// Refreshes and draws the monitor until the operator quits.
+    fn run(&mut self, terminal: &mut DefaultTerminal) -> io::Result<()> {
+        let mut refreshed = Instant::now();
+        loop {
+            terminal.draw(|frame| self.draw(frame))?;
+
+            let timeout = REFRESH_INTERVAL.saturating_sub(refreshed.elapsed());
+            if event::poll(timeout)? {
+                let Event::Key(key) = event::read()? else {
+                    continue;
+                };
+                if key.kind != KeyEventKind::Press {
+                    continue;
+                }
+                match key.code {
+                    KeyCode::Char('q') | KeyCode::Esc => return Ok(()),
+                    KeyCode::Up => self.table.select_previous(),
+                    KeyCode::Down => self.table.select_next(),
+                    KeyCode::Char('+') => self.adjust(LIMIT_STEP)?,
+                    KeyCode::Char('-') => self.adjust(-LIMIT_STEP)?,
+                    _ => {}
+                }
+            } else {
+                self.refresh()?;
+                refreshed = Instant::now();
+            }
+        }
+    }
+}
+
+/// Monitors the instance listening on the control socket at `path`.
+pub fn run(path: &Path) -> io::Result<()> {
+    let mut monitor = Monitor {
+        client: Client::connect(path)?,
+        limiters: Vec::new(),
+        table: TableState::default(),
+        stats: None,
+        status: String::new(),
+    };
+    monitor.refresh()?;
+
+    let mut terminal = ratatui::init();
+    let result = monitor.run(&mut terminal);
+    ratatui::restore();
+    result
+}
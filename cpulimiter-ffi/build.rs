@@ -0,0 +1,21 @@
+//! Generates the C header, `cpulimiter.h`, in the output directory.
+//!
+//! The copy in `include` is checked against it by the tests.
+
+use std::env;
+use std::path::PathBuf;
+
+fn main() {
+    println!("cargo:rerun-if-changed=src/lib.rs");
+    println!("cargo:rerun-if-changed=cbindgen.toml");
+
+    let crate_dir = env::var("CARGO_MANIFEST_DIR").unwrap();
+    let out_dir = PathBuf::from(env::var("OUT_DIR").unwrap());
+    let config = cbindgen::Config::from_file(format!("{crate_dir}/cbindgen.toml")).unwrap();
+    cbindgen::Builder::new()
+        .with_src(format!("{crate_dir}/src/lib.rs"))
+        .with_config(config)
+        .generate()
+        .expect("couldn't generate the C header")
+        .write_to_file(out_dir.join("cpulimiter.h"));
+}
@@ -0,0 +1,71 @@
+#ifndef CPULIMITER_H
+#define CPULIMITER_H
+
+/* Generated from src/lib.rs by cbindgen, do not edit. */
+
+#include <stdint.h>
+
+/**
+ * A handle to the limiter of a process, created by `cpulimit_new`.
+ */
+typedef struct CpuLimit CpuLimit;
+
+/**
+ * Limits the CPU usage of the process `pid` to `limit`, a percentage of one core.
+ *
+ * Returns a handle to release with `cpulimit_free`, or `NULL` on failure.
+ */
+CpuLimit *cpulimit_new(uint32_t pid, double limit);
+
+/**
+ * Updates the limit, returning once it is in effect.
+ *
+ * Returns `0` on success, `-1` on failure.
+ *
+ * # Safety
+ *
+ * `limiter` must be `NULL` or a handle returned by `cpulimit_new`, not freed yet.
+ */
+int cpulimit_set_limit(const CpuLimit *limiter, double limit);
+
+/**
+ * Stops limiting the process, which is resumed. The handle must still be freed.
+ *
+ * Returns `0` on success, `-1` on failure.
+ *
+ * # Safety
+ *
+ * `limiter` must be `NULL` or a handle returned by `cpulimit_new`, not freed yet.
+ */
+int cpulimit_stop(const CpuLimit *limiter);
+
+/**
+ * Retrieves the CPU usage of the process: `1.0` means one fully used core.
+ *
+ * Returns `-1.0` if `limiter` is `NULL`.
+ *
+ * # Safety
+ *
+ * `limiter` must be `NULL` or a handle returned by `cpulimit_new`, not freed yet.
+ */
+double cpulimit_cpu_usage(const CpuLimit *limiter);
+
+/**
+ * Releases the handle, stopping the limiter if it is still running.
+ *
+ * Does nothing if `limiter` is `NULL`.
+ *
+ * # Safety
+ *
+ * `limiter` must be `NULL` or a handle returned by `cpulimit_new`, not freed yet.
+ */
+void cpulimit_free(CpuLimit *limiter);
+
+/**
+ * Describes why the last failing function called by this thread failed.
+ *
+ * Returns `NULL` if none failed. The message is valid until the next failure on the thread.
+ */
+const char *cpulimit_last_error(void);
+
+#endif  /* CPULIMITER_H */
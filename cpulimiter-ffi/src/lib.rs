@@ -0,0 +1,190 @@
+//! Embed the limiter in programs written in C, C++ or Go.
+//!
+//! The functions are declared in `include/cpulimiter.h`, generated from this
+//! crate by its build script. The failing functions return `NULL` or `-1`,
+//! and [`cpulimit_last_error`] tells why.
+//!
+//! ```c
+//! #include "cpulimiter.h"
+//!
+//! CpuLimit *limiter = cpulimit_new(1048, 30.0);
+//! if (limiter == NULL) {
+//!     fprintf(stderr, "%s\n", cpulimit_last_error());
+//!     return 1;
+//! }
+//! cpulimit_set_limit(limiter, 10.0);
+//! cpulimit_free(limiter);
+//! ```
+#![cfg(target_os = "linux")]
+
+use std::cell::RefCell;
+use std::ffi::{c_char, c_int, CString};
+use std::ptr;
+
+use cpulimiter::{CpuLimit, Error, Pid};
+
+thread_local! {
+    /// Why the last failing function called by this thread failed.
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+/// Records `message` as the last error of the thread.
+fn set_last_error(message: String) {
+    // the messages of the crate have no NUL byte
+    let message = CString::new(message).ok();
+    LAST_ERROR.with(|last| *last.borrow_mut() = message);
+}
+
+/// Converts `result` to a status, `0` on success or `-1` on failure.
+fn status(result: Result<(), Error>) -> c_int {
+    match result {
+        Ok(()) => 0,
+        Err(err) => {
+            set_last_error(err.to_string());
+            -1
+        }
+    }
+}
+
+/// Retrieves the limiter behind `limiter`, recording an error if it is `NULL`.
+///
+/// # Safety
+///
+/// `limiter` must be `NULL` or a handle returned by [`cpulimit_new`], not freed yet.
+unsafe fn handle<'a>(limiter: *const CpuLimit) -> Option<&'a CpuLimit> {
+    // SAFETY: Valid or `NULL`, as required from the caller.
+    let limiter = unsafe { limiter.as_ref() };
+    if limiter.is_none() {
+        set_last_error("the handle is NULL".to_string());
+    }
+    limiter
+}
+
+/// Limits the CPU usage of the process `pid` to `limit`, a percentage of one core.
+///
+/// Returns a handle to release with `cpulimit_free`, or `NULL` on failure.
+#[no_mangle]
+pub extern "C" fn cpulimit_new(pid: u32, limit: f64) -> *mut CpuLimit {
+    match CpuLimit::new(Pid::from(pid), limit) {
+        Ok(limiter) => Box::into_raw(Box::new(limiter)),
+        Err(err) => {
+            set_last_error(err.to_string());
+            ptr::null_mut()
+        }
+    }
+}
+
+/// Updates the limit, returning once it is in effect.
+///
+/// Returns `0` on success, `-1` on failure.
+///
+/// # Safety
+///
+/// `limiter` must be `NULL` or a handle returned by `cpulimit_new`, not freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn cpulimit_set_limit(limiter: *const CpuLimit, limit: f64) -> c_int {
+    // SAFETY: Forwarded from the caller.
+    match unsafe { handle(limiter) } {
+        Some(limiter) => status(limiter.set_limit(limit)),
+        None => -1,
+    }
+}
+
+/// Stops limiting the process, which is resumed. The handle must still be freed.
+///
+/// Returns `0` on success, `-1` on failure.
+///
+/// # Safety
+///
+/// `limiter` must be `NULL` or a handle returned by `cpulimit_new`, not freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn cpulimit_stop(limiter: *const CpuLimit) -> c_int {
+    // SAFETY: Forwarded from the caller.
+    match unsafe { handle(limiter) } {
+        Some(limiter) => status(limiter.stop()),
+        None => -1,
+    }
+}
+
+/// Retrieves the CPU usage of the process: `1.0` means one fully used core.
+///
+/// Returns `-1.0` if `limiter` is `NULL`.
+///
+/// # Safety
+///
+/// `limiter` must be `NULL` or a handle returned by `cpulimit_new`, not freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn cpulimit_cpu_usage(limiter: *const CpuLimit) -> f64 {
+    // SAFETY: Forwarded from the caller.
+    match unsafe { handle(limiter) } {
+        Some(limiter) => limiter.cpu_usage(),
+        None => -1_f64,
+    }
+}
+
+/// Releases the handle, stopping the limiter if it is still running.
+///
+/// Does nothing if `limiter` is `NULL`.
+///
+/// # Safety
+///
+/// `limiter` must be `NULL` or a handle returned by `cpulimit_new`, not freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn cpulimit_free(limiter: *mut CpuLimit) {
+    if !limiter.is_null() {
+        // SAFETY: Allocated by `cpulimit_new` and not freed yet, as required from the caller.
+        drop(unsafe { Box::from_raw(limiter) });
+    }
+}
+
+/// Describes why the last failing function called by this thread failed.
+///
+/// Returns `NULL` if none failed. The message is valid until the next failure on the thread.
+#[no_mangle]
+pub extern "C" fn cpulimit_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| {
+        last.borrow()
+            .as_ref()
+            .map_or(ptr::null(), |message| message.as_ptr())
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use std::ffi::CStr;
+    use std::process::Command;
+
+    use super::*;
+
+    #[test]
+    fn header() {
+        let generated = include_str!(concat!(env!("OUT_DIR"), "/cpulimiter.h"));
+        assert!(
+            generated == include_str!("../include/cpulimiter.h"),
+            "include/cpulimiter.h is outdated, copy the header generated in {}",
+            env!("OUT_DIR")
+        );
+    }
+
+    #[test]
+    fn lifecycle() {
+        let mut child = Command::new("sleep").arg("10").spawn().unwrap();
+
+        let limiter = cpulimit_new(child.id(), 30_f64);
+        assert!(!limiter.is_null());
+        // SAFETY: The handle is only used before being freed.
+        unsafe {
+            assert_eq!(cpulimit_set_limit(limiter, 10_f64), 0);
+            assert_eq!(cpulimit_set_limit(limiter, -1_f64), -1);
+            let error = CStr::from_ptr(cpulimit_last_error());
+            assert!(error.to_str().unwrap().contains("-1%"));
+            assert!(cpulimit_cpu_usage(limiter) >= 0_f64);
+            assert_eq!(cpulimit_stop(limiter), 0);
+            cpulimit_free(limiter);
+            assert_eq!(cpulimit_stop(ptr::null()), -1);
+        }
+
+        child.kill().unwrap();
+        child.wait().unwrap();
+    }
+}
@@ -0,0 +1,16 @@
+//! Parse arbitrary bytes as a `/proc/<pid>/stat` file: malformed files must be
+//! reported as errors, never panic.
+//!
+//! Run with `cargo +nightly fuzz run stat_file` from the `cpulimiter` directory.
+
+#![no_main]
+
+use cpulimiter::ProcessInfo;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(info) = ProcessInfo::from_stat(data) {
+        // the fields are read back from the input
+        let _ = (info.comm(), info.utime(), info.rss());
+    }
+});
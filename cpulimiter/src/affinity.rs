@@ -0,0 +1,98 @@
+//! Approximate a limit by pinning processes to a subset of the cores.
+//!
+//! A limit of 2.5 cores pins every thread to 3 cores: the processes can't use
+//! more, but they may use less when the cores are shared with other processes.
+//!
+//! See `man sched_setaffinity` for a description of the CPU masks.
+
+use std::collections::HashMap;
+use std::io;
+
+use crate::pid::{CpuSet, Pid};
+
+/// The threads pinned to a subset of the cores, along with their original mask.
+pub(crate) struct Pinned {
+    /// The cores the current process is allowed to run on.
+    available: Vec<usize>,
+    mask: CpuSet,
+    originals: HashMap<Pid, CpuSet>,
+}
+
+impl Pinned {
+    /// Prepares a mask allowing the given number of `cores`.
+    pub fn new(cores: f64) -> io::Result<Self> {
+        let available = Pid::from(std::process::id()).affinity()?.cpus();
+        let mut pinned = Self {
+            available,
+            mask: CpuSet::empty(),
+            originals: HashMap::new(),
+        };
+        pinned.mask = pinned.mask_for(cores);
+        Ok(pinned)
+    }
+
+    /// Builds a mask of the first available cores, enough to run `cores` cores.
+    ///
+    /// At least one core is always allowed.
+    fn mask_for(&self, cores: f64) -> CpuSet {
+        let count = (cores.ceil() as usize).clamp(1, self.available.len().max(1));
+        CpuSet::from_cpus(self.available.iter().copied().take(count))
+    }
+
+    /// Pins the threads of `pid` that were not handled yet.
+    pub fn attach(&mut self, pid: Pid) -> io::Result<()> {
+        for tid in pid.threads()? {
+            if self.originals.contains_key(&tid) {
+                continue;
+            }
+
+            let original = tid.affinity()?;
+            tid.set_affinity(&self.mask)?;
+            self.originals.insert(tid, original);
+        }
+        Ok(())
+    }
+
+    /// Recomputes the mask for the given number of `cores` and applies it to
+    /// every thread still alive.
+    pub fn set_limit(&mut self, cores: f64) -> io::Result<()> {
+        let mask = self.mask_for(cores);
+        if mask == self.mask {
+            return Ok(());
+        }
+
+        self.mask = mask;
+        // threads that exited are forgotten
+        self.originals
+            .retain(|tid, _| tid.set_affinity(&self.mask).is_ok());
+        Ok(())
+    }
+
+    /// Restores the original mask of every thread still alive.
+    pub fn release(self) {
+        for (tid, original) in self.originals {
+            let _ = tid.set_affinity(&original);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use super::Pinned;
+    use crate::pid::CpuSet;
+
+    #[test]
+    fn mask_rounds_up() {
+        let pinned = Pinned {
+            available: vec![0, 2, 4, 6, 8, 10, 12, 14],
+            mask: CpuSet::empty(),
+            originals: HashMap::new(),
+        };
+        assert_eq!(pinned.mask_for(4_f64).cpus(), [0, 2, 4, 6]);
+        assert_eq!(pinned.mask_for(2.5).cpus(), [0, 2, 4]);
+        assert_eq!(pinned.mask_for(0.1).cpus(), [0]);
+        assert_eq!(pinned.mask_for(16_f64).cpus().len(), 8);
+    }
+}
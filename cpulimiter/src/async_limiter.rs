@@ -0,0 +1,282 @@
+//! Run the control loop on a tokio task instead of a dedicated thread.
+//!
+//! Only the signal backend is available: the task suspends and resumes the
+//! target process, sleeping with [`tokio::time::sleep`] in between.
+
+use std::sync::mpsc::SendError;
+use std::sync::Arc;
+use std::time::Duration;
+
+use parking_lot::{Mutex, RwLock};
+use tokio::sync::mpsc::error::TryRecvError;
+use tokio::sync::{mpsc, oneshot};
+use tokio::task::JoinHandle;
+use tokio::time;
+
+use crate::controller;
+use crate::error::{Error, Result};
+use crate::event::{Event, Observers};
+use crate::limiter::{Command, CpuLimit, ExitReason, LimitMode, SLICE_DURATION};
+use crate::pid::Pid;
+use crate::process_group::{ChildrenMode, GroupStats, ProcessGroup};
+use crate::schedule::ActiveLimit;
+use crate::target::Target;
+
+/// A command along with the channel to acknowledge it on, once handled.
+type Request = (Command, oneshot::Sender<Result<()>>);
+
+/// A handle to manage a limiter running on a tokio task, see [`CpuLimit::new_async`].
+///
+/// The limiter is stopped once the handle and all its clones are dropped.
+#[derive(Clone)]
+pub struct AsyncCpuLimit {
+    shared: Arc<Shared>,
+}
+
+/// The state shared by the clones of an [`AsyncCpuLimit`].
+struct Shared {
+    sender: mpsc::Sender<Request>,
+    group: Arc<RwLock<ProcessGroup>>,
+    observers: Observers,
+    task: Mutex<Option<JoinHandle<Result<ExitReason>>>>,
+}
+
+impl CpuLimit {
+    /// Limits the CPU time of the target process from a tokio task.
+    ///
+    /// Must be called from within a tokio runtime with the time driver enabled.
+    pub fn new_async(pid: Pid, limit: f64) -> Result<AsyncCpuLimit> {
+        LimitMode::PerCore.check(limit)?;
+        let mut group = ProcessGroup::new(&[Target::Pid(pid)], ChildrenMode::Exclude)?;
+        group.check_permission()?;
+        group.set_limit(limit);
+
+        let (tx, rx) = mpsc::channel(1);
+        let observers = group.observers().clone();
+        let group = Arc::new(RwLock::new(group));
+        let task = tokio::spawn(limiter_task(limit, group.clone(), rx, observers.clone()));
+
+        Ok(AsyncCpuLimit {
+            shared: Arc::new(Shared {
+                sender: tx,
+                group,
+                observers,
+                task: Mutex::new(Some(task)),
+            }),
+        })
+    }
+}
+
+impl AsyncCpuLimit {
+    /// Updates the limit applied to the target process.
+    ///
+    /// Completes once the new limit is in effect.
+    pub async fn set_limit(&self, limit: f64) -> Result<()> {
+        LimitMode::PerCore.check(limit)?;
+        self.request(Command::Limit(limit)).await
+    }
+
+    /// Moves the limit gradually from its current value to `limit` over `duration`.
+    pub async fn ramp_to(&self, limit: f64, duration: Duration) -> Result<()> {
+        LimitMode::PerCore.check(limit)?;
+        self.request(Command::Ramp(limit, duration)).await
+    }
+
+    /// Suspends the target process until [`resume`](Self::resume) is called.
+    pub async fn pause(&self) -> Result<()> {
+        self.request(Command::Pause).await
+    }
+
+    /// Lets the target process run again after [`pause`](Self::pause).
+    pub async fn resume(&self) -> Result<()> {
+        self.request(Command::Resume).await
+    }
+
+    /// Stops the limiting task.
+    ///
+    /// Completes once the target process is resumed and the task is about to exit.
+    pub async fn stop(&self) -> Result<()> {
+        self.request(Command::Stop).await
+    }
+
+    /// Sends `command` to the limiting task and waits until it is handled.
+    async fn request(&self, command: Command) -> Result<()> {
+        let (ack, acked) = oneshot::channel();
+        self.shared
+            .sender
+            .send((command, ack))
+            .await
+            .map_err(|mpsc::error::SendError((command, _))| SendError(command))?;
+        acked.await.map_err(|_| Error::Unacknowledged)?
+    }
+
+    /// Indicates whether the limiting task is still running.
+    pub fn is_running(&self) -> bool {
+        self.shared
+            .task
+            .lock()
+            .as_ref()
+            .is_some_and(|task| !task.is_finished())
+    }
+
+    /// Waits for the limiting task to finish and tells why it stopped.
+    ///
+    /// Only one of the clones of the handle can join the task.
+    pub async fn join(&self) -> Result<ExitReason> {
+        let task = self.shared.task.lock().take().ok_or(Error::Joined)?;
+        task.await.map_err(|_| Error::Panicked)?
+    }
+
+    /// Subscribes to the events of the limiter.
+    ///
+    /// The receiver is disconnected once the limiter stops.
+    pub fn events(&self) -> std::sync::mpsc::Receiver<Event> {
+        self.shared.observers.subscribe()
+    }
+
+    /// Retrieves the CPU usage of the target process, in cores.
+    pub fn cpu_usage(&self) -> f64 {
+        self.shared.group.read().cpu_usage()
+    }
+
+    /// Retrieves the statistics of the target process.
+    pub fn stats(&self) -> GroupStats {
+        self.shared.group.read().stats()
+    }
+}
+
+/// The limiting function, to be run on a tokio task.
+///
+/// Stops when every handle is dropped, leaving the process resumed.
+async fn limiter_task(
+    limit: f64,
+    group: Arc<RwLock<ProcessGroup>>,
+    mut rx: mpsc::Receiver<Request>,
+    observers: Observers,
+) -> Result<ExitReason> {
+    let mut limit = ActiveLimit::new(limit);
+    let mut working_rate = 1_f64;
+    let mut paused = false;
+
+    // acknowledged once the process is resumed
+    let mut stop_ack = None;
+
+    let reason = loop {
+        match rx.try_recv() {
+            Ok((cmd, ack)) => {
+                let mut result = Ok(());
+                match cmd {
+                    Command::Limit(new_limit) => limit.set_base(new_limit),
+                    Command::Ramp(new_limit, duration) => limit.ramp_to(new_limit, duration),
+                    Command::SetSchedule(schedule) => limit.set_schedule(schedule),
+                    Command::SetChildrenMode(children_mode) => {
+                        group.write().set_children_mode(children_mode);
+                    }
+                    Command::Pause => {
+                        paused = true;
+                        result = group.read().suspend();
+                    }
+                    Command::Resume => paused = false,
+                    Command::Stop => {
+                        stop_ack = Some((ack, group.read().resume()));
+                        break Ok(ExitReason::Stopped);
+                    }
+                }
+                let _ = ack.send(result);
+            }
+            Err(TryRecvError::Disconnected) => {
+                if let Err(err) = group.read().resume() {
+                    break Err(err);
+                }
+                break Ok(ExitReason::Stopped);
+            }
+            Err(TryRecvError::Empty) => {}
+        }
+
+        if let Some(new_limit) = limit.refresh() {
+            group.write().set_limit(new_limit);
+            observers.notify(Event::LimitChanged(new_limit));
+        }
+
+        let updated = group.write().update();
+        match updated {
+            Ok(()) => {}
+            Err(Error::DeadTarget) => break Ok(ExitReason::TargetExited),
+            Err(err) => break Err(err),
+        }
+
+        if paused {
+            if let Err(err) = group.read().suspend() {
+                break Err(err);
+            }
+            time::sleep(SLICE_DURATION).await;
+            continue;
+        }
+
+        let cpu_usage = group.read().cpu_usage();
+        let target = LimitMode::PerCore.cores(limit.get());
+        working_rate = controller::correct_rate(working_rate, target, cpu_usage);
+
+        if let Err(err) = group.read().resume() {
+            break Err(err);
+        }
+        let work_time = SLICE_DURATION.mul_f64(working_rate);
+        time::sleep(work_time).await;
+
+        let sleep_time = SLICE_DURATION - work_time;
+        if !sleep_time.is_zero() {
+            if let Err(err) = group.read().suspend() {
+                break Err(err);
+            }
+            time::sleep(sleep_time).await;
+        }
+
+        group
+            .write()
+            .record_cycle(working_rate, !sleep_time.is_zero());
+        observers.notify(Event::ThrottleCycle {
+            work: work_time,
+            sleep: sleep_time,
+        });
+    };
+
+    if reason.is_err() {
+        // don't leave the process suspended
+        let _ = group.read().resume();
+    }
+    if let Ok(reason) = &reason {
+        observers.notify(match reason {
+            ExitReason::Stopped => Event::Stopped,
+            ExitReason::TargetExited => Event::TargetExited,
+            ExitReason::BudgetExhausted => Event::BudgetExhausted,
+            ExitReason::DeadlineReached => Event::DeadlineReached,
+        });
+    }
+    observers.close();
+
+    if let Some((ack, result)) = stop_ack {
+        let _ = ack.send(result);
+    }
+    reason
+}
+
+#[cfg(test)]
+mod test {
+    use std::process::Command;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn set_limit_and_stop() {
+        let mut child = Command::new("sleep").arg("10").spawn().unwrap();
+        let limiter = CpuLimit::new_async(Pid::from(child.id()), 50_f64).unwrap();
+
+        limiter.set_limit(20_f64).await.unwrap();
+        limiter.stop().await.unwrap();
+        assert_eq!(limiter.join().await.unwrap(), ExitReason::Stopped);
+        assert!(!Pid::from(child.id()).is_stopped());
+
+        child.kill().unwrap();
+        child.wait().unwrap();
+    }
+}
@@ -0,0 +1,258 @@
+//! Abstract the processes away from procfs and signals.
+//!
+//! A limiter reads the processes from procfs and throttles them with signals
+//! unless it is given another [`ProcessBackend`], such as a [`MockBackend`]
+//! simulating a process tree in memory: tests then run without root nor real
+//! processes.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::io;
+use std::time::Duration;
+
+use parking_lot::Mutex;
+
+use crate::error::{Error, Result};
+use crate::pid::{Pid, Signal};
+use crate::process_iterator::ProcessIterator;
+
+/// The operations a limiter performs on the processes it limits.
+pub trait ProcessBackend: Send + Sync {
+    /// Lists the processes currently alive.
+    fn pids(&self) -> io::Result<Vec<Pid>>;
+
+    /// Retrieves the parent of `pid`, `None` if it exited or has none.
+    fn parent(&self, pid: Pid) -> Option<Pid>;
+
+    /// Retrieves the CPU time consumed by `pid` since it started.
+    fn cputime(&self, pid: Pid) -> io::Result<Duration>;
+
+    /// Indicates whether `pid` is currently stopped.
+    fn is_stopped(&self, pid: Pid) -> bool;
+
+    /// Sends `signal` to `pid`.
+    ///
+    /// Fails with [`Error::SignalFailed`] and `ESRCH` if the process doesn't exist.
+    fn signal(&self, pid: Pid, signal: Signal) -> Result<()>;
+
+    /// Indicates whether `pid` exists.
+    fn alive(&self, pid: Pid) -> bool {
+        !matches!(
+            self.signal(pid, Signal::SIGNULL),
+            Err(Error::SignalFailed {
+                errno: libc::ESRCH,
+                ..
+            })
+        )
+    }
+}
+
+impl fmt::Debug for dyn ProcessBackend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("ProcessBackend")
+    }
+}
+
+/// The real processes, read from procfs and sent signals.
+#[derive(Clone, Copy, Default, Debug)]
+pub struct Procfs;
+
+impl ProcessBackend for Procfs {
+    fn pids(&self) -> io::Result<Vec<Pid>> {
+        Ok(ProcessIterator::new()?.collect())
+    }
+
+    fn parent(&self, pid: Pid) -> Option<Pid> {
+        Some(pid.info().ok()?.ppid())
+    }
+
+    fn cputime(&self, pid: Pid) -> io::Result<Duration> {
+        let info = pid.info()?;
+        Ok(info.utime() + info.stime())
+    }
+
+    fn is_stopped(&self, pid: Pid) -> bool {
+        pid.is_stopped()
+    }
+
+    fn signal(&self, pid: Pid, signal: Signal) -> Result<()> {
+        pid.kill(&signal)
+    }
+}
+
+/// A simulated process of a [`MockBackend`].
+struct MockProcess {
+    parent: Option<Pid>,
+    cputime: Duration,
+    stopped: bool,
+}
+
+/// A process tree simulated in memory.
+///
+/// The processes only consume CPU time when [`run`](Self::run) is called,
+/// each one as if it had a core of its own, unless it is stopped.
+#[derive(Default)]
+pub struct MockBackend {
+    processes: Mutex<HashMap<Pid, MockProcess>>,
+}
+
+impl MockBackend {
+    /// Creates a backend without any process.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a running process, forked by `parent` if any.
+    pub fn spawn(&self, pid: Pid, parent: Option<Pid>) {
+        self.processes.lock().insert(
+            pid,
+            MockProcess {
+                parent,
+                cputime: Duration::ZERO,
+                stopped: false,
+            },
+        );
+    }
+
+    /// Terminates `pid`, its children lose their parent.
+    pub fn exit(&self, pid: Pid) {
+        let mut processes = self.processes.lock();
+        processes.remove(&pid);
+        for process in processes.values_mut() {
+            if process.parent == Some(pid) {
+                process.parent = None;
+            }
+        }
+    }
+
+    /// Lets every process that isn't stopped consume `duration` of CPU time.
+    pub fn run(&self, duration: Duration) {
+        for process in self.processes.lock().values_mut() {
+            if !process.stopped {
+                process.cputime += duration;
+            }
+        }
+    }
+}
+
+impl ProcessBackend for MockBackend {
+    fn pids(&self) -> io::Result<Vec<Pid>> {
+        Ok(self.processes.lock().keys().copied().collect())
+    }
+
+    fn parent(&self, pid: Pid) -> Option<Pid> {
+        self.processes.lock().get(&pid)?.parent
+    }
+
+    fn cputime(&self, pid: Pid) -> io::Result<Duration> {
+        self.processes
+            .lock()
+            .get(&pid)
+            .map(|process| process.cputime)
+            .ok_or_else(|| io::Error::from(io::ErrorKind::NotFound))
+    }
+
+    fn is_stopped(&self, pid: Pid) -> bool {
+        self.processes
+            .lock()
+            .get(&pid)
+            .is_some_and(|process| process.stopped)
+    }
+
+    fn signal(&self, pid: Pid, signal: Signal) -> Result<()> {
+        let mut processes = self.processes.lock();
+        let Some(process) = processes.get_mut(&pid) else {
+            return Err(Error::SignalFailed {
+                pid,
+                signal,
+                errno: libc::ESRCH,
+            });
+        };
+
+        match signal {
+            Signal::SIGSTOP | Signal::SIGTSTP => process.stopped = true,
+            Signal::SIGCONT => process.stopped = false,
+            Signal::SIGNULL => {}
+            Signal::SIGKILL | Signal::SIGTERM => {
+                drop(processes);
+                self.exit(pid);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::event::Event;
+    use crate::process_group::{ChildrenMode, ForkGuard, ProcessGroup};
+
+    #[test]
+    fn simulated_tree() {
+        let mock = Arc::new(MockBackend::new());
+        let (target, child, grandchild) = (Pid::from(100), Pid::from(101), Pid::from(102));
+        mock.spawn(target, None);
+        mock.spawn(child, Some(target));
+
+        let mut group =
+            ProcessGroup::with_backend(&[target], ChildrenMode::Include, mock.clone()).unwrap();
+        assert_eq!(group.stats().children, 1);
+
+        mock.spawn(grandchild, Some(child));
+        mock.run(Duration::from_millis(10));
+        group.update().unwrap();
+        assert_eq!(group.stats().children, 2);
+        assert_eq!(group.total_cpu_time(), Duration::from_millis(30));
+
+        group.suspend().unwrap();
+        assert!([target, child, grandchild]
+            .iter()
+            .all(|&pid| mock.is_stopped(pid)));
+        mock.run(Duration::from_millis(10));
+        group.update().unwrap();
+        assert_eq!(group.total_cpu_time(), Duration::from_millis(30));
+
+        mock.exit(child);
+        group.resume().unwrap();
+        group.update().unwrap();
+        let mut detached = group.stats().detached;
+        detached.sort();
+        assert_eq!(detached, vec![child, grandchild]);
+        assert!(!mock.is_stopped(grandchild));
+
+        mock.exit(target);
+        assert!(matches!(group.update(), Err(Error::DeadTarget)));
+    }
+
+    #[test]
+    fn fork_bomb() {
+        let mock = Arc::new(MockBackend::new());
+        let target = Pid::from(100);
+        mock.spawn(target, None);
+
+        let mut group =
+            ProcessGroup::with_backend(&[target], ChildrenMode::Include, mock.clone()).unwrap();
+        group.set_fork_guard(Some(ForkGuard {
+            max_children: 2,
+            window: Duration::from_secs(60),
+        }));
+        let events = group.observers().subscribe();
+
+        for pid in 101..103 {
+            mock.spawn(Pid::from(pid), Some(target));
+        }
+        group.update().unwrap();
+        assert!(!group.guard_suspended());
+
+        mock.spawn(Pid::from(103), Some(Pid::from(101)));
+        group.update().unwrap();
+        assert!(group.guard_suspended());
+        assert!((100..104).all(|pid| mock.is_stopped(Pid::from(pid))));
+        assert!(events
+            .try_iter()
+            .any(|event| event == Event::ForkBombDetected { children: 3 }));
+    }
+}
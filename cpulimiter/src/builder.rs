@@ -0,0 +1,979 @@
+//! Configure a limiter step by step before starting it.
+
+use std::io;
+use std::os::unix::process::CommandExt;
+use std::path::PathBuf;
+use std::process::{self, Child};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::backend::ProcessBackend;
+use crate::controller::PidGains;
+use crate::error::{Error, Result};
+use crate::follow::{FollowMode, Follower};
+use crate::inhibit::InhibitCheck;
+use crate::limiter::{
+    AdaptiveLimit, Backend, BudgetAction, CpuLimit, GroupPolicy, LimitMode, RelativeLimit,
+    Settings, TokenBucket, ViolationPolicy, SLICE_DURATION,
+};
+use crate::privileges;
+use crate::process_group::{
+    ChildrenMode, ForkGuard, MemoryGuard, ProcessGroup, StopSignal, StoppedPolicy, SuspendMode,
+    DEFAULT_SMOOTHING,
+};
+use crate::procfs::ProcfsRoot;
+use crate::recovery::Journal;
+use crate::spawn::StoppedChild;
+use crate::target::Target;
+use crate::throttler::Throttler;
+use crate::Pid;
+
+/// A builder to configure and start a [`CpuLimit`].
+///
+/// # Example
+///
+/// ```no_run
+/// use std::time::Duration;
+/// use cpulimiter::{ChildrenMode, CpuLimit, Pid};
+///
+/// let handle = CpuLimit::builder(25.0)
+///     .target(Pid::from(1048))
+///     .children(ChildrenMode::Include)
+///     .slice(Duration::from_millis(50))
+///     .build()
+///     .unwrap();
+/// ```
+#[derive(Clone, Debug)]
+pub struct CpuLimitBuilder {
+    targets: Vec<Target>,
+    /// The process and the only thread of it to limit, if any.
+    thread: Option<(Pid, Pid)>,
+    limit: f64,
+    children_mode: ChildrenMode,
+    /// The children left out of the group.
+    excluded_pids: Vec<Pid>,
+    excluded_names: Vec<String>,
+    stopped_policy: StoppedPolicy,
+    suspend_mode: SuspendMode,
+    stop_signal: StopSignal,
+    mode: LimitMode,
+    backend: Backend,
+    slice: Duration,
+    smoothing: f64,
+    usage_window: Option<Duration>,
+    include_reaped: bool,
+    paused: bool,
+    budget: Option<(Duration, BudgetAction)>,
+    adaptive: Option<AdaptiveLimit>,
+    relative: Option<RelativeLimit>,
+    ceiling: Option<f64>,
+    burst: Option<TokenBucket>,
+    policy: GroupPolicy,
+    controller: Option<PidGains>,
+    io_limit: Option<u64>,
+    enforcement_alert: Option<u32>,
+    hysteresis: Option<f64>,
+    min_work: Duration,
+    timeline: Option<usize>,
+    recovery_dir: Option<PathBuf>,
+    duration: Option<Duration>,
+    violation: Option<(ViolationPolicy, f64, Duration)>,
+    procfs: Option<ProcfsRoot>,
+    fork_guard: Option<ForkGuard>,
+    memory_guard: Option<MemoryGuard>,
+    process_backend: Option<Arc<dyn ProcessBackend>>,
+    throttler: Option<Arc<dyn Throttler>>,
+    inhibit: Option<Arc<dyn InhibitCheck>>,
+    run_as: Option<u32>,
+    follow: Option<(FollowMode, Duration)>,
+}
+
+impl CpuLimitBuilder {
+    /// Creates a builder enforcing `limit` with the default options.
+    pub fn new(limit: f64) -> Self {
+        Self {
+            targets: Vec::new(),
+            thread: None,
+            limit,
+            children_mode: ChildrenMode::default(),
+            excluded_pids: Vec::new(),
+            excluded_names: Vec::new(),
+            stopped_policy: StoppedPolicy::default(),
+            suspend_mode: SuspendMode::default(),
+            stop_signal: StopSignal::default(),
+            mode: LimitMode::default(),
+            backend: Backend::default(),
+            slice: SLICE_DURATION,
+            smoothing: DEFAULT_SMOOTHING,
+            usage_window: None,
+            include_reaped: false,
+            paused: false,
+            budget: None,
+            adaptive: None,
+            relative: None,
+            ceiling: None,
+            burst: None,
+            policy: GroupPolicy::default(),
+            controller: None,
+            io_limit: None,
+            enforcement_alert: None,
+            hysteresis: None,
+            min_work: Duration::ZERO,
+            timeline: None,
+            recovery_dir: None,
+            duration: None,
+            violation: None,
+            procfs: None,
+            fork_guard: None,
+            memory_guard: None,
+            process_backend: None,
+            throttler: None,
+            inhibit: None,
+            run_as: None,
+            follow: None,
+        }
+    }
+
+    /// Overrides the limit given at creation.
+    pub(crate) fn with_limit(mut self, limit: f64) -> Self {
+        self.limit = limit;
+        self
+    }
+
+    /// Adds a process, or a set of processes, to the limited group.
+    #[must_use]
+    pub fn target(mut self, target: impl Into<Target>) -> Self {
+        self.targets.push(target.into());
+        self
+    }
+
+    /// Adds several processes to the limited group, sharing a single budget.
+    #[must_use]
+    pub fn targets(mut self, pids: &[Pid]) -> Self {
+        self.targets.extend(pids.iter().copied().map(Target::Pid));
+        self
+    }
+
+    /// Limits the thread `tid` of `pid` only, instead of whole processes.
+    ///
+    /// See [`CpuLimit::new_for_thread`].
+    #[must_use]
+    pub fn thread(mut self, pid: Pid, tid: Pid) -> Self {
+        self.thread = Some((pid, tid));
+        self
+    }
+
+    /// Sets whether the children of the targets are limited too.
+    #[must_use]
+    pub fn children(mut self, children_mode: ChildrenMode) -> Self {
+        self.children_mode = children_mode;
+        self
+    }
+
+    /// Never limits nor suspends the children with these PIDs, even with [`ChildrenMode::Include`].
+    ///
+    /// Their own children are still limited. The processes forked in a cgroup,
+    /// by [`Backend::CgroupV2`] or [`SuspendMode::Freezer`], can't be left out.
+    #[must_use]
+    pub fn exclude_pids(mut self, pids: &[Pid]) -> Self {
+        self.excluded_pids.extend_from_slice(pids);
+        self
+    }
+
+    /// Never limits nor suspends the children with these command names, see
+    /// [`exclude_pids`](Self::exclude_pids).
+    ///
+    /// Names are compared as for [`Target::Name`], at every update: a child
+    /// executing an excluded program leaves the group.
+    #[must_use]
+    pub fn exclude_names(mut self, names: &[&str]) -> Self {
+        self.excluded_names
+            .extend(names.iter().map(ToString::to_string));
+        self
+    }
+
+    /// Sets how to handle the processes stopped by someone else, a debugger or a user.
+    #[must_use]
+    pub fn stopped(mut self, stopped_policy: StoppedPolicy) -> Self {
+        self.stopped_policy = stopped_policy;
+        self
+    }
+
+    /// Sets how the targets and their children are suspended.
+    ///
+    /// Commands spawned with [`SuspendMode::ProcessGroup`] lead their own process group.
+    #[must_use]
+    pub fn suspend_mode(mut self, suspend_mode: SuspendMode) -> Self {
+        self.suspend_mode = suspend_mode;
+        self
+    }
+
+    /// Sets the signal suspending the processes, `SIGSTOP` by default.
+    ///
+    /// Not used by the freezer, which doesn't send any signal.
+    #[must_use]
+    pub fn stop_signal(mut self, stop_signal: StopSignal) -> Self {
+        self.stop_signal = stop_signal;
+        self
+    }
+
+    /// Sets what a limit of 100% stands for.
+    #[must_use]
+    pub fn mode(mut self, mode: LimitMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Sets the mechanism used to enforce the limit.
+    #[must_use]
+    pub fn backend(mut self, backend: Backend) -> Self {
+        self.backend = backend;
+        self
+    }
+
+    /// Sets the duration of a control slice (100 ms by default).
+    ///
+    /// Shorter slices are smoother but cost more signals per second. Must not be zero.
+    #[must_use]
+    pub fn slice(mut self, slice: Duration) -> Self {
+        self.slice = slice;
+        self
+    }
+
+    /// Lets the processes work for `min_work` at least every slice, even if it
+    /// exceeds the limit.
+    ///
+    /// Keeps the threads which must run regularly inside the targets, such as
+    /// heartbeats or garbage collectors, from starving under very low limits.
+    /// Requires the signal backend, and must be shorter than a slice.
+    #[must_use]
+    pub fn min_work_slice(mut self, min_work: Duration) -> Self {
+        self.min_work = min_work;
+        self
+    }
+
+    /// Sets the weight of the previous measurements in the CPU usage, between 0 and 1.
+    ///
+    /// Each slice, the working rate is corrected by the ratio between the limit
+    /// and this exponential moving average. Higher values smooth out
+    /// fluctuations but react slower (0.8 by default): bursty workloads are
+    /// held steadily, but the limit overshoots for a few slices when the load
+    /// changes. Lower values correct faster, at the cost of oscillations.
+    #[must_use]
+    pub fn smoothing(mut self, smoothing: f64) -> Self {
+        self.smoothing = smoothing.clamp(0_f64, 1_f64);
+        self
+    }
+
+    /// Averages the CPU usage over a sliding `window` rather than exponentially.
+    ///
+    /// Every measurement in the window weighs the same, and older ones not at
+    /// all: the average stays stable within the window, and forgets a burst
+    /// completely once it is over. Windows spanning a few slices are the most
+    /// responsive, longer ones hold the limit over the window more precisely.
+    /// Overrides the [`smoothing`](Self::smoothing).
+    #[must_use]
+    pub fn usage_window(mut self, window: Duration) -> Self {
+        self.usage_window = Some(window);
+        self
+    }
+
+    /// Counts the CPU time of the exited children the processes waited for.
+    ///
+    /// A process forking short-lived workers then can't escape the limit,
+    /// even when its children aren't included. Off by default.
+    #[must_use]
+    pub fn include_reaped(mut self, include_reaped: bool) -> Self {
+        self.include_reaped = include_reaped;
+        self
+    }
+
+    /// Starts with the targets suspended, until [`CpuLimit::resume`] is called.
+    #[must_use]
+    pub fn paused(mut self, paused: bool) -> Self {
+        self.paused = paused;
+        self
+    }
+
+    /// Lets the targets consume at most `budget` of CPU time, then applies `action`.
+    ///
+    /// Only the CPU time consumed once the limiter started counts.
+    #[must_use]
+    pub fn budget(mut self, budget: Duration, action: BudgetAction) -> Self {
+        self.budget = Some((budget, action));
+        self
+    }
+
+    /// Only enforces the limit when the rest of the system is busy.
+    #[must_use]
+    pub fn adaptive(mut self, adaptive: AdaptiveLimit) -> Self {
+        self.adaptive = Some(adaptive);
+        self
+    }
+
+    /// Bounds the CPU usage of the group by that of another process, see [`RelativeLimit`].
+    #[must_use]
+    pub fn relative(mut self, relative: RelativeLimit) -> Self {
+        self.relative = Some(relative);
+        self
+    }
+
+    /// Throttles the group so that the utilization of the whole machine stays
+    /// under `ceiling`, as a percentage of all the cores, see [`SystemLimit`](crate::SystemLimit).
+    ///
+    /// The limit still applies on top of it.
+    #[must_use]
+    pub fn system_ceiling(mut self, ceiling: f64) -> Self {
+        self.ceiling = Some(ceiling);
+        self
+    }
+
+    /// Lets the group exceed the limit in bursts, while its average stays within
+    /// the refill rate of the [`TokenBucket`].
+    ///
+    /// Suits interactive programs, which need to respond quickly but are idle
+    /// most of the time. Requires the signal backend, a positive capacity and
+    /// a refill rate between 0 and the limit.
+    #[must_use]
+    pub fn burst(mut self, bucket: TokenBucket) -> Self {
+        self.burst = Some(bucket);
+        self
+    }
+
+    /// Leaves the group alone while its usage is below the limit by more than
+    /// `margin`, a percentage like the limit, rather than signaling it every slice.
+    ///
+    /// Throttling starts once the usage reaches the limit, and stops once it
+    /// falls below the band again. Requires the signal backend, and a margin
+    /// between 0 and the limit.
+    #[must_use]
+    pub fn hysteresis(mut self, margin: f64) -> Self {
+        self.hysteresis = Some(margin);
+        self
+    }
+
+    /// Lifts the limit once `duration` elapsed: the processes are resumed, and
+    /// the limiter stops with [`ExitReason::DeadlineReached`](crate::ExitReason::DeadlineReached).
+    #[must_use]
+    pub fn for_duration(mut self, duration: Duration) -> Self {
+        self.duration = Some(duration);
+        self
+    }
+
+    /// Applies `policy` when the CPU usage of the group exceeds the limit by more
+    /// than `excess` percent of it for `duration`, although it is throttled.
+    ///
+    /// The policy applies again if the violation lasts another `duration`.
+    /// Requires the signal backend, and a non-negative excess.
+    #[must_use]
+    pub fn on_violation(
+        mut self,
+        policy: ViolationPolicy,
+        excess: f64,
+        duration: Duration,
+    ) -> Self {
+        self.violation = match policy {
+            ViolationPolicy::Throttle => None,
+            _ => Some((policy, excess, duration)),
+        };
+        self
+    }
+
+    /// Sets whether each process of the group is also throttled to its own limit.
+    #[must_use]
+    pub fn policy(mut self, policy: GroupPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Computes the working rate with a PID controller rather than by correcting
+    /// it with the ratio between the limit and the CPU usage.
+    ///
+    /// Converges faster and holds the limit more precisely on bursty
+    /// workloads, given suitable `gains`. Only used by the signal backend.
+    #[must_use]
+    pub fn pid_controller(mut self, gains: PidGains) -> Self {
+        self.controller = Some(gains);
+        self
+    }
+
+    /// Also limits the disk I/O of the group to `bytes_per_second`, see [`IoLimit`](crate::IoLimit).
+    ///
+    /// The group is suspended for whole slices while it exceeds its I/O
+    /// budget, whatever its CPU usage. Requires the signal backend.
+    #[must_use]
+    pub fn io_limit(mut self, bytes_per_second: u64) -> Self {
+        self.io_limit = Some(bytes_per_second);
+        self
+    }
+
+    /// Sends an [`Event::EnforcementFailure`](crate::Event::EnforcementFailure) when
+    /// the CPU usage stays above the limit for `slices` consecutive slices although
+    /// the group is suspended, or when a process can't be signaled.
+    ///
+    /// Only detected by the signal backend. Sent once per streak of failing slices,
+    /// at least one.
+    #[must_use]
+    pub fn enforcement_alert(mut self, slices: u32) -> Self {
+        self.enforcement_alert = Some(u32::max(slices, 1));
+        self
+    }
+
+    /// Records the CPU usage, the limit and the working rate of the last
+    /// `slices` time slices, see [`CpuLimit::timeline`].
+    ///
+    /// Only [`Backend::Signal`] works in time slices: nothing is recorded otherwise.
+    #[must_use]
+    pub fn timeline(mut self, slices: usize) -> Self {
+        self.timeline = (slices > 0).then_some(slices);
+        self
+    }
+
+    /// Lists the members in a file of `dir` while limiting them, for
+    /// [`recover`](crate::recover) to resume them if this process is killed
+    /// while they are suspended.
+    ///
+    /// Fails with [`Error::Recovery`] when building if the file can't be created.
+    #[must_use]
+    pub fn recovery_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.recovery_dir = Some(dir.into());
+        self
+    }
+
+    /// Reads the processes from the procfs mounted at `root`, see [`ProcfsRoot::install`].
+    ///
+    /// The root is installed for the whole process when the limiter is built.
+    #[must_use]
+    pub fn procfs(mut self, root: ProcfsRoot) -> Self {
+        self.procfs = Some(root);
+        self
+    }
+
+    /// Suspends the whole group as soon as children join it faster than `fork_guard` allows.
+    ///
+    /// Only meaningful with [`ChildrenMode::Include`]. A
+    /// [`Event::ForkBombDetected`](crate::Event::ForkBombDetected) is sent, and
+    /// the group stays suspended until [`CpuLimit::resume`] is called.
+    #[must_use]
+    pub fn fork_guard(mut self, fork_guard: ForkGuard) -> Self {
+        self.fork_guard = Some(fork_guard);
+        self
+    }
+
+    /// Samples the resident memory of the group at each slice, and applies the
+    /// action of `memory_guard` when it exceeds the threshold.
+    ///
+    /// A [`Event::MemoryExceeded`](crate::Event::MemoryExceeded) is sent. With
+    /// [`MemoryAction::Suspend`](crate::MemoryAction::Suspend), the group stays
+    /// suspended until [`CpuLimit::resume`] is called.
+    #[must_use]
+    pub fn memory_guard(mut self, memory_guard: MemoryGuard) -> Self {
+        self.memory_guard = Some(memory_guard);
+        self
+    }
+
+    /// Reads and signals the targets through `backend` rather than procfs and libc.
+    ///
+    /// Requires the signal backend and targets given by PID. A
+    /// [`MockBackend`](crate::MockBackend) simulates processes in memory.
+    #[must_use]
+    pub fn process_backend(mut self, backend: Arc<dyn ProcessBackend>) -> Self {
+        self.process_backend = Some(backend);
+        self
+    }
+
+    /// Stops and resumes the group with `throttler` rather than the default signals.
+    ///
+    /// Requires the signal backend and a [`GroupPolicy::Shared`] limit. The
+    /// guards, the budget actions and the final resumption still use signals.
+    #[must_use]
+    pub fn throttler(mut self, throttler: Arc<dyn Throttler>) -> Self {
+        self.throttler = Some(throttler);
+        self
+    }
+
+    /// Leaves running through the slice the processes `check` inhibits the
+    /// suspension of, such as the holders of a lock others wait for.
+    ///
+    /// Asked before each suspension. Requires suspending the processes with
+    /// signals: the freezer stops the whole group at once.
+    #[must_use]
+    pub fn inhibit(mut self, check: Arc<dyn InhibitCheck>) -> Self {
+        self.inhibit = Some(check);
+        self
+    }
+
+    /// Prepares the limiter to keep running once the process switches to the
+    /// user `uid` with [`drop_privileges`](crate::drop_privileges).
+    ///
+    /// Fails with [`Error::PermissionDenied`] when building if a target
+    /// wouldn't be signaled by that user anymore, that is unless its real or
+    /// saved user ID is `uid`. Requires the signal backend, without the freezer.
+    #[must_use]
+    pub fn run_as(mut self, uid: u32) -> Self {
+        self.run_as = Some(uid);
+        self
+    }
+
+    /// Keeps limiting the target when it exits and a process recognized by
+    /// `mode` starts within `timeout`, such as a service restarted by its supervisor.
+    ///
+    /// Requires the signal backend and a single target given by PID. The oldest
+    /// process started after the target is limited instead, and a
+    /// [`Event::Reattached`](crate::Event::Reattached) is sent.
+    #[must_use]
+    pub fn follow(mut self, mode: FollowMode, timeout: Duration) -> Self {
+        self.follow = Some((mode, timeout));
+        self
+    }
+
+    /// Starts limiting the targets.
+    ///
+    /// Fails with [`Error::InvalidLimit`] unless the limits are positive and
+    /// within the cores online, according to the [`LimitMode`].
+    ///
+    /// Fails with [`Error::Unsupported`] for a zero [slice](Self::slice), and
+    /// unless the options below use the signal backend ([`Backend::Signal`]):
+    ///
+    /// - [`GroupPolicy::PerProcess`], suspending each process in turn;
+    /// - an [I/O limit](Self::io_limit), which must be positive;
+    /// - a [custom throttler](Self::throttler), with a shared limit;
+    /// - a [violation policy](Self::on_violation), with a non-negative excess;
+    /// - [bursts](Self::burst), refilled at a rate between 0 and the limit;
+    /// - a [hysteresis](Self::hysteresis) margin, between 0 and the limit;
+    /// - a [minimum work slice](Self::min_work_slice), shorter than a slice;
+    /// - a [relative limit](Self::relative), with a positive factor;
+    /// - a [system ceiling](Self::system_ceiling), between 0 and 100%;
+    /// - an [inhibit check](Self::inhibit) or [`run_as`](Self::run_as), without the freezer;
+    /// - a [process backend](Self::process_backend) or [`follow`](Self::follow), with targets
+    ///   given by PID.
+    ///
+    /// Fails with [`Error::Cgroup`] if the cgroup backend is combined with
+    /// dynamic targets such as [`Target::Cgroup`], or with the freezer.
+    pub fn build(self) -> Result<CpuLimit> {
+        self.mode.check(self.limit)?;
+        if self.slice.is_zero() {
+            return Err(Error::Unsupported("the slice must be longer than zero"));
+        }
+        if let GroupPolicy::PerProcess(limit) = self.policy {
+            self.mode.check(limit)?;
+        }
+        if let GroupPolicy::PerProcess(_) = self.policy {
+            if self.backend != Backend::Signal || self.suspend_mode != SuspendMode::PerProcess {
+                return Err(Error::Unsupported(
+                    "per-process limits require suspending each process with a signal",
+                ));
+            }
+        }
+        if let Some(margin) = self.hysteresis {
+            if !(margin > 0_f64 && margin < self.limit) || self.backend != Backend::Signal {
+                return Err(Error::Unsupported(
+                    "a hysteresis margin requires the signal backend and must be between 0 and the limit",
+                ));
+            }
+        }
+        if let Some(relative) = self.relative {
+            if !(relative.factor > 0_f64 && relative.factor.is_finite())
+                || self.backend != Backend::Signal
+            {
+                return Err(Error::Unsupported(
+                    "a relative limit requires the signal backend and a positive factor",
+                ));
+            }
+        }
+        if let Some(ceiling) = self.ceiling {
+            if !(ceiling > 0_f64 && ceiling <= 100_f64) || self.backend != Backend::Signal {
+                return Err(Error::Unsupported(
+                    "a system ceiling requires the signal backend and must be between 0 and 100%",
+                ));
+            }
+        }
+        if let Some(bucket) = self.burst {
+            if bucket.capacity.is_zero()
+                || !(bucket.refill_rate > 0_f64 && bucket.refill_rate <= self.limit)
+                || self.backend != Backend::Signal
+            {
+                return Err(Error::Unsupported(
+                    "bursts require the signal backend, a capacity and a refill rate between 0 and the limit",
+                ));
+            }
+        }
+        if let Some((_, excess, _)) = self.violation {
+            if excess.is_nan() || excess < 0_f64 || self.backend != Backend::Signal {
+                return Err(Error::Unsupported(
+                    "a violation policy requires the signal backend and a non-negative excess",
+                ));
+            }
+        }
+        if !self.min_work.is_zero()
+            && (self.min_work >= self.slice || self.backend != Backend::Signal)
+        {
+            return Err(Error::Unsupported(
+                "a minimum work slice requires the signal backend and must be shorter than a slice",
+            ));
+        }
+        if self.inhibit.is_some()
+            && (self.backend != Backend::Signal || self.suspend_mode == SuspendMode::Freezer)
+        {
+            return Err(Error::Unsupported(
+                "an inhibit check requires suspending each process with a signal",
+            ));
+        }
+        if self.run_as.is_some()
+            && (self.backend != Backend::Signal
+                || self.suspend_mode == SuspendMode::Freezer
+                || self.process_backend.is_some())
+        {
+            return Err(Error::Unsupported(
+                "dropping privileges requires the signal backend without the freezer",
+            ));
+        }
+        if self.io_limit == Some(0) {
+            return Err(Error::InvalidIoLimit);
+        }
+        if self.io_limit.is_some() && self.backend != Backend::Signal {
+            return Err(Error::Unsupported(
+                "an I/O limit requires suspending the processes with signals",
+            ));
+        }
+        if self.backend == Backend::CgroupV2 && self.targets.iter().any(Target::is_dynamic) {
+            return Err(Error::Cgroup(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "dynamic targets can't be moved to a dedicated cgroup",
+            )));
+        }
+        if self.backend == Backend::CgroupV2 && self.suspend_mode == SuspendMode::Freezer {
+            return Err(Error::Cgroup(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "the processes can't be in both the freezer and the limiting cgroup",
+            )));
+        }
+
+        if self.process_backend.is_some()
+            && (self.backend != Backend::Signal
+                || self.thread.is_some()
+                || !self
+                    .targets
+                    .iter()
+                    .all(|target| matches!(target, Target::Pid(_))))
+        {
+            return Err(Error::Unsupported(
+                "a process backend requires the signal backend and targets given by PID",
+            ));
+        }
+
+        if self.throttler.is_some()
+            && (self.backend != Backend::Signal || self.policy != GroupPolicy::Shared)
+        {
+            return Err(Error::Unsupported(
+                "a custom throttler requires the signal backend and a shared limit",
+            ));
+        }
+
+        let follower = match (self.follow, self.targets.as_slice()) {
+            (None, _) => None,
+            (Some((mode, timeout)), [Target::Pid(pid)])
+                if self.backend == Backend::Signal
+                    && self.thread.is_none()
+                    && self.process_backend.is_none() =>
+            {
+                Some(Follower::new(*pid, mode, timeout)?)
+            }
+            (Some(_), _) => {
+                return Err(Error::Unsupported(
+                    "following a restarted target requires the signal backend and a single target given by PID",
+                ))
+            }
+        };
+
+        if let Some(root) = self.procfs {
+            root.install();
+        }
+
+        let mut group = match (self.thread, self.process_backend) {
+            (Some((pid, tid)), _) => ProcessGroup::for_thread(pid, tid)?,
+            _ if self.targets.is_empty() => return Err(Error::NoTarget),
+            (None, Some(backend)) => {
+                let pids: Vec<Pid> = self
+                    .targets
+                    .iter()
+                    .filter_map(|target| match target {
+                        Target::Pid(pid) => Some(*pid),
+                        _ => None,
+                    })
+                    .collect();
+                ProcessGroup::with_backend(&pids, self.children_mode, backend)?
+            }
+            (None, None) => ProcessGroup::new(&self.targets, self.children_mode)?,
+        };
+        group.exclude(&self.excluded_pids, &self.excluded_names);
+        group.set_smoothing(self.smoothing);
+        group.set_usage_window(self.usage_window);
+        group.set_include_reaped(self.include_reaped);
+        group.set_fork_guard(self.fork_guard);
+        group.set_memory_guard(self.memory_guard);
+        group.set_timeline(self.timeline);
+        group.set_stopped_policy(self.stopped_policy);
+        group.set_stop_signal(self.stop_signal);
+        group.set_inhibit(self.inhibit);
+        group.set_suspend_mode(self.suspend_mode)?;
+
+        if let Some(uid) = self.run_as {
+            if let Some(pid) = group
+                .members()
+                .find(|&pid| !privileges::can_signal_as(pid, uid))
+            {
+                return Err(Error::PermissionDenied(pid));
+            }
+        }
+        if let Some(dir) = &self.recovery_dir {
+            group.set_journal(Some(Journal::create(dir).map_err(Error::Recovery)?));
+        }
+
+        CpuLimit::start_group(
+            group,
+            Settings {
+                limit: self.limit,
+                mode: self.mode,
+                backend: self.backend,
+                slice: self.slice,
+                paused: self.paused,
+                budget: self.budget,
+                adaptive: self.adaptive,
+                relative: self.relative,
+                ceiling: self.ceiling,
+                burst: self.burst,
+                policy: self.policy,
+                controller: self.controller,
+                io_limit: self.io_limit,
+                enforcement_alert: self.enforcement_alert,
+                hysteresis: self.hysteresis,
+                min_work: self.min_work,
+                duration: self.duration,
+                violation: self.violation,
+            },
+            self.throttler,
+            follower,
+        )
+    }
+
+    /// Spawns `command` and limits the resulting process, along with the other targets.
+    ///
+    /// The process is attached to the limiter before it executes the program,
+    /// so it never runs unthrottled. When starting paused, the process is
+    /// suspended right after executing the program.
+    pub fn spawn(mut self, mut command: process::Command) -> Result<(CpuLimit, Child)> {
+        if self.suspend_mode == SuspendMode::ProcessGroup {
+            command.process_group(0);
+        }
+        let child = StoppedChild::spawn(command).map_err(Error::Command)?;
+
+        // the child must be resumed to call `exec`
+        let paused = std::mem::replace(&mut self.paused, false);
+        self.targets.push(Target::Pid(child.pid));
+        let handle = match self.build() {
+            Ok(handle) => handle,
+            Err(err) => {
+                child.abort();
+                return Err(err);
+            }
+        };
+
+        // the child is attached to the limiter, it can now call `exec`
+        match child.exec() {
+            Ok(child) => {
+                if paused {
+                    handle.pause()?;
+                }
+                Ok((handle, child))
+            }
+            Err(err) => {
+                let _ = handle.stop();
+                Err(Error::Command(err))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::backend::MockBackend;
+    use crate::throttler::SignalThrottler;
+
+    /// Returns the error `builder` fails with, before any target is resolved.
+    fn rejected(builder: CpuLimitBuilder) -> Error {
+        match builder.build() {
+            Ok(_) => panic!("the builder was accepted"),
+            Err(err) => err,
+        }
+    }
+
+    #[test]
+    fn validation() {
+        let second = Duration::from_secs(1);
+        let per_process = GroupPolicy::PerProcess(10_f64);
+        let mock = || -> Arc<dyn ProcessBackend> { Arc::new(MockBackend::new()) };
+        let unsupported = [
+            (
+                "zero slice",
+                CpuLimitBuilder::new(50_f64).slice(Duration::ZERO),
+            ),
+            (
+                "per-process limits with cgroups",
+                CpuLimitBuilder::new(50_f64)
+                    .policy(per_process)
+                    .backend(Backend::CgroupV2),
+            ),
+            (
+                "per-process limits with process groups",
+                CpuLimitBuilder::new(50_f64)
+                    .policy(per_process)
+                    .suspend_mode(SuspendMode::ProcessGroup),
+            ),
+            (
+                "hysteresis above the limit",
+                CpuLimitBuilder::new(50_f64).hysteresis(60_f64),
+            ),
+            (
+                "hysteresis with cgroups",
+                CpuLimitBuilder::new(50_f64)
+                    .hysteresis(5_f64)
+                    .backend(Backend::CgroupV2),
+            ),
+            (
+                "zero relative factor",
+                CpuLimitBuilder::new(50_f64).relative(RelativeLimit {
+                    reference: Pid::from(1),
+                    factor: 0_f64,
+                }),
+            ),
+            (
+                "ceiling above 100%",
+                CpuLimitBuilder::new(50_f64).system_ceiling(150_f64),
+            ),
+            (
+                "ceiling with cgroups",
+                CpuLimitBuilder::new(50_f64)
+                    .system_ceiling(50_f64)
+                    .backend(Backend::CgroupV2),
+            ),
+            (
+                "burst without capacity",
+                CpuLimitBuilder::new(50_f64).burst(TokenBucket {
+                    capacity: Duration::ZERO,
+                    refill_rate: 10_f64,
+                }),
+            ),
+            (
+                "burst refilled above the limit",
+                CpuLimitBuilder::new(50_f64).burst(TokenBucket {
+                    capacity: second,
+                    refill_rate: 60_f64,
+                }),
+            ),
+            (
+                "negative violation excess",
+                CpuLimitBuilder::new(50_f64).on_violation(ViolationPolicy::Notify, -1_f64, second),
+            ),
+            (
+                "minimum work over a slice",
+                CpuLimitBuilder::new(50_f64).min_work_slice(second),
+            ),
+            (
+                "minimum work with cgroups",
+                CpuLimitBuilder::new(50_f64)
+                    .min_work_slice(Duration::from_millis(10))
+                    .backend(Backend::CgroupV2),
+            ),
+            (
+                "inhibit check with the freezer",
+                CpuLimitBuilder::new(50_f64)
+                    .inhibit(Arc::new(|_: Pid| false))
+                    .suspend_mode(SuspendMode::Freezer),
+            ),
+            (
+                "run_as with the freezer",
+                CpuLimitBuilder::new(50_f64)
+                    .run_as(1000)
+                    .suspend_mode(SuspendMode::Freezer),
+            ),
+            (
+                "run_as with a process backend",
+                CpuLimitBuilder::new(50_f64)
+                    .run_as(1000)
+                    .process_backend(mock()),
+            ),
+            (
+                "I/O limit with cgroups",
+                CpuLimitBuilder::new(50_f64)
+                    .io_limit(1024)
+                    .backend(Backend::CgroupV2),
+            ),
+            (
+                "process backend with a thread",
+                CpuLimitBuilder::new(50_f64)
+                    .process_backend(mock())
+                    .thread(Pid::from(1), Pid::from(1)),
+            ),
+            (
+                "process backend with a dynamic target",
+                CpuLimitBuilder::new(50_f64)
+                    .process_backend(mock())
+                    .target(Target::User(1000)),
+            ),
+            (
+                "throttler with per-process limits",
+                CpuLimitBuilder::new(50_f64)
+                    .throttler(Arc::new(SignalThrottler))
+                    .policy(per_process),
+            ),
+            (
+                "follow without a target",
+                CpuLimitBuilder::new(50_f64).follow(FollowMode::Name, second),
+            ),
+        ];
+        for (case, builder) in unsupported {
+            let err = rejected(builder);
+            assert!(matches!(err, Error::Unsupported(_)), "{case}: {err:?}");
+        }
+
+        assert!(matches!(
+            rejected(CpuLimitBuilder::new(0_f64)),
+            Error::InvalidLimit(_)
+        ));
+        assert!(matches!(
+            rejected(CpuLimitBuilder::new(150_f64).mode(LimitMode::TotalSystem)),
+            Error::InvalidLimit(_)
+        ));
+        assert!(matches!(
+            rejected(CpuLimitBuilder::new(50_f64).policy(GroupPolicy::PerProcess(-1_f64))),
+            Error::InvalidLimit(_)
+        ));
+        assert!(matches!(
+            rejected(CpuLimitBuilder::new(50_f64).io_limit(0)),
+            Error::InvalidIoLimit
+        ));
+        assert!(matches!(
+            rejected(
+                CpuLimitBuilder::new(50_f64)
+                    .backend(Backend::CgroupV2)
+                    .target(Target::User(1000))
+            ),
+            Error::Cgroup(_)
+        ));
+        assert!(matches!(
+            rejected(
+                CpuLimitBuilder::new(50_f64)
+                    .backend(Backend::CgroupV2)
+                    .suspend_mode(SuspendMode::Freezer)
+            ),
+            Error::Cgroup(_)
+        ));
+        // every option is valid, but there is nothing to limit
+        assert!(matches!(
+            rejected(CpuLimitBuilder::new(50_f64)),
+            Error::NoTarget
+        ));
+    }
+}
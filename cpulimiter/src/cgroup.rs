@@ -0,0 +1,336 @@
+//! Enforce a CPU limit with the cgroup v2 `cpu` controller.
+//!
+//! Instead of suspending the processes, they are moved to a dedicated cgroup
+//! whose `cpu.max` quota is set according to the limit: the kernel then
+//! throttles them smoothly without any signal.
+//!
+//! See the kernel documentation (`Documentation/admin-guide/cgroup-v2.rst`)
+//! for a description of the interface files.
+//!
+//! A cgroup without any controller is also used to suspend processes at once
+//! with the freezer (`cgroup.freeze`).
+//!
+//! The cgroups are created under the one this process belongs to, such as the
+//! cgroup of its systemd service or the subtree delegated to its user. No
+//! process may remain in a cgroup whose children use controllers, the root
+//! aside: this process first moves to the leaf `cpulimit-limiter` under it.
+//! When that is not possible, for instance because other processes share the
+//! cgroup, the cgroups are created under the root if it is writable.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process;
+
+use crate::pid::Pid;
+use crate::procfs;
+
+/// The period of the `cpu.max` quota, in microseconds.
+const PERIOD_US: u64 = 100_000;
+
+/// The smallest quota accepted by the kernel, in microseconds.
+const MIN_QUOTA_US: u64 = 1_000;
+
+/// The cgroup this process moves to, under the one it belonged to.
+const LEAF: &str = "cpulimit-limiter";
+
+/// A cgroup created to limit a set of processes.
+pub(crate) struct Cgroup {
+    path: PathBuf,
+    /// Where the unified hierarchy is mounted.
+    mount: PathBuf,
+    /// The cgroup each process was moved out of.
+    origins: Vec<(Pid, PathBuf)>,
+}
+
+impl Cgroup {
+    /// Creates a dedicated cgroup and moves `pids` into it.
+    ///
+    /// Processes forked afterwards by the members are automatically created in the cgroup.
+    pub fn create(pids: &[Pid]) -> io::Result<Self> {
+        let mount = mount_point()?;
+        let parent = parent_cgroup(&mount, Some("cpu"))?;
+        Self::create_in(mount, &parent, "cpulimit", pids)
+    }
+
+    /// Creates a dedicated cgroup to freeze `pids`, and moves them into it.
+    ///
+    /// No controller is needed, processes forked afterwards by the members
+    /// are frozen along with them.
+    pub fn create_freezer(pids: &[Pid]) -> io::Result<Self> {
+        let mount = mount_point()?;
+        let parent = parent_cgroup(&mount, None)?;
+        Self::create_in(mount, &parent, "cpulimit-freezer", pids)
+    }
+
+    /// Creates the cgroup `{prefix}-{first pid}` under `parent` and moves `pids` into it.
+    fn create_in(mount: PathBuf, parent: &Path, prefix: &str, pids: &[Pid]) -> io::Result<Self> {
+        let name = pids.first().map(ToString::to_string).unwrap_or_default();
+        let path = parent.join(format!("{prefix}-{name}"));
+        if !path.exists() {
+            fs::create_dir(&path).map_err(|err| not_writable(parent, err))?;
+        }
+
+        let mut cgroup = Self {
+            path,
+            mount,
+            origins: Vec::new(),
+        };
+
+        for &pid in pids {
+            if let Err(err) = cgroup.attach(pid) {
+                cgroup.release();
+                return Err(err);
+            }
+        }
+
+        Ok(cgroup)
+    }
+
+    /// Moves `pid` into the cgroup, remembering where it came from.
+    pub fn attach(&mut self, pid: Pid) -> io::Result<()> {
+        if self.origins.iter().any(|(member, _)| *member == pid) {
+            return Ok(());
+        }
+
+        let origin = current_cgroup(&self.mount, pid)?;
+        fs::write(self.path.join("cgroup.procs"), pid.to_string())?;
+        self.origins.push((pid, origin));
+        Ok(())
+    }
+
+    /// Sets the quota of the cgroup to the given number of `cores`.
+    pub fn set_limit(&self, cores: f64) -> io::Result<()> {
+        let quota = (cores * PERIOD_US as f64) as u64;
+        let quota = u64::max(quota, MIN_QUOTA_US);
+        fs::write(self.path.join("cpu.max"), format!("{quota} {PERIOD_US}"))
+    }
+
+    /// Freezes or thaws every process of the cgroup.
+    ///
+    /// The kernel completes the freezing asynchronously.
+    pub fn freeze(&self, frozen: bool) -> io::Result<()> {
+        fs::write(
+            self.path.join("cgroup.freeze"),
+            if frozen { "1" } else { "0" },
+        )
+    }
+
+    /// Moves every process back to its original cgroup and removes the cgroup.
+    ///
+    /// Processes that were forked inside the cgroup follow the first target.
+    pub fn release(self) {
+        let _ = fs::write(self.path.join("cpu.max"), format!("max {PERIOD_US}"));
+        let _ = self.freeze(false);
+
+        for (pid, origin) in &self.origins {
+            let _ = fs::write(origin.join("cgroup.procs"), pid.to_string());
+        }
+
+        if let Some((_, fallback)) = self.origins.first() {
+            if let Ok(procs) = fs::read_to_string(self.path.join("cgroup.procs")) {
+                for pid in procs.lines() {
+                    let _ = fs::write(fallback.join("cgroup.procs"), pid);
+                }
+            }
+        }
+
+        let _ = fs::remove_dir(&self.path);
+    }
+}
+
+/// Finds where the unified cgroup hierarchy is mounted.
+///
+/// This is usually `/sys/fs/cgroup`, or `/sys/fs/cgroup/unified` on hybrid systems.
+pub(crate) fn mount_point() -> io::Result<PathBuf> {
+    // the mounts of this process, wherever the procfs of the targets is
+    let mounts = fs::read_to_string("/proc/self/mounts")?;
+
+    mounts
+        .lines()
+        .map(|line| line.split_whitespace().collect::<Vec<_>>())
+        .find(|fields| fields.get(2) == Some(&"cgroup2"))
+        .and_then(|fields| fields.get(1).map(PathBuf::from))
+        .ok_or_else(|| io::Error::new(io::ErrorKind::Unsupported, "cgroup v2 is not mounted"))
+}
+
+/// Retrieves the path of the cgroup `pid` currently belongs to.
+fn current_cgroup(root: &Path, pid: Pid) -> io::Result<PathBuf> {
+    let content = fs::read_to_string(procfs::path(format!("{pid}/cgroup")))?;
+    parse_cgroup(root, &content)
+}
+
+/// Finds the cgroup to create the cgroups under, with `controller` enabled
+/// for its children.
+///
+/// That is the cgroup of this process, or the root of the hierarchy as a
+/// fallback, see the module documentation.
+fn parent_cgroup(mount: &Path, controller: Option<&str>) -> io::Result<PathBuf> {
+    let own = own_cgroup(mount)?;
+    match delegate(mount, &own, Pid::from(process::id()), controller) {
+        Ok(parent) => Ok(parent),
+        Err(err) if own != mount => delegate(mount, mount, Pid::from(process::id()), controller)
+            // the reason the cgroup of this process couldn't be used matters more
+            .map_err(|_| err),
+        Err(err) => Err(err),
+    }
+}
+
+/// Prepares `own`, the cgroup of `pid`, to create cgroups under it with
+/// `controller` enabled, and returns it.
+///
+/// Unless `own` is the root, `pid` moves to its leaf [`LEAF`] first, if it
+/// is not there already.
+fn delegate(mount: &Path, own: &Path, pid: Pid, controller: Option<&str>) -> io::Result<PathBuf> {
+    let parent = match own.parent() {
+        Some(parent) if own.ends_with(LEAF) => parent,
+        _ => own,
+    };
+
+    if parent != mount {
+        let leaf = parent.join(LEAF);
+        if !leaf.exists() {
+            fs::create_dir(&leaf).map_err(|err| not_writable(parent, err))?;
+        }
+        fs::write(leaf.join("cgroup.procs"), pid.to_string())
+            .map_err(|err| not_writable(parent, err))?;
+    }
+
+    let Some(controller) = controller else {
+        return Ok(parent.to_path_buf());
+    };
+    let has_controller = |file: &str| {
+        fs::read_to_string(parent.join(file)).map(|controllers| {
+            controllers
+                .split_whitespace()
+                .any(|available| available == controller)
+        })
+    };
+    if !has_controller("cgroup.controllers")? {
+        return Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!(
+                "the {controller} controller is not available in {}",
+                parent.display()
+            ),
+        ));
+    }
+    if !has_controller("cgroup.subtree_control")? {
+        fs::write(
+            parent.join("cgroup.subtree_control"),
+            format!("+{controller}"),
+        )
+        .map_err(|err| match err.raw_os_error() {
+            Some(libc::EBUSY) => io::Error::new(
+                io::ErrorKind::ResourceBusy,
+                format!(
+                    "other processes share the cgroup {}: run in a cgroup of its own, \
+                         with `systemd-run --scope` for instance",
+                    parent.display()
+                ),
+            ),
+            _ => not_writable(parent, err),
+        })?;
+    }
+    Ok(parent.to_path_buf())
+}
+
+/// Retrieves the path of the cgroup this process belongs to.
+fn own_cgroup(root: &Path) -> io::Result<PathBuf> {
+    // this process, wherever the procfs of the targets is
+    parse_cgroup(root, &fs::read_to_string("/proc/self/cgroup")?)
+}
+
+/// Finds the path of the unified hierarchy in the content of a `cgroup` file.
+fn parse_cgroup(root: &Path, content: &str) -> io::Result<PathBuf> {
+    // the unified hierarchy is listed as `0::<path>`
+    content
+        .lines()
+        .find_map(|line| line.strip_prefix("0::"))
+        .map(|path| root.join(path.trim_start_matches('/')))
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "not in a cgroup v2 hierarchy"))
+}
+
+/// Explains a failure to create a cgroup under `parent` for lack of permission.
+fn not_writable(parent: &Path, err: io::Error) -> io::Error {
+    match err.kind() {
+        io::ErrorKind::PermissionDenied | io::ErrorKind::ReadOnlyFilesystem => io::Error::new(
+            err.kind(),
+            format!(
+                "the cgroup {} is not writable: run as root, or in a cgroup delegated to the user",
+                parent.display()
+            ),
+        ),
+        _ => err,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::process::Command;
+
+    use super::*;
+
+    #[test]
+    fn parse() {
+        let content = "1:cpu:/\n0::/user.slice/user-1000.slice/user@1000.service/app.slice\n";
+        assert_eq!(
+            parse_cgroup(Path::new("/sys/fs/cgroup"), content).unwrap(),
+            Path::new("/sys/fs/cgroup/user.slice/user-1000.slice/user@1000.service/app.slice")
+        );
+        assert!(parse_cgroup(Path::new("/sys/fs/cgroup"), "1:cpu:/\n").is_err());
+    }
+
+    #[test]
+    fn delegate_from_service() {
+        // needs a writable hierarchy with a controller, as root
+        let Ok(mount) = mount_point() else {
+            return;
+        };
+        let controllers = fs::read_to_string(mount.join("cgroup.controllers")).unwrap_or_default();
+        let Some(controller) = controllers.split_whitespace().next() else {
+            return;
+        };
+        let enabled = fs::read_to_string(mount.join("cgroup.subtree_control")).unwrap_or_default();
+        if fs::write(
+            mount.join("cgroup.subtree_control"),
+            format!("+{controller}"),
+        )
+        .is_err()
+        {
+            return;
+        }
+
+        // a service whose process creates cgroups
+        let service = mount.join(format!("cpulimit-test-{}", process::id()));
+        fs::create_dir(&service).unwrap();
+        let mut child = Command::new("sleep").arg("10").spawn().unwrap();
+        let pid = Pid::from(child.id());
+        fs::write(service.join("cgroup.procs"), pid.to_string()).unwrap();
+
+        let parent = delegate(&mount, &service, pid, Some(controller));
+        let procs = fs::read_to_string(service.join(LEAF).join("cgroup.procs"));
+        let subtree = fs::read_to_string(service.join("cgroup.subtree_control"));
+        // once moved, the leaf is recognized
+        let again = delegate(&mount, &service.join(LEAF), pid, Some(controller));
+
+        child.kill().unwrap();
+        child.wait().unwrap();
+        let _ = fs::remove_dir(service.join(LEAF));
+        let _ = fs::remove_dir(&service);
+        if !enabled.split_whitespace().any(|name| name == controller) {
+            let _ = fs::write(
+                mount.join("cgroup.subtree_control"),
+                format!("-{controller}"),
+            );
+        }
+
+        assert_eq!(parent.unwrap(), service);
+        assert_eq!(procs.unwrap().trim(), pid.to_string());
+        assert!(subtree
+            .unwrap()
+            .split_whitespace()
+            .any(|name| name == controller));
+        assert_eq!(again.unwrap(), service);
+    }
+}
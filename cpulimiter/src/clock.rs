@@ -0,0 +1,120 @@
+//! The system constants the stat files are expressed in: clock ticks and memory pages.
+//!
+//! They are queried with `sysconf` at first use. When the query fails, the
+//! usual values of Linux are assumed rather than dividing by `-1`. Tests and
+//! platforms exposing a procfs with other units may set them with
+//! [`SystemClock::install`].
+
+use std::time::Duration;
+
+use std::sync::OnceLock;
+
+use parking_lot::RwLock;
+
+/// The number of clock ticks per second assumed when it can't be queried, `USER_HZ` on Linux.
+const DEFAULT_TICKS_PER_SECOND: u64 = 100;
+
+/// The size of a memory page assumed when it can't be queried, in bytes.
+const DEFAULT_PAGE_SIZE: u64 = 4096;
+
+/// The constants in use by the whole library.
+static CLOCK: OnceLock<RwLock<SystemClock>> = OnceLock::new();
+
+fn clock() -> &'static RwLock<SystemClock> {
+    CLOCK.get_or_init(|| RwLock::new(SystemClock::detect()))
+}
+
+/// The units of the times and memory sizes read from procfs.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct SystemClock {
+    ticks_per_second: u64,
+    page_size: u64,
+}
+
+impl SystemClock {
+    /// Describes a system with the given constants.
+    ///
+    /// A zero is replaced by the default value: 100 ticks per second, or pages of 4 KiB.
+    pub fn new(ticks_per_second: u64, page_size: u64) -> Self {
+        Self {
+            ticks_per_second: if ticks_per_second == 0 {
+                DEFAULT_TICKS_PER_SECOND
+            } else {
+                ticks_per_second
+            },
+            page_size: if page_size == 0 {
+                DEFAULT_PAGE_SIZE
+            } else {
+                page_size
+            },
+        }
+    }
+
+    /// Queries the constants of the running system.
+    pub fn detect() -> Self {
+        // SAFETY: Inherently unsafe as a syscall, but the parameter is valid.
+        let ticks_per_second = unsafe { libc::sysconf(libc::_SC_CLK_TCK) };
+        // SAFETY: Inherently unsafe as a syscall, but the parameter is valid.
+        let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) };
+        // `sysconf` returns -1 on failure
+        Self::new(
+            u64::try_from(ticks_per_second).unwrap_or(0),
+            u64::try_from(page_size).unwrap_or(0),
+        )
+    }
+
+    /// Retrieves the constants currently in use.
+    pub fn current() -> Self {
+        *clock().read()
+    }
+
+    /// Uses these constants from now on, in the whole process.
+    pub fn install(self) {
+        *clock().write() = self;
+    }
+
+    /// Retrieves the number of clock ticks per second.
+    pub fn ticks_per_second(&self) -> u64 {
+        self.ticks_per_second
+    }
+
+    /// Retrieves the size of a memory page, in bytes.
+    pub fn page_size(&self) -> u64 {
+        self.page_size
+    }
+
+    /// Converts a number of clock ticks, the unit of the times in stat files.
+    pub fn ticks_to_duration(&self, ticks: u64) -> Duration {
+        Duration::from_secs_f64(ticks as f64 / self.ticks_per_second as f64)
+    }
+
+    /// Converts a number of memory pages to bytes.
+    pub fn pages_to_bytes(&self, pages: u64) -> u64 {
+        pages.saturating_mul(self.page_size)
+    }
+}
+
+impl Default for SystemClock {
+    fn default() -> Self {
+        Self::new(DEFAULT_TICKS_PER_SECOND, DEFAULT_PAGE_SIZE)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn conversions() {
+        let clock = SystemClock::new(250, 16384);
+        assert_eq!(clock.ticks_to_duration(500), Duration::from_secs(2));
+        assert_eq!(clock.pages_to_bytes(3), 3 * 16384);
+
+        // a failed query doesn't lead to a division by zero
+        assert_eq!(SystemClock::new(0, 0), SystemClock::default());
+        assert_eq!(
+            SystemClock::default().ticks_to_duration(150),
+            Duration::from_millis(1500)
+        );
+    }
+}
@@ -0,0 +1,316 @@
+//! Control running limiters through a Unix socket.
+//!
+//! Clients send one command per line, and get one JSON object per line back:
+//!
+//! - `LIST` lists the limiters, with their limit and CPU usage;
+//! - `SET <pid> <limit>` changes the limit of the limiter of `<pid>`;
+//! - `STOP <pid>` stops it, resuming the process;
+//! - `STATS <pid>` details its statistics.
+//!
+//! Failures are reported as `{"error":"<message>"}`, and numbers which
+//! aren't finite as `null`.
+
+use std::fmt::Write as _;
+use std::fs::{self, Permissions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use crate::error::{Error, Result};
+use crate::limiter::CpuLimit;
+use crate::pid::Pid;
+use crate::trace::warn;
+
+/// How long the server waits after failing to accept a client, doubled at
+/// each consecutive failure up to [`MAX_ACCEPT_DELAY`].
+const ACCEPT_DELAY: Duration = Duration::from_millis(10);
+
+/// The longest wait after failing to accept a client.
+const MAX_ACCEPT_DELAY: Duration = Duration::from_secs(1);
+
+/// Lists the limiters, each one along with a process it limits.
+type Limiters = dyn Fn() -> Vec<(Pid, CpuLimit)> + Send + Sync;
+
+/// A thread serving the control protocol on a Unix socket.
+///
+/// The socket is only accessible to the owner of the process, and removed
+/// when the server is dropped.
+///
+/// # Example
+///
+/// ```no_run
+/// use cpulimiter::{ControlServer, CpuLimit, Pid};
+///
+/// let pid = Pid::from(1048);
+/// let limiter = CpuLimit::new(pid, 30.0).unwrap();
+/// let server = ControlServer::bind("/run/cpulimit.sock", move || {
+///     vec![(pid, limiter.clone())]
+/// })
+/// .unwrap();
+/// ```
+pub struct ControlServer {
+    path: PathBuf,
+    shutdown: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl ControlServer {
+    /// Listens on `path`, serving the limiters returned by `limiters` at each command.
+    ///
+    /// A socket left behind by a previous server is replaced. Fails with
+    /// [`Error::ControlSocket`] if another server is listening on `path`, or if
+    /// the socket can't be created.
+    pub fn bind<F>(path: impl Into<PathBuf>, limiters: F) -> Result<Self>
+    where
+        F: Fn() -> Vec<(Pid, CpuLimit)> + Send + Sync + 'static,
+    {
+        let path = path.into();
+        let listener = listen(&path).map_err(Error::ControlSocket)?;
+
+        let shutdown = Arc::new(AtomicBool::new(false));
+        let shutdown_clone = shutdown.clone();
+        let limiters: Arc<Limiters> = Arc::new(limiters);
+        let thread = thread::Builder::new().spawn(move || {
+            let mut delay = ACCEPT_DELAY;
+            for stream in listener.incoming() {
+                if shutdown_clone.load(Ordering::Relaxed) {
+                    return;
+                }
+                let stream = match stream {
+                    Ok(stream) => stream,
+                    Err(err) => {
+                        // out of file descriptors for instance, which retrying at once won't fix
+                        warn!(error = %err, "couldn't accept a client on the control socket");
+                        thread::sleep(delay);
+                        delay = (delay * 2).min(MAX_ACCEPT_DELAY);
+                        continue;
+                    }
+                };
+                delay = ACCEPT_DELAY;
+                let limiters = limiters.clone();
+                // a slow client must not hold the others up
+                let _ = thread::Builder::new().spawn(move || serve(stream, &*limiters));
+            }
+        });
+        let thread = match thread {
+            Ok(thread) => thread,
+            Err(err) => {
+                let _ = fs::remove_file(&path);
+                return Err(Error::Spawn(err));
+            }
+        };
+
+        Ok(Self {
+            path,
+            shutdown,
+            thread: Some(thread),
+        })
+    }
+
+    /// Retrieves the path of the socket.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for ControlServer {
+    fn drop(&mut self) {
+        self.shutdown.store(true, Ordering::Relaxed);
+        // wake the thread up, blocked until a client connects
+        let _ = UnixStream::connect(&self.path);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// Creates the socket at `path`, only accessible to the owner.
+fn listen(path: &Path) -> io::Result<UnixListener> {
+    if path.exists() {
+        if UnixStream::connect(path).is_ok() {
+            return Err(io::Error::new(
+                io::ErrorKind::AddrInUse,
+                "another server is listening on the socket",
+            ));
+        }
+        // left behind by a server that didn't exit cleanly
+        fs::remove_file(path)?;
+    }
+
+    let listener = UnixListener::bind(path)?;
+    if let Err(err) = fs::set_permissions(path, Permissions::from_mode(0o600)) {
+        let _ = fs::remove_file(path);
+        return Err(err);
+    }
+    Ok(listener)
+}
+
+/// Answers the commands of a client until it disconnects.
+fn serve(stream: UnixStream, limiters: &Limiters) {
+    let Ok(mut writer) = stream.try_clone() else {
+        return;
+    };
+    for line in BufReader::new(stream).lines() {
+        let Ok(line) = line else {
+            return;
+        };
+        let response = handle(&line, limiters).unwrap_or_else(|err| error(&err));
+        if writeln!(writer, "{response}").is_err() {
+            return;
+        }
+    }
+}
+
+/// Runs the command on `line`, returning the JSON response.
+fn handle(line: &str, limiters: &Limiters) -> std::result::Result<String, String> {
+    let mut words = line.split_whitespace();
+    let command = words.next().unwrap_or_default();
+    let mut pid = || -> std::result::Result<Pid, String> {
+        let pid = words.next().ok_or("missing PID")?;
+        pid.parse().map_err(|_| format!("invalid PID {pid}"))
+    };
+
+    match command.to_ascii_uppercase().as_str() {
+        "LIST" => {
+            let mut json = String::from(r#"{"limiters":["#);
+            let running = limiters()
+                .into_iter()
+                .filter(|(_, limiter)| limiter.is_running());
+            for (i, (pid, limiter)) in running.enumerate() {
+                if i > 0 {
+                    json.push(',');
+                }
+                let _ = write!(
+                    json,
+                    r#"{{"pid":{pid},"limit":{},"usage":{}}}"#,
+                    number(limiter.stats().limit),
+                    number(limiter.cpu_usage_percent_single_core())
+                );
+            }
+            json.push_str("]}");
+            Ok(json)
+        }
+        "SET" => {
+            let limiter = find(limiters, pid()?)?;
+            let limit = words.next().ok_or("missing limit")?;
+            let limit: f64 = limit
+                .parse()
+                .map_err(|_| format!("invalid limit {limit}"))?;
+            limiter.set_limit(limit).map_err(|err| err.to_string())?;
+            Ok(r#"{"ok":true}"#.to_string())
+        }
+        "STOP" => {
+            find(limiters, pid()?)?
+                .stop()
+                .map_err(|err| err.to_string())?;
+            Ok(r#"{"ok":true}"#.to_string())
+        }
+        "STATS" => {
+            let pid = pid()?;
+            let limiter = find(limiters, pid)?;
+            let stats = limiter.stats();
+            Ok(format!(
+                r#"{{"pid":{pid},"limit":{},"usage":{},"working_rate":{},"children":{},"throttle_cycles":{},"cpu_time":{}}}"#,
+                number(stats.limit),
+                number(limiter.cpu_usage_percent_single_core()),
+                number(stats.working_rate),
+                stats.children,
+                stats.throttle_cycles,
+                number(limiter.total_cpu_time().as_secs_f64())
+            ))
+        }
+        "" => Err("missing command".to_string()),
+        _ => Err(format!("unknown command {command}")),
+    }
+}
+
+/// Retrieves the running limiter of `pid`.
+fn find(limiters: &Limiters, pid: Pid) -> std::result::Result<CpuLimit, String> {
+    limiters()
+        .into_iter()
+        .find(|(limited, limiter)| *limited == pid && limiter.is_running())
+        .map(|(_, limiter)| limiter)
+        .ok_or_else(|| format!("no limiter for the process {pid}"))
+}
+
+/// Writes `value` as a JSON number, or `null` if it isn't finite.
+fn number(value: f64) -> String {
+    if value.is_finite() {
+        value.to_string()
+    } else {
+        "null".to_string()
+    }
+}
+
+/// Writes the JSON response reporting `message`.
+fn error(message: &str) -> String {
+    let mut json = String::from(r#"{"error":""#);
+    for c in message.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            c if c.is_control() => {
+                let _ = write!(json, "\\u{:04x}", c as u32);
+            }
+            c => json.push(c),
+        }
+    }
+    json.push_str("\"}");
+    json
+}
+
+#[cfg(test)]
+mod test {
+    use std::process::Command;
+
+    use super::*;
+
+    #[test]
+    fn protocol() {
+        let mut child = Command::new("sleep").arg("10").spawn().unwrap();
+        let pid = Pid::from(child.id());
+        let limiter = CpuLimit::new(pid, 30_f64).unwrap();
+
+        let path = std::env::temp_dir().join(format!("cpulimit-test-{}.sock", std::process::id()));
+        let server = ControlServer::bind(&path, move || vec![(pid, limiter.clone())]).unwrap();
+        assert!(ControlServer::bind(&path, Vec::new).is_err());
+
+        let stream = UnixStream::connect(server.path()).unwrap();
+        let mut writer = stream.try_clone().unwrap();
+        let mut lines = BufReader::new(stream).lines();
+        let mut request = |command: &str| {
+            writeln!(writer, "{command}").unwrap();
+            lines.next().unwrap().unwrap()
+        };
+
+        assert!(request("LIST").starts_with(&format!(r#"{{"limiters":[{{"pid":{pid},"limit":30,"#)));
+        assert_eq!(request(&format!("SET {pid} 50")), r#"{"ok":true}"#);
+        // the group reports the new limit from the next slice on
+        thread::sleep(crate::limiter::SLICE_DURATION * 2);
+        assert!(request(&format!("STATS {pid}")).contains(r#""limit":50,"#));
+        assert_eq!(
+            request("SET 1 50"),
+            r#"{"error":"no limiter for the process 1"}"#
+        );
+        assert_eq!(request("JUMP"), r#"{"error":"unknown command JUMP"}"#);
+        assert_eq!(request(&format!("STOP {pid}")), r#"{"ok":true}"#);
+
+        drop(server);
+        assert!(!path.exists());
+        child.kill().unwrap();
+        child.wait().unwrap();
+    }
+
+    #[test]
+    fn non_finite() {
+        assert_eq!(number(12.5), "12.5");
+        assert_eq!(number(f64::NAN), "null");
+        assert_eq!(number(f64::INFINITY), "null");
+    }
+}
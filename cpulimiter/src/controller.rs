@@ -0,0 +1,137 @@
+//! Compute the working rate of a group from its CPU usage.
+//!
+//! By default, the limiter corrects the working rate by the ratio between the
+//! limit and the CPU usage at each slice, which oscillates with bursty
+//! workloads. A PID controller reacts to the error between the two instead,
+//! with an integral term holding the limit precisely in the steady state.
+
+use std::time::Duration;
+
+/// The most the working rate grows from one slice to the next, as a factor.
+const MAX_STEP: f64 = 2_f64;
+
+/// The CPU usage, in cores, below which a group is considered idle.
+const IDLE_USAGE: f64 = 1e-3;
+
+/// Corrects the `working_rate` by the ratio between the `target` and the
+/// measured `usage`, in cores: the default control law.
+///
+/// An idle group is unconstrained, but the rate grows at most by [`MAX_STEP`]
+/// per slice: a group that was idle for a few slices only doesn't run for
+/// whole slices as soon as it is active again, and the correction never
+/// divides by zero.
+pub(crate) fn correct_rate(working_rate: f64, target: f64, usage: f64) -> f64 {
+    let step = if usage < IDLE_USAGE {
+        MAX_STEP
+    } else {
+        f64::min(target / usage, MAX_STEP)
+    };
+    f64::min(working_rate * step, 1_f64)
+}
+
+/// The gains of a PID controller, the error being measured in cores.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct PidGains {
+    /// The proportional gain, reacting to the current error.
+    pub kp: f64,
+    /// The integral gain, per second, cancelling the error in the steady state.
+    pub ki: f64,
+    /// The derivative gain, in seconds, damping fast changes of the CPU usage.
+    pub kd: f64,
+}
+
+impl Default for PidGains {
+    fn default() -> Self {
+        Self {
+            kp: 0.5,
+            ki: 1.5,
+            kd: 0.0,
+        }
+    }
+}
+
+/// A PID controller turning the CPU usage of a group into its working rate.
+pub(crate) struct PidController {
+    gains: PidGains,
+    integral: f64,
+    /// The CPU usage at the previous update, if any.
+    previous: Option<f64>,
+}
+
+impl PidController {
+    pub fn new(gains: PidGains) -> Self {
+        Self {
+            gains,
+            integral: 0_f64,
+            previous: None,
+        }
+    }
+
+    /// Computes the working rate, between 0 and 1, given the `target` and the
+    /// measured `usage` in cores, `elapsed` after the previous update.
+    ///
+    /// The integral never grows beyond what saturates the working rate (anti-windup).
+    pub fn update(&mut self, target: f64, usage: f64, elapsed: Duration) -> f64 {
+        let elapsed = elapsed.as_secs_f64();
+        let error = target - usage;
+        let proportional = self.gains.kp * error;
+        // on the measurement rather than the error, not to kick when the limit changes
+        let derivative = match self.previous.replace(usage) {
+            Some(previous) if elapsed > 0_f64 => -self.gains.kd * (usage - previous) / elapsed,
+            _ => 0_f64,
+        };
+
+        self.integral += self.gains.ki * error * elapsed;
+        let output = proportional + self.integral + derivative;
+        let working_rate = output.clamp(0_f64, 1_f64);
+        if working_rate != output {
+            // saturated: keep the integral just enough to stay so
+            self.integral = working_rate - proportional - derivative;
+        }
+        working_rate
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn bounded_correction() {
+        assert_eq!(correct_rate(0.1, 0.3, 0_f64), 0.2);
+        assert_eq!(correct_rate(0.8, 0.3, 0_f64), 1_f64);
+        assert_eq!(correct_rate(0.1, 0.3, 0.01), 0.2);
+        assert_eq!(correct_rate(0.5, 0.25, 0.5), 0.25);
+
+        // idle for a few slices, then active again
+        let mut rate = 0.3;
+        for _ in 0..2 {
+            rate = correct_rate(rate, 0.3, 0_f64);
+        }
+        assert_eq!(rate, 1_f64);
+        rate = correct_rate(rate, 0.3, 1_f64);
+        assert_eq!(rate, 0.3);
+    }
+
+    #[test]
+    fn converges() {
+        let slice = Duration::from_millis(100);
+        let mut controller = PidController::new(PidGains::default());
+        // a process using a whole core when running, measured as the group does
+        let mut usage = 0_f64;
+        let mut rate = 1_f64;
+        for _ in 0..200 {
+            usage = 0.8 * usage + 0.2 * rate;
+            rate = controller.update(0.3, usage, slice);
+        }
+        assert!((usage - 0.3).abs() < 0.01);
+        assert!((rate - 0.3).abs() < 0.01);
+
+        // saturated for a long time, without winding up
+        for _ in 0..200 {
+            rate = controller.update(0.3, 0.0, slice);
+        }
+        assert_eq!(rate, 1_f64);
+        assert!(controller.update(0.3, 0.6, slice) < 1_f64);
+    }
+}
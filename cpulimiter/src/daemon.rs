@@ -0,0 +1,409 @@
+//! Limit every process matching a set of rules, as processes come and go.
+//!
+//! The daemon scans `/proc` periodically, starts a [`CpuLimit`] for each new
+//! process matching one of the rules, and forgets the limiters of the processes
+//! which exited or no longer match.
+//!
+//! With the `serde` feature, the rules and the limits of the processes can be
+//! saved, so that a new daemon resumes where the previous one stopped.
+
+use std::collections::{HashMap, HashSet};
+#[cfg(feature = "serde")]
+use std::fs;
+#[cfg(feature = "serde")]
+use std::path::Path;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use parking_lot::Mutex;
+
+use crate::error::{Error, Result};
+use crate::event::Observers;
+use crate::limiter::CpuLimit;
+use crate::pid::Pid;
+use crate::process_group::ChildrenMode;
+use crate::target::Target;
+use crate::trace::Trace;
+
+/// The default interval between two scans.
+const SCAN_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Limits each process designated by `matcher` to `limit`, separately.
+#[derive(Clone, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Rule {
+    pub matcher: Target,
+    pub limit: f64,
+    pub children: ChildrenMode,
+}
+
+/// A notable event in the lifecycle of a daemon.
+#[derive(Clone, Debug)]
+pub enum DaemonEvent {
+    /// A matching process is now limited.
+    Attached(Pid),
+    /// A process is no longer limited: it exited, no longer matches, or the rules changed.
+    Detached(Pid),
+    /// A matching process couldn't be limited, it is not retried while it matches.
+    Failed(Pid, Arc<Error>),
+}
+
+impl Trace for DaemonEvent {
+    #[cfg(feature = "tracing")]
+    fn trace(&self) {
+        match self {
+            DaemonEvent::Attached(pid) => {
+                tracing::debug!(pid = %pid, "limiting a matching process");
+            }
+            DaemonEvent::Detached(pid) => {
+                tracing::debug!(pid = %pid, "no longer limiting a process");
+            }
+            DaemonEvent::Failed(pid, err) => {
+                tracing::warn!(pid = %pid, error = %err, "couldn't limit a matching process");
+            }
+        }
+    }
+}
+
+/// The limiters started by the daemon, and what they were started for.
+#[derive(Default)]
+struct State {
+    rules: Vec<Rule>,
+    limiters: HashMap<Pid, CpuLimit>,
+    failed: HashSet<Pid>,
+    /// The limits of the processes limited by a previous daemon, along with
+    /// their start time, applied again once they match.
+    restored: HashMap<Pid, (u64, f64)>,
+}
+
+/// The state of a daemon, as saved by [`LimiterDaemon::save_state`].
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct SavedState {
+    rules: Vec<Rule>,
+    targets: Vec<SavedTarget>,
+}
+
+/// A process limited by a daemon.
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct SavedTarget {
+    pid: Pid,
+    /// Tells the process apart from a new one with the same PID.
+    start_time: u64,
+    limit: f64,
+}
+
+/// A handle to a thread limiting every process matching a set of rules.
+///
+/// When several rules match a process, the first one applies. The limiters are
+/// stopped when the daemon is stopped or dropped.
+pub struct LimiterDaemon {
+    state: Arc<Mutex<State>>,
+    observers: Observers<DaemonEvent>,
+    interval: Duration,
+    /// Dropped to stop the scanning thread.
+    stop: Mutex<Option<Sender<()>>>,
+    thread: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl LimiterDaemon {
+    /// Creates a daemon applying `rules`, call [`start`](Self::start) to run it.
+    pub fn new(rules: Vec<Rule>) -> Self {
+        Self {
+            state: Arc::new(Mutex::new(State {
+                rules,
+                ..State::default()
+            })),
+            observers: Observers::default(),
+            interval: SCAN_INTERVAL,
+            stop: Mutex::new(None),
+            thread: Mutex::new(None),
+        }
+    }
+
+    /// Creates a daemon from the state saved at `path` by [`save_state`](Self::save_state).
+    ///
+    /// The processes limited before are limited again with the limits they had,
+    /// as long as they still match the rules. Fails with [`Error::State`] if
+    /// the file can't be read or parsed.
+    #[cfg(feature = "serde")]
+    pub fn load_state(path: impl AsRef<Path>) -> Result<Self> {
+        let json = fs::read(path).map_err(Error::State)?;
+        let saved: SavedState =
+            serde_json::from_slice(&json).map_err(|err| Error::State(err.into()))?;
+
+        let daemon = Self::new(saved.rules);
+        daemon.state.lock().restored = saved
+            .targets
+            .into_iter()
+            .map(|target| (target.pid, (target.start_time, target.limit)))
+            .collect();
+        Ok(daemon)
+    }
+
+    /// Saves the rules and the limit of each process to `path`, as JSON.
+    ///
+    /// The file is replaced atomically, so that it is never left half-written.
+    /// Fails with [`Error::State`] if it can't be written.
+    #[cfg(feature = "serde")]
+    pub fn save_state(&self, path: impl AsRef<Path>) -> Result<()> {
+        let saved = {
+            let state = self.state.lock();
+            let limited = state
+                .limiters
+                .iter()
+                .filter(|(_, limiter)| limiter.is_running())
+                .filter_map(|(&pid, limiter)| {
+                    Some(SavedTarget {
+                        pid,
+                        start_time: pid.get_start_time()?,
+                        limit: limiter.stats().limit,
+                    })
+                });
+            // restored processes not matched yet
+            let pending = state
+                .restored
+                .iter()
+                .map(|(&pid, &(start_time, limit))| SavedTarget {
+                    pid,
+                    start_time,
+                    limit,
+                });
+            SavedState {
+                rules: state.rules.clone(),
+                targets: limited.chain(pending).collect(),
+            }
+        };
+
+        let json = serde_json::to_vec_pretty(&saved).map_err(|err| Error::State(err.into()))?;
+        let path = path.as_ref();
+        let temporary = path.with_extension("tmp");
+        fs::write(&temporary, json)
+            .and_then(|()| fs::rename(&temporary, path))
+            .map_err(Error::State)
+    }
+
+    /// Sets the interval between two scans of the processes, 1 second by default.
+    pub fn interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Starts the scanning thread, does nothing if it is already running.
+    pub fn start(&self) -> Result<()> {
+        let mut thread = self.thread.lock();
+        if thread.is_some() {
+            return Ok(());
+        }
+
+        let (tx, rx) = mpsc::channel();
+        let state = self.state.clone();
+        let observers = self.observers.clone();
+        let interval = self.interval;
+        *thread = Some(
+            thread::Builder::new().spawn(move || daemon_fn(&state, &observers, &rx, interval))?,
+        );
+        *self.stop.lock() = Some(tx);
+        Ok(())
+    }
+
+    /// Stops the scanning thread along with every limiter.
+    ///
+    /// Returns once the limited processes are resumed.
+    pub fn stop(&self) {
+        self.stop.lock().take();
+        if let Some(thread) = self.thread.lock().take() {
+            let _ = thread.join();
+        }
+    }
+
+    /// Replaces the rules, every process is limited again according to the new ones.
+    pub fn set_rules(&self, rules: Vec<Rule>) {
+        let mut state = self.state.lock();
+        state.rules = rules;
+        state.failed.clear();
+        detach_all(&mut state, &self.observers);
+    }
+
+    /// Lists the running limiters along with the process each one limits.
+    pub fn limiters(&self) -> Vec<(Pid, CpuLimit)> {
+        let state = self.state.lock();
+        state
+            .limiters
+            .iter()
+            .map(|(&pid, limiter)| (pid, limiter.clone()))
+            .collect()
+    }
+
+    /// Subscribes to the events of the daemon.
+    ///
+    /// The receiver is disconnected once the daemon is dropped.
+    pub fn events(&self) -> Receiver<DaemonEvent> {
+        self.observers.subscribe()
+    }
+}
+
+impl Drop for LimiterDaemon {
+    fn drop(&mut self) {
+        self.stop();
+        self.observers.close();
+    }
+}
+
+/// Scans the processes every `interval`, until `stop` hangs up.
+fn daemon_fn(
+    state: &Mutex<State>,
+    observers: &Observers<DaemonEvent>,
+    stop: &Receiver<()>,
+    interval: Duration,
+) {
+    loop {
+        scan(&mut state.lock(), observers);
+        match stop.recv_timeout(interval) {
+            Err(RecvTimeoutError::Timeout) => {}
+            _ => break,
+        }
+    }
+
+    detach_all(&mut state.lock(), observers);
+}
+
+/// Limits the new matching processes, and forgets the ones which exited or no longer match.
+fn scan(state: &mut State, observers: &Observers<DaemonEvent>) {
+    let State {
+        rules,
+        limiters,
+        failed,
+        restored,
+    } = state;
+
+    // the first rule matching a process applies
+    let mut matching: HashMap<Pid, &Rule> = HashMap::new();
+    for rule in rules.iter() {
+        for pid in rule.matcher.resolve().unwrap_or_default() {
+            matching.entry(pid).or_insert(rule);
+        }
+    }
+    failed.retain(|pid| matching.contains_key(pid));
+    restored.retain(|pid, _| matching.contains_key(pid));
+
+    limiters.retain(|pid, limiter| {
+        let running = limiter.is_running() && matching.contains_key(pid);
+        if !running {
+            // fails if the limiter already stopped on its own
+            let _ = limiter.stop();
+            observers.notify(DaemonEvent::Detached(*pid));
+        }
+        running
+    });
+
+    for (pid, rule) in matching {
+        if limiters.contains_key(&pid) || failed.contains(&pid) {
+            continue;
+        }
+
+        // unless the PID was reused meanwhile
+        let limit = match restored.remove(&pid) {
+            Some((start_time, limit)) if pid.get_start_time() == Some(start_time) => limit,
+            _ => rule.limit,
+        };
+        let limiter = CpuLimit::builder(limit)
+            .target(pid)
+            .children(rule.children)
+            .build();
+        match limiter {
+            Ok(limiter) => {
+                limiters.insert(pid, limiter);
+                observers.notify(DaemonEvent::Attached(pid));
+            }
+            Err(err) => {
+                failed.insert(pid);
+                observers.notify(DaemonEvent::Failed(pid, Arc::new(err)));
+            }
+        }
+    }
+}
+
+/// Stops every limiter.
+fn detach_all(state: &mut State, observers: &Observers<DaemonEvent>) {
+    for (pid, limiter) in state.limiters.drain() {
+        let _ = limiter.stop();
+        observers.notify(DaemonEvent::Detached(pid));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::process::Command;
+
+    use super::*;
+
+    #[test]
+    fn attach_and_detach() {
+        let mut child = Command::new("sleep").arg("10").spawn().unwrap();
+        let pid = Pid::from(child.id());
+
+        let daemon = LimiterDaemon::new(vec![Rule {
+            matcher: Target::Pid(pid),
+            limit: 50_f64,
+            children: ChildrenMode::Exclude,
+        }])
+        .interval(Duration::from_millis(100));
+        let events = daemon.events();
+        daemon.start().unwrap();
+
+        assert!(matches!(
+            events.recv_timeout(Duration::from_secs(5)),
+            Ok(DaemonEvent::Attached(attached)) if attached == pid
+        ));
+        assert_eq!(daemon.limiters().len(), 1);
+
+        child.kill().unwrap();
+        child.wait().unwrap();
+        assert!(matches!(
+            events.recv_timeout(Duration::from_secs(5)),
+            Ok(DaemonEvent::Detached(detached)) if detached == pid
+        ));
+        assert!(daemon.limiters().is_empty());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn save_and_restore() {
+        let mut child = Command::new("sleep").arg("10").spawn().unwrap();
+        let pid = Pid::from(child.id());
+        let path = std::env::temp_dir().join(format!("cpulimit-test-{}.json", std::process::id()));
+
+        let daemon = LimiterDaemon::new(vec![Rule {
+            matcher: Target::Pid(pid),
+            limit: 50_f64,
+            children: ChildrenMode::Exclude,
+        }])
+        .interval(Duration::from_millis(100));
+        let events = daemon.events();
+        daemon.start().unwrap();
+        assert!(events.recv_timeout(Duration::from_secs(5)).is_ok());
+        daemon.limiters()[0].1.set_limit(20_f64).unwrap();
+        // the group reports the new limit from the next slice on
+        thread::sleep(Duration::from_millis(200));
+        daemon.save_state(&path).unwrap();
+        drop(daemon);
+
+        // the process keeps the limit it had, rather than the one of the rule
+        let daemon = LimiterDaemon::load_state(&path)
+            .unwrap()
+            .interval(Duration::from_millis(100));
+        let events = daemon.events();
+        daemon.start().unwrap();
+        assert!(events.recv_timeout(Duration::from_secs(5)).is_ok());
+        assert_eq!(daemon.limiters()[0].1.stats().limit, 20_f64);
+
+        drop(daemon);
+        fs::remove_file(&path).unwrap();
+        child.kill().unwrap();
+        child.wait().unwrap();
+    }
+}
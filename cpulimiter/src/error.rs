@@ -1,15 +1,91 @@
 use thiserror::Error;
 
+#[cfg(target_os = "linux")]
 use crate::limiter::Command;
+use crate::pid::Pid;
+#[cfg(unix)]
+use crate::pid::Signal;
+#[cfg(any(target_os = "macos", target_os = "freebsd"))]
+use crate::portable::Command;
 
 #[derive(Error, Debug)]
 pub enum Error {
     #[error("The target process is dead")]
     DeadTarget,
+    #[error("No target process was given")]
+    NoTarget,
+    #[error("The limit {0}% is not positive or exceeds the available cores")]
+    InvalidLimit(f64),
+    #[error("The I/O limit must be a positive number of bytes per second")]
+    InvalidIoLimit,
+    #[error("The target process {0} exited and its PID was reused")]
+    PidReused(Pid),
+    /// Neither zombies nor kernel threads respond to `SIGSTOP`.
+    #[cfg(target_os = "linux")]
+    #[error("The target process {0} is a zombie or a kernel thread, which can't be throttled")]
+    UnthrottleableTarget(Pid),
+    #[cfg(target_os = "linux")]
+    #[error("Couldn't parse the stat file of the process {0}")]
+    InvalidStat(Pid, #[source] std::io::Error),
     #[error("Couldn't spawn the limiting thread")]
     Spawn(#[from] std::io::Error),
+    #[cfg(windows)]
+    #[error("A call to the Windows API failed")]
+    Windows(#[source] std::io::Error),
+    #[error("Couldn't spawn the command")]
+    Command(#[source] std::io::Error),
+    #[error("Unsupported combination of options: {0}")]
+    Unsupported(&'static str),
+    #[error("Couldn't set up the cgroup")]
+    Cgroup(#[source] std::io::Error),
+    #[cfg(all(target_os = "linux", feature = "serde"))]
+    #[error("Couldn't save or restore the state of the daemon")]
+    State(#[source] std::io::Error),
+    #[cfg(target_os = "linux")]
+    #[error("Couldn't set up the control socket")]
+    ControlSocket(#[source] std::io::Error),
+    #[cfg(target_os = "linux")]
+    #[error("Couldn't record the targets for recovery")]
+    Recovery(#[source] std::io::Error),
+    #[cfg(target_os = "linux")]
+    #[error("Couldn't switch to the user the limiter runs as")]
+    Privileges(#[source] std::io::Error),
+    #[error("Couldn't change the scheduling priority")]
+    Priority(#[source] std::io::Error),
+    #[error("Couldn't change the CPU affinity")]
+    Affinity(#[source] std::io::Error),
+    /// `errno` tells whether the process is gone (`ESRCH`) or belongs to
+    /// someone else (`EPERM`).
+    #[cfg(unix)]
+    #[error("Couldn't send {signal:?} to the process {pid}: {}", std::io::Error::from_raw_os_error(*errno))]
+    SignalFailed {
+        pid: Pid,
+        signal: Signal,
+        errno: i32,
+    },
+    #[cfg(unix)]
+    #[error("Not allowed to signal the process {0}")]
+    PermissionDenied(Pid),
+    #[error("The limiting thread panicked")]
+    Panicked,
+    #[error("The limiting thread was already joined")]
+    Joined,
+    #[cfg(unix)]
     #[error("Couldn't send command to the limiting thread")]
     Send(#[from] std::sync::mpsc::SendError<Command>),
+    #[error("The limiting thread exited before handling the command")]
+    Unacknowledged,
 }
 
 pub type Result<T> = core::result::Result<T, Error>;
+
+/// Checks that `limit` is a positive percentage, at most `max`.
+///
+/// Fails with [`Error::InvalidLimit`] otherwise, NaN included.
+pub(crate) fn check_limit(limit: f64, max: f64) -> Result<()> {
+    if limit > 0_f64 && limit <= max {
+        Ok(())
+    } else {
+        Err(Error::InvalidLimit(limit))
+    }
+}
@@ -0,0 +1,125 @@
+//! Report what happens in the limiting thread.
+
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::Arc;
+use std::time::Duration;
+
+use parking_lot::Mutex;
+
+use crate::pid::Pid;
+use crate::trace::Trace;
+
+/// A notable event in the lifecycle of a limiter.
+#[derive(Clone, PartialEq, Debug)]
+pub enum Event {
+    /// The limit was changed to the given percentage.
+    LimitChanged(f64),
+    /// A child process was added to the group.
+    ChildAttached(Pid),
+    /// A child process left the group.
+    ChildDetached(Pid),
+    /// The group was allowed to run for `work`, then suspended for `sleep`.
+    ThrottleCycle { work: Duration, sleep: Duration },
+    /// Children joined the group faster than the [`ForkGuard`](crate::ForkGuard)
+    /// allows: the group is suspended until [`CpuLimit::resume`](crate::CpuLimit::resume).
+    ForkBombDetected { children: usize },
+    /// The resident memory of the group, in bytes, exceeds the
+    /// [`MemoryGuard`](crate::MemoryGuard) threshold.
+    MemoryExceeded { rss: u64 },
+    /// The target exited and was replaced by the given process, now limited
+    /// instead, see [`CpuLimitBuilder::follow`](crate::CpuLimitBuilder::follow).
+    Reattached(Pid),
+    /// The limit can't be enforced: the CPU usage of the group, in cores, stayed
+    /// above it although the group was suspended, or a process couldn't be signaled.
+    ///
+    /// Processes in uninterruptible sleep or kernel threads ignore `SIGSTOP`. Only
+    /// sent when enabled with [`CpuLimitBuilder::enforcement_alert`](crate::CpuLimitBuilder::enforcement_alert).
+    EnforcementFailure { usage: f64 },
+    /// The CPU usage of the group, in cores, exceeded the limit by more than
+    /// the tolerance for the whole duration given to
+    /// [`CpuLimitBuilder::on_violation`](crate::CpuLimitBuilder::on_violation), although it was throttled.
+    LimitViolated { usage: f64 },
+    /// The CPU time budget was consumed, see [`BudgetAction`](crate::BudgetAction).
+    BudgetExhausted,
+    /// The duration given to [`CpuLimitBuilder::for_duration`](crate::CpuLimitBuilder::for_duration)
+    /// elapsed: the processes were resumed and the limiter stopped.
+    DeadlineReached,
+    /// A target process exited and left the group, which keeps being limited
+    /// while other targets remain. Followed by [`Event::TargetExited`] otherwise.
+    TargetDied(Pid),
+    /// The target process(es) exited, the limiter stopped.
+    TargetExited,
+    /// The limiter was stopped on request.
+    Stopped,
+}
+
+impl Trace for Event {
+    #[cfg(feature = "tracing")]
+    fn trace(&self) {
+        match self {
+            Event::LimitChanged(limit) => tracing::info!(limit, "limit changed"),
+            Event::ChildAttached(pid) => tracing::debug!(pid = %pid, "child attached"),
+            Event::ChildDetached(pid) => tracing::debug!(pid = %pid, "child detached"),
+            Event::ThrottleCycle { work, sleep } => {
+                tracing::trace!(?work, ?sleep, "throttle cycle")
+            }
+            Event::ForkBombDetected { children } => {
+                tracing::warn!(children, "fork bomb detected, suspending the group");
+            }
+            Event::MemoryExceeded { rss } => tracing::warn!(rss, "resident memory exceeded"),
+            Event::Reattached(pid) => {
+                tracing::info!(pid = %pid, "reattached to a restarted target")
+            }
+            Event::EnforcementFailure { usage } => {
+                tracing::warn!(usage, "the limit can't be enforced")
+            }
+            Event::LimitViolated { usage } => tracing::warn!(usage, "limit violated"),
+            Event::BudgetExhausted => tracing::info!("CPU time budget exhausted"),
+            Event::DeadlineReached => tracing::info!("deadline reached, limit lifted"),
+            Event::TargetDied(pid) => tracing::debug!(pid = %pid, "target died"),
+            Event::TargetExited => tracing::debug!("target exited"),
+            Event::Stopped => tracing::debug!("stopped"),
+        }
+    }
+}
+
+/// The subscribers to the events of a limiter.
+///
+/// Subscribers are disconnected once the limiter stops, so that receivers do not block forever.
+pub(crate) struct Observers<E = Event>(Arc<Mutex<Option<Vec<Sender<E>>>>>);
+
+impl<E> Clone for Observers<E> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+impl<E> Default for Observers<E> {
+    fn default() -> Self {
+        Self(Arc::new(Mutex::new(Some(Vec::new()))))
+    }
+}
+
+impl<E: Clone + Trace> Observers<E> {
+    /// Registers a new subscriber.
+    pub fn subscribe(&self) -> Receiver<E> {
+        let (tx, rx) = mpsc::channel();
+        if let Some(subscribers) = self.0.lock().as_mut() {
+            subscribers.push(tx);
+        }
+        rx
+    }
+
+    /// Traces `event` and sends it to every subscriber, forgetting the ones that hung up.
+    pub fn notify(&self, event: E) {
+        event.trace();
+        if let Some(subscribers) = self.0.lock().as_mut() {
+            subscribers.retain(|subscriber| subscriber.send(event.clone()).is_ok());
+        }
+    }
+
+    /// Disconnects all the subscribers, current and future.
+    pub fn close(&self) {
+        self.0.lock().take();
+    }
+}
@@ -0,0 +1,149 @@
+//! Keep limiting a target restarted under a new PID, by a supervisor for instance.
+
+use std::time::{Duration, Instant};
+
+use crate::error::{Error, Result};
+use crate::pid::Pid;
+use crate::target::Target;
+
+/// How the process replacing a target which exited is recognized.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum FollowMode {
+    /// It runs the same executable as the target.
+    Exe,
+    /// It has the same command name as the target.
+    Name,
+}
+
+/// Looks for the process replacing the followed target once it exited.
+pub(crate) struct Follower {
+    matcher: Target,
+    timeout: Duration,
+    /// The followed process, never its own replacement.
+    pid: Pid,
+    /// The start time of the followed process: its replacement starts later.
+    start_time: u64,
+    /// When the followed process was found dead, if it is.
+    lost: Option<Instant>,
+}
+
+impl Follower {
+    /// Follows `pid`, waiting up to `timeout` for a replacement once it exited.
+    ///
+    /// Fails with [`Error::DeadTarget`] if the process can't be read.
+    pub fn new(pid: Pid, mode: FollowMode, timeout: Duration) -> Result<Self> {
+        let matcher = match mode {
+            FollowMode::Exe => pid.get_exe().map(Target::Exe),
+            FollowMode::Name => pid.get_name().map(Target::Name),
+        };
+        let (Some(matcher), Some(start_time)) = (matcher, pid.get_start_time()) else {
+            return Err(Error::DeadTarget);
+        };
+
+        Ok(Self {
+            matcher,
+            timeout,
+            pid,
+            start_time,
+            lost: None,
+        })
+    }
+
+    /// Looks for the replacement of the followed process, which exited: the
+    /// oldest matching process started after it, or in the same clock tick
+    /// with a greater PID. Zombies are left out.
+    ///
+    /// Returns `None` while waiting for it, and fails with [`Error::DeadTarget`]
+    /// once the timeout elapsed. The replacement is followed from then on.
+    pub fn find(&mut self) -> Result<Option<Pid>> {
+        let lost = *self.lost.get_or_insert_with(Instant::now);
+        let replacement = self
+            .matcher
+            .resolve()
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|&pid| pid != self.pid && !pid.is_zombie())
+            .filter_map(|pid| Some((pid.get_start_time()?, pid)))
+            .filter(|&candidate| candidate > (self.start_time, self.pid))
+            .min();
+
+        match replacement {
+            Some((start_time, pid)) => {
+                self.pid = pid;
+                self.start_time = start_time;
+                self.lost = None;
+                Ok(Some(pid))
+            }
+            None if lost.elapsed() >= self.timeout => Err(Error::DeadTarget),
+            None => Ok(None),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+    use std::path::{Path, PathBuf};
+    use std::process::{Child, Command};
+    use std::thread;
+
+    use crate::{CpuLimit, Event};
+
+    use super::*;
+
+    /// Kills the spawned processes and removes the executable, even if the test fails.
+    struct Cleanup {
+        exe: PathBuf,
+        children: Vec<Child>,
+    }
+
+    impl Drop for Cleanup {
+        fn drop(&mut self) {
+            for child in &mut self.children {
+                let _ = child.kill();
+                let _ = child.wait();
+            }
+            let _ = fs::remove_file(&self.exe);
+        }
+    }
+
+    /// Spawns `exe`, and waits for it to run under the name of the executable.
+    fn spawn(exe: &Path) -> Child {
+        let child = Command::new(exe).arg("10").spawn().unwrap();
+        let name = exe.file_name().unwrap().to_str().unwrap();
+        let pid = Pid::from(child.id());
+        while pid.get_name().as_deref() != Some(name) {
+            thread::sleep(Duration::from_millis(10));
+        }
+        child
+    }
+
+    #[test]
+    fn reattach() {
+        // `sleep` under a command name no other test uses
+        let exe = std::env::temp_dir().join(format!("cplf{}", std::process::id()));
+        let _ = fs::remove_file(&exe);
+        std::os::unix::fs::symlink("/bin/sleep", &exe).unwrap();
+        let mut cleanup = Cleanup {
+            exe: exe.clone(),
+            children: vec![spawn(&exe)],
+        };
+
+        let limiter = CpuLimit::builder(50_f64)
+            .target(Pid::from(cleanup.children[0].id()))
+            .follow(FollowMode::Name, Duration::from_secs(5))
+            .build()
+            .unwrap();
+        let events = limiter.events();
+
+        cleanup.children[0].kill().unwrap();
+        cleanup.children[0].wait().unwrap();
+        cleanup.children.push(spawn(&exe));
+        let restarted = Pid::from(cleanup.children[1].id());
+        let reattached = events
+            .iter()
+            .find(|event| matches!(event, Event::Reattached(_) | Event::TargetExited));
+        assert_eq!(reattached, Some(Event::Reattached(restarted)));
+        assert!(limiter.is_running());
+    }
+}
@@ -0,0 +1,79 @@
+//! Limit a process for the duration of a scope.
+
+use std::ops::Deref;
+
+use crate::limiter::CpuLimit;
+use crate::pid::{Pid, Signal};
+
+/// Limits a process until it is dropped, see [`CpuLimit::scoped`].
+///
+/// Unlike a [`CpuLimit`], which keeps limiting while any of its clones is
+/// alive, the limiter is stopped when the guard is dropped, and `SIGCONT` is
+/// sent to the process last: it is never left suspended, even if someone
+/// else stopped it meanwhile.
+///
+/// # Example
+///
+/// ```no_run
+/// use cpulimiter::{CpuLimit, Pid};
+///
+/// {
+///     let _guard = CpuLimit::scoped(Pid::from(1048), 30.0).unwrap();
+///     // limited to 30% until the end of the scope
+/// }
+/// // running freely again
+/// ```
+#[must_use = "the process is no longer limited once the guard is dropped"]
+pub struct LimitGuard {
+    pid: Pid,
+    limiter: CpuLimit,
+}
+
+impl LimitGuard {
+    pub(crate) fn new(pid: Pid, limiter: CpuLimit) -> Self {
+        Self { pid, limiter }
+    }
+}
+
+impl Deref for LimitGuard {
+    type Target = CpuLimit;
+
+    fn deref(&self) -> &CpuLimit {
+        &self.limiter
+    }
+}
+
+impl Drop for LimitGuard {
+    fn drop(&mut self) {
+        // fails if the limiter already stopped on its own
+        let _ = self.limiter.stop();
+        // so that it can't signal the process after the `SIGCONT`
+        let _ = self.limiter.join();
+        let _ = self.pid.kill(&Signal::SIGCONT);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::process::Command;
+
+    use super::*;
+
+    #[test]
+    fn scoped() {
+        let mut child = Command::new("sleep").arg("10").spawn().unwrap();
+        let pid = Pid::from(child.id());
+
+        let limiter = {
+            let guard = CpuLimit::scoped(pid, 10_f64).unwrap();
+            assert!(guard.is_running());
+            // a clone doesn't keep the limiter running past the scope
+            guard.clone()
+        };
+        assert!(!limiter.is_running());
+        assert!(!pid.is_stopped());
+
+        child.kill().unwrap();
+        child.wait().unwrap();
+    }
+}
@@ -0,0 +1,93 @@
+//! Skip the suspension of the processes holding resources others wait for.
+//!
+//! Stopping a process which holds a lock, an X11 grab or a database file
+//! stalls every process waiting for it, the limiter's owner included. An
+//! [`InhibitCheck`] is asked before each suspension, and the process keeps
+//! running through the slice when it says so.
+
+use std::fmt;
+use std::fs;
+use std::path::PathBuf;
+
+use crate::pid::Pid;
+use crate::procfs;
+
+/// Decides whether a process must be left running rather than suspended.
+///
+/// Closures taking a [`Pid`] and returning a `bool` implement it.
+///
+/// # Example
+///
+/// ```no_run
+/// use std::sync::Arc;
+/// use cpulimiter::{CpuLimit, OpenFile, Pid};
+///
+/// // never stop the process while it has the database open
+/// let handle = CpuLimit::builder(25.0)
+///     .target(Pid::from(1048))
+///     .inhibit(Arc::new(OpenFile::new("/var/lib/app/db.sqlite")))
+///     .build()
+///     .unwrap();
+/// ```
+pub trait InhibitCheck: Send + Sync {
+    /// Indicates whether `pid` must not be suspended this time.
+    ///
+    /// Called before each suspension: it should be cheap.
+    fn inhibits(&self, pid: Pid) -> bool;
+}
+
+impl fmt::Debug for dyn InhibitCheck {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("InhibitCheck")
+    }
+}
+
+impl<F: Fn(Pid) -> bool + Send + Sync> InhibitCheck for F {
+    fn inhibits(&self, pid: Pid) -> bool {
+        self(pid)
+    }
+}
+
+/// Inhibits the suspension of the processes with a file descriptor open on
+/// a path, or on a file under it if it is a directory.
+///
+/// The path is compared to the targets of the links in `/proc/<pid>/fd`, as is:
+/// it should be absolute and free of symbolic links.
+#[derive(Clone, Debug)]
+pub struct OpenFile {
+    path: PathBuf,
+}
+
+impl OpenFile {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+}
+
+impl InhibitCheck for OpenFile {
+    fn inhibits(&self, pid: Pid) -> bool {
+        let Ok(fds) = fs::read_dir(procfs::path(format!("{pid}/fd"))) else {
+            return false;
+        };
+        fds.filter_map(|fd| fs::read_link(fd.ok()?.path()).ok())
+            .any(|target| target.starts_with(&self.path))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn open_file() {
+        let current = Pid::from(std::process::id());
+        let path = std::env::temp_dir().join(format!("cpulimit-inhibit-{current}"));
+        let file = fs::File::create(&path).unwrap();
+        assert!(OpenFile::new(&path).inhibits(current));
+        assert!(OpenFile::new(std::env::temp_dir()).inhibits(current));
+
+        drop(file);
+        assert!(!OpenFile::new(&path).inhibits(current));
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,245 @@
+//! Limit the disk I/O of a group of processes.
+//!
+//! The bytes each process read from and wrote to storage are read from
+//! `/proc/<pid>/io` at every slice. A group exceeding its budget is suspended
+//! with `SIGSTOP` until it paid back what it used in excess, then resumed with
+//! `SIGCONT`, as the CPU limiter does.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use parking_lot::{Condvar, Mutex};
+
+use crate::error::{Error, Result};
+use crate::limiter::SLICE_DURATION;
+use crate::pid::Pid;
+use crate::process_group::{ChildrenMode, ProcessGroup};
+use crate::target::Target;
+
+/// The I/O budget of a group, in bytes per second.
+pub(crate) struct IoBudget {
+    limit: u64,
+    /// The bytes used beyond the budget, to be paid back while suspended.
+    debt: f64,
+    /// The I/O counter of each member at the previous update.
+    counters: HashMap<Pid, u64>,
+    /// The bytes per second measured at the previous update.
+    rate: f64,
+}
+
+impl IoBudget {
+    /// Fails with [`Error::InvalidIoLimit`] if `limit` is zero.
+    pub fn new(limit: u64) -> Result<Self> {
+        if limit == 0 {
+            return Err(Error::InvalidIoLimit);
+        }
+        Ok(Self {
+            limit,
+            debt: 0_f64,
+            counters: HashMap::new(),
+            rate: 0_f64,
+        })
+    }
+
+    /// Fails with [`Error::InvalidIoLimit`] if `limit` is zero.
+    pub fn set_limit(&mut self, limit: u64) -> Result<()> {
+        if limit == 0 {
+            return Err(Error::InvalidIoLimit);
+        }
+        self.limit = limit;
+        Ok(())
+    }
+
+    /// Accounts for the I/O of `members` during the `elapsed` time, and
+    /// indicates whether the group must be suspended.
+    ///
+    /// A new member only counts from its first update on, and the I/O of the
+    /// members that exited meanwhile is lost.
+    pub fn update(&mut self, members: impl Iterator<Item = Pid>, elapsed: Duration) -> bool {
+        let mut bytes = 0_u64;
+        let mut counters = HashMap::new();
+        for pid in members {
+            let Some(counter) = pid.get_io_bytes() else {
+                continue;
+            };
+            let previous = self.counters.get(&pid).copied().unwrap_or(counter);
+            bytes = bytes.saturating_add(counter.saturating_sub(previous));
+            counters.insert(pid, counter);
+        }
+        self.counters = counters;
+
+        let elapsed = elapsed.as_secs_f64();
+        if elapsed > 0_f64 {
+            self.rate = bytes as f64 / elapsed;
+        }
+        // unused budget isn't saved for later bursts
+        self.debt = f64::max(
+            self.debt + bytes as f64 - self.limit as f64 * elapsed,
+            0_f64,
+        );
+        self.debt > 0_f64
+    }
+
+    /// Retrieves the I/O rate measured at the previous update, in bytes per second.
+    pub fn rate(&self) -> f64 {
+        self.rate
+    }
+}
+
+/// The state shared between the handle and the limiting thread.
+struct State {
+    budget: IoBudget,
+    shutdown: bool,
+}
+
+/// A handle to a thread limiting the disk I/O of a process, in bytes per second.
+///
+/// The process is resumed when the handle is dropped. Combined with a
+/// [`CpuLimit`](crate::CpuLimit) on the same process, prefer
+/// [`CpuLimitBuilder::io_limit`](crate::CpuLimitBuilder::io_limit): both
+/// limiters would resume what the other one suspended.
+///
+/// # Example
+///
+/// ```no_run
+/// use cpulimiter::{IoLimit, Pid};
+///
+/// // 10 MiB/s
+/// let handle = IoLimit::new(Pid::from(1048), 10 << 20).unwrap();
+/// handle.set_limit(1 << 20).unwrap();
+/// ```
+pub struct IoLimit {
+    shared: Arc<(Mutex<State>, Condvar)>,
+    thread: Option<JoinHandle<Result<()>>>,
+}
+
+impl IoLimit {
+    /// Limits the I/O of `pid` to `bytes_per_second`.
+    ///
+    /// Fails with [`Error::InvalidIoLimit`] if `bytes_per_second` is zero, or
+    /// with [`Error::PermissionDenied`] if the process can't be signaled.
+    pub fn new(pid: Pid, bytes_per_second: u64) -> Result<Self> {
+        Self::start(
+            ProcessGroup::new(&[Target::Pid(pid)], ChildrenMode::Exclude)?,
+            bytes_per_second,
+        )
+    }
+
+    /// Limits the I/O of `pid` and its children, sharing `bytes_per_second`.
+    pub fn new_with_children(pid: Pid, bytes_per_second: u64) -> Result<Self> {
+        Self::start(
+            ProcessGroup::new(&[Target::Pid(pid)], ChildrenMode::Include)?,
+            bytes_per_second,
+        )
+    }
+
+    fn start(group: ProcessGroup, bytes_per_second: u64) -> Result<Self> {
+        let budget = IoBudget::new(bytes_per_second)?;
+        group.check_permission()?;
+
+        let shared = Arc::new((
+            Mutex::new(State {
+                budget,
+                shutdown: false,
+            }),
+            Condvar::new(),
+        ));
+        let shared_clone = shared.clone();
+        let thread = thread::Builder::new().spawn(move || io_limiter_fn(group, &shared_clone))?;
+
+        Ok(Self {
+            shared,
+            thread: Some(thread),
+        })
+    }
+
+    /// Updates the limit, in bytes per second.
+    ///
+    /// Fails with [`Error::InvalidIoLimit`] if `bytes_per_second` is zero.
+    pub fn set_limit(&self, bytes_per_second: u64) -> Result<()> {
+        self.shared.0.lock().budget.set_limit(bytes_per_second)
+    }
+
+    /// Retrieves the I/O rate measured during the previous slice, in bytes per second.
+    pub fn io_rate(&self) -> f64 {
+        self.shared.0.lock().budget.rate()
+    }
+
+    /// Indicates whether the limiting thread is still running.
+    ///
+    /// The thread stops once the process exited, or when it can't be signaled anymore.
+    pub fn is_running(&self) -> bool {
+        self.thread
+            .as_ref()
+            .is_some_and(|thread| !thread.is_finished())
+    }
+}
+
+impl Drop for IoLimit {
+    fn drop(&mut self) {
+        let (state, wakeup) = &*self.shared;
+        state.lock().shutdown = true;
+        wakeup.notify_one();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// The limiting function, to be run in a separate thread.
+fn io_limiter_fn(mut group: ProcessGroup, shared: &(Mutex<State>, Condvar)) -> Result<()> {
+    let (state, wakeup) = shared;
+    let mut last_update = Instant::now();
+
+    let mut state = state.lock();
+    let result = loop {
+        wakeup.wait_for(&mut state, SLICE_DURATION);
+        if state.shutdown {
+            break Ok(());
+        }
+
+        match group.update() {
+            Ok(()) => {}
+            Err(Error::DeadTarget) => break Ok(()),
+            Err(err) => break Err(err),
+        }
+        let now = Instant::now();
+        let exceeded = state.budget.update(group.members(), now - last_update);
+        last_update = now;
+
+        let signaled = if exceeded {
+            group.suspend()
+        } else {
+            group.resume()
+        };
+        if let Err(err) = signaled {
+            break Err(err);
+        }
+    };
+
+    let _ = group.resume();
+    result
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn io_budget() {
+        assert!(matches!(IoBudget::new(0), Err(Error::InvalidIoLimit)));
+
+        // the first update of a process is its starting point
+        let mut budget = IoBudget::new(1 << 20).unwrap();
+        let pid = Pid::from(std::process::id());
+        assert!(!budget.update([pid].into_iter(), Duration::ZERO));
+        assert!(budget.counters.contains_key(&pid));
+
+        // 4 MiB at once, paid back in 4 seconds
+        budget.debt = (4 << 20) as f64;
+        assert!(budget.update(std::iter::empty(), Duration::from_secs(3)));
+        assert!(!budget.update(std::iter::empty(), Duration::from_secs(1)));
+    }
+}
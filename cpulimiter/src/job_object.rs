@@ -0,0 +1,203 @@
+//! Limit the CPU usage of a process on Windows with a Job Object.
+//!
+//! The target process is assigned to a job whose CPU rate is hard-capped:
+//! the Windows scheduler enforces the limit, no thread is needed.
+//! Processes created by the target afterwards automatically belong to the job.
+//!
+//! See the documentation of `JOBOBJECT_CPU_RATE_CONTROL_INFORMATION`.
+
+use std::io;
+use std::mem::size_of;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use parking_lot::Mutex;
+use windows_sys::Win32::Foundation::{CloseHandle, HANDLE};
+use windows_sys::Win32::System::JobObjects::{
+    AssignProcessToJobObject, CreateJobObjectW, JobObjectBasicAccountingInformation,
+    JobObjectCpuRateControlInformation, QueryInformationJobObject, SetInformationJobObject,
+    JOBOBJECT_BASIC_ACCOUNTING_INFORMATION, JOBOBJECT_CPU_RATE_CONTROL_INFORMATION,
+    JOBOBJECT_CPU_RATE_CONTROL_INFORMATION_0, JOB_OBJECT_CPU_RATE_CONTROL_ENABLE,
+    JOB_OBJECT_CPU_RATE_CONTROL_HARD_CAP,
+};
+use windows_sys::Win32::System::Threading::{PROCESS_SET_QUOTA, PROCESS_TERMINATE};
+
+use crate::error::{self, Error, Result};
+use crate::Pid;
+
+/// The CPU rate of a job is expressed in 1/10000th of the whole machine.
+const FULL_RATE: f64 = 10_000_f64;
+
+/// An owned Windows handle, closed on drop.
+pub(crate) struct Handle(HANDLE);
+
+// SAFETY: Kernel object handles can be used from any thread.
+unsafe impl Send for Handle {}
+// SAFETY: The job functions used here are thread-safe.
+unsafe impl Sync for Handle {}
+
+impl Handle {
+    /// Takes ownership of `handle`, failing if it is null.
+    pub fn new(handle: HANDLE) -> io::Result<Self> {
+        if handle.is_null() {
+            Err(io::Error::last_os_error())
+        } else {
+            Ok(Self(handle))
+        }
+    }
+
+    /// Retrieves the raw handle.
+    #[inline]
+    pub fn raw(&self) -> HANDLE {
+        self.0
+    }
+}
+
+impl Drop for Handle {
+    fn drop(&mut self) {
+        // SAFETY: The handle is valid and owned.
+        unsafe { CloseHandle(self.0) };
+    }
+}
+
+/// The last measurement of the CPU time used by the job.
+struct Sample {
+    at: Instant,
+    total_time: Duration,
+    cpu_usage: f64,
+}
+
+/// A handle to manage the CPU limit enforced on the target process.
+#[derive(Clone)]
+pub struct CpuLimit {
+    job: Arc<Handle>,
+    sample: Arc<Mutex<Sample>>,
+}
+
+impl CpuLimit {
+    /// Limits the CPU time of the target process and the processes it creates.
+    pub fn new(pid: Pid, limit: f64) -> Result<Self> {
+        // SAFETY: Inherently unsafe as a system call, but null parameters are allowed.
+        let job = Handle::new(unsafe { CreateJobObjectW(std::ptr::null(), std::ptr::null()) })
+            .map_err(Error::Windows)?;
+
+        let process = pid
+            .open(PROCESS_SET_QUOTA | PROCESS_TERMINATE)
+            .map_err(Error::Windows)?;
+        // SAFETY: Inherently unsafe as a system call, but both handles are valid.
+        if unsafe { AssignProcessToJobObject(job.raw(), process.raw()) } == 0 {
+            return Err(Error::Windows(io::Error::last_os_error()));
+        }
+
+        let handle = CpuLimit {
+            job: Arc::new(job),
+            sample: Arc::new(Mutex::new(Sample {
+                at: Instant::now(),
+                total_time: Duration::from_secs(0),
+                cpu_usage: 0_f64,
+            })),
+        };
+        handle.sample.lock().total_time = handle.accounting()?;
+        handle.set_limit(limit)?;
+
+        Ok(handle)
+    }
+
+    /// Limits the CPU time of the target process and its children.
+    ///
+    /// Only the children created after the call are limited.
+    pub fn new_with_children(pid: Pid, limit: f64) -> Result<Self> {
+        Self::new(pid, limit)
+    }
+
+    /// Updates the limit applied to the target process.
+    pub fn set_limit(&self, limit: f64) -> Result<()> {
+        let cores = std::thread::available_parallelism().map_or(1, usize::from);
+        error::check_limit(limit, 100_f64 * cores as f64)?;
+        let rate = (limit / 100_f64 / cores as f64 * FULL_RATE).clamp(1_f64, FULL_RATE);
+
+        self.set_rate_control(
+            JOB_OBJECT_CPU_RATE_CONTROL_ENABLE | JOB_OBJECT_CPU_RATE_CONTROL_HARD_CAP,
+            rate as u32,
+        )
+    }
+
+    /// Lifts the limit.
+    ///
+    /// The process stays in the job, but its CPU rate is not controlled anymore.
+    pub fn stop(&self) -> Result<()> {
+        self.set_rate_control(0, 0)
+    }
+
+    /// Retrieves the CPU usage of the target process(es) since the previous call.
+    ///
+    /// This is the ratio of CPU time used per unit of time: `1.0` means one fully used core.
+    pub fn cpu_usage(&self) -> f64 {
+        let mut sample = self.sample.lock();
+        if let Ok(total_time) = self.accounting() {
+            let elapsed = sample.at.elapsed();
+            if !elapsed.is_zero() {
+                let consumed = total_time.saturating_sub(sample.total_time);
+                sample.cpu_usage = consumed.as_secs_f64() / elapsed.as_secs_f64();
+                sample.at = Instant::now();
+                sample.total_time = total_time;
+            }
+        }
+        sample.cpu_usage
+    }
+
+    /// Retrieves the total amount of CPU time used by the target process(es).
+    pub fn total_cpu_time(&self) -> Duration {
+        self.accounting()
+            .unwrap_or_else(|_| self.sample.lock().total_time)
+    }
+
+    /// Sets the CPU rate control information of the job.
+    fn set_rate_control(&self, flags: u32, rate: u32) -> Result<()> {
+        let info = JOBOBJECT_CPU_RATE_CONTROL_INFORMATION {
+            ControlFlags: flags,
+            Anonymous: JOBOBJECT_CPU_RATE_CONTROL_INFORMATION_0 { CpuRate: rate },
+        };
+
+        // SAFETY: Inherently unsafe as a system call, but the structure matches the class.
+        let res = unsafe {
+            SetInformationJobObject(
+                self.job.raw(),
+                JobObjectCpuRateControlInformation,
+                (&info as *const JOBOBJECT_CPU_RATE_CONTROL_INFORMATION).cast(),
+                size_of::<JOBOBJECT_CPU_RATE_CONTROL_INFORMATION>() as u32,
+            )
+        };
+
+        if res == 0 {
+            Err(Error::Windows(io::Error::last_os_error()))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Retrieves the CPU time used by all the processes of the job.
+    fn accounting(&self) -> Result<Duration> {
+        // SAFETY: The structure only contains integers, it can be zeroed.
+        let mut info: JOBOBJECT_BASIC_ACCOUNTING_INFORMATION = unsafe { std::mem::zeroed() };
+
+        // SAFETY: Inherently unsafe as a system call, but the structure matches the class.
+        let res = unsafe {
+            QueryInformationJobObject(
+                self.job.raw(),
+                JobObjectBasicAccountingInformation,
+                (&mut info as *mut JOBOBJECT_BASIC_ACCOUNTING_INFORMATION).cast(),
+                size_of::<JOBOBJECT_BASIC_ACCOUNTING_INFORMATION>() as u32,
+                std::ptr::null_mut(),
+            )
+        };
+
+        if res == 0 {
+            return Err(Error::Windows(io::Error::last_os_error()));
+        }
+
+        // times are expressed in 100 ns intervals
+        let ticks = info.TotalUserTime + info.TotalKernelTime;
+        Ok(Duration::from_nanos(ticks as u64 * 100))
+    }
+}
@@ -9,13 +9,157 @@
 //! handle.set_limit(42.0);
 //! handle.stop();
 //! ```
+//!
+//! # Platforms
+//!
+//! Linux is fully supported. On macOS and FreeBSD, only the basic
+//! [`CpuLimit`] API is available. On Windows, the same API is implemented with a Job Object
+//! instead of signals.
 
+#[cfg(target_os = "linux")]
+mod affinity;
+#[cfg(all(target_os = "linux", feature = "async"))]
+mod async_limiter;
+#[cfg(target_os = "linux")]
+mod backend;
+#[cfg(target_os = "linux")]
+mod builder;
+#[cfg(target_os = "linux")]
+mod cgroup;
+#[cfg(target_os = "linux")]
+mod clock;
+#[cfg(target_os = "linux")]
+mod control;
+#[cfg(target_os = "linux")]
+mod controller;
+#[cfg(target_os = "linux")]
+mod daemon;
 mod error;
+#[cfg(target_os = "linux")]
+mod event;
+#[cfg(target_os = "linux")]
+mod follow;
+#[cfg(target_os = "linux")]
+mod guard;
+#[cfg(target_os = "linux")]
+mod inhibit;
+#[cfg(target_os = "linux")]
+mod io_limit;
+#[cfg(windows)]
+mod job_object;
+#[cfg(target_os = "linux")]
+mod limit_tree;
+#[cfg(target_os = "linux")]
 mod limiter;
+#[cfg(all(target_os = "linux", feature = "metrics"))]
+mod metrics;
 mod pid;
+#[cfg(target_os = "linux")]
+mod pidfd;
+#[cfg(target_os = "linux")]
+mod pool;
+#[cfg(any(target_os = "macos", target_os = "freebsd"))]
+mod portable;
+#[cfg(target_os = "linux")]
+mod priority;
+#[cfg(target_os = "linux")]
+mod privileges;
+#[cfg(target_os = "linux")]
+mod proc_events;
+#[cfg(target_os = "linux")]
 mod process_group;
+#[cfg(target_os = "linux")]
+mod process_info;
+#[cfg(any(target_os = "linux", target_os = "macos", target_os = "freebsd"))]
 mod process_iterator;
+#[cfg(target_os = "linux")]
+mod procfs;
+#[cfg(target_os = "linux")]
+mod recovery;
+#[cfg(target_os = "linux")]
+mod rescue;
+#[cfg(target_os = "linux")]
+mod schedule;
+#[cfg(target_os = "linux")]
+mod spawn;
+#[cfg(any(target_os = "linux", target_os = "freebsd"))]
 mod stat_iterator;
+#[cfg(target_os = "linux")]
+pub mod system;
+#[cfg(target_os = "linux")]
+mod system_limit;
+#[cfg(target_os = "linux")]
+mod target;
+#[cfg(target_os = "linux")]
+mod throttler;
+#[cfg(target_os = "linux")]
+mod timeline;
+#[cfg(target_os = "linux")]
+mod trace;
 
-pub use limiter::CpuLimit;
+#[cfg(all(target_os = "linux", feature = "async"))]
+pub use async_limiter::AsyncCpuLimit;
+#[cfg(target_os = "linux")]
+pub use backend::{MockBackend, ProcessBackend, Procfs};
+#[cfg(target_os = "linux")]
+pub use builder::CpuLimitBuilder;
+#[cfg(target_os = "linux")]
+pub use clock::SystemClock;
+#[cfg(target_os = "linux")]
+pub use control::ControlServer;
+#[cfg(target_os = "linux")]
+pub use controller::PidGains;
+#[cfg(target_os = "linux")]
+pub use daemon::{DaemonEvent, LimiterDaemon, Rule};
+pub use error::Error;
+#[cfg(target_os = "linux")]
+pub use event::Event;
+#[cfg(target_os = "linux")]
+pub use follow::FollowMode;
+#[cfg(target_os = "linux")]
+pub use guard::LimitGuard;
+#[cfg(target_os = "linux")]
+pub use inhibit::{InhibitCheck, OpenFile};
+#[cfg(target_os = "linux")]
+pub use io_limit::IoLimit;
+#[cfg(windows)]
+pub use job_object::CpuLimit;
+#[cfg(target_os = "linux")]
+pub use limit_tree::{LimitTree, LimitTreeHandle};
+#[cfg(target_os = "linux")]
+pub use limiter::{
+    AdaptiveLimit, Backend, BudgetAction, CpuLimit, ExitReason, GroupPolicy, LimitMode,
+    RelativeLimit, RunOptions, TokenBucket, ViolationPolicy,
+};
 pub use pid::Pid;
+#[cfg(unix)]
+pub use pid::Signal;
+#[cfg(target_os = "linux")]
+pub use pool::{LimiterPool, PoolMember};
+#[cfg(any(target_os = "macos", target_os = "freebsd"))]
+pub use portable::CpuLimit;
+#[cfg(target_os = "linux")]
+pub use privileges::drop_privileges;
+#[cfg(target_os = "linux")]
+pub use process_group::{
+    ChildrenMode, DutyCycle, ForkGuard, GroupStats, MemoryAction, MemoryGuard, ProcessGroup,
+    ProcessStats, StopSignal, StoppedPolicy, SuspendMode,
+};
+#[cfg(target_os = "linux")]
+pub use process_info::ProcessInfo;
+#[cfg(target_os = "linux")]
+pub use process_iterator::ProcessIterator;
+#[cfg(target_os = "linux")]
+pub use procfs::ProcfsRoot;
+#[cfg(target_os = "linux")]
+pub use recovery::recover;
+#[cfg(target_os = "linux")]
+pub use schedule::{TimeOfDay, TimeRange};
+#[cfg(target_os = "linux")]
+pub use system_limit::SystemLimit;
+#[cfg(target_os = "linux")]
+pub use target::Target;
+#[cfg(target_os = "linux")]
+pub use throttler::{SignalThrottler, Throttler};
+#[cfg(target_os = "linux")]
+pub use timeline::{Timeline, TimelineSample};
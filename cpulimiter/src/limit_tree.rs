@@ -0,0 +1,343 @@
+//! Share a limit between nested groups of processes, according to weights.
+//!
+//! Every group of the tree is throttled by its own [`CpuLimit`], whose limit is
+//! adjusted periodically: the limit of a node is divided between its children
+//! in proportion to their weights, and the share a child doesn't use goes to
+//! its siblings (max-min fairness). A child using its whole share is thus
+//! guaranteed it, whatever the others do, and the tree never exceeds its limit.
+
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use parking_lot::{Condvar, Mutex};
+
+use crate::builder::CpuLimitBuilder;
+use crate::error::{Error, Result};
+use crate::limiter::{CpuLimit, LimitMode};
+
+/// How often the limits of the groups are adjusted.
+const REBALANCE_INTERVAL: Duration = Duration::from_millis(500);
+
+/// The fraction of its limit a group must use to be considered wanting more.
+const SATURATION: f64 = 0.9;
+
+/// How much more than its usage a group that doesn't use its limit is given, to grow.
+const HEADROOM: f64 = 1.2;
+
+/// The smallest limit given to a group, as a percentage.
+const MIN_LIMIT: f64 = 0.5;
+
+/// A node of a [`LimitTree`].
+enum Node {
+    Group(Box<CpuLimitBuilder>),
+    Tree(LimitTree),
+}
+
+/// A limit shared between weighted groups of processes, and nested trees.
+///
+/// Limits are percentages of one core, as with [`LimitMode::PerCore`].
+///
+/// # Example
+///
+/// ```no_run
+/// use cpulimiter::{ChildrenMode, CpuLimit, LimitTree, Pid};
+///
+/// // the service gets at least 3/4 of 50%, its helpers at least 1/4
+/// let tree = LimitTree::new(50.0)
+///     .group(3.0, CpuLimit::builder(50.0).target(Pid::from(1048)))
+///     .group(
+///         1.0,
+///         CpuLimit::builder(50.0)
+///             .targets(&[Pid::from(1051), Pid::from(1052)])
+///             .children(ChildrenMode::Include),
+///     )
+///     .build()
+///     .unwrap();
+/// ```
+pub struct LimitTree {
+    limit: f64,
+    children: Vec<(f64, Node)>,
+}
+
+impl LimitTree {
+    /// Creates a tree sharing `limit` between its children.
+    ///
+    /// Nested in another tree, `limit` caps what the tree gets from its parent.
+    pub fn new(limit: f64) -> Self {
+        Self {
+            limit,
+            children: Vec::new(),
+        }
+    }
+
+    /// Adds a group of processes, configured by `builder`, with the given `weight`.
+    ///
+    /// The limit and the mode of the builder are overridden by the tree.
+    #[must_use]
+    pub fn group(mut self, weight: f64, builder: CpuLimitBuilder) -> Self {
+        self.children.push((weight, Node::Group(Box::new(builder))));
+        self
+    }
+
+    /// Adds a nested tree with the given `weight`.
+    #[must_use]
+    pub fn subtree(mut self, weight: f64, tree: LimitTree) -> Self {
+        self.children.push((weight, Node::Tree(tree)));
+        self
+    }
+
+    /// Starts limiting every group of the tree.
+    ///
+    /// Fails with [`Error::InvalidLimit`] if a limit isn't positive or exceeds
+    /// the cores online, or with [`Error::Unsupported`] if a weight isn't
+    /// positive. The groups already started are stopped on failure.
+    pub fn build(self) -> Result<LimitTreeHandle> {
+        let limit = self.limit;
+        let root = self.start(limit)?;
+
+        let shared: Arc<(Mutex<State>, Condvar)> = Arc::new((
+            Mutex::new(State {
+                root,
+                shutdown: false,
+            }),
+            Condvar::new(),
+        ));
+        let shared_clone = shared.clone();
+        let thread = thread::Builder::new().spawn(move || rebalance_fn(&shared_clone))?;
+
+        Ok(LimitTreeHandle {
+            shared,
+            thread: Some(thread),
+        })
+    }
+
+    /// Starts the groups of the tree, sharing `available` by weight.
+    fn start(self, available: f64) -> Result<Running> {
+        LimitMode::PerCore.check(self.limit)?;
+        if self
+            .children
+            .iter()
+            .any(|(weight, _)| !weight.is_finite() || *weight <= 0_f64)
+        {
+            return Err(Error::Unsupported(
+                "the weights of a limit tree must be positive",
+            ));
+        }
+
+        // nothing is known about the usage of the groups yet
+        let wanted: Vec<(f64, f64)> = self
+            .children
+            .iter()
+            .map(|(weight, _)| (*weight, f64::INFINITY))
+            .collect();
+        let shares = share(f64::min(available, self.limit), &wanted);
+
+        let mut children = Vec::new();
+        for ((weight, node), share) in self.children.into_iter().zip(shares) {
+            let limit = f64::max(share, MIN_LIMIT);
+            let running = match node {
+                Node::Group(builder) => Running::Group {
+                    limiter: builder.mode(LimitMode::PerCore).with_limit(limit).build()?,
+                    limit,
+                },
+                Node::Tree(tree) => tree.start(limit)?,
+            };
+            children.push((weight, running));
+        }
+
+        Ok(Running::Tree {
+            limit: self.limit,
+            children,
+        })
+    }
+}
+
+/// A node of a running tree.
+enum Running {
+    Group {
+        limiter: CpuLimit,
+        limit: f64,
+    },
+    Tree {
+        limit: f64,
+        children: Vec<(f64, Running)>,
+    },
+}
+
+impl Running {
+    /// Estimates how much of a core the node would use, as a percentage.
+    ///
+    /// A group using most of its limit could use more: it wants everything.
+    fn wanted(&self) -> f64 {
+        match self {
+            Running::Group { limiter, limit } => {
+                if !limiter.is_running() {
+                    return 0_f64;
+                }
+                let usage = limiter.cpu_usage() * 100_f64;
+                if usage >= *limit * SATURATION {
+                    f64::INFINITY
+                } else {
+                    usage * HEADROOM
+                }
+            }
+            Running::Tree { limit, children } => f64::min(
+                *limit,
+                children.iter().map(|(_, child)| child.wanted()).sum(),
+            ),
+        }
+    }
+
+    /// Shares `available` between the groups of the node, by weight and by use.
+    fn rebalance(&mut self, available: f64) {
+        match self {
+            Running::Group { limiter, limit } => {
+                let new_limit = f64::max(available, MIN_LIMIT);
+                // each update waits for the limiting thread, skip the negligible ones
+                if (new_limit - *limit).abs() >= MIN_LIMIT && limiter.set_limit(new_limit).is_ok() {
+                    *limit = new_limit;
+                }
+            }
+            Running::Tree { limit, children } => {
+                let wanted: Vec<(f64, f64)> = children
+                    .iter()
+                    .map(|(weight, child)| (*weight, child.wanted()))
+                    .collect();
+                let shares = share(f64::min(available, *limit), &wanted);
+                for ((_, child), share) in children.iter_mut().zip(shares) {
+                    child.rebalance(share);
+                }
+            }
+        }
+    }
+
+    /// Lists the limiters of the groups, depth first.
+    fn limiters(&self, limiters: &mut Vec<CpuLimit>) {
+        match self {
+            Running::Group { limiter, .. } => limiters.push(limiter.clone()),
+            Running::Tree { children, .. } => {
+                for (_, child) in children {
+                    child.limiters(limiters);
+                }
+            }
+        }
+    }
+}
+
+/// Shares `available` between children given as `(weight, wanted)`, by max-min fairness.
+///
+/// Each child gets a share proportional to its weight, but no more than it
+/// wants: the rest is shared again between the others.
+fn share(available: f64, children: &[(f64, f64)]) -> Vec<f64> {
+    let mut shares = vec![0_f64; children.len()];
+    let mut remaining = available;
+    let mut unsatisfied: Vec<usize> = (0..children.len()).collect();
+
+    while !unsatisfied.is_empty() {
+        let total_weight: f64 = unsatisfied.iter().map(|&i| children[i].0).sum();
+        let (satisfied, others): (Vec<usize>, Vec<usize>) = unsatisfied
+            .iter()
+            .partition(|&&i| children[i].1 <= remaining * children[i].0 / total_weight);
+
+        if satisfied.is_empty() {
+            for i in others {
+                shares[i] = remaining * children[i].0 / total_weight;
+            }
+            break;
+        }
+        for i in satisfied {
+            shares[i] = children[i].1;
+            remaining -= children[i].1;
+        }
+        unsatisfied = others;
+    }
+    shares
+}
+
+/// The state shared between the handle and the rebalancing thread.
+struct State {
+    root: Running,
+    shutdown: bool,
+}
+
+/// A handle to a running [`LimitTree`].
+///
+/// The groups are stopped, thus resumed, when the handle is dropped, unless
+/// clones of their limiters are kept.
+pub struct LimitTreeHandle {
+    shared: Arc<(Mutex<State>, Condvar)>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl LimitTreeHandle {
+    /// Updates the limit shared by the whole tree, from the next rebalancing on.
+    ///
+    /// Fails with [`Error::InvalidLimit`] unless `limit` is positive and within the cores online.
+    pub fn set_limit(&self, limit: f64) -> Result<()> {
+        LimitMode::PerCore.check(limit)?;
+        if let Running::Tree { limit: root, .. } = &mut self.shared.0.lock().root {
+            *root = limit;
+        }
+        Ok(())
+    }
+
+    /// Retrieves the limiters of the groups, depth first in the order they were added.
+    pub fn limiters(&self) -> Vec<CpuLimit> {
+        let mut limiters = Vec::new();
+        self.shared.0.lock().root.limiters(&mut limiters);
+        limiters
+    }
+}
+
+impl Drop for LimitTreeHandle {
+    fn drop(&mut self) {
+        let (state, wakeup) = &*self.shared;
+        state.lock().shutdown = true;
+        wakeup.notify_one();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// The rebalancing function, to be run in a separate thread.
+fn rebalance_fn(shared: &(Mutex<State>, Condvar)) {
+    let (state, wakeup) = shared;
+    let mut state = state.lock();
+    loop {
+        wakeup.wait_for(&mut state, REBALANCE_INTERVAL);
+        if state.shutdown {
+            return;
+        }
+        // the root is a tree, capped by its own limit
+        state.root.rebalance(f64::INFINITY);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::share;
+
+    #[test]
+    fn max_min_fairness() {
+        // everyone wants more: shared by weight
+        let shares = share(60_f64, &[(1_f64, f64::INFINITY), (2_f64, f64::INFINITY)]);
+        assert_eq!(shares, vec![20_f64, 40_f64]);
+
+        // what the first one doesn't use goes to the others
+        let shares = share(
+            60_f64,
+            &[
+                (2_f64, 10_f64),
+                (1_f64, f64::INFINITY),
+                (1_f64, f64::INFINITY),
+            ],
+        );
+        assert_eq!(shares, vec![10_f64, 25_f64, 25_f64]);
+
+        // nobody wants everything
+        let shares = share(60_f64, &[(1_f64, 5_f64), (1_f64, 10_f64)]);
+        assert_eq!(shares, vec![5_f64, 10_f64]);
+    }
+}
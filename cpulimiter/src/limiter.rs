@@ -1,110 +1,1518 @@
-use std::sync::mpsc::{self, Receiver, SyncSender};
+use std::collections::HashMap;
+use std::io;
+use std::path::PathBuf;
+use std::process::{self, Child};
+use std::sync::mpsc::{self, Receiver, SendError, SyncSender};
 use std::sync::Arc;
-use std::thread;
-use std::time::Duration;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
 
-use parking_lot::RwLock;
+use parking_lot::{Mutex, RwLock};
 
-use crate::error::Result;
-use crate::process_group::{ChildrenMode, ProcessGroup};
-use crate::Pid;
+use crate::affinity::Pinned;
+use crate::builder::CpuLimitBuilder;
+use crate::cgroup::Cgroup;
+use crate::controller::{self, PidController, PidGains};
+use crate::error::{self, Error, Result};
+use crate::event::{Event, Observers};
+use crate::follow::Follower;
+use crate::guard::LimitGuard;
+use crate::io_limit::IoBudget;
+#[cfg(feature = "metrics")]
+use crate::metrics;
+use crate::pid::{Pid, Signal};
+use crate::priority::Deprioritized;
+use crate::proc_events::ProcEventListener;
+use crate::process_group::{ChildrenMode, DutyCycle, GroupStats, ProcessGroup};
+use crate::rescue;
+use crate::schedule::{ActiveLimit, TimeRange};
+use crate::system::{self, SystemUsage};
+use crate::target::Target;
+use crate::throttler::{SignalThrottler, Throttler};
+use crate::timeline::Timeline;
+use crate::trace::{debug, info_span};
 
-/// The granularity of the control slice.
+/// The default granularity of the control slice.
 ///
-/// The monitoring thread will wake up every `SLICE_DURATION` to compute
+/// The monitoring thread will wake up every slice to compute
 /// the length of the next work slice for the monitored process(es).
 pub const SLICE_DURATION: Duration = Duration::from_millis(100);
 
+/// The working rate at or below which the group is suspended for whole slices.
+const SATURATED_RATE: f64 = 0.01;
+
+/// The target, in cores, below which a limit following other processes never
+/// falls: a working rate of zero would never grow again.
+const MIN_FOLLOWED_TARGET: f64 = 0.01;
+
+/// How much the CPU usage may exceed the target while the group is suspended,
+/// as a factor, before the slice counts as failing to enforce the limit.
+const ENFORCEMENT_TOLERANCE: f64 = 1.1;
+
 /// Messages sent to the limiting thread to change its behavior.
 pub enum Command {
     Limit(f64),
+    Ramp(f64, Duration),
+    SetSchedule(Vec<(TimeRange, f64)>),
+    SetChildrenMode(ChildrenMode),
+    Pause,
+    Resume,
     Stop,
 }
 
-/// A handle to manage the CPU limit enforced on the target process.
+/// A command along with the channel to acknowledge it on, once handled.
+type Request = (Command, SyncSender<Result<()>>);
+
+/// The parameters of a limiting thread.
+#[derive(Clone, Copy)]
+pub(crate) struct Settings {
+    pub limit: f64,
+    pub mode: LimitMode,
+    pub backend: Backend,
+    pub slice: Duration,
+    /// Whether the group is kept suspended until [`CpuLimit::resume`] is called.
+    pub paused: bool,
+    /// The CPU time the group may consume, and what happens afterwards.
+    pub budget: Option<(Duration, BudgetAction)>,
+    /// Whether the limit is only enforced when the system is busy.
+    pub adaptive: Option<AdaptiveLimit>,
+    /// The process whose CPU usage bounds that of the group, if any.
+    pub relative: Option<RelativeLimit>,
+    /// The utilization of the whole machine the group may bring it up to, as
+    /// a percentage of all the cores.
+    pub ceiling: Option<f64>,
+    /// The bursts allowed above the limit.
+    pub burst: Option<TokenBucket>,
+    pub policy: GroupPolicy,
+    /// The gains of the controller computing the working rate, if not the default correction.
+    pub controller: Option<PidGains>,
+    /// The disk I/O the group may do, in bytes per second.
+    pub io_limit: Option<u64>,
+    /// The consecutive slices the limit may fail to be enforced for, before an
+    /// [`Event::EnforcementFailure`] is sent.
+    pub enforcement_alert: Option<u32>,
+    /// How far below the limit the usage must fall, as a percentage like the
+    /// limit, before the group stops being throttled.
+    pub hysteresis: Option<f64>,
+    /// The time each process works at least every slice, whatever the limit.
+    pub min_work: Duration,
+    /// How long the limit is enforced for, from the start of the thread.
+    pub duration: Option<Duration>,
+    /// What to do when the group exceeds the limit while throttled, along with
+    /// the excess tolerated, as a percentage of the limit, and for how long.
+    pub violation: Option<(ViolationPolicy, f64, Duration)>,
+}
+
+/// The mechanism used to enforce the limit.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum Backend {
+    /// Suspend and resume the processes with `SIGSTOP` and `SIGCONT`.
+    #[default]
+    Signal,
+    /// Let the kernel throttle the processes with the cgroup v2 `cpu.max` quota.
+    ///
+    /// Requires write access to `/sys/fs/cgroup` (root or delegated cgroups).
+    /// Processes forked by the targets are always limited, whatever the [`ChildrenMode`].
+    CgroupV2,
+    /// Move the processes to the `SCHED_IDLE` scheduling policy, without suspending them.
+    ///
+    /// The limit is not enforced as a rate: the processes only run when the
+    /// CPU would be idle otherwise. Suitable for workloads where stopping the
+    /// processes breaks timers or network connections.
+    Priority,
+    /// Pin the processes to as many cores as the limit allows, rounded up.
+    ///
+    /// A coarse approximation: 50% on an 8-core machine with [`LimitMode::TotalSystem`]
+    /// pins the processes to 4 cores, but a limit below one core isn't enforced.
+    Affinity,
+}
+
+/// What a limit of 100% stands for.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum LimitMode {
+    /// 100% is one fully used core: limits above 100% allow several cores.
+    #[default]
+    PerCore,
+    /// 100% is the whole machine, all cores included.
+    TotalSystem,
+}
+
+impl LimitMode {
+    /// Converts a `limit` percentage to the number of cores it allows.
+    pub(crate) fn cores(self, limit: f64) -> f64 {
+        match self {
+            LimitMode::PerCore => limit / 100_f64,
+            LimitMode::TotalSystem => limit / 100_f64 * system::num_cpus() as f64,
+        }
+    }
+
+    /// Checks that `limit` is positive and doesn't exceed the cores online.
+    ///
+    /// Fails with [`Error::InvalidLimit`] otherwise.
+    pub(crate) fn check(self, limit: f64) -> Result<()> {
+        let max = match self {
+            LimitMode::PerCore => 100_f64 * system::num_cpus() as f64,
+            LimitMode::TotalSystem => 100_f64,
+        };
+        error::check_limit(limit, max)
+    }
+}
+
+/// Only enforce the limit when the rest of the system is busy.
+///
+/// The target runs freely while the other processes use less than `threshold`
+/// percent of the whole machine: the limit only applies under contention.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct AdaptiveLimit {
+    pub threshold: f64,
+}
+
+impl AdaptiveLimit {
+    /// Indicates whether the limit can be relaxed, given the system utilization
+    /// (between 0 and 1) and the CPU usage of the group (in cores).
+    fn relaxed(self, system: f64, group: f64) -> bool {
+        let others = f64::max(system * system::num_cpus() as f64 - group, 0_f64);
+        others / system::num_cpus() as f64 * 100_f64 < self.threshold
+    }
+}
+
+/// Keep the CPU usage of the group at most `factor` times that of another process,
+/// such as background indexing below the consumption of a foreground application.
+///
+/// The limit still applies on top of it. Only the limit remains once the
+/// reference process exits.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct RelativeLimit {
+    /// The process whose CPU usage is followed.
+    pub reference: Pid,
+    pub factor: f64,
+}
+
+/// Let the group burst above the limit, as long as it saved enough CPU time before.
+///
+/// The bucket is full at first, and fills at `refill_rate` while the group
+/// uses less. The group runs freely while the bucket isn't empty, draining
+/// it at the rate of its CPU usage, and is limited again once it is empty.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct TokenBucket {
+    /// The CPU time the group may burst for, counted over all cores.
+    pub capacity: Duration,
+    /// The CPU usage which fills the bucket, as a percentage like the limit,
+    /// and at most the limit: the long-term average of the group.
+    pub refill_rate: f64,
+}
+
+impl TokenBucket {
+    /// Updates the CPU time in the bucket (in seconds), given the CPU usage
+    /// of the group (in cores) during `elapsed`.
+    fn refill(self, mode: LimitMode, tokens: f64, usage: f64, elapsed: Duration) -> f64 {
+        let refilled = tokens + (mode.cores(self.refill_rate) - usage) * elapsed.as_secs_f64();
+        refilled.clamp(0_f64, self.capacity.as_secs_f64())
+    }
+}
+
+/// What to do when the group keeps exceeding the limit although it is throttled,
+/// see [`CpuLimitBuilder::on_violation`].
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum ViolationPolicy {
+    /// Keep throttling the group, without reporting it.
+    #[default]
+    Throttle,
+    /// Send the signal to the processes, such as `SIGTERM` or `SIGKILL`, and
+    /// keep limiting the ones that survive.
+    Kill(Signal),
+    /// Send an [`Event::LimitViolated`] and keep throttling.
+    Notify,
+}
+
+/// What to do once the CPU time budget of the group is consumed.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum BudgetAction {
+    /// Stop limiting: the processes run freely afterwards.
+    Unthrottle,
+    /// Keep the processes suspended, until [`CpuLimit::resume`] or [`CpuLimit::stop`] is called.
+    Suspend,
+    /// Send `SIGTERM` to the processes and stop limiting.
+    Terminate,
+}
+
+/// How the limit applies to the processes of a group.
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub enum GroupPolicy {
+    /// The processes share a single budget.
+    #[default]
+    Shared,
+    /// Each process is also throttled to its own limit, a percentage in the same [`LimitMode`].
+    ///
+    /// The limit of the limiter still applies to the group as a whole.
+    /// Requires the signal backend and [`SuspendMode::PerProcess`](crate::SuspendMode::PerProcess).
+    PerProcess(f64),
+}
+
+/// Why the limiting thread stopped.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ExitReason {
+    /// The limiter was stopped on request.
+    Stopped,
+    /// The target process(es) exited.
+    TargetExited,
+    /// The CPU time budget was consumed, see [`BudgetAction`].
+    BudgetExhausted,
+    /// The duration given to [`CpuLimitBuilder::for_duration`] elapsed.
+    DeadlineReached,
+}
+
+/// A callback receiving a limiter once started.
+type OnStart = Box<dyn FnOnce(&CpuLimit)>;
+
+/// When [`CpuLimit::run_blocking`] returns, besides the target exiting.
+#[derive(Default)]
+pub struct RunOptions {
+    children_mode: ChildrenMode,
+    budget: Option<Duration>,
+    deadline: Option<Duration>,
+    on_start: Option<OnStart>,
+}
+
+impl RunOptions {
+    /// Returns only once the target exits or the limiter is stopped.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets whether the children of the target are limited along with it.
+    #[must_use]
+    pub fn children(mut self, children_mode: ChildrenMode) -> Self {
+        self.children_mode = children_mode;
+        self
+    }
+
+    /// Returns once the target consumed `budget` of CPU time, and lets it run freely.
+    #[must_use]
+    pub fn budget(mut self, budget: Duration) -> Self {
+        self.budget = Some(budget);
+        self
+    }
+
+    /// Returns after `deadline`, and lets the target run freely.
+    #[must_use]
+    pub fn deadline(mut self, deadline: Duration) -> Self {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    /// Calls `on_start` with the limiter once it started, for instance to keep
+    /// a clone to change the limit or [stop](CpuLimit::stop) it from another thread.
+    #[must_use]
+    pub fn on_start(mut self, on_start: impl FnOnce(&CpuLimit) + 'static) -> Self {
+        self.on_start = Some(Box::new(on_start));
+        self
+    }
+}
+
+/// A handle to manage the CPU limit enforced on the target process(es).
+///
+/// The limiter is stopped once the handle and all its clones are dropped.
+/// The processes it suspended are resumed if its thread panics, and if this
+/// process dies of a fatal signal or of a termination signal it doesn't handle.
 #[derive(Clone)]
 pub struct CpuLimit {
-    sender: SyncSender<Command>,
+    shared: Arc<Shared>,
+}
+
+/// The state shared by the clones of a [`CpuLimit`].
+struct Shared {
+    sender: SyncSender<Request>,
+    /// What the limits given to the handle stand for.
+    mode: LimitMode,
     group: Arc<RwLock<ProcessGroup>>,
+    observers: Observers,
+    thread: Mutex<Option<JoinHandle<Result<ExitReason>>>>,
+}
+
+impl Drop for Shared {
+    /// Stops the limiting thread and makes sure no process is left suspended.
+    fn drop(&mut self) {
+        if let Some(thread) = self.thread.get_mut().take() {
+            // fails if the thread already stopped on its own
+            let (ack, _) = mpsc::sync_channel(1);
+            let _ = self.sender.send((Command::Stop, ack));
+            let _ = thread.join();
+        }
+
+        let _ = self.group.read().resume();
+    }
+}
+
+/// Resumes the group if the limiting thread panics, rather than leaving it
+/// suspended for good.
+struct ResumeOnPanic<'a>(&'a RwLock<ProcessGroup>);
+
+impl Drop for ResumeOnPanic<'_> {
+    fn drop(&mut self) {
+        if thread::panicking() {
+            // the locks taken by the thread were released while unwinding
+            let _ = self.0.read().resume();
+        }
+    }
 }
 
 /// The limiting function, to be run in a separate thread.
-fn limiter_fn(limit: f64, group: &Arc<RwLock<ProcessGroup>>, rx: &Receiver<Command>) {
-    let mut limit = limit / 100_f64;
+///
+/// The group is stopped and resumed by the `throttler`. When the target exits,
+/// the `follower` looks for its replacement, if any.
+fn limiter_fn<T: Throttler + ?Sized>(
+    settings: Settings,
+    group: &Arc<RwLock<ProcessGroup>>,
+    rx: &Receiver<Request>,
+    observers: &Observers,
+    throttler: &T,
+    mut follower: Option<Follower>,
+) -> Result<ExitReason> {
+    let _resume = ResumeOnPanic(group);
+    let Settings {
+        mode,
+        slice,
+        mut paused,
+        ..
+    } = settings;
+    let mut limit = ActiveLimit::new(settings.limit);
     let mut working_rate = 1_f64;
+    // the working rate of each process, with a per-process limit
+    let mut member_rates = HashMap::new();
+    let mut controller = settings.controller.map(PidController::new);
+    let mut system = SystemUsage::new();
+    // measures the reference process of a relative limit, until it exits
+    let mut reference = settings.relative.and_then(|relative| {
+        ProcessGroup::new(&[Target::Pid(relative.reference)], ChildrenMode::Exclude).ok()
+    });
+    let mut io_budget = settings.io_limit.map(IoBudget::new).transpose()?;
+    let mut io_update = Instant::now();
+    // the consecutive slices the usage exceeded the limit while suspended
+    let mut unenforced = 0;
+    // since when the usage exceeds the limit beyond the tolerance while throttled
+    let mut violated_since = None;
+    // whether the usage fell below the hysteresis band, the group running freely
+    let mut quiet = false;
+    let started = Instant::now();
+    // the CPU time the group may still burst for, in seconds
+    let mut tokens = settings
+        .burst
+        .map_or(0_f64, |burst| burst.capacity.as_secs_f64());
+    let mut refilled = Instant::now();
+    throttler.apply(mode.cores(limit.get()))?;
+
+    // catch new children as soon as they are forked, when allowed to and real
+    let start_listener = || {
+        let group_read = group.read();
+        (group_read.includes_children() && group_read.uses_procfs())
+            .then(|| ProcEventListener::start(group.clone()).ok())
+            .flatten()
+    };
+    let mut listener = start_listener();
+
+    // acknowledged once everything is cleaned up
+    let mut stop_ack = None;
+    let mut exhausted = false;
 
-    loop {
-        if let Ok(cmd) = rx.try_recv() {
+    let reason = loop {
+        if let Ok((cmd, ack)) = rx.try_recv() {
+            let mut result = Ok(());
             match cmd {
-                Command::Limit(new_limit) => limit = new_limit,
+                Command::Limit(new_limit) => limit.set_base(new_limit),
+                Command::Ramp(new_limit, duration) => limit.ramp_to(new_limit, duration),
+                Command::SetSchedule(schedule) => limit.set_schedule(schedule),
+                Command::SetChildrenMode(children_mode) => {
+                    // stop the listener first, it locks the group
+                    drop(listener.take());
+                    group.write().set_children_mode(children_mode);
+                    listener = start_listener();
+                }
+                Command::Pause => {
+                    paused = true;
+                    result = throttler.suspend(&group.read());
+                }
+                Command::Resume => {
+                    paused = false;
+                    group.write().rearm_guards();
+                }
                 Command::Stop => {
-                    group.read().resume();
-                    break;
+                    stop_ack = Some((ack, throttler.resume(&group.read())));
+                    break Ok(ExitReason::Stopped);
                 }
             }
+            let _ = ack.send(result);
         }
 
-        if group.write().update().is_err() {
-            // bail-out if the target process is dead.
-            break;
+        if settings
+            .duration
+            .is_some_and(|duration| started.elapsed() >= duration)
+        {
+            if let Err(err) = throttler.resume(&group.read()) {
+                break Err(err);
+            }
+            break Ok(ExitReason::DeadlineReached);
+        }
+
+        if let Some(new_limit) = limit.refresh() {
+            group.write().set_limit(new_limit);
+            observers.notify(Event::LimitChanged(new_limit));
+            if let Err(err) = throttler.apply(mode.cores(new_limit)) {
+                break Err(err);
+            }
+        }
+
+        let updated = group.write().update();
+        match updated {
+            Ok(()) => {}
+            // bail-out if the target process is dead, unless it is replaced
+            Err(Error::DeadTarget) => match follower.as_mut().map(Follower::find) {
+                None | Some(Err(_)) => break Ok(ExitReason::TargetExited),
+                Some(Ok(None)) => {
+                    thread::sleep(slice);
+                    continue;
+                }
+                Some(Ok(Some(pid))) => {
+                    let reattached = group.write().reattach(pid);
+                    if let Err(err) = reattached.and_then(|()| group.read().check_permission()) {
+                        break Err(err);
+                    }
+                    observers.notify(Event::Reattached(pid));
+                }
+            },
+            Err(err) => break Err(err),
+        }
+        if group.read().guard_suspended() {
+            // suspended until resumed explicitly
+            paused = true;
+        }
+
+        match enforce_budget(&settings, group, observers, &mut paused, &mut exhausted) {
+            Ok(Some(reason)) => break Ok(reason),
+            Err(err) => break Err(err),
+            Ok(None) => {}
+        }
+
+        // suspended for whole slices while paying back the excess I/O
+        let io_exceeded = io_budget.as_mut().is_some_and(|budget| {
+            let now = Instant::now();
+            let exceeded = budget.update(group.read().members(), now - io_update);
+            io_update = now;
+            exceeded
+        });
+
+        if paused || io_exceeded {
+            // keep suspending the group, new children included
+            if let Err(err) = throttler.suspend(&group.read()) {
+                break Err(err);
+            }
+            quiet = false;
+            thread::sleep(slice);
+            continue;
+        }
+
+        // sampled once per slice, a second sample would cover next to no time
+        let utilization = if settings.adaptive.is_some() || settings.ceiling.is_some() {
+            system.update()
+        } else {
+            0_f64
+        };
+        if let Some(adaptive) = settings.adaptive {
+            if adaptive.relaxed(utilization, group.read().cpu_usage()) {
+                if let Err(err) = throttler.resume(&group.read()) {
+                    break Err(err);
+                }
+                working_rate = 1_f64;
+                group.write().record_cycle(working_rate, false);
+                thread::sleep(slice);
+                continue;
+            }
+        }
+
+        if let Some(burst) = settings.burst {
+            let now = Instant::now();
+            let usage = group.read().cpu_usage();
+            tokens = burst.refill(mode, tokens, usage, now - refilled);
+            refilled = now;
+            if tokens > 0_f64 {
+                if let Err(err) = throttler.resume(&group.read()) {
+                    break Err(err);
+                }
+                working_rate = 1_f64;
+                group.write().record_cycle(working_rate, false);
+                thread::sleep(slice);
+                continue;
+            }
         }
 
         let cpu_usage = group.read().cpu_usage();
-        working_rate *= limit / cpu_usage;
-        working_rate = f64::min(working_rate, 1_f64);
+        let mut target = mode.cores(limit.get());
+        if let (Some(relative), Some(meter)) = (settings.relative, &mut reference) {
+            match meter.update() {
+                Ok(()) => {
+                    let followed = relative.factor * meter.cpu_usage();
+                    target = f64::min(target, f64::max(followed, MIN_FOLLOWED_TARGET));
+                }
+                Err(_) => reference = None,
+            }
+        }
+        if let Some(ceiling) = settings.ceiling {
+            // the group gets what the other processes leave below the ceiling
+            let cores = system::num_cpus() as f64;
+            let others = f64::max(utilization * cores - cpu_usage, 0_f64);
+            let left = LimitMode::TotalSystem.cores(ceiling) - others;
+            target = f64::min(target, f64::max(left, MIN_FOLLOWED_TARGET));
+        }
+        if let Some(margin) = settings.hysteresis {
+            // throttled from the limit up, until the usage falls below the band
+            let was_quiet = quiet;
+            if cpu_usage >= target {
+                quiet = false;
+            } else if cpu_usage < target - mode.cores(margin) {
+                quiet = true;
+            }
+            if quiet {
+                // resumed once, rather than signaled every slice
+                if !was_quiet {
+                    if let Err(err) = throttler.resume(&group.read()) {
+                        break Err(err);
+                    }
+                }
+                working_rate = 1_f64;
+                group.write().record_cycle(working_rate, false);
+                thread::sleep(slice);
+                continue;
+            }
+        }
+        // a process in uninterruptible sleep ignores the signals: its usage
+        // isn't the outcome of the working rate, which is kept as is
+        if !group.read().uninterruptible() {
+            working_rate = match &mut controller {
+                Some(controller) => controller.update(target, cpu_usage, slice),
+                None => controller::correct_rate(working_rate, target, cpu_usage),
+            };
+        }
+        debug!(usage = cpu_usage, cores = target, working_rate, "slice");
+        if let Some(slices) = settings.enforcement_alert {
+            if working_rate <= SATURATED_RATE && cpu_usage > target * ENFORCEMENT_TOLERANCE {
+                unenforced += 1;
+                if unenforced == slices {
+                    observers.notify(Event::EnforcementFailure { usage: cpu_usage });
+                }
+            } else {
+                unenforced = 0;
+            }
+        }
+        if let Some((policy, excess, duration)) = settings.violation {
+            if working_rate < 1_f64 && cpu_usage > target * (1_f64 + excess / 100_f64) {
+                let since = *violated_since.get_or_insert_with(Instant::now);
+                if since.elapsed() >= duration {
+                    // reported again if it lasts another `duration`
+                    violated_since = None;
+                    observers.notify(Event::LimitViolated { usage: cpu_usage });
+                    if let ViolationPolicy::Kill(signal) = policy {
+                        if let Err(err) = group.read().signal_all(&signal) {
+                            break Err(err);
+                        }
+                    }
+                }
+            } else {
+                violated_since = None;
+            }
+        }
 
-        group.read().resume();
-        let work_time = SLICE_DURATION.mul_f64(working_rate);
-        thread::sleep(work_time);
+        let (work_time, throttled) = match settings.policy {
+            GroupPolicy::Shared => {
+                if let Err(err) = throttler.resume(&group.read()) {
+                    break Err(err);
+                }
+                let work_time = slice.mul_f64(working_rate).max(settings.min_work);
+                thread::sleep(work_time);
 
-        let sleep_time = SLICE_DURATION - work_time;
-        group.read().suspend();
+                let throttled = work_time < slice;
+                if throttled {
+                    if let Err(err) = throttler.suspend(&group.read()) {
+                        break Err(err);
+                    }
+                }
+                (work_time, throttled)
+            }
+            GroupPolicy::PerProcess(member_limit) => {
+                let cores = mode.cores(member_limit);
+                match throttle_members(
+                    group,
+                    &mut member_rates,
+                    cores,
+                    working_rate,
+                    slice,
+                    settings.min_work,
+                ) {
+                    Ok(throttled) => throttled,
+                    Err(err) => break Err(err),
+                }
+            }
+        };
+
+        let sleep_time = slice - work_time;
         thread::sleep(sleep_time);
+
+        let working_rate = match settings.policy {
+            GroupPolicy::Shared => working_rate,
+            GroupPolicy::PerProcess(_) => work_time.as_secs_f64() / slice.as_secs_f64(),
+        };
+        group.write().record_cycle(working_rate, throttled);
+        observers.notify(Event::ThrottleCycle {
+            work: work_time,
+            sleep: sleep_time,
+        });
+    };
+
+    if reason.is_err() {
+        // don't leave the processes that could be signaled suspended
+        let _ = throttler.resume(&group.read());
+    }
+    if let (Some(_), Err(Error::SignalFailed { .. } | Error::PermissionDenied(_))) =
+        (settings.enforcement_alert, &reason)
+    {
+        let usage = group.read().cpu_usage();
+        observers.notify(Event::EnforcementFailure { usage });
+    }
+    // the members were resumed, nothing is left to recover
+    group.write().set_journal(None);
+    notify_exit(observers, &reason, stop_ack);
+    reason
+}
+
+/// Lets each process of the group work for its own share of `slice`, bounded by
+/// the `working_rate` of the whole group, and suspends it afterwards.
+///
+/// The working rate of each process is adjusted so that it uses `cores`, but
+/// it works for `min_work` at least. Returns once the last process was
+/// suspended, with how long it worked and whether any process was suspended.
+fn throttle_members(
+    group: &RwLock<ProcessGroup>,
+    rates: &mut HashMap<Pid, f64>,
+    cores: f64,
+    working_rate: f64,
+    slice: Duration,
+    min_work: Duration,
+) -> Result<(Duration, bool)> {
+    let processes = group.read().stats().processes;
+    rates.retain(|pid, _| processes.iter().any(|process| process.pid == *pid));
+
+    let mut work_times: Vec<(Duration, Pid)> = processes
+        .iter()
+        .map(|process| {
+            let rate = rates.entry(process.pid).or_insert(1_f64);
+            *rate = controller::correct_rate(*rate, cores, process.cpu_usage);
+            let work_time = slice.mul_f64(f64::min(*rate, working_rate));
+            (work_time.max(min_work), process.pid)
+        })
+        .collect();
+    work_times.sort();
+
+    let start = Instant::now();
+    group.read().resume()?;
+    let mut work_time = Duration::ZERO;
+    let mut throttled = false;
+    for (member_time, pid) in work_times {
+        work_time = member_time;
+        if member_time >= slice {
+            break;
+        }
+        thread::sleep(member_time.saturating_sub(start.elapsed()));
+        group.read().suspend_process(pid)?;
+        throttled = true;
+    }
+    Ok((work_time, throttled))
+}
+
+/// Reports the end of the limiting thread to the observers and disconnects them.
+///
+/// No event is sent if the thread failed.
+/// The `Stop` command is acknowledged last, with the result of the resumption.
+fn notify_exit(
+    observers: &Observers,
+    reason: &Result<ExitReason>,
+    stop_ack: Option<(SyncSender<Result<()>>, Result<()>)>,
+) {
+    #[cfg(feature = "tracing")]
+    if let Err(err) = reason {
+        tracing::warn!(error = %err, "limiter failed");
+    }
+    if let Ok(reason) = reason {
+        observers.notify(match reason {
+            ExitReason::Stopped => Event::Stopped,
+            ExitReason::TargetExited => Event::TargetExited,
+            ExitReason::BudgetExhausted => Event::BudgetExhausted,
+            ExitReason::DeadlineReached => Event::DeadlineReached,
+        });
+    }
+    observers.close();
+
+    if let Some((ack, result)) = stop_ack {
+        let _ = ack.send(result);
+    }
+}
+
+/// Applies the budget policy once the group consumed its CPU time budget.
+///
+/// Returns why the limiting thread must stop, if it must.
+fn enforce_budget(
+    settings: &Settings,
+    group: &RwLock<ProcessGroup>,
+    observers: &Observers,
+    paused: &mut bool,
+    exhausted: &mut bool,
+) -> Result<Option<ExitReason>> {
+    let Some((budget, action)) = settings.budget else {
+        return Ok(None);
+    };
+    if *exhausted || group.read().consumed_cpu_time() < budget {
+        return Ok(None);
+    }
+    *exhausted = true;
+
+    match action {
+        BudgetAction::Unthrottle => {
+            group.read().resume()?;
+            Ok(Some(ExitReason::BudgetExhausted))
+        }
+        BudgetAction::Terminate => {
+            group.read().terminate()?;
+            Ok(Some(ExitReason::BudgetExhausted))
+        }
+        BudgetAction::Suspend => {
+            observers.notify(Event::BudgetExhausted);
+            *paused = true;
+            group.read().suspend()?;
+            Ok(None)
+        }
+    }
+}
+
+/// A mechanism throttling the processes without any signal.
+enum Enforcer {
+    Cgroup(Cgroup),
+    Priority(Deprioritized),
+    Affinity(Pinned),
+}
+
+impl Enforcer {
+    /// Starts enforcing the limit on `pid` too.
+    fn attach(&mut self, pid: Pid) -> io::Result<()> {
+        match self {
+            Enforcer::Cgroup(cgroup) => cgroup.attach(pid),
+            Enforcer::Priority(deprioritized) => deprioritized.attach(pid),
+            Enforcer::Affinity(pinned) => pinned.attach(pid),
+        }
+    }
+
+    /// Allows the processes to use the given number of `cores`.
+    ///
+    /// The priority backend can't enforce a specific rate.
+    fn set_limit(&mut self, cores: f64) -> Result<()> {
+        match self {
+            Enforcer::Cgroup(cgroup) => cgroup.set_limit(cores).map_err(Error::Cgroup),
+            Enforcer::Priority(_) => Ok(()),
+            Enforcer::Affinity(pinned) => pinned.set_limit(cores).map_err(Error::Affinity),
+        }
+    }
+
+    /// Stops enforcing the limit, restoring the processes as they were.
+    fn release(self) {
+        match self {
+            Enforcer::Cgroup(cgroup) => cgroup.release(),
+            Enforcer::Priority(deprioritized) => deprioritized.release(),
+            Enforcer::Affinity(pinned) => pinned.release(),
+        }
     }
 }
 
+/// The limiting function of the kernel-enforced backends, to be run in a separate thread.
+///
+/// The kernel enforces the limit: the thread only forwards the commands and
+/// keeps the CPU usage and the members up to date.
+fn enforcer_limiter_fn(
+    mut enforcer: Enforcer,
+    settings: Settings,
+    group: &Arc<RwLock<ProcessGroup>>,
+    rx: &Receiver<Request>,
+    observers: &Observers,
+) -> Result<ExitReason> {
+    let _resume = ResumeOnPanic(group);
+    let mut paused = settings.paused;
+    let mut limit = ActiveLimit::new(settings.limit);
+    let mut system = SystemUsage::new();
+    let mut relaxed = false;
+    let started = Instant::now();
+    // acknowledged once everything is cleaned up
+    let mut stop_ack = None;
+    let mut exhausted = false;
+
+    let reason = loop {
+        if let Ok((cmd, ack)) = rx.try_recv() {
+            let mut result = Ok(());
+            match cmd {
+                Command::Limit(new_limit) => limit.set_base(new_limit),
+                Command::Ramp(new_limit, duration) => limit.ramp_to(new_limit, duration),
+                Command::SetSchedule(schedule) => limit.set_schedule(schedule),
+                Command::SetChildrenMode(children_mode) => {
+                    group.write().set_children_mode(children_mode);
+                }
+                Command::Pause => {
+                    paused = true;
+                    result = group.read().suspend();
+                }
+                Command::Resume => {
+                    paused = false;
+                    group.write().rearm_guards();
+                    result = group.read().resume();
+                }
+                Command::Stop => {
+                    stop_ack = Some((ack, group.read().resume()));
+                    break Ok(ExitReason::Stopped);
+                }
+            }
+            let _ = ack.send(result);
+        }
+
+        if settings
+            .duration
+            .is_some_and(|duration| started.elapsed() >= duration)
+        {
+            if let Err(err) = group.read().resume() {
+                break Err(err);
+            }
+            break Ok(ExitReason::DeadlineReached);
+        }
+
+        let changed = limit.refresh();
+        if let Some(new_limit) = changed {
+            group.write().set_limit(new_limit);
+        }
+
+        let was_relaxed = relaxed;
+        if let Some(adaptive) = settings.adaptive {
+            relaxed = adaptive.relaxed(system.update(), group.read().cpu_usage());
+        }
+        if changed.is_some() || relaxed != was_relaxed {
+            let cores = if relaxed {
+                system::num_cpus() as f64
+            } else {
+                settings.mode.cores(limit.get())
+            };
+            // the previous quota would stay in force
+            if let Err(err) = enforcer.set_limit(cores) {
+                break Err(err);
+            }
+        }
+        if let Some(new_limit) = changed {
+            observers.notify(Event::LimitChanged(new_limit));
+        }
+
+        let updated = group.write().update();
+        match updated {
+            Ok(()) => {}
+            // bail-out if the target process is dead.
+            Err(Error::DeadTarget) => break Ok(ExitReason::TargetExited),
+            Err(err) => break Err(err),
+        }
+        if group.read().guard_suspended() {
+            // suspended until resumed explicitly
+            paused = true;
+        }
+
+        match enforce_budget(&settings, group, observers, &mut paused, &mut exhausted) {
+            Ok(Some(reason)) => break Ok(reason),
+            Err(err) => break Err(err),
+            Ok(None) => {}
+        }
+
+        // children that existed before the attachment must be moved explicitly
+        for member in group.read().members() {
+            let _ = enforcer.attach(member);
+        }
+
+        if paused {
+            if let Err(err) = group.read().suspend() {
+                break Err(err);
+            }
+        }
+
+        thread::sleep(settings.slice);
+    };
+
+    enforcer.release();
+    if reason.is_err() {
+        let _ = group.read().resume();
+    }
+    // the members were resumed, nothing is left to recover
+    group.write().set_journal(None);
+    notify_exit(observers, &reason, stop_ack);
+    reason
+}
+
 impl CpuLimit {
+    /// Creates a builder to configure the limiter before starting it.
+    pub fn builder(limit: f64) -> CpuLimitBuilder {
+        CpuLimitBuilder::new(limit)
+    }
+
+    /// Limits the CPU time of the target process in the calling thread, until
+    /// it exits or a condition of `options` is met.
+    ///
+    /// Also returns once [`CpuLimit::stop`] is called on a clone, see
+    /// [`RunOptions::on_start`]. The target runs freely afterwards.
+    pub fn run_blocking(pid: Pid, limit: f64, options: RunOptions) -> Result<ExitReason> {
+        let mut builder = Self::builder(limit)
+            .target(pid)
+            .children(options.children_mode);
+        if let Some(budget) = options.budget {
+            builder = builder.budget(budget, BudgetAction::Unthrottle);
+        }
+        if let Some(deadline) = options.deadline {
+            builder = builder.for_duration(deadline);
+        }
+        let limiter = builder.build()?;
+        if let Some(on_start) = options.on_start {
+            on_start(&limiter);
+        }
+        limiter.join()
+    }
+
     /// Limits the CPU time of the target process only.
     pub fn new(pid: Pid, limit: f64) -> Result<Self> {
-        Self::start_limit(pid, limit, ChildrenMode::Exclude)
+        CpuLimitBuilder::new(limit).target(pid).build()
+    }
+
+    /// Limits the CPU time of the target process until the returned guard is dropped.
+    ///
+    /// See [`LimitGuard`].
+    pub fn scoped(pid: Pid, limit: f64) -> Result<LimitGuard> {
+        Ok(LimitGuard::new(pid, Self::new(pid, limit)?))
+    }
+
+    /// Limits the CPU time of the thread `tid` of the target process.
+    ///
+    /// Only the CPU time of the thread counts against the limit. However, stop
+    /// signals apply to whole processes: the other threads are suspended along
+    /// with `tid`.
+    pub fn new_for_thread(pid: Pid, tid: Pid, limit: f64) -> Result<Self> {
+        CpuLimitBuilder::new(limit).thread(pid, tid).build()
     }
 
     /// Limits the CPU time of the target process and its children.
     pub fn new_with_children(pid: Pid, limit: f64) -> Result<Self> {
-        Self::start_limit(pid, limit, ChildrenMode::Include)
+        CpuLimitBuilder::new(limit)
+            .target(pid)
+            .children(ChildrenMode::Include)
+            .build()
+    }
+
+    /// Limits the CPU time of the target process, `limit` being interpreted according to `mode`.
+    pub fn new_with_mode(pid: Pid, limit: f64, mode: LimitMode) -> Result<Self> {
+        CpuLimitBuilder::new(limit).target(pid).mode(mode).build()
+    }
+
+    /// Limits the CPU time of the target process using the given enforcement `backend`.
+    pub fn new_with_backend(pid: Pid, limit: f64, backend: Backend) -> Result<Self> {
+        CpuLimitBuilder::new(limit)
+            .target(pid)
+            .backend(backend)
+            .build()
+    }
+
+    /// Limits the combined CPU time of every process of the cgroup at `path`.
+    ///
+    /// See [`Target::Cgroup`].
+    pub fn new_for_cgroup(path: impl Into<PathBuf>, limit: f64) -> Result<Self> {
+        CpuLimitBuilder::new(limit)
+            .target(Target::Cgroup(path.into()))
+            .build()
+    }
+
+    /// Limits the combined CPU time of every process owned by the user `uid`.
+    ///
+    /// See [`Target::User`].
+    pub fn new_for_user(uid: u32, limit: f64) -> Result<Self> {
+        CpuLimitBuilder::new(limit)
+            .target(Target::User(uid))
+            .build()
+    }
+
+    /// Limits the combined CPU time of every process of the container `id`.
+    ///
+    /// See [`Target::Container`].
+    pub fn new_for_container(id: impl Into<String>, limit: f64) -> Result<Self> {
+        CpuLimitBuilder::new(limit)
+            .target(Target::Container(id.into()))
+            .build()
+    }
+
+    /// Limits the CPU time of the target process only when the other processes
+    /// use more than `threshold` percent of the machine.
+    ///
+    /// See [`AdaptiveLimit`].
+    pub fn new_adaptive(pid: Pid, limit: f64, threshold: f64) -> Result<Self> {
+        CpuLimitBuilder::new(limit)
+            .target(pid)
+            .adaptive(AdaptiveLimit { threshold })
+            .build()
+    }
+
+    /// Limits the CPU usage of the target process to `factor` times that of
+    /// the `reference` process.
+    ///
+    /// See [`RelativeLimit`].
+    pub fn new_relative(pid: Pid, reference: Pid, factor: f64) -> Result<Self> {
+        CpuLimitBuilder::new(100_f64)
+            .target(pid)
+            .mode(LimitMode::TotalSystem)
+            .relative(RelativeLimit { reference, factor })
+            .build()
+    }
+
+    /// Lets the target process consume at most `budget` of CPU time, then applies `action`.
+    ///
+    /// The CPU time consumed before the call does not count. The rate is not limited.
+    pub fn new_with_budget(pid: Pid, budget: Duration, action: BudgetAction) -> Result<Self> {
+        CpuLimitBuilder::new(100_f64)
+            .target(pid)
+            .mode(LimitMode::TotalSystem)
+            .budget(budget, action)
+            .build()
+    }
+
+    /// Limits the combined CPU time of several unrelated processes.
+    ///
+    /// The processes share a single budget: `limit` applies to the sum of their CPU usage.
+    pub fn new_multi(pids: &[Pid], limit: f64) -> Result<Self> {
+        CpuLimitBuilder::new(limit).targets(pids).build()
+    }
+
+    /// Spawns `command` and limits the CPU time of the resulting process.
+    ///
+    /// The process is attached to the limiter before it executes the program,
+    /// so it never runs unthrottled.
+    pub fn spawn(command: process::Command, limit: f64) -> Result<(Self, Child)> {
+        CpuLimitBuilder::new(limit).spawn(command)
+    }
+
+    /// Spawns `command` and limits the CPU time of the resulting process and its children.
+    pub fn spawn_with_children(command: process::Command, limit: f64) -> Result<(Self, Child)> {
+        CpuLimitBuilder::new(limit)
+            .children(ChildrenMode::Include)
+            .spawn(command)
     }
 
-    /// Limits the CPU time of the target process (and its children if asked to).
-    fn start_limit(pid: Pid, limit: f64, children_mode: ChildrenMode) -> Result<Self> {
+    /// Starts the limiting thread enforcing the `settings` on `group`.
+    ///
+    /// Fails with [`Error::PermissionDenied`] if a process of the group can't be signaled.
+    pub(crate) fn start_group(
+        mut group: ProcessGroup,
+        settings: Settings,
+        throttler: Option<Arc<dyn Throttler>>,
+        follower: Option<Follower>,
+    ) -> Result<Self> {
+        group.check_permission()?;
+        group.set_limit(settings.limit);
+        rescue::install();
+
         let (tx, rx) = mpsc::sync_channel(1);
-        let group = ProcessGroup::new(pid, children_mode)?;
+        let observers = group.observers().clone();
         let group = Arc::new(RwLock::new(group));
 
         let group_clone = group.clone();
-        thread::Builder::new().spawn(move || limiter_fn(limit, &group_clone, &rx))?;
+        let observers_clone = observers.clone();
+        let span = info_span!(
+            "limiter",
+            targets = ?group.read().members().collect::<Vec<_>>(),
+            backend = ?settings.backend,
+        );
+        let thread = match settings.backend {
+            Backend::Signal => thread::Builder::new().spawn(move || {
+                span.in_scope(|| match throttler {
+                    Some(throttler) => limiter_fn(
+                        settings,
+                        &group_clone,
+                        &rx,
+                        &observers_clone,
+                        &*throttler,
+                        follower,
+                    ),
+                    None => limiter_fn(
+                        settings,
+                        &group_clone,
+                        &rx,
+                        &observers_clone,
+                        &SignalThrottler,
+                        follower,
+                    ),
+                })
+            })?,
+            Backend::CgroupV2 => {
+                let members: Vec<Pid> = group.read().members().collect();
+                let cgroup = Cgroup::create(&members).map_err(Error::Cgroup)?;
+                if let Err(err) = cgroup.set_limit(settings.mode.cores(settings.limit)) {
+                    cgroup.release();
+                    return Err(Error::Cgroup(err));
+                }
+
+                let enforcer = Enforcer::Cgroup(cgroup);
+                thread::Builder::new().spawn(move || {
+                    span.in_scope(|| {
+                        enforcer_limiter_fn(enforcer, settings, &group_clone, &rx, &observers_clone)
+                    })
+                })?
+            }
+            Backend::Priority => {
+                let mut deprioritized = Deprioritized::new();
+                for member in group.read().members() {
+                    if let Err(err) = deprioritized.attach(member) {
+                        deprioritized.release();
+                        return Err(Error::Priority(err));
+                    }
+                }
 
-        Ok(CpuLimit { sender: tx, group })
+                let enforcer = Enforcer::Priority(deprioritized);
+                thread::Builder::new().spawn(move || {
+                    span.in_scope(|| {
+                        enforcer_limiter_fn(enforcer, settings, &group_clone, &rx, &observers_clone)
+                    })
+                })?
+            }
+            Backend::Affinity => {
+                let mut pinned =
+                    Pinned::new(settings.mode.cores(settings.limit)).map_err(Error::Affinity)?;
+                for member in group.read().members() {
+                    if let Err(err) = pinned.attach(member) {
+                        pinned.release();
+                        return Err(Error::Affinity(err));
+                    }
+                }
+
+                let enforcer = Enforcer::Affinity(pinned);
+                thread::Builder::new().spawn(move || {
+                    span.in_scope(|| {
+                        enforcer_limiter_fn(enforcer, settings, &group_clone, &rx, &observers_clone)
+                    })
+                })?
+            }
+        };
+
+        Ok(CpuLimit {
+            shared: Arc::new(Shared {
+                sender: tx,
+                mode: settings.mode,
+                group,
+                observers,
+                thread: Mutex::new(Some(thread)),
+            }),
+        })
     }
 
     /// Updates the limit applied to the target process.
+    ///
+    /// Returns once the new limit is in effect. Fails with [`Error::InvalidLimit`]
+    /// unless `limit` is positive and within the cores online: see
+    /// [`CpuLimit::suspend_indefinitely`] to keep the processes from running at all.
     pub fn set_limit(&self, limit: f64) -> Result<()> {
-        self.sender.send(Command::Limit(limit))?;
-        Ok(())
+        self.shared.mode.check(limit)?;
+        self.request(Command::Limit(limit))
+    }
+
+    /// Moves the limit gradually from its current value to `limit` over `duration`.
+    ///
+    /// Returns as soon as the change started. The limit is updated at every slice.
+    pub fn ramp_to(&self, limit: f64, duration: Duration) -> Result<()> {
+        self.shared.mode.check(limit)?;
+        self.request(Command::Ramp(limit, duration))
+    }
+
+    /// Overrides the limit during the given daily time windows.
+    ///
+    /// The first window containing the current local time sets the limit;
+    /// outside of all windows, the limit set with [`CpuLimit::set_limit`] applies.
+    /// An empty schedule removes the overrides.
+    pub fn set_schedule(&self, schedule: Vec<(TimeRange, f64)>) -> Result<()> {
+        for (_, limit) in &schedule {
+            self.shared.mode.check(*limit)?;
+        }
+        self.request(Command::SetSchedule(schedule))
+    }
+
+    /// Starts or stops limiting the children of the target process(es).
+    ///
+    /// With the cgroup backend, the processes forked by the targets are always
+    /// limited: only the tracking of the existing children changes.
+    pub fn set_children_mode(&self, children_mode: ChildrenMode) -> Result<()> {
+        self.request(Command::SetChildrenMode(children_mode))
+    }
+
+    /// Suspends the target process(es) until [`CpuLimit::resume`] is called.
+    pub fn pause(&self) -> Result<()> {
+        self.request(Command::Pause)
+    }
+
+    /// Keeps the target process(es) suspended, as a limit of 0% would, until
+    /// [`CpuLimit::resume`] is called.
+    ///
+    /// The same as [`CpuLimit::pause`]: limits must be positive.
+    pub fn suspend_indefinitely(&self) -> Result<()> {
+        self.pause()
+    }
+
+    /// Lets the target process(es) run again under the limit.
+    pub fn resume(&self) -> Result<()> {
+        self.request(Command::Resume)
     }
 
     /// Stops the limiting thread.
+    ///
+    /// Returns once the target process(es) are resumed and the thread is about to exit.
     pub fn stop(&self) -> Result<()> {
-        self.sender.send(Command::Stop)?;
-        Ok(())
+        self.request(Command::Stop)
     }
 
-    /// Retrieves the CPU usage of the target process.
+    /// Sends `command` to the limiting thread and waits until it is handled.
+    fn request(&self, command: Command) -> Result<()> {
+        let (ack, acked) = mpsc::sync_channel(1);
+        self.shared
+            .sender
+            .send((command, ack))
+            .map_err(|SendError((command, _))| SendError(command))?;
+        acked.recv().map_err(|_| Error::Unacknowledged)?
+    }
+
+    /// Indicates whether the limiting thread is still running.
+    pub fn is_running(&self) -> bool {
+        self.shared
+            .thread
+            .lock()
+            .as_ref()
+            .is_some_and(|thread| !thread.is_finished())
+    }
+
+    /// Waits for the limiting thread to finish and tells why it stopped.
+    ///
+    /// Only one of the clones of the handle can join the thread.
+    /// Fails with the error that stopped the thread, if any, for instance
+    /// [`Error::SignalFailed`] when the processes can't be signaled.
+    pub fn join(&self) -> Result<ExitReason> {
+        let thread = self.shared.thread.lock().take().ok_or(Error::Joined)?;
+        thread.join().map_err(|_| Error::Panicked)?
+    }
+
+    /// Blocks until the target process(es) exited, whether the limiter still
+    /// runs or not.
+    ///
+    /// Notified at once through pidfds where the kernel supports them. Waits for
+    /// the current targets: with dynamic targets such as a cgroup, the processes
+    /// matching now, and with
+    /// [`CpuLimitBuilder::follow`](crate::CpuLimitBuilder::follow), not the replacements.
+    pub fn wait_for_exit(&self) {
+        let targets = self.shared.group.read().targets().to_vec();
+        for pid in targets {
+            pid.wait_exit(None);
+        }
+    }
+
+    /// Subscribes to the events of the limiter.
+    ///
+    /// The receiver is disconnected once the limiter stops.
+    pub fn events(&self) -> Receiver<Event> {
+        self.shared.observers.subscribe()
+    }
+
+    /// Retrieves the CPU usage of the target process(es).
+    ///
+    /// This is the ratio of CPU time used per unit of time: `1.0` means one fully used core.
     pub fn cpu_usage(&self) -> f64 {
-        self.group.read().cpu_usage()
+        self.shared.group.read().cpu_usage()
+    }
+
+    /// Retrieves the CPU usage as a percentage of one core (may exceed 100%).
+    pub fn cpu_usage_percent_single_core(&self) -> f64 {
+        self.cpu_usage() * 100_f64
     }
 
-    /// Retrieves the total amount of CPU time used by the target process.
+    /// Retrieves the CPU usage as a percentage of the whole machine.
+    pub fn cpu_usage_percent_total(&self) -> f64 {
+        self.cpu_usage() * 100_f64 / system::num_cpus() as f64
+    }
+
+    /// Retrieves the CPU usage of every process of the group and the state of the throttling.
+    ///
+    /// The working rate and the throttle cycles are only tracked by [`Backend::Signal`].
+    pub fn stats(&self) -> GroupStats {
+        self.shared.group.read().stats()
+    }
+
+    /// Retrieves the working rate the limiter currently applies, and its range and
+    /// average over the last 10 seconds.
+    ///
+    /// See [`DutyCycle`].
+    pub fn duty_cycle(&self) -> DutyCycle {
+        self.shared.group.read().duty_cycle()
+    }
+
+    /// Retrieves the CPU usage, limit and working rate of the recent time slices, oldest first.
+    ///
+    /// Empty unless enabled with [`CpuLimitBuilder::timeline`].
+    pub fn timeline(&self) -> Timeline {
+        self.shared.group.read().timeline()
+    }
+
+    /// Renders the state of the limiter in the Prometheus text exposition format.
+    ///
+    /// Suitable as the body of a `/metrics` HTTP endpoint.
+    #[cfg(feature = "metrics")]
+    pub fn render_prometheus(&self) -> String {
+        metrics::render(&self.shared.group.read())
+    }
+
+    /// Retrieves the total amount of CPU time used by the target process(es).
     pub fn total_cpu_time(&self) -> Duration {
-        self.group.read().total_cpu_time()
+        self.shared.group.read().total_cpu_time()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::backend::{MockBackend, ProcessBackend};
+
+    #[test]
+    fn run_blocking() {
+        let mut child = process::Command::new("sleep").arg("10").spawn().unwrap();
+        let pid = Pid::from(child.id());
+
+        let options = RunOptions::new().deadline(Duration::from_millis(300));
+        let reason = CpuLimit::run_blocking(pid, 50_f64, options).unwrap();
+        assert_eq!(reason, ExitReason::DeadlineReached);
+
+        let options = RunOptions::new().on_start(|limiter| {
+            let limiter = limiter.clone();
+            thread::spawn(move || {
+                thread::sleep(Duration::from_millis(300));
+                limiter.stop().unwrap();
+            });
+        });
+        let reason = CpuLimit::run_blocking(pid, 50_f64, options).unwrap();
+        assert_eq!(reason, ExitReason::Stopped);
+
+        child.kill().unwrap();
+        child.wait().unwrap();
+    }
+
+    #[test]
+    fn wait_for_exit() {
+        let mut first = process::Command::new("sleep").arg("10").spawn().unwrap();
+        let mut second = process::Command::new("sleep").arg("10").spawn().unwrap();
+        let pids = [Pid::from(first.id()), Pid::from(second.id())];
+        let limiter = CpuLimit::new_multi(&pids, 50_f64).unwrap();
+        let events = limiter.events();
+
+        first.kill().unwrap();
+        let died = events
+            .iter()
+            .find(|event| matches!(event, Event::TargetDied(_)));
+        assert_eq!(died, Some(Event::TargetDied(pids[0])));
+        assert!(limiter.is_running());
+
+        let waiter = thread::spawn(move || limiter.wait_for_exit());
+        thread::sleep(Duration::from_millis(100));
+        assert!(!waiter.is_finished());
+        second.kill().unwrap();
+        waiter.join().unwrap();
+        first.wait().unwrap();
+        second.wait().unwrap();
+    }
+
+    #[test]
+    fn resume_on_panic() {
+        /// Suspends the group, then panics.
+        struct Panicking;
+
+        impl Throttler for Panicking {
+            fn suspend(&self, group: &ProcessGroup) -> Result<()> {
+                group.suspend()?;
+                panic!("throttler bug");
+            }
+
+            fn resume(&self, group: &ProcessGroup) -> Result<()> {
+                group.resume()
+            }
+        }
+
+        let mut child = process::Command::new("sh")
+            .args(["-c", "while :; do :; done"])
+            .spawn()
+            .unwrap();
+        let pid = Pid::from(child.id());
+
+        let limiter = CpuLimit::builder(10_f64)
+            .target(pid)
+            .throttler(Arc::new(Panicking))
+            .build()
+            .unwrap();
+        assert!(matches!(limiter.join(), Err(Error::Panicked)));
+        assert!(!pid.is_stopped());
+
+        child.kill().unwrap();
+        child.wait().unwrap();
+    }
+
+    #[test]
+    fn per_process() {
+        let mock = Arc::new(MockBackend::new());
+        let (hog, idle) = (Pid::from(100), Pid::from(101));
+        mock.spawn(hog, None);
+        mock.spawn(idle, Some(hog));
+        let group =
+            ProcessGroup::with_backend(&[hog], ChildrenMode::Include, mock.clone()).unwrap();
+        mock.signal(idle, Signal::SIGSTOP).unwrap();
+        mock.run(Duration::from_secs(1));
+        mock.signal(idle, Signal::SIGCONT).unwrap();
+        let group = RwLock::new(group);
+        group.write().update().unwrap();
+
+        let slice = Duration::from_millis(50);
+        let mut rates = HashMap::new();
+        let (work_time, throttled) =
+            throttle_members(&group, &mut rates, 0.5, 1_f64, slice, Duration::ZERO).unwrap();
+        // only the process above its own limit is suspended
+        assert!(throttled);
+        assert_eq!(work_time, slice);
+        assert!(rates[&hog] < 1_f64);
+        assert_eq!(rates[&idle], 1_f64);
+        assert!(mock.is_stopped(hog));
+        assert!(!mock.is_stopped(idle));
+    }
+
+    #[test]
+    fn token_bucket() {
+        let bucket = TokenBucket {
+            capacity: Duration::from_secs(2),
+            refill_rate: 50_f64,
+        };
+        let mode = LimitMode::PerCore;
+
+        // drained by a burst of 2 cores, 1.5 above the refill rate
+        let tokens = bucket.refill(mode, 2_f64, 2_f64, Duration::from_secs(1));
+        assert!((tokens - 0.5).abs() < 1e-9);
+        assert_eq!(
+            bucket.refill(mode, tokens, 2_f64, Duration::from_secs(1)),
+            0_f64
+        );
+
+        // refilled while idle, up to the capacity
+        let tokens = bucket.refill(mode, 0_f64, 0_f64, Duration::from_secs(2));
+        assert!((tokens - 1_f64).abs() < 1e-9);
+        assert_eq!(
+            bucket.refill(mode, tokens, 0_f64, Duration::from_secs(10)),
+            2_f64
+        );
     }
 }
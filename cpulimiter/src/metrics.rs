@@ -0,0 +1,53 @@
+//! Render the state of a limiter in the Prometheus text exposition format.
+//!
+//! See <https://prometheus.io/docs/instrumenting/exposition_formats/>.
+
+use std::fmt::Write;
+
+use crate::process_group::ProcessGroup;
+
+/// Writes the metrics of `group`, one `# HELP`, `# TYPE` and sample per metric.
+pub(crate) fn render(group: &ProcessGroup) -> String {
+    let (sigstop, sigcont) = group.signals_sent();
+    let metrics = [
+        (
+            "cpulimit_target_usage",
+            "gauge",
+            "CPU usage of the limited processes, 1 is one fully used core.",
+            group.cpu_usage(),
+        ),
+        (
+            "cpulimit_effective_limit",
+            "gauge",
+            "Limit in effect, as a percentage.",
+            group.limit(),
+        ),
+        (
+            "cpulimit_children_count",
+            "gauge",
+            "Number of children of the targets in the group.",
+            group.children_count() as f64,
+        ),
+        (
+            "cpulimit_sigstop_total",
+            "counter",
+            "Number of SIGSTOP sent.",
+            sigstop as f64,
+        ),
+        (
+            "cpulimit_sigcont_total",
+            "counter",
+            "Number of SIGCONT sent.",
+            sigcont as f64,
+        ),
+    ];
+
+    let mut output = String::new();
+    for (name, kind, help, value) in metrics {
+        // writing to a `String` can't fail
+        let _ = writeln!(output, "# HELP {name} {help}");
+        let _ = writeln!(output, "# TYPE {name} {kind}");
+        let _ = writeln!(output, "{name} {value}");
+    }
+    output
+}
@@ -1,39 +1,131 @@
 //! Handle processes described by their PID.
 
 use std::fmt::Display;
+#[cfg(target_os = "linux")]
+use std::path::PathBuf;
 use std::str::FromStr;
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+use std::sync::OnceLock;
 use std::time::Duration;
+#[cfg(target_os = "linux")]
+use std::{fs, io};
 
-use lazy_static::lazy_static;
+#[cfg(target_os = "linux")]
+use crate::clock::SystemClock;
+#[cfg(unix)]
+use crate::error::Error;
+#[cfg(target_os = "linux")]
+use crate::limiter::{CpuLimit, ExitReason, RunOptions};
+#[cfg(target_os = "linux")]
+use crate::process_info::ProcessInfo;
+#[cfg(target_os = "linux")]
+use crate::procfs;
+#[cfg(target_os = "freebsd")]
+use crate::stat_iterator::ProcInfo;
+#[cfg(target_os = "linux")]
+use crate::stat_iterator::{StatField, StatFile, StatFileIter};
 
-use crate::stat_iterator::StatFile;
+/// Whether the kernel lists the children of each thread (`CONFIG_PROC_CHILDREN`).
+#[cfg(target_os = "linux")]
+static CHILDREN_FILES: OnceLock<bool> = OnceLock::new();
 
-lazy_static!(
-    /// The number of clock ticks per second.
-    ///
-    /// This is a kernel constant (fixed at compile-time).
-    // SAFETY: Inherently unsafe as a syscall, but the parameter is valid.
-    static ref CLOCK_TICKS: i64 = unsafe {
-        libc::sysconf(libc::_SC_CLK_TCK)
-    };
-);
+/// The ratio to convert Mach absolute time units to nanoseconds.
+#[cfg(target_os = "macos")]
+static TIMEBASE: OnceLock<(u32, u32)> = OnceLock::new();
+
+/// The flag of the kernel threads in the stat files, `PF_KTHREAD`.
+#[cfg(target_os = "linux")]
+const PF_KTHREAD: u32 = 0x0020_0000;
 
 /// Linux signals
+#[cfg(unix)]
 #[allow(clippy::upper_case_acronyms)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub enum Signal {
     /// Pause the process in its current state.
     SIGSTOP,
+    /// Ask the process to pause, as Ctrl-Z does: it may handle or ignore it.
+    SIGTSTP,
     /// Resume the process execution.
     SIGCONT,
     /// Check process existence.
     SIGNULL,
+    /// Terminate the process immediately.
+    SIGKILL,
+    /// Ask the process to terminate.
+    SIGTERM,
+}
+
+/// The scheduling parameters of a thread.
+#[cfg(target_os = "linux")]
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Scheduling {
+    policy: libc::c_int,
+    priority: libc::c_int,
+    nice: libc::c_int,
+}
+
+#[cfg(target_os = "linux")]
+impl Scheduling {
+    /// The lowest priority: only run when the CPU would be idle otherwise.
+    pub const IDLE: Self = Self {
+        policy: libc::SCHED_IDLE,
+        priority: 0,
+        nice: 19,
+    };
+}
+
+/// A set of cores a thread is allowed to run on.
+#[cfg(target_os = "linux")]
+#[derive(Clone, Copy)]
+pub(crate) struct CpuSet(libc::cpu_set_t);
+
+#[cfg(target_os = "linux")]
+impl CpuSet {
+    /// Creates a set without any core.
+    pub fn empty() -> Self {
+        // SAFETY: `cpu_set_t` is a plain C struct, zeroed means empty.
+        Self(unsafe { std::mem::zeroed() })
+    }
+
+    /// Creates a set of the given cores.
+    pub fn from_cpus(cpus: impl IntoIterator<Item = usize>) -> Self {
+        let mut set = Self::empty();
+        for cpu in cpus {
+            // SAFETY: Out of range cores are ignored by `CPU_SET`.
+            unsafe { libc::CPU_SET(cpu, &mut set.0) };
+        }
+        set
+    }
+
+    /// Lists the cores of the set.
+    pub fn cpus(&self) -> Vec<usize> {
+        (0..libc::CPU_SETSIZE as usize)
+            // SAFETY: The index is in range.
+            .filter(|&cpu| unsafe { libc::CPU_ISSET(cpu, &self.0) })
+            .collect()
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl PartialEq for CpuSet {
+    fn eq(&self, other: &Self) -> bool {
+        // SAFETY: Both sets are valid.
+        unsafe { libc::CPU_EQUAL(&self.0, &other.0) }
+    }
 }
 
 /// The representation of a process running on the system.
-#[derive(Copy, Clone, PartialEq, PartialOrd, Eq, Hash, Debug)]
+#[derive(Copy, Clone, PartialEq, PartialOrd, Eq, Ord, Hash, Debug)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(transparent)
+)]
 pub struct Pid(u32);
 
 /// The PID of the `init` daemon process.
+#[cfg(any(target_os = "linux", target_os = "macos", target_os = "freebsd"))]
 const INIT: Pid = Pid(1);
 
 impl FromStr for Pid {
@@ -58,26 +150,347 @@ impl From<u32> for Pid {
     }
 }
 
+impl From<Pid> for u32 {
+    fn from(pid: Pid) -> Self {
+        pid.0
+    }
+}
+
 impl Display for Pid {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{}", self.0)
     }
 }
 
+#[cfg(target_os = "linux")]
 impl Pid {
     /// Retrieves the parent process identifier (`ppid`).
     #[must_use]
     pub fn get_ppid(&self) -> Self {
         StatFile::open(*self)
+            .and_then(|stat| stat.field(StatField::Ppid))
+            .unwrap_or(Self(0))
+    }
+
+    /// Retrieves the current CPU time, sum of the `utime` (user mode) and `stime` (kernel mode).
+    pub fn get_cputime(&self) -> Duration {
+        StatFile::open(*self)
+            .and_then(|stat| cputime(&stat.iter()))
+            .unwrap_or_default()
+    }
+
+    /// Retrieves the CPU time of the exited children the process waited for,
+    /// sum of the `cutime` and `cstime`.
+    ///
+    /// Includes the children these children waited for, and so on.
+    pub fn get_reaped_cputime(&self) -> Duration {
+        StatFile::open(*self)
+            .and_then(|stat| reaped_cputime(&stat.iter()))
+            .unwrap_or_default()
+    }
+
+    /// Retrieves the time the process started after system boot, in clock ticks.
+    ///
+    /// Tells apart two processes that got the same PID.
+    pub fn get_start_time(&self) -> Option<u64> {
+        StatFile::open(*self)
+            .and_then(|stat| start_time(&stat.iter()))
             .ok()
-            .and_then(|stat| {
-                let mut stat = stat.iter();
-                stat.nth(3).map(ToOwned::to_owned)
+    }
+
+    /// Limits the CPU usage of the process to `limit` percent of a core, until it exits.
+    ///
+    /// Blocks the calling thread, see [`CpuLimit::run_blocking`] for more conditions to return.
+    pub fn limit(&self, limit: f64) -> Result<ExitReason, Error> {
+        CpuLimit::run_blocking(*self, limit, RunOptions::new())
+    }
+
+    /// Reads the details of the process at once from its stat file.
+    pub fn info(&self) -> io::Result<ProcessInfo> {
+        ProcessInfo::parse(StatFile::open(*self)?.iter())
+    }
+
+    /// Retrieves the path of the executable run by the process.
+    pub fn get_exe(&self) -> Option<PathBuf> {
+        fs::read_link(procfs::path(format!("{self}/exe"))).ok()
+    }
+
+    /// Retrieves the command name of the process, truncated to 15 bytes by the kernel.
+    pub fn get_name(&self) -> Option<String> {
+        let comm = fs::read_to_string(procfs::path(format!("{self}/comm"))).ok()?;
+        Some(comm.trim_end_matches('\n').to_string())
+    }
+
+    /// Retrieves the paths of the cgroups of the process, one per hierarchy, from `/proc/<pid>/cgroup`.
+    pub fn get_cgroups(&self) -> Option<Vec<PathBuf>> {
+        let cgroups = fs::read_to_string(procfs::path(format!("{self}/cgroup"))).ok()?;
+        Some(
+            cgroups
+                .lines()
+                .filter_map(|line| line.splitn(3, ':').nth(2))
+                .map(PathBuf::from)
+                .collect(),
+        )
+    }
+
+    /// Retrieves the real user ID owning the process, from `/proc/<pid>/status`.
+    pub fn get_uid(&self) -> Option<u32> {
+        let status = fs::read_to_string(procfs::path(format!("{self}/status"))).ok()?;
+        status
+            .lines()
+            .find_map(|line| line.strip_prefix("Uid:"))
+            .and_then(|uids| uids.split_whitespace().next())
+            .and_then(|uid| uid.parse().ok())
+    }
+
+    /// Retrieves the resident memory of the process in bytes, `VmRSS` in `/proc/<pid>/status`.
+    ///
+    /// Kernel threads and zombies have none.
+    pub fn get_rss(&self) -> Option<u64> {
+        let status = fs::read_to_string(procfs::path(format!("{self}/status"))).ok()?;
+        let kib: u64 = status
+            .lines()
+            .find_map(|line| line.strip_prefix("VmRSS:"))
+            .and_then(|rss| rss.split_whitespace().next())
+            .and_then(|kib| kib.parse().ok())?;
+        Some(kib.saturating_mul(1024))
+    }
+
+    /// Retrieves the bytes the process read from and wrote to storage, from `/proc/<pid>/io`.
+    ///
+    /// Requires the permission to trace the process. Data read from the page
+    /// cache isn't counted, and writes are counted when the pages get dirty.
+    pub fn get_io_bytes(&self) -> Option<u64> {
+        let counters = fs::read_to_string(procfs::path(format!("{self}/io"))).ok()?;
+        let mut bytes = 0_u64;
+        for line in counters.lines() {
+            if let Some(value) = line
+                .strip_prefix("read_bytes: ")
+                .or_else(|| line.strip_prefix("write_bytes: "))
+            {
+                bytes = bytes.saturating_add(value.parse().ok()?);
+            }
+        }
+        Some(bytes)
+    }
+
+    /// Indicates whether the process is currently stopped by a signal or a debugger.
+    pub fn is_stopped(&self) -> bool {
+        StatFile::open(*self)
+            .ok()
+            .and_then(|stat| stat.field(StatField::State).ok())
+            .is_some_and(|state| matches!(state, 'T' | 't'))
+    }
+
+    /// Indicates whether the process exited and waits for its parent to reap it.
+    pub fn is_zombie(&self) -> bool {
+        StatFile::open(*self)
+            .ok()
+            .and_then(|stat| stat.field(StatField::State).ok())
+            .is_some_and(|state: char| state == 'Z')
+    }
+
+    /// Indicates whether the process is a kernel thread, which ignores signals.
+    pub fn is_kernel_thread(&self) -> bool {
+        StatFile::open(*self)
+            .ok()
+            .and_then(|stat| stat.field(StatField::Flags).ok())
+            .is_some_and(|flags: u32| flags & PF_KTHREAD != 0)
+    }
+
+    /// Retrieves the current CPU time of the thread `tid` of the process.
+    pub fn get_thread_cputime(&self, tid: Pid) -> Duration {
+        StatFile::open_thread(*self, tid)
+            .and_then(|stat| cputime(&stat.iter()))
+            .unwrap_or_default()
+    }
+
+    /// Lists the threads of the process.
+    pub fn threads(&self) -> io::Result<Vec<Pid>> {
+        let mut threads = Vec::new();
+        for entry in fs::read_dir(procfs::path(format!("{self}/task")))? {
+            if let Some(Ok(tid)) = entry?.file_name().to_str().map(Pid::from_str) {
+                threads.push(tid);
+            }
+        }
+        Ok(threads)
+    }
+
+    /// Lists the descendants of the process, from the `children` files of their threads.
+    ///
+    /// Much cheaper than walking `/proc`, but returns `None` if the kernel
+    /// doesn't provide these files. The processes exiting meanwhile are skipped.
+    pub fn descendants(&self) -> Option<Vec<Pid>> {
+        if !*CHILDREN_FILES.get_or_init(|| procfs::path("1/task/1/children").exists()) {
+            return None;
+        }
+
+        let mut descendants = Vec::new();
+        let mut parents = vec![*self];
+        while let Some(parent) = parents.pop() {
+            for tid in parent.threads().unwrap_or_default() {
+                let Ok(children) =
+                    fs::read_to_string(procfs::path(format!("{parent}/task/{tid}/children")))
+                else {
+                    continue;
+                };
+                for child in children
+                    .split_whitespace()
+                    .filter_map(|pid| pid.parse().ok())
+                {
+                    descendants.push(child);
+                    parents.push(child);
+                }
+            }
+        }
+        Some(descendants)
+    }
+
+    /// Indicates whether the thread `tid` of the process is alive or not.
+    pub fn thread_alive(&self, tid: Pid) -> bool {
+        // a thread that can't be signaled still exists
+        !matches!(
+            self.tgkill(tid, &Signal::SIGNULL),
+            Err(Error::SignalFailed {
+                errno: libc::ESRCH,
+                ..
             })
-            .and_then(|ppid| Self::from_str(&ppid).ok())
-            .unwrap_or(Self(0))
+        )
+    }
+
+    /// Retrieves the scheduling parameters of the thread `self`.
+    pub(crate) fn scheduling(self) -> io::Result<Scheduling> {
+        let tid = self.0 as libc::pid_t;
+
+        // SAFETY: Inherently unsafe as a syscall, but the ID is a valid value.
+        let policy = unsafe { libc::sched_getscheduler(tid) };
+        if policy < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        // SAFETY: `sched_param` is a plain C struct that can be zeroed.
+        let mut param: libc::sched_param = unsafe { std::mem::zeroed() };
+        // SAFETY: Inherently unsafe as a syscall, but the structure is valid.
+        if unsafe { libc::sched_getparam(tid, &mut param) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        // -1 is a valid nice value, errors are only reported through `errno`
+        // SAFETY: `errno` is thread-local, it can always be written.
+        unsafe { *libc::__errno_location() = 0 };
+        // SAFETY: Inherently unsafe as a syscall, but the parameters are valid values.
+        let nice = unsafe { libc::getpriority(libc::PRIO_PROCESS, tid as libc::id_t) };
+        let err = io::Error::last_os_error();
+        if nice == -1 && err.raw_os_error() != Some(0) {
+            return Err(err);
+        }
+
+        Ok(Scheduling {
+            policy,
+            priority: param.sched_priority,
+            nice,
+        })
+    }
+
+    /// Sets the scheduling parameters of the thread `self`.
+    pub(crate) fn set_scheduling(self, scheduling: &Scheduling) -> io::Result<()> {
+        let tid = self.0 as libc::pid_t;
+
+        // SAFETY: `sched_param` is a plain C struct that can be zeroed.
+        let mut param: libc::sched_param = unsafe { std::mem::zeroed() };
+        param.sched_priority = scheduling.priority;
+        // SAFETY: Inherently unsafe as a syscall, but the structure is valid.
+        if unsafe { libc::sched_setscheduler(tid, scheduling.policy, &param) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        // SAFETY: Inherently unsafe as a syscall, but the parameters are valid values.
+        let res =
+            unsafe { libc::setpriority(libc::PRIO_PROCESS, tid as libc::id_t, scheduling.nice) };
+        if res != 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(())
+    }
+
+    /// Retrieves the cores the thread `self` is allowed to run on.
+    pub(crate) fn affinity(self) -> io::Result<CpuSet> {
+        let mut set = CpuSet::empty();
+        // SAFETY: Inherently unsafe as a syscall, but the set is valid and its size is right.
+        let res = unsafe {
+            libc::sched_getaffinity(
+                self.0 as libc::pid_t,
+                std::mem::size_of::<libc::cpu_set_t>(),
+                &mut set.0,
+            )
+        };
+        if res != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(set)
+    }
+
+    /// Restricts the thread `self` to the cores of `set`.
+    pub(crate) fn set_affinity(self, set: &CpuSet) -> io::Result<()> {
+        // SAFETY: Inherently unsafe as a syscall, but the set is valid and its size is right.
+        let res = unsafe {
+            libc::sched_setaffinity(
+                self.0 as libc::pid_t,
+                std::mem::size_of::<libc::cpu_set_t>(),
+                &set.0,
+            )
+        };
+        if res != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    /// Sends `signal` to the thread `tid` of the process.
+    ///
+    /// Stop signals still apply to the whole process.
+    #[inline]
+    pub(crate) fn tgkill(self, tid: Pid, signal: &Signal) -> Result<(), Error> {
+        // SAFETY: Inherently unsafe as a syscall but the IDs and the signal are valid values.
+        let res = unsafe {
+            libc::syscall(
+                libc::SYS_tgkill,
+                self.0 as libc::pid_t,
+                tid.0 as libc::pid_t,
+                signal.as_raw(),
+            )
+        };
+
+        if res == 0 {
+            Ok(())
+        } else {
+            Err(self.signal_failed(signal))
+        }
+    }
+
+    /// Retrieves the process group of the process, `None` if it exited.
+    pub(crate) fn process_group(self) -> Option<Pid> {
+        // SAFETY: Inherently unsafe as a syscall but the PID is a valid value.
+        let pgid = unsafe { libc::getpgid(self.0 as libc::pid_t) };
+        (pgid > 0).then_some(Pid(pgid as u32))
     }
 
+    /// Sends `signal` at once to every process of the process group `self`.
+    pub(crate) fn kill_group(self, signal: &Signal) -> Result<(), Error> {
+        // SAFETY: Inherently unsafe as a syscall but the PGID and the signal are valid values.
+        let res = unsafe { libc::killpg(self.0 as libc::pid_t, signal.as_raw()) };
+
+        if res == 0 {
+            Ok(())
+        } else {
+            Err(self.signal_failed(signal))
+        }
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "macos", target_os = "freebsd"))]
+impl Pid {
     /// Indicates whether `self` is a child of `other`.
     pub fn is_child_of(&self, other: Pid) -> bool {
         let mut ppid = *self;
@@ -89,43 +502,312 @@ impl Pid {
         ppid == other
     }
 
-    /// Retrieves the current CPU time, sum of the `utime` (user mode) and `stime` (kernel mode).
-    pub fn get_cputime(&self) -> Duration {
-        StatFile::open(*self)
-            .ok()
-            .map(|stat| {
-                let stat = stat.iter();
-                let time: u64 = stat
-                    .skip(13)
-                    .take(2) // utime and stime (unit: clock ticks)
-                    .map(|t| t.parse::<u64>().unwrap_or_default())
-                    .sum();
-                Duration::from_secs_f64(time as f64 / *CLOCK_TICKS as f64)
-            })
-            .unwrap_or(Duration::from_secs(0))
-    }
-
     /// Indicates whether the process is alive or not.
     pub fn alive(&self) -> bool {
-        self.kill(&Signal::SIGNULL).is_ok()
+        // a process that can't be signaled still exists
+        !matches!(
+            self.kill(&Signal::SIGNULL),
+            Err(Error::SignalFailed {
+                errno: libc::ESRCH,
+                ..
+            })
+        )
     }
 
     /// Sends `signal` to the process.
     #[inline]
-    pub(crate) fn kill(self, signal: &Signal) -> Result<(), ()> {
-        let sig = match signal {
+    pub(crate) fn kill(self, signal: &Signal) -> Result<(), Error> {
+        // SAFETY: Inherently unsafe as a syscall but the PID and the signal are valid values.
+        let res = unsafe { libc::kill(self.0 as _, signal.as_raw()) };
+
+        if res == 0 {
+            Ok(())
+        } else {
+            Err(self.signal_failed(signal))
+        }
+    }
+
+    /// Describes the failure of the last attempt to send `signal` to the process.
+    fn signal_failed(self, signal: &Signal) -> Error {
+        Error::SignalFailed {
+            pid: self,
+            signal: *signal,
+            errno: std::io::Error::last_os_error()
+                .raw_os_error()
+                .unwrap_or_default(),
+        }
+    }
+}
+
+#[cfg(target_os = "macos")]
+impl Pid {
+    /// Retrieves the parent process identifier (`ppid`).
+    #[must_use]
+    pub fn get_ppid(&self) -> Self {
+        self.proc_pidinfo::<libc::proc_bsdinfo>(libc::PROC_PIDTBSDINFO)
+            .map_or(Self(0), |info| Self(info.pbi_ppid))
+    }
+
+    /// Retrieves the current CPU time, sum of the user mode and kernel mode times.
+    pub fn get_cputime(&self) -> Duration {
+        self.proc_pidinfo::<libc::proc_taskinfo>(libc::PROC_PIDTASKINFO)
+            .map_or(Duration::from_secs(0), |info| {
+                // the times are expressed in Mach absolute time units
+                let (numer, denom) = *TIMEBASE.get_or_init(|| {
+                    let mut info = libc::mach_timebase_info { numer: 1, denom: 1 };
+                    // SAFETY: Inherently unsafe as a syscall, but the structure is valid.
+                    unsafe { libc::mach_timebase_info(&mut info) };
+                    (info.numer, u32::max(info.denom, 1))
+                });
+                let time = (info.pti_total_user + info.pti_total_system) as u128;
+                Duration::from_nanos((time * numer as u128 / denom as u128) as u64)
+            })
+    }
+
+    /// Retrieves information about the process with `proc_pidinfo`.
+    fn proc_pidinfo<T>(&self, flavor: libc::c_int) -> Option<T> {
+        let size = std::mem::size_of::<T>() as libc::c_int;
+        let mut info = std::mem::MaybeUninit::<T>::uninit();
+
+        // SAFETY: Inherently unsafe as a syscall, but the buffer matches the flavor.
+        let res =
+            unsafe { libc::proc_pidinfo(self.0 as _, flavor, 0, info.as_mut_ptr().cast(), size) };
+
+        // SAFETY: The kernel filled the whole structure.
+        (res == size).then(|| unsafe { info.assume_init() })
+    }
+}
+
+#[cfg(target_os = "freebsd")]
+impl Pid {
+    /// Retrieves the parent process identifier (`ppid`).
+    #[must_use]
+    pub fn get_ppid(&self) -> Self {
+        ProcInfo::query(self.0 as libc::pid_t).map_or(Self(0), |info| info.ppid())
+    }
+
+    /// Retrieves the current CPU time, sum of the user mode and kernel mode times.
+    pub fn get_cputime(&self) -> Duration {
+        ProcInfo::query(self.0 as libc::pid_t).map_or(Duration::from_secs(0), |info| info.cputime())
+    }
+}
+
+#[cfg(unix)]
+impl Signal {
+    /// Retrieves the signal number.
+    pub(crate) fn as_raw(&self) -> libc::c_int {
+        match self {
             Signal::SIGNULL => 0,
             Signal::SIGSTOP => libc::SIGSTOP,
+            Signal::SIGTSTP => libc::SIGTSTP,
             Signal::SIGCONT => libc::SIGCONT,
+            Signal::SIGKILL => libc::SIGKILL,
+            Signal::SIGTERM => libc::SIGTERM,
+        }
+    }
+}
+
+/// Sums the `utime` (user mode) and `stime` (kernel mode) fields of a stat file.
+#[cfg(target_os = "linux")]
+pub(crate) fn cputime(stat: &StatFileIter) -> io::Result<Duration> {
+    let utime: u64 = stat.field(StatField::Utime)?;
+    let stime: u64 = stat.field(StatField::Stime)?;
+    Ok(ticks_to_duration(utime + stime))
+}
+
+/// Sums the `cutime` and `cstime` fields of a stat file, the CPU time of the reaped children.
+#[cfg(target_os = "linux")]
+pub(crate) fn reaped_cputime(stat: &StatFileIter) -> io::Result<Duration> {
+    let cutime: u64 = stat.field(StatField::Cutime)?;
+    let cstime: u64 = stat.field(StatField::Cstime)?;
+    Ok(ticks_to_duration(cutime + cstime))
+}
+
+/// Converts a number of clock ticks, the unit of the times in stat files.
+#[cfg(target_os = "linux")]
+pub(crate) fn ticks_to_duration(ticks: u64) -> Duration {
+    SystemClock::current().ticks_to_duration(ticks)
+}
+
+/// Parses the `starttime` field of a stat file, in clock ticks after system boot.
+#[cfg(target_os = "linux")]
+pub(crate) fn start_time(stat: &StatFileIter) -> io::Result<u64> {
+    stat.field(StatField::Starttime)
+}
+
+#[cfg(windows)]
+impl Pid {
+    /// Opens a handle to the process with the given access `rights`.
+    pub(crate) fn open(
+        &self,
+        rights: windows_sys::Win32::System::Threading::PROCESS_ACCESS_RIGHTS,
+    ) -> std::io::Result<crate::job_object::Handle> {
+        use windows_sys::Win32::System::Threading::OpenProcess;
+
+        // SAFETY: Inherently unsafe as a system call, but the parameters are valid.
+        let handle = unsafe { OpenProcess(rights, 0, self.0) };
+        crate::job_object::Handle::new(handle)
+    }
+
+    /// Retrieves the current CPU time, sum of the user mode and kernel mode times.
+    pub fn get_cputime(&self) -> Duration {
+        use windows_sys::Win32::Foundation::FILETIME;
+        use windows_sys::Win32::System::Threading::{
+            GetProcessTimes, PROCESS_QUERY_LIMITED_INFORMATION,
         };
 
-        // SAFETY: Inherently unsafe as a syscall but the PID and the signal are valid values.
-        let res = unsafe { libc::kill(self.0 as _, sig) };
+        let Ok(process) = self.open(PROCESS_QUERY_LIMITED_INFORMATION) else {
+            return Duration::from_secs(0);
+        };
 
+        let zero = FILETIME {
+            dwLowDateTime: 0,
+            dwHighDateTime: 0,
+        };
+        let (mut creation, mut exit, mut kernel, mut user) = (zero, zero, zero, zero);
+        // SAFETY: Inherently unsafe as a system call, but the handle and pointers are valid.
+        let res = unsafe {
+            GetProcessTimes(
+                process.raw(),
+                &mut creation,
+                &mut exit,
+                &mut kernel,
+                &mut user,
+            )
+        };
         if res == 0 {
-            Ok(())
-        } else {
-            Err(())
+            return Duration::from_secs(0);
         }
+
+        // FILETIME values are expressed in 100 ns intervals
+        let ticks =
+            |time: FILETIME| (u64::from(time.dwHighDateTime) << 32) | u64::from(time.dwLowDateTime);
+        Duration::from_nanos((ticks(kernel) + ticks(user)) * 100)
+    }
+
+    /// Indicates whether the process is alive or not.
+    pub fn alive(&self) -> bool {
+        use windows_sys::Win32::Foundation::STILL_ACTIVE;
+        use windows_sys::Win32::System::Threading::{
+            GetExitCodeProcess, PROCESS_QUERY_LIMITED_INFORMATION,
+        };
+
+        let Ok(process) = self.open(PROCESS_QUERY_LIMITED_INFORMATION) else {
+            return false;
+        };
+
+        let mut code = 0;
+        // SAFETY: Inherently unsafe as a system call, but the handle and pointer are valid.
+        let res = unsafe { GetExitCodeProcess(process.raw(), &mut code) };
+        res != 0 && code == STILL_ACTIVE as u32
+    }
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod test {
+    use std::process::Command;
+    use std::thread;
+    use std::time::Duration;
+
+    use super::Pid;
+
+    #[test]
+    fn descendants() {
+        let mut child = Command::new("sh")
+            .args(["-c", "sleep 10 & sleep 10 & wait"])
+            .spawn()
+            .unwrap();
+        thread::sleep(Duration::from_millis(200));
+
+        let pid = Pid::from(child.id());
+        let mut descendants = pid.descendants().unwrap();
+        descendants.sort();
+        assert_eq!(descendants.len(), 2);
+        assert!(descendants
+            .iter()
+            .all(|descendant| descendant.is_child_of(pid)));
+
+        for descendant in descendants {
+            let _ = descendant.kill(&super::Signal::SIGKILL);
+        }
+        child.wait().unwrap();
+    }
+
+    #[test]
+    fn threads_cputime() {
+        let pid = Pid::from(std::process::id());
+        let before = pid.get_cputime();
+
+        // each thread spins until it used 200 ms, on any number of cores
+        let spinners: Vec<_> = (0..2)
+            .map(|_| {
+                thread::spawn(move || {
+                    // SAFETY: Always successful.
+                    let tid = Pid::from(unsafe { libc::gettid() } as u32);
+                    while pid.get_thread_cputime(tid) < Duration::from_millis(200) {}
+                })
+            })
+            .collect();
+        for spinner in spinners {
+            spinner.join().unwrap();
+        }
+
+        // summed over the threads, not the main one only
+        assert!(pid.get_cputime() - before >= Duration::from_millis(400));
+    }
+
+    #[test]
+    fn unthrottleable() {
+        let current = Pid::from(std::process::id());
+        assert!(!current.is_kernel_thread() && !current.is_zombie());
+        // kthreadd
+        assert!(Pid::from(2).is_kernel_thread());
+
+        let mut child = Command::new("true").spawn().unwrap();
+        let pid = Pid::from(child.id());
+        for _ in 0..100 {
+            if pid.is_zombie() {
+                break;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert!(pid.is_zombie());
+        assert!(matches!(
+            crate::CpuLimit::new(pid, 50_f64),
+            Err(crate::Error::UnthrottleableTarget(target)) if target == pid
+        ));
+        child.wait().unwrap();
+    }
+
+    #[test]
+    fn rss() {
+        let rss = Pid::from(std::process::id()).get_rss().unwrap();
+        assert!(rss > 0 && rss % 1024 == 0);
+        // kthreadd
+        assert_eq!(Pid::from(2).get_rss(), None);
+    }
+
+    #[test]
+    fn reaped_cputime() {
+        let mut child = Command::new("sh")
+            .args([
+                "-c",
+                "(i=0; while [ $i -lt 100000 ]; do i=$((i+1)); done); sleep 10",
+            ])
+            .spawn()
+            .unwrap();
+        let pid = Pid::from(child.id());
+
+        // the subshell is reaped once its loop is over
+        for _ in 0..100 {
+            if !pid.get_reaped_cputime().is_zero() {
+                break;
+            }
+            thread::sleep(Duration::from_millis(100));
+        }
+        assert!(!pid.get_reaped_cputime().is_zero());
+
+        child.kill().unwrap();
+        child.wait().unwrap();
     }
 }
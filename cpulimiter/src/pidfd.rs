@@ -0,0 +1,171 @@
+//! Designate processes by file descriptor rather than by PID.
+//!
+//! A pidfd keeps referring to the process it was opened for once it exits:
+//! signals sent through it can't reach another process which got the same PID,
+//! and it becomes readable as soon as the process exits. Available since
+//! Linux 5.3, the callers fall back to `kill(2)` and polling otherwise.
+
+use std::io;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::ptr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::error::{Error, Result};
+use crate::pid::{Pid, Signal};
+
+/// How often the process is checked for exiting, without a pidfd.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Set once the kernel turned out not to support pidfds, not to ask it again.
+static UNSUPPORTED: AtomicBool = AtomicBool::new(false);
+
+/// A file descriptor referring to a process.
+#[derive(Debug)]
+pub(crate) struct PidFd {
+    fd: OwnedFd,
+    pid: Pid,
+}
+
+impl PidFd {
+    /// Opens a pidfd for `pid`.
+    ///
+    /// Fails with `ESRCH` if the process doesn't exist, and `ENOSYS` if the
+    /// kernel doesn't support pidfds.
+    pub fn open(pid: Pid) -> io::Result<Self> {
+        if UNSUPPORTED.load(Ordering::Relaxed) {
+            return Err(io::Error::from_raw_os_error(libc::ENOSYS));
+        }
+        // SAFETY: the call takes a PID and no flags, and returns a new descriptor.
+        let fd = unsafe { libc::syscall(libc::SYS_pidfd_open, u32::from(pid), 0) };
+        if fd < 0 {
+            let err = io::Error::last_os_error();
+            if err.raw_os_error() == Some(libc::ENOSYS) {
+                UNSUPPORTED.store(true, Ordering::Relaxed);
+            }
+            return Err(err);
+        }
+        // SAFETY: the descriptor was just opened, and is owned by no one else.
+        let fd = unsafe { OwnedFd::from_raw_fd(fd as libc::c_int) };
+        Ok(Self { fd, pid })
+    }
+
+    /// Sends `signal` to the process, failing with `ESRCH` once it exited.
+    pub fn send_signal(&self, signal: &Signal) -> Result<()> {
+        // SAFETY: the descriptor is valid, and no `siginfo` is passed.
+        let res = unsafe {
+            libc::syscall(
+                libc::SYS_pidfd_send_signal,
+                self.fd.as_raw_fd(),
+                signal.as_raw(),
+                ptr::null::<libc::siginfo_t>(),
+                0,
+            )
+        };
+        if res == 0 {
+            Ok(())
+        } else {
+            Err(Error::SignalFailed {
+                pid: self.pid,
+                signal: *signal,
+                errno: io::Error::last_os_error()
+                    .raw_os_error()
+                    .unwrap_or_default(),
+            })
+        }
+    }
+
+    /// Waits up to `timeout`, or for good if `None`, for the process to exit.
+    ///
+    /// Returns whether it exited.
+    pub fn wait(&self, timeout: Option<Duration>) -> io::Result<bool> {
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        loop {
+            let mut pollfd = libc::pollfd {
+                fd: self.fd.as_raw_fd(),
+                events: libc::POLLIN,
+                revents: 0,
+            };
+            let millis = deadline.map_or(-1, |deadline| {
+                let left = deadline.saturating_duration_since(Instant::now());
+                // rounded up, not to spin before the deadline
+                libc::c_int::try_from(left.as_nanos().div_ceil(1_000_000))
+                    .unwrap_or(libc::c_int::MAX)
+            });
+            // SAFETY: `pollfd` is a single valid entry.
+            match unsafe { libc::poll(&mut pollfd, 1, millis) } {
+                -1 => {
+                    let err = io::Error::last_os_error();
+                    if err.kind() != io::ErrorKind::Interrupted {
+                        return Err(err);
+                    }
+                }
+                0 => return Ok(false),
+                _ => return Ok(true),
+            }
+        }
+    }
+}
+
+impl Pid {
+    /// Waits up to `timeout`, or for good if `None`, for the process to exit.
+    ///
+    /// Returns whether it exited. Notified at once through a pidfd where the
+    /// kernel supports it, checked every 100 ms otherwise. Unlike [`Pid::alive`],
+    /// a zombie counts as exited with a pidfd.
+    pub fn wait_exit(&self, timeout: Option<Duration>) -> bool {
+        match PidFd::open(*self) {
+            Ok(pidfd) => match pidfd.wait(timeout) {
+                Ok(exited) => exited,
+                Err(_) => self.poll_exit(timeout),
+            },
+            Err(err) if err.raw_os_error() == Some(libc::ESRCH) => true,
+            Err(_) => self.poll_exit(timeout),
+        }
+    }
+
+    /// Checks whether the process exited every [`POLL_INTERVAL`], up to `timeout`.
+    fn poll_exit(&self, timeout: Option<Duration>) -> bool {
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        loop {
+            if !self.alive() {
+                return true;
+            }
+            let left = deadline.map_or(POLL_INTERVAL, |deadline| {
+                deadline.saturating_duration_since(Instant::now())
+            });
+            if left.is_zero() {
+                return false;
+            }
+            thread::sleep(left.min(POLL_INTERVAL));
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::process::Command;
+
+    use super::*;
+
+    #[test]
+    fn wait_exit() {
+        let mut child = Command::new("sleep").arg("10").spawn().unwrap();
+        let pid = Pid::from(child.id());
+        let pidfd = PidFd::open(pid).unwrap();
+        assert!(!pid.wait_exit(Some(Duration::from_millis(50))));
+
+        pidfd.send_signal(&Signal::SIGKILL).unwrap();
+        assert!(pidfd.wait(Some(Duration::from_secs(5))).unwrap());
+        child.wait().unwrap();
+        // the PID may have been reused, not the descriptor
+        assert!(matches!(
+            pidfd.send_signal(&Signal::SIGCONT),
+            Err(Error::SignalFailed {
+                errno: libc::ESRCH,
+                ..
+            })
+        ));
+    }
+}
@@ -0,0 +1,431 @@
+//! Limit many processes from a single control thread.
+//!
+//! Every member of the pool is throttled with its own time slice, like a
+//! [`CpuLimit`](crate::CpuLimit) would, but the suspensions and resumptions of
+//! all the members are scheduled on a timing wheel driven by one thread. The
+//! thread sleeps while the pool is empty.
+//!
+//! While the machine is saturated, the members with the lowest priorities are
+//! held below their limits, more so every slice, to leave the CPU to the
+//! others. They are let back up to their limits once it isn't anymore.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Instant;
+
+use parking_lot::{Condvar, Mutex};
+
+use crate::controller;
+use crate::error::{Error, Result};
+use crate::limiter::{LimitMode, SLICE_DURATION};
+use crate::pid::Pid;
+use crate::process_group::{ChildrenMode, GroupStats, ProcessGroup};
+use crate::rescue;
+use crate::system::SystemUsage;
+use crate::target::Target;
+
+/// The number of ticks in a slice, and of slots in the wheel.
+///
+/// The working time of a member is rounded to a tick, that is 5% of the slice.
+const SLOTS: usize = 20;
+
+/// The utilization of the machine, between 0 and 1, from which it is saturated.
+const SATURATION: f64 = 0.95;
+
+/// How much the pressure on the low-priority members changes every slice.
+const PRESSURE_STEP: f64 = 0.1;
+
+/// The share of its limit the member with the lowest priority keeps under full pressure.
+const MIN_SHARE: f64 = 0.1;
+
+/// Designates a member of a [`LimiterPool`].
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct PoolMember(u64);
+
+/// What happens to a member when its slot is reached.
+#[derive(Clone, Copy)]
+enum Phase {
+    /// A new slice starts: the member is measured and resumed.
+    Slice,
+    /// The working time of the member is over: it is suspended.
+    Suspend,
+}
+
+/// A ring of slots, one per tick, holding the members to handle at that tick.
+struct TimingWheel {
+    slots: Vec<Vec<(PoolMember, Phase)>>,
+    current: usize,
+}
+
+impl TimingWheel {
+    fn new() -> Self {
+        Self {
+            slots: (0..SLOTS).map(|_| Vec::new()).collect(),
+            current: 0,
+        }
+    }
+
+    /// Handles `member` in `ticks` ticks, between 1 and a whole slice.
+    fn schedule(&mut self, ticks: usize, member: PoolMember, phase: Phase) {
+        debug_assert!((1..=SLOTS).contains(&ticks));
+        let slot = (self.current + ticks) % SLOTS;
+        self.slots[slot].push((member, phase));
+    }
+
+    /// Moves to the next tick and takes what is due.
+    fn advance(&mut self) -> Vec<(PoolMember, Phase)> {
+        self.current = (self.current + 1) % SLOTS;
+        std::mem::take(&mut self.slots[self.current])
+    }
+}
+
+/// A process limited by the pool.
+struct Member {
+    group: ProcessGroup,
+    limit: f64,
+    /// The higher, the less the member is throttled when the machine is saturated.
+    priority: u8,
+    /// The share of the limit the member is held to, below 1 under pressure.
+    share: f64,
+    working_rate: f64,
+}
+
+/// The state shared between the handle and the control thread.
+#[derive(Default)]
+struct State {
+    members: HashMap<PoolMember, Member>,
+    /// The members added since the last tick, not on the wheel yet.
+    added: Vec<PoolMember>,
+    next_id: u64,
+    /// How hard the low-priority members are throttled, between 0 and 1.
+    pressure: f64,
+    shutdown: bool,
+}
+
+/// A handle to a thread limiting many processes, each one to its own limit.
+///
+/// Cheaper than a [`CpuLimit`](crate::CpuLimit) per process when there are
+/// dozens of them. The members are resumed when the pool is dropped.
+pub struct LimiterPool {
+    shared: Arc<(Mutex<State>, Condvar)>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl LimiterPool {
+    /// Starts the control thread of an empty pool.
+    pub fn new() -> Result<Self> {
+        rescue::install();
+        let shared: Arc<(Mutex<State>, Condvar)> = Arc::default();
+        let shared_clone = shared.clone();
+        let thread = thread::Builder::new().spawn(move || pool_fn(&shared_clone))?;
+
+        Ok(Self {
+            shared,
+            thread: Some(thread),
+        })
+    }
+
+    /// Limits the CPU time of `pid` to `limit`, as a percentage of one core.
+    ///
+    /// Fails with [`Error::InvalidLimit`] unless `limit` is positive and within
+    /// the cores online, or with [`Error::PermissionDenied`] if the process
+    /// can't be signaled.
+    pub fn add(&self, pid: Pid, limit: f64) -> Result<PoolMember> {
+        self.add_with_priority(pid, limit, 0)
+    }
+
+    /// Limits the CPU time of `pid` to `limit` like [`add`](Self::add), with a
+    /// `priority` above that of the members added without one.
+    ///
+    /// While the machine is saturated, the members with lower priorities are
+    /// throttled harder, and the ones with the highest priority only to their limits.
+    pub fn add_with_priority(&self, pid: Pid, limit: f64, priority: u8) -> Result<PoolMember> {
+        LimitMode::PerCore.check(limit)?;
+        let mut group = ProcessGroup::new(&[Target::Pid(pid)], ChildrenMode::Exclude)?;
+        group.check_permission()?;
+        group.set_limit(limit);
+
+        let (state, wakeup) = &*self.shared;
+        let mut state = state.lock();
+        let member = PoolMember(state.next_id);
+        state.next_id += 1;
+        state.members.insert(
+            member,
+            Member {
+                group,
+                limit,
+                priority,
+                share: 1_f64,
+                working_rate: 1_f64,
+            },
+        );
+        state.added.push(member);
+        wakeup.notify_one();
+        Ok(member)
+    }
+
+    /// Updates the limit of `member`, from its next slice on.
+    ///
+    /// Fails with [`Error::DeadTarget`] if the member left the pool.
+    pub fn set_limit(&self, member: PoolMember, limit: f64) -> Result<()> {
+        LimitMode::PerCore.check(limit)?;
+        let mut state = self.shared.0.lock();
+        let member = state.members.get_mut(&member).ok_or(Error::DeadTarget)?;
+        member.limit = limit;
+        member.group.set_limit(limit);
+        Ok(())
+    }
+
+    /// Updates the priority of `member`, from the next slice on.
+    ///
+    /// Fails with [`Error::DeadTarget`] if the member left the pool.
+    pub fn set_priority(&self, member: PoolMember, priority: u8) -> Result<()> {
+        let mut state = self.shared.0.lock();
+        let member = state.members.get_mut(&member).ok_or(Error::DeadTarget)?;
+        member.priority = priority;
+        Ok(())
+    }
+
+    /// Stops limiting `member`, and resumes it.
+    ///
+    /// Fails with [`Error::DeadTarget`] if the member already left the pool.
+    pub fn remove(&self, member: PoolMember) -> Result<()> {
+        let member = self
+            .shared
+            .0
+            .lock()
+            .members
+            .remove(&member)
+            .ok_or(Error::DeadTarget)?;
+        member.group.resume()
+    }
+
+    /// Indicates whether `member` is still limited.
+    ///
+    /// Members leave the pool when their process exits or can't be signaled anymore.
+    pub fn contains(&self, member: PoolMember) -> bool {
+        self.shared.0.lock().members.contains_key(&member)
+    }
+
+    /// Retrieves the statistics of `member`, if it is still limited.
+    pub fn stats(&self, member: PoolMember) -> Option<GroupStats> {
+        let state = self.shared.0.lock();
+        state
+            .members
+            .get(&member)
+            .map(|member| member.group.stats())
+    }
+
+    /// Retrieves the number of processes limited by the pool.
+    pub fn len(&self) -> usize {
+        self.shared.0.lock().members.len()
+    }
+
+    /// Indicates whether the pool limits no process.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl Drop for LimiterPool {
+    fn drop(&mut self) {
+        let (state, wakeup) = &*self.shared;
+        state.lock().shutdown = true;
+        wakeup.notify_one();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// The control function, to be run in a separate thread.
+fn pool_fn(shared: &(Mutex<State>, Condvar)) {
+    let (state, wakeup) = shared;
+    let tick = SLICE_DURATION / SLOTS as u32;
+    let mut wheel = TimingWheel::new();
+    let mut next_tick = Instant::now();
+    let mut system = SystemUsage::new();
+    let mut ticks = 0_usize;
+
+    let mut state = state.lock();
+    loop {
+        if state.shutdown {
+            for member in state.members.values() {
+                let _ = member.group.resume();
+            }
+            return;
+        }
+        if state.members.is_empty() {
+            wakeup.wait(&mut state);
+            next_tick = Instant::now();
+            continue;
+        }
+        if wakeup.wait_until(&mut state, next_tick).timed_out() {
+            next_tick += tick;
+            ticks += 1;
+        } else {
+            // woken up by a handle, or spuriously
+            continue;
+        }
+        if ticks.is_multiple_of(SLOTS) {
+            rebalance(&mut state, system.update());
+        }
+
+        // the new members start their first slice at this tick
+        let mut due = std::mem::take(&mut state.added)
+            .into_iter()
+            .map(|id| (id, Phase::Slice))
+            .collect::<Vec<_>>();
+        due.extend(wheel.advance());
+
+        for (id, phase) in due {
+            let Some(member) = state.members.get_mut(&id) else {
+                // removed from the pool meanwhile
+                continue;
+            };
+            let handled = match phase {
+                Phase::Slice => start_slice(member, id, &mut wheel),
+                Phase::Suspend => member.group.suspend(),
+            };
+            if handled.is_err() {
+                // the process exited or can't be signaled anymore
+                let member = state.members.remove(&id).unwrap();
+                let _ = member.group.resume();
+            }
+        }
+    }
+}
+
+/// Updates the pressure on the low-priority members given the `utilization`
+/// of the machine, between 0 and 1, and the share of its limit each member gets.
+///
+/// Under full pressure, the shares range from [`MIN_SHARE`] for the lowest
+/// priority to 1 for the highest.
+fn rebalance(state: &mut State, utilization: f64) {
+    let step = if utilization >= SATURATION {
+        PRESSURE_STEP
+    } else {
+        -PRESSURE_STEP
+    };
+    state.pressure = (state.pressure + step).clamp(0_f64, 1_f64);
+
+    let priorities = state.members.values().map(|member| member.priority);
+    let lowest = priorities.clone().min().unwrap_or_default();
+    let highest = priorities.max().unwrap_or_default();
+    for member in state.members.values_mut() {
+        let rank = if highest > lowest {
+            f64::from(highest - member.priority) / f64::from(highest - lowest)
+        } else {
+            0_f64
+        };
+        member.share = 1_f64 - state.pressure * rank * (1_f64 - MIN_SHARE);
+    }
+}
+
+/// Measures `member`, lets it run for its working time, and schedules the rest of its slice.
+fn start_slice(member: &mut Member, id: PoolMember, wheel: &mut TimingWheel) -> Result<()> {
+    member.group.update()?;
+
+    let cpu_usage = member.group.cpu_usage();
+    let target = LimitMode::PerCore.cores(member.limit * member.share);
+    member.working_rate = controller::correct_rate(member.working_rate, target, cpu_usage);
+
+    let work_ticks = (member.working_rate * SLOTS as f64).round() as usize;
+    if work_ticks == 0 {
+        member.group.suspend()?;
+    } else {
+        member.group.resume()?;
+        if work_ticks < SLOTS {
+            wheel.schedule(work_ticks, id, Phase::Suspend);
+        }
+    }
+    member
+        .group
+        .record_cycle(member.working_rate, work_ticks < SLOTS);
+    wheel.schedule(SLOTS, id, Phase::Slice);
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use std::process::Command;
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn add_and_remove() {
+        let mut children: Vec<_> = (0..2)
+            .map(|_| Command::new("sleep").arg("10").spawn().unwrap())
+            .collect();
+        let pool = LimiterPool::new().unwrap();
+
+        let members: Vec<_> = children
+            .iter()
+            .map(|child| pool.add(Pid::from(child.id()), 50_f64).unwrap())
+            .collect();
+        assert_eq!(pool.len(), 2);
+        assert!(matches!(
+            pool.set_limit(members[0], -5_f64),
+            Err(Error::InvalidLimit(_))
+        ));
+        thread::sleep(Duration::from_millis(300));
+
+        pool.remove(members[0]).unwrap();
+        assert!(!pool.contains(members[0]));
+        assert_eq!(pool.stats(members[1]).unwrap().limit, 50_f64);
+        assert!(!Pid::from(children[0].id()).is_stopped());
+        drop(pool);
+        assert!(!Pid::from(children[1].id()).is_stopped());
+
+        for child in &mut children {
+            child.kill().unwrap();
+            child.wait().unwrap();
+        }
+    }
+
+    #[test]
+    fn priorities() {
+        let mut children: Vec<_> = (0..3)
+            .map(|_| Command::new("sleep").arg("10").spawn().unwrap())
+            .collect();
+        let mut state = State::default();
+        for (id, (child, priority)) in children.iter().zip([0, 5, 10]).enumerate() {
+            let pid = Pid::from(child.id());
+            let member = Member {
+                group: ProcessGroup::new(&[Target::Pid(pid)], ChildrenMode::Exclude).unwrap(),
+                limit: 50_f64,
+                priority,
+                share: 1_f64,
+                working_rate: 1_f64,
+            };
+            state.members.insert(PoolMember(id as u64), member);
+        }
+        let shares = |state: &State| -> Vec<f64> {
+            (0..3)
+                .map(|id| state.members[&PoolMember(id)].share)
+                .collect()
+        };
+
+        // the pressure builds up while the machine is saturated
+        rebalance(&mut state, 1_f64);
+        let first = shares(&state);
+        assert!(first[0] < first[1] && first[1] < first[2] && first[2] == 1_f64);
+        for _ in 0..20 {
+            rebalance(&mut state, 1_f64);
+        }
+        assert!((shares(&state)[0] - MIN_SHARE).abs() < 1e-9);
+
+        // and is released once it isn't
+        for _ in 0..20 {
+            rebalance(&mut state, 0.5);
+        }
+        assert_eq!(shares(&state), vec![1_f64; 3]);
+
+        for child in &mut children {
+            child.kill().unwrap();
+            child.wait().unwrap();
+        }
+    }
+}
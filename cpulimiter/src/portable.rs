@@ -0,0 +1,183 @@
+//! A minimal signal-based limiter for the Unix systems without procfs.
+//!
+//! The process information comes from the platform-specific implementations
+//! of [`Pid`] and [`ProcessIterator`]: only the basic [`CpuLimit`] API is
+//! available on these systems.
+
+use std::sync::mpsc::{self, Receiver, SyncSender};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use parking_lot::RwLock;
+
+use crate::error::{self, Error, Result};
+use crate::pid::Signal;
+use crate::process_iterator::ProcessIterator;
+use crate::Pid;
+
+/// The granularity of the control slice.
+pub const SLICE_DURATION: Duration = Duration::from_millis(100);
+
+/// Messages sent to the limiting thread to change its behavior.
+pub enum Command {
+    Limit(f64),
+    Stop,
+}
+
+/// The target process and its children, if they are included.
+struct Group {
+    target: Pid,
+    include_children: bool,
+    children: Vec<Pid>,
+    last_update: Instant,
+    total_time: Duration,
+    cpu_usage: f64,
+}
+
+impl Group {
+    /// Computes the CPU usage since the last call and smoothly updates the value.
+    fn update(&mut self) -> Result<()> {
+        if !self.target.alive() {
+            return Err(Error::DeadTarget);
+        }
+
+        let prev_time = self.total_time;
+        self.total_time = self.target.get_cputime();
+
+        if self.include_children {
+            if let Ok(processes) = ProcessIterator::new() {
+                self.children = processes
+                    .filter(|&process| process != self.target && process.is_child_of(self.target))
+                    .collect();
+                self.total_time += self.children.iter().map(Pid::get_cputime).sum();
+            }
+        }
+
+        let consumed = self.total_time.saturating_sub(prev_time);
+
+        if !prev_time.is_zero() {
+            let elapsed = self.last_update.elapsed();
+            self.last_update = Instant::now();
+
+            let cpu_usage = consumed.as_secs_f64() / elapsed.as_secs_f64();
+
+            // smooth out strong fluctuations
+            self.cpu_usage = 0.8 * self.cpu_usage + 0.2 * cpu_usage;
+        }
+
+        Ok(())
+    }
+
+    /// Sends a signal to the target process and its children.
+    fn kill(&self, signal: &Signal) {
+        let _ = self.target.kill(signal);
+        for child in &self.children {
+            let _ = child.kill(signal);
+        }
+    }
+}
+
+/// A handle to manage the CPU limit enforced on the target process.
+#[derive(Clone)]
+pub struct CpuLimit {
+    sender: SyncSender<Command>,
+    group: Arc<RwLock<Group>>,
+}
+
+/// The limiting function, to be run in a separate thread.
+fn limiter_fn(limit: f64, group: &Arc<RwLock<Group>>, rx: &Receiver<Command>) {
+    let mut limit = limit / 100_f64;
+    let mut working_rate = 1_f64;
+
+    loop {
+        if let Ok(cmd) = rx.try_recv() {
+            match cmd {
+                Command::Limit(new_limit) => limit = new_limit / 100_f64,
+                Command::Stop => {
+                    group.read().kill(&Signal::SIGCONT);
+                    break;
+                }
+            }
+        }
+
+        if group.write().update().is_err() {
+            // bail-out if the target process is dead.
+            break;
+        }
+
+        let cpu_usage = group.read().cpu_usage;
+        working_rate *= limit / cpu_usage;
+        working_rate = f64::min(working_rate, 1_f64);
+
+        group.read().kill(&Signal::SIGCONT);
+        let work_time = SLICE_DURATION.mul_f64(working_rate);
+        thread::sleep(work_time);
+
+        let sleep_time = SLICE_DURATION - work_time;
+        group.read().kill(&Signal::SIGSTOP);
+        thread::sleep(sleep_time);
+    }
+}
+
+/// Checks that `limit` is positive and doesn't exceed the cores available.
+fn check_limit(limit: f64) -> Result<()> {
+    let cores = thread::available_parallelism().map_or(1, usize::from);
+    error::check_limit(limit, 100_f64 * cores as f64)
+}
+
+impl CpuLimit {
+    /// Limits the CPU time of the target process only.
+    pub fn new(pid: Pid, limit: f64) -> Result<Self> {
+        Self::start_limit(pid, limit, false)
+    }
+
+    /// Limits the CPU time of the target process and its children.
+    pub fn new_with_children(pid: Pid, limit: f64) -> Result<Self> {
+        Self::start_limit(pid, limit, true)
+    }
+
+    /// Limits the CPU time of the target process (and its children if asked to).
+    fn start_limit(pid: Pid, limit: f64, include_children: bool) -> Result<Self> {
+        check_limit(limit)?;
+        let (tx, rx) = mpsc::sync_channel(1);
+        let mut group = Group {
+            target: pid,
+            include_children,
+            children: Vec::new(),
+            last_update: Instant::now(),
+            total_time: Duration::from_secs(0),
+            cpu_usage: 0_f64,
+        };
+        group.update()?;
+        let group = Arc::new(RwLock::new(group));
+
+        let group_clone = group.clone();
+        thread::Builder::new().spawn(move || limiter_fn(limit, &group_clone, &rx))?;
+
+        Ok(CpuLimit { sender: tx, group })
+    }
+
+    /// Updates the limit applied to the target process.
+    pub fn set_limit(&self, limit: f64) -> Result<()> {
+        check_limit(limit)?;
+        self.sender.send(Command::Limit(limit))?;
+        Ok(())
+    }
+
+    /// Stops the limiting thread.
+    pub fn stop(&self) -> Result<()> {
+        self.sender.send(Command::Stop)?;
+        Ok(())
+    }
+
+    /// Retrieves the CPU usage of the target process(es).
+    pub fn cpu_usage(&self) -> f64 {
+        self.group.read().cpu_usage
+    }
+
+    /// Retrieves the total amount of CPU time used by the target process(es).
+    pub fn total_cpu_time(&self) -> Duration {
+        self.group.read().total_time
+    }
+}
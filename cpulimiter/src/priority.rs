@@ -0,0 +1,46 @@
+//! Lower the scheduling priority of processes instead of suspending them.
+//!
+//! Every thread is moved to the `SCHED_IDLE` policy: it only runs when no
+//! other thread of the system wants the CPU. The processes are never stopped,
+//! so their timers and network connections are not disturbed.
+//!
+//! See `man sched` for a description of the policies.
+
+use std::collections::HashMap;
+use std::io;
+
+use crate::pid::{Pid, Scheduling};
+
+/// The threads moved to `SCHED_IDLE`, along with their original scheduling.
+pub(crate) struct Deprioritized {
+    originals: HashMap<Pid, Scheduling>,
+}
+
+impl Deprioritized {
+    pub fn new() -> Self {
+        Self {
+            originals: HashMap::new(),
+        }
+    }
+
+    /// Lowers the priority of the threads of `pid` that were not handled yet.
+    pub fn attach(&mut self, pid: Pid) -> io::Result<()> {
+        for tid in pid.threads()? {
+            if self.originals.contains_key(&tid) {
+                continue;
+            }
+
+            let original = tid.scheduling()?;
+            tid.set_scheduling(&Scheduling::IDLE)?;
+            self.originals.insert(tid, original);
+        }
+        Ok(())
+    }
+
+    /// Restores the original scheduling of every thread still alive.
+    pub fn release(self) {
+        for (tid, original) in self.originals {
+            let _ = tid.set_scheduling(&original);
+        }
+    }
+}
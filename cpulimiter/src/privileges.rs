@@ -0,0 +1,102 @@
+//! Drop the privileges of the process once the limiters are set up.
+//!
+//! The kernel lets an unprivileged process signal the processes whose real or
+//! saved user ID is its real or effective user ID. Once the real, effective
+//! and saved IDs are all set to the new user, only the processes of that user
+//! remain within reach.
+
+use std::fs;
+use std::io;
+
+use crate::error::{Error, Result};
+use crate::pid::Pid;
+use crate::procfs;
+
+/// Indicates whether a process running as `uid` only may signal `pid`.
+pub(crate) fn can_signal_as(pid: Pid, uid: u32) -> bool {
+    if uid == 0 {
+        return true;
+    }
+    let Ok(status) = fs::read_to_string(procfs::path(format!("{pid}/status"))) else {
+        return false;
+    };
+    // real, effective, saved and filesystem user IDs
+    let uids: Vec<u32> = status
+        .lines()
+        .find_map(|line| line.strip_prefix("Uid:"))
+        .map(|uids| {
+            uids.split_whitespace()
+                .filter_map(|uid| uid.parse().ok())
+                .collect()
+        })
+        .unwrap_or_default();
+    uids.first() == Some(&uid) || uids.get(2) == Some(&uid)
+}
+
+/// Switches the whole process to the user `uid` and the group `gid` for good,
+/// supplementary groups dropped, unless it already runs as them only.
+///
+/// Call it last, once everything requiring root is set up: the limiters built
+/// with [`CpuLimitBuilder::run_as`](crate::CpuLimitBuilder::run_as), their
+/// recovery files, control sockets... Fails with [`Error::Privileges`].
+///
+/// # Example
+///
+/// ```no_run
+/// use cpulimiter::{drop_privileges, CpuLimit, Pid};
+///
+/// let limiter = CpuLimit::builder(10.0)
+///     .target(Pid::from(1048))
+///     .run_as(1000)
+///     .build()
+///     .unwrap();
+/// drop_privileges(1000, 1000).unwrap();
+/// ```
+pub fn drop_privileges(uid: u32, gid: u32) -> Result<()> {
+    drop_to(uid, gid).map_err(Error::Privileges)
+}
+
+/// Switches the whole process to `uid` and `gid`, see [`drop_privileges`].
+fn drop_to(uid: u32, gid: u32) -> io::Result<()> {
+    // SAFETY: these calls only read the IDs of the process.
+    let current = unsafe {
+        (
+            libc::getuid(),
+            libc::geteuid(),
+            libc::getgid(),
+            libc::getegid(),
+        )
+    };
+    if current == (uid, uid, gid, gid) {
+        return Ok(());
+    }
+
+    // the groups first, as changing them requires the privileges of root
+    // SAFETY: an empty list of supplementary groups is valid.
+    if unsafe { libc::setgroups(0, std::ptr::null()) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    // SAFETY: plain system calls, applied to every thread by the C library.
+    if unsafe { libc::setresgid(gid, gid, gid) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    // SAFETY: as above.
+    if unsafe { libc::setresuid(uid, uid, uid) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn can_signal() {
+        let current = Pid::from(std::process::id());
+        let uid = current.get_uid().unwrap();
+        assert!(can_signal_as(current, uid));
+        assert!(can_signal_as(current, 0));
+        assert!(!can_signal_as(current, uid + 1));
+    }
+}
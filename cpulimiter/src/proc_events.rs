@@ -0,0 +1,226 @@
+//! Receive process lifecycle events from the kernel in real time.
+//!
+//! The proc connector reports every `fork`, `exec` and `exit` over a netlink
+//! socket, so that new children can be suspended as soon as they appear
+//! instead of waiting for the next rescan of `/proc`.
+//!
+//! Subscribing requires the `CAP_NET_ADMIN` capability: without it, the
+//! children are only discovered by [`ProcessGroup::update`].
+//!
+//! See `include/uapi/linux/cn_proc.h` in the kernel sources for the message layout.
+
+use std::io;
+use std::mem::size_of;
+use std::os::unix::io::{AsRawFd, FromRawFd, OwnedFd};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use parking_lot::RwLock;
+
+use crate::pid::Pid;
+use crate::process_group::ProcessGroup;
+
+/// The netlink protocol of the kernel connector.
+const NETLINK_CONNECTOR: libc::c_int = 11;
+/// The connector index and value of the proc connector.
+const CN_IDX_PROC: u32 = 1;
+const CN_VAL_PROC: u32 = 1;
+/// The operation to start receiving events.
+const PROC_CN_MCAST_LISTEN: u32 = 1;
+/// The event reporting a new task.
+const PROC_EVENT_FORK: u32 = 1;
+
+/// The size of `struct nlmsghdr`.
+const NLMSG_HEADER_LEN: usize = 16;
+/// The size of `struct cn_msg`, without its payload.
+const CN_MSG_HEADER_LEN: usize = 20;
+/// The offset of `struct proc_event` in a message.
+const EVENT_OFFSET: usize = NLMSG_HEADER_LEN + CN_MSG_HEADER_LEN;
+/// The offset of the event data in `struct proc_event`.
+const EVENT_DATA_OFFSET: usize = EVENT_OFFSET + 16;
+
+/// How often the listening thread checks whether it should stop.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// A thread adding new children to a [`ProcessGroup`] as soon as they are forked.
+pub(crate) struct ProcEventListener {
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl ProcEventListener {
+    /// Subscribes to the proc connector and starts listening for the children of `group`.
+    pub fn start(group: Arc<RwLock<ProcessGroup>>) -> io::Result<Self> {
+        let socket = subscribe()?;
+        let stop = Arc::new(AtomicBool::new(false));
+
+        let stop_clone = stop.clone();
+        let thread = thread::Builder::new().spawn(move || {
+            let mut buf = [0_u8; 1024];
+            while !stop_clone.load(Ordering::Relaxed) {
+                // SAFETY: Inherently unsafe as a syscall, but the buffer is valid for its whole length.
+                let len = unsafe {
+                    libc::recv(socket.as_raw_fd(), buf.as_mut_ptr().cast(), buf.len(), 0)
+                };
+
+                if len < 0 {
+                    // timeout: check the stop flag again
+                    continue;
+                }
+
+                if let Some((parent, child)) = parse_fork(&buf[..len as usize]) {
+                    group.write().adopt(parent, child);
+                }
+            }
+        })?;
+
+        Ok(Self {
+            stop,
+            thread: Some(thread),
+        })
+    }
+}
+
+impl Drop for ProcEventListener {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Opens a netlink socket and subscribes to the proc connector.
+fn subscribe() -> io::Result<OwnedFd> {
+    // SAFETY: Inherently unsafe as a syscall, but the parameters are valid.
+    let fd = unsafe {
+        libc::socket(
+            libc::AF_NETLINK,
+            libc::SOCK_DGRAM | libc::SOCK_CLOEXEC,
+            NETLINK_CONNECTOR,
+        )
+    };
+    if fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    // SAFETY: The file descriptor was just created and is owned by nobody else.
+    let socket = unsafe { OwnedFd::from_raw_fd(fd) };
+
+    // SAFETY: `sockaddr_nl` is a plain C struct that can be zeroed.
+    let mut addr: libc::sockaddr_nl = unsafe { std::mem::zeroed() };
+    addr.nl_family = libc::AF_NETLINK as libc::sa_family_t;
+    addr.nl_groups = CN_IDX_PROC;
+    // SAFETY: Inherently unsafe as a syscall, but the address is valid.
+    let res = unsafe {
+        libc::bind(
+            fd,
+            (&addr as *const libc::sockaddr_nl).cast(),
+            size_of::<libc::sockaddr_nl>() as libc::socklen_t,
+        )
+    };
+    if res != 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    let timeout = libc::timeval {
+        tv_sec: 0,
+        tv_usec: POLL_INTERVAL.as_micros() as libc::suseconds_t,
+    };
+    // SAFETY: Inherently unsafe as a syscall, but the option value is valid.
+    let res = unsafe {
+        libc::setsockopt(
+            fd,
+            libc::SOL_SOCKET,
+            libc::SO_RCVTIMEO,
+            (&timeout as *const libc::timeval).cast(),
+            size_of::<libc::timeval>() as libc::socklen_t,
+        )
+    };
+    if res != 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    let len = EVENT_OFFSET + size_of::<u32>();
+    let mut msg = Vec::with_capacity(len);
+    // struct nlmsghdr
+    msg.extend_from_slice(&(len as u32).to_ne_bytes());
+    msg.extend_from_slice(&(libc::NLMSG_DONE as u16).to_ne_bytes());
+    msg.extend_from_slice(&0_u16.to_ne_bytes()); // flags
+    msg.extend_from_slice(&0_u32.to_ne_bytes()); // sequence number
+    msg.extend_from_slice(&std::process::id().to_ne_bytes());
+    // struct cn_msg
+    msg.extend_from_slice(&CN_IDX_PROC.to_ne_bytes());
+    msg.extend_from_slice(&CN_VAL_PROC.to_ne_bytes());
+    msg.extend_from_slice(&0_u32.to_ne_bytes()); // sequence number
+    msg.extend_from_slice(&0_u32.to_ne_bytes()); // acknowledgment
+    msg.extend_from_slice(&(size_of::<u32>() as u16).to_ne_bytes());
+    msg.extend_from_slice(&0_u16.to_ne_bytes()); // flags
+
+    // enum proc_cn_mcast_op
+    msg.extend_from_slice(&PROC_CN_MCAST_LISTEN.to_ne_bytes());
+
+    // SAFETY: Inherently unsafe as a syscall, but the buffer is valid for its whole length.
+    let res = unsafe { libc::send(fd, msg.as_ptr().cast(), msg.len(), 0) };
+    if res < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(socket)
+}
+
+/// Extracts the parent and the child from a fork event.
+///
+/// Events about new threads are ignored: only new processes are reported.
+fn parse_fork(msg: &[u8]) -> Option<(Pid, Pid)> {
+    let read = |offset: usize| -> Option<u32> {
+        let bytes = msg.get(offset..offset + size_of::<u32>())?;
+        Some(u32::from_ne_bytes(bytes.try_into().ok()?))
+    };
+
+    if read(EVENT_OFFSET)? != PROC_EVENT_FORK {
+        return None;
+    }
+
+    // struct fork_proc_event
+    let parent_tgid = read(EVENT_DATA_OFFSET + 4)?;
+    let child_pid = read(EVENT_DATA_OFFSET + 8)?;
+    let child_tgid = read(EVENT_DATA_OFFSET + 12)?;
+
+    (child_pid == child_tgid).then(|| (Pid::from(parent_tgid), Pid::from(child_tgid)))
+}
+
+#[cfg(test)]
+mod test {
+    use super::{parse_fork, EVENT_DATA_OFFSET, EVENT_OFFSET, PROC_EVENT_FORK};
+    use crate::pid::Pid;
+
+    fn fork_event(parent: u32, child_pid: u32, child_tgid: u32) -> Vec<u8> {
+        let mut msg = vec![0; EVENT_DATA_OFFSET + 16];
+        msg[EVENT_OFFSET..EVENT_OFFSET + 4].copy_from_slice(&PROC_EVENT_FORK.to_ne_bytes());
+        for (idx, value) in [parent, parent, child_pid, child_tgid].iter().enumerate() {
+            let offset = EVENT_DATA_OFFSET + 4 * idx;
+            msg[offset..offset + 4].copy_from_slice(&value.to_ne_bytes());
+        }
+        msg
+    }
+
+    #[test]
+    fn new_process() {
+        let msg = fork_event(1200, 1300, 1300);
+        assert_eq!(parse_fork(&msg), Some((Pid::from(1200), Pid::from(1300))));
+    }
+
+    #[test]
+    fn new_thread() {
+        let msg = fork_event(1200, 1301, 1200);
+        assert_eq!(parse_fork(&msg), None);
+    }
+
+    #[test]
+    fn truncated_message() {
+        let msg = fork_event(1200, 1300, 1300);
+        assert_eq!(parse_fork(&msg[..EVENT_DATA_OFFSET + 8]), None);
+    }
+}
@@ -1,44 +1,492 @@
 //! Track the CPU usage of a process (and its children).
 
-use std::collections::HashSet;
-use std::time::{Duration, Instant};
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::io;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
 
+use parking_lot::Mutex;
+
+use crate::backend::ProcessBackend;
+use crate::cgroup::Cgroup;
 use crate::error::{Error, Result};
-use crate::pid::{Pid, Signal};
+use crate::event::{Event, Observers};
+use crate::inhibit::InhibitCheck;
+use crate::pid::{self, Pid, Signal};
+use crate::pidfd::PidFd;
 use crate::process_iterator::ProcessIterator;
+use crate::recovery::Journal;
+use crate::rescue;
+use crate::stat_iterator::{StatField, StatFileIter, StatReader};
+use crate::target::{self, Target};
+use crate::timeline::{Recorder, Timeline, TimelineSample};
+use crate::trace::{debug, warn};
+
+/// The default weight of the previous measurements in the CPU usage.
+pub(crate) const DEFAULT_SMOOTHING: f64 = 0.8;
+
+/// How long the working rates of the time slices are kept for [`DutyCycle`].
+const DUTY_CYCLE_WINDOW: Duration = Duration::from_secs(10);
 
 /// Whether the child processes should be monitored.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
 pub enum ChildrenMode {
     Include,
+    #[default]
     Exclude,
 }
 
-impl Default for ChildrenMode {
-    fn default() -> Self {
-        ChildrenMode::Exclude
+/// How to handle the processes stopped by someone else, a debugger or a user.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum StoppedPolicy {
+    /// Neither suspend nor resume a process that is already stopped (or traced)
+    /// when the group is suspended: it is only resumed by whoever stopped it.
+    ///
+    /// A process stopped while the group is suspended can't be told apart,
+    /// it is resumed along with the group.
+    #[default]
+    Skip,
+    /// Suspend and resume every process, whatever its state.
+    Override,
+}
+
+/// How the processes of a group are suspended and resumed.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum SuspendMode {
+    /// Send `SIGSTOP` and `SIGCONT` to each process in turn.
+    ///
+    /// With [`ChildrenMode::Include`], children may fork while their siblings
+    /// are being stopped.
+    #[default]
+    PerProcess,
+    /// Signal the process groups of the targets at once, with `killpg`.
+    ///
+    /// Every process of these process groups is suspended, including the ones
+    /// outside the limited group: this fits targets leading their own process
+    /// group. Members in other process groups are signaled in turn, and the
+    /// [`StoppedPolicy`] doesn't apply to the process groups.
+    ProcessGroup,
+    /// Freeze the members with the cgroup v2 freezer (`cgroup.freeze`).
+    ///
+    /// The members are moved to a dedicated cgroup, where their children are
+    /// created too: these are frozen whatever the [`ChildrenMode`]. Requires
+    /// write access to the unified hierarchy.
+    Freezer,
+}
+
+/// The signal suspending the processes, in [`SuspendMode::PerProcess`] and
+/// [`SuspendMode::ProcessGroup`].
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum StopSignal {
+    /// `SIGSTOP`, which can't be handled nor ignored.
+    #[default]
+    Stop,
+    /// `SIGTSTP`, which shells and terminal applications handle gracefully,
+    /// restoring the terminal for instance. A process ignoring it isn't limited.
+    Tstp,
+}
+
+impl StopSignal {
+    fn signal(self) -> Signal {
+        match self {
+            StopSignal::Stop => Signal::SIGSTOP,
+            StopSignal::Tstp => Signal::SIGTSTP,
+        }
     }
 }
 
-/// An abstraction to compute the CPU usage of a process and its children.
+/// Suspends the whole group as soon as children join it too fast, such as in a fork bomb.
+///
+/// The children are counted as they are discovered: at each update, or as soon
+/// as they are forked when the proc connector is available. The group stays
+/// suspended until it is explicitly resumed.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct ForkGuard {
+    /// The number of children allowed to join the group within `window`.
+    pub max_children: usize,
+    pub window: Duration,
+}
+
+/// What a [`MemoryGuard`] does once the group uses too much memory.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum MemoryAction {
+    /// Only send an [`Event::MemoryExceeded`], again whenever the threshold is crossed.
+    #[default]
+    Notify,
+    /// Suspend the whole group until it is explicitly resumed.
+    Suspend,
+    /// Send `SIGTERM` to every process of the group.
+    Terminate,
+}
+
+/// Watches the resident memory of the whole group, sampled at each update.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct MemoryGuard {
+    /// The resident memory allowed to the group, in bytes.
+    pub max_rss: u64,
+    pub action: MemoryAction,
+}
+
+/// The CPU usage of a process of a group.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct ProcessStats {
+    pub pid: Pid,
+    /// The CPU time used by all the threads since the process started.
+    pub cpu_time: Duration,
+    /// The CPU usage since the previous update, `1.0` is one fully used core.
+    ///
+    /// Summed over the threads, so it reaches the number of threads running
+    /// on separate cores. Zero until the process was measured twice.
+    pub cpu_usage: f64,
+    /// The number of threads at the last update, which bounds the cores the process can use.
+    ///
+    /// Zero if it isn't read from procfs, or for a limited thread.
+    pub threads: u64,
+    /// The part of `cpu_time` spent in user mode.
+    ///
+    /// Zero, as `system_time`, if it isn't read from procfs, or for a limited thread.
+    pub user_time: Duration,
+    /// The part of `cpu_time` spent in kernel mode, on behalf of the process.
+    pub system_time: Duration,
+    /// The part of `cpu_usage` spent in kernel mode.
+    pub system_usage: f64,
+    /// The state at the last update, such as `R` (running) or `D` (uninterruptible
+    /// sleep), see [`ProcessInfo::state`](crate::ProcessInfo::state).
+    ///
+    /// `None` if it isn't read from procfs, or for a limited thread.
+    pub state: Option<char>,
+}
+
+/// What is read about a member of a group at each update.
+#[derive(Clone, Copy, Default)]
+struct Sample {
+    cpu_time: Duration,
+    user_time: Duration,
+    system_time: Duration,
+    threads: u64,
+    state: Option<char>,
+}
+
+impl Sample {
+    /// Reads the fields of a stat file, with the CPU time of the reaped children if `include_reaped`.
+    ///
+    /// The kernel sums the times of every thread of the process in its stat file.
+    fn parse(stat: &StatFileIter, include_reaped: bool) -> io::Result<Self> {
+        let mut user_ticks: u64 = stat.field(StatField::Utime)?;
+        let mut system_ticks: u64 = stat.field(StatField::Stime)?;
+        if include_reaped {
+            user_ticks += stat.field::<u64>(StatField::Cutime)?;
+            system_ticks += stat.field::<u64>(StatField::Cstime)?;
+        }
+
+        Ok(Self {
+            cpu_time: pid::ticks_to_duration(user_ticks + system_ticks),
+            user_time: pid::ticks_to_duration(user_ticks),
+            system_time: pid::ticks_to_duration(system_ticks),
+            threads: stat.field(StatField::NumThreads)?,
+            state: Some(stat.field(StatField::State)?),
+        })
+    }
+
+    /// Only knows the CPU time, read elsewhere than from a stat file.
+    fn cpu_time(cpu_time: Duration) -> Self {
+        Self {
+            cpu_time,
+            ..Self::default()
+        }
+    }
+}
+
+/// A snapshot of where the CPU goes inside a group.
+#[derive(Clone, PartialEq, Debug)]
+pub struct GroupStats {
+    /// The processes of the group, sorted by PID.
+    pub processes: Vec<ProcessStats>,
+    /// The number of children of the targets among the processes.
+    pub children: usize,
+    /// The children which joined the group at the last update, or were adopted since.
+    pub attached: Vec<Pid>,
+    /// The children which left the group at the last update, because they exited
+    /// or were reparented.
+    pub detached: Vec<Pid>,
+    /// The fraction of each time slice the group is allowed to run, between 0 and 1.
+    pub working_rate: f64,
+    /// The number of time slices the group was suspended during.
+    pub throttle_cycles: u64,
+    /// The limit in effect, as a percentage.
+    pub limit: f64,
+}
+
+/// The fraction of each time slice the group is allowed to run, between 0 and 1.
+///
+/// A working rate stuck at 1 while the usage exceeds the limit means the limit
+/// can't be reached, for instance because processes escape the group.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct DutyCycle {
+    /// The working rate of the last time slice.
+    pub current: f64,
+    /// The lowest working rate over the last 10 seconds.
+    pub min: f64,
+    /// The highest working rate over the last 10 seconds.
+    pub max: f64,
+    /// The average working rate over the last 10 seconds.
+    pub average: f64,
+}
+
+impl GroupStats {
+    /// Retrieves the CPU time used by the processes in user mode.
+    pub fn user_time(&self) -> Duration {
+        self.processes.iter().map(|stats| stats.user_time).sum()
+    }
+
+    /// Retrieves the CPU time used by the kernel on behalf of the processes.
+    pub fn system_time(&self) -> Duration {
+        self.processes.iter().map(|stats| stats.system_time).sum()
+    }
+
+    /// Retrieves the number of threads of the processes, the most cores they can use at once.
+    pub fn threads(&self) -> u64 {
+        self.processes.iter().map(|stats| stats.threads).sum()
+    }
+
+    /// Retrieves the fraction of the CPU usage spent in kernel mode, between 0 and 1.
+    ///
+    /// A share close to 1 hints at I/O or a storm of system calls rather than
+    /// computation. Zero while the group is idle.
+    pub fn system_share(&self) -> f64 {
+        let usage: f64 = self.processes.iter().map(|stats| stats.cpu_usage).sum();
+        if usage <= 0_f64 {
+            return 0_f64;
+        }
+        let system: f64 = self.processes.iter().map(|stats| stats.system_usage).sum();
+        f64::min(system / usage, 1_f64)
+    }
+}
+
+/// The processes stopped by a group.
+///
+/// Real processes are also tracked by [`rescue`], to be resumed if this
+/// process dies abruptly.
+struct Stopped {
+    pids: HashSet<Pid>,
+    /// Whether the PIDs are those of real processes.
+    tracked: bool,
+}
+
+impl Stopped {
+    fn new(tracked: bool) -> Self {
+        Self {
+            pids: HashSet::new(),
+            tracked,
+        }
+    }
+
+    fn insert(&mut self, pid: Pid) -> bool {
+        if self.tracked {
+            rescue::track(pid);
+        }
+        self.pids.insert(pid)
+    }
+
+    fn remove(&mut self, pid: &Pid) -> bool {
+        let removed = self.pids.remove(pid);
+        if removed && self.tracked {
+            rescue::untrack(*pid);
+        }
+        removed
+    }
+
+    fn contains(&self, pid: &Pid) -> bool {
+        self.pids.contains(pid)
+    }
+
+    fn iter(&self) -> impl Iterator<Item = &Pid> {
+        self.pids.iter()
+    }
+}
+
+/// An abstraction to compute the CPU usage of processes and their children.
+///
+/// Handed to a [`Throttler`](crate::Throttler) to stop and resume its processes.
 pub struct ProcessGroup {
-    target: Pid,
+    /// What the group was created for.
+    sources: Vec<Target>,
+    /// The processes currently designated by the sources.
+    targets: Vec<Pid>,
+    /// The start time of the processes given as sources, to detect PID reuse.
+    start_times: HashMap<Pid, u64>,
+    /// The members signaled through a pidfd rather than by PID, where supported.
+    pidfds: HashMap<Pid, PidFd>,
+    /// The only thread of the target that is measured, if any.
+    thread: Option<Pid>,
     children_mode: ChildrenMode,
     children: HashSet<Pid>,
+    /// The children never to include, by PID or by command name.
+    excluded_pids: HashSet<Pid>,
+    excluded_names: Vec<String>,
+    /// The children which joined the group since the last update began.
+    attached: Vec<Pid>,
+    /// The children which left the group since the last update began.
+    detached: Vec<Pid>,
     last_update: Instant,
     total_time: Duration,
+    /// Whether the CPU time of the children reaped by the members is counted too.
+    include_reaped: bool,
+    /// The CPU time and usage of each member at the last update.
+    processes: HashMap<Pid, ProcessStats>,
+    /// The stat files of the members, kept open between updates.
+    stat_files: HashMap<Pid, StatReader>,
+    /// The stat file of the measured thread, if any.
+    thread_stat: Option<StatReader>,
+    working_rate: f64,
+    /// The working rate of each time slice within [`DUTY_CYCLE_WINDOW`], oldest first.
+    working_rates: VecDeque<(Instant, f64)>,
+    throttle_cycles: u64,
+    /// The limit in effect, as a percentage.
+    limit: f64,
+    /// The number of `SIGSTOP` and `SIGCONT` sent.
+    sigstop_count: AtomicU64,
+    sigcont_count: AtomicU64,
+    /// The CPU time consumed since the first update.
+    consumed: Option<Duration>,
     cpu_usage: f64,
+    /// The weight of the previous value of `cpu_usage` when updating it.
+    smoothing: f64,
+    /// The duration the CPU usage is averaged over instead, if any.
+    usage_window: Option<Duration>,
+    /// The CPU time consumed at each update within the window, oldest first.
+    usage_samples: VecDeque<(Instant, Duration)>,
+    /// Whether the group was last suspended (rather than resumed).
+    suspended: AtomicBool,
+    stopped_policy: StoppedPolicy,
+    /// The processes stopped by the group, the only ones it may resume.
+    stopped: Mutex<Stopped>,
+    suspend_mode: SuspendMode,
+    stop_signal: StopSignal,
+    /// Asked before suspending each process, which is left running if it says so.
+    inhibit: Option<Arc<dyn InhibitCheck>>,
+    /// The cgroup the members are frozen with, in [`SuspendMode::Freezer`].
+    freezer: Option<Cgroup>,
+    fork_guard: Option<ForkGuard>,
+    /// When the children joined the group, within the window of the guard.
+    recent_children: VecDeque<Instant>,
+    /// Whether the fork guard tripped since it was last rearmed.
+    fork_bomb: bool,
+    memory_guard: Option<MemoryGuard>,
+    /// Whether the memory guard tripped since it was last rearmed.
+    memory_exceeded: bool,
+    /// The recent time slices, if recorded.
+    recorder: Option<Recorder>,
+    /// The file listing the members, for [`recover`](crate::recover) to resume them.
+    journal: Option<Journal>,
+    /// Where the processes are read from and signaled, procfs and libc if `None`.
+    backend: Option<Arc<dyn ProcessBackend>>,
+    observers: Observers,
 }
 
 impl ProcessGroup {
-    /// Instantiates a process group.
-    pub fn new(pid: Pid, children_mode: ChildrenMode) -> Result<Self> {
+    /// Instantiates a process group sharing a single CPU budget between `targets`.
+    pub(crate) fn new(targets: &[Target], children_mode: ChildrenMode) -> Result<Self> {
+        Self::with_thread(targets, None, children_mode, None)
+    }
+
+    /// Instantiates a group measuring the CPU usage of the thread `tid` of `pid` only.
+    pub(crate) fn for_thread(pid: Pid, tid: Pid) -> Result<Self> {
+        Self::with_thread(&[Target::Pid(pid)], Some(tid), ChildrenMode::Exclude, None)
+    }
+
+    /// Instantiates a group of `targets` read and signaled through `backend` only.
+    ///
+    /// Children excluded by name are still read from procfs.
+    pub(crate) fn with_backend(
+        targets: &[Pid],
+        children_mode: ChildrenMode,
+        backend: Arc<dyn ProcessBackend>,
+    ) -> Result<Self> {
+        let sources: Vec<Target> = targets.iter().copied().map(Target::Pid).collect();
+        Self::with_thread(&sources, None, children_mode, Some(backend))
+    }
+
+    fn with_thread(
+        sources: &[Target],
+        thread: Option<Pid>,
+        children_mode: ChildrenMode,
+        backend: Option<Arc<dyn ProcessBackend>>,
+    ) -> Result<Self> {
+        if backend.is_none() {
+            for source in sources {
+                if let Target::Pid(pid) = *source {
+                    if pid.is_zombie() || pid.is_kernel_thread() {
+                        return Err(Error::UnthrottleableTarget(pid));
+                    }
+                }
+            }
+        }
+
         let mut group = Self {
-            target: pid,
+            targets: sources
+                .iter()
+                .filter_map(|source| match source {
+                    Target::Pid(pid) => Some(*pid),
+                    _ => None,
+                })
+                .collect(),
+            start_times: sources
+                .iter()
+                .filter_map(|source| match source {
+                    Target::Pid(pid) if backend.is_none() => Some((*pid, pid.get_start_time()?)),
+                    _ => None,
+                })
+                .collect(),
+            sources: sources.to_vec(),
+            thread,
             children: HashSet::new(),
+            excluded_pids: HashSet::new(),
+            excluded_names: Vec::new(),
+            attached: Vec::new(),
+            detached: Vec::new(),
             children_mode,
+            include_reaped: false,
             cpu_usage: 0_f64,
+            smoothing: DEFAULT_SMOOTHING,
+            usage_window: None,
+            usage_samples: VecDeque::new(),
             last_update: Instant::now(),
             total_time: Duration::from_secs(0),
+            processes: HashMap::new(),
+            stat_files: HashMap::new(),
+            thread_stat: None,
+            working_rate: 1_f64,
+            working_rates: VecDeque::new(),
+            throttle_cycles: 0,
+            limit: 0_f64,
+            sigstop_count: AtomicU64::new(0),
+            sigcont_count: AtomicU64::new(0),
+            consumed: None,
+            suspended: AtomicBool::new(false),
+            stopped_policy: StoppedPolicy::default(),
+            stopped: Mutex::new(Stopped::new(backend.is_none())),
+            suspend_mode: SuspendMode::default(),
+            stop_signal: StopSignal::default(),
+            inhibit: None,
+            freezer: None,
+            fork_guard: None,
+            recent_children: VecDeque::new(),
+            fork_bomb: false,
+            memory_guard: None,
+            memory_exceeded: false,
+            recorder: None,
+            journal: None,
+            pidfds: HashMap::new(),
+            backend,
+            observers: Observers::default(),
         };
 
         group.update()?;
@@ -46,71 +494,1204 @@ impl ProcessGroup {
     }
 
     /// Computes the CPU usage since the last call and smoothly updates the value.
-    pub fn update(&mut self) -> Result<()> {
-        if !self.target.alive() {
-            return Err(Error::DeadTarget);
+    ///
+    /// Dead targets are dropped from the group, which is considered dead once
+    /// none of them remain. Groups with dynamic targets only die when a target
+    /// disappears, for instance when a cgroup is removed.
+    ///
+    /// Fails with [`Error::PidReused`] if a target was replaced by another
+    /// process with the same PID, which is left alone.
+    pub(crate) fn update(&mut self) -> Result<()> {
+        if self.sources.iter().any(Target::is_dynamic) {
+            self.refresh_targets()?;
+        } else {
+            let (alive, dead) = std::mem::take(&mut self.targets)
+                .into_iter()
+                .partition(|&pid| self.alive(pid));
+            self.targets = alive;
+            for pid in dead {
+                self.observers.notify(Event::TargetDied(pid));
+            }
+            if self.targets.is_empty() {
+                return Err(Error::DeadTarget);
+            }
         }
 
-        let prev_time = self.total_time;
-        self.total_time = self.target.get_cputime();
+        let include_reaped = self.include_reaped;
+
+        let mut times = HashMap::new();
+        for pid in self.targets.clone() {
+            let times_read = match &self.backend {
+                Some(backend) => backend
+                    .cputime(pid)
+                    .ok()
+                    .map(|time| Ok((Sample::cpu_time(time), 0))),
+                None => self.read_stat(pid, |stat| {
+                    Ok((
+                        Sample::parse(&stat, include_reaped)?,
+                        pid::start_time(&stat)?,
+                    ))
+                }),
+            };
+            // an exited target is noticed at the next update
+            let (sampled, start_time) = match times_read {
+                Some(Ok((sampled, start_time))) => (sampled, Some(start_time)),
+                Some(Err(err)) => return Err(Error::InvalidStat(pid, err)),
+                None => (Sample::default(), None),
+            };
+            let reused = self
+                .start_times
+                .get(&pid)
+                .is_some_and(|&start| start_time.is_some_and(|now| now != start));
+            if reused {
+                self.targets.retain(|&target| target != pid);
+                self.stopped.lock().remove(&pid);
+                self.stat_files.remove(&pid);
+                self.pidfds.remove(&pid);
+                return Err(Error::PidReused(pid));
+            }
+            times.insert(pid, sampled);
+        }
+
+        if let Some(tid) = self.thread {
+            let pid = self.targets[0];
+            if !pid.thread_alive(tid) {
+                return Err(Error::DeadTarget);
+            }
+            times.insert(pid, Sample::cpu_time(self.read_thread_cputime(pid, tid)?));
+        }
 
+        self.attached.clear();
+        self.detached.clear();
         if let ChildrenMode::Include = self.children_mode {
-            if let Ok(processes) = ProcessIterator::new() {
-                self.children.clear();
-                for process in processes {
-                    if process != self.target && process.is_child_of(self.target) {
-                        self.children.insert(process);
-                        self.total_time += process.get_cputime();
-                    }
-                }
+            self.update_children();
+            for process in self.children.clone() {
+                let sampled = match &self.backend {
+                    Some(backend) => backend
+                        .cputime(process)
+                        .ok()
+                        .map(|time| Ok(Sample::cpu_time(time))),
+                    None => self.read_stat(process, |stat| Sample::parse(&stat, include_reaped)),
+                };
+                let sampled = match sampled {
+                    Some(sampled) => sampled.map_err(|err| Error::InvalidStat(process, err))?,
+                    None => Sample::default(),
+                };
+                times.insert(process, sampled);
             }
         }
 
-        let consumed = self.total_time - prev_time;
+        // forget the files of the processes which left the group
+        self.stat_files.retain(|pid, _| times.contains_key(pid));
+
+        let elapsed = self.last_update.elapsed().as_secs_f64();
+        self.processes = times
+            .into_iter()
+            .map(|(pid, sample)| {
+                let usage = |time: Duration, previous: Duration| {
+                    time.saturating_sub(previous).as_secs_f64() / elapsed
+                };
+                let previous = self.processes.get(&pid);
+                let stats = ProcessStats {
+                    pid,
+                    cpu_time: sample.cpu_time,
+                    cpu_usage: previous
+                        .map_or(0_f64, |previous| usage(sample.cpu_time, previous.cpu_time)),
+                    user_time: sample.user_time,
+                    system_time: sample.system_time,
+                    system_usage: previous.map_or(0_f64, |previous| {
+                        usage(sample.system_time, previous.system_time)
+                    }),
+                    threads: sample.threads,
+                    state: sample.state,
+                };
+                (pid, stats)
+            })
+            .collect();
+
+        let prev_time = self.total_time;
+        self.total_time = self.processes.values().map(|stats| stats.cpu_time).sum();
+
+        // the total decreases when a member of the group exits
+        let consumed = self.total_time.saturating_sub(prev_time);
+        self.consumed = Some(
+            self.consumed
+                .map_or(Duration::from_secs(0), |total| total + consumed),
+        );
 
-        if !prev_time.is_zero() {
-            let elapsed = self.last_update.elapsed();
-            self.last_update = Instant::now();
+        // the usage of each process is measured since then, whatever the mode
+        let elapsed = self.last_update.elapsed();
+        self.last_update = Instant::now();
 
+        if let Some(window) = self.usage_window {
+            self.cpu_usage = self.window_usage(window);
+        } else if !prev_time.is_zero() {
             let cpu_usage = consumed.as_secs_f64() / elapsed.as_secs_f64();
 
             // smooth out strong fluctuations
-            self.cpu_usage = 0.8 * self.cpu_usage + 0.2 * cpu_usage;
+            self.cpu_usage = self.smoothing * self.cpu_usage + (1_f64 - self.smoothing) * cpu_usage;
+        }
+
+        self.open_pidfds();
+        self.record_members();
+        self.check_memory();
+        Ok(())
+    }
+
+    /// Records the CPU time consumed so far, and computes the average usage over the last `window`.
+    ///
+    /// The oldest sample kept is the last one before the window, for the
+    /// average to span the whole window once enough updates happened.
+    fn window_usage(&mut self, window: Duration) -> f64 {
+        let now = Instant::now();
+        self.usage_samples
+            .push_back((now, self.consumed_cpu_time()));
+        while self
+            .usage_samples
+            .get(1)
+            .is_some_and(|&(time, _)| now - time >= window)
+        {
+            self.usage_samples.pop_front();
+        }
+
+        let (first_time, first_consumed) = self.usage_samples[0];
+        let elapsed = now - first_time;
+        if elapsed.is_zero() {
+            return self.cpu_usage;
+        }
+        (self.consumed_cpu_time() - first_consumed).as_secs_f64() / elapsed.as_secs_f64()
+    }
+
+    /// Attaches the new descendants of the targets, and detaches the children
+    /// which exited or were reparented out of the group.
+    ///
+    /// The other children are kept as they are. When the descendants can't be
+    /// listed, only the children which exited are detached.
+    fn update_children(&mut self) {
+        let found = self.descendants().map(|mut found| {
+            found.retain(|&pid| !self.is_excluded(pid));
+            found
+        });
+        let left: Vec<Pid> = self
+            .children
+            .iter()
+            .filter(|child| match &found {
+                Some(found) => !found.contains(child),
+                None => !self.alive(**child) || self.is_excluded(**child),
+            })
+            .copied()
+            .collect();
+        for child in left {
+            self.detach_child(child);
+        }
+
+        for child in found.unwrap_or_default() {
+            if self.children.contains(&child) || self.is_kernel_thread(child) {
+                continue;
+            }
+            self.children.insert(child);
+            if let Some(freezer) = &mut self.freezer {
+                // forked before the members were moved to the freezer
+                let _ = freezer.attach(child);
+            } else if self.suspended.load(Ordering::Relaxed) {
+                let _ = self.suspend_process(child);
+            }
+            self.attached.push(child);
+            self.observers.notify(Event::ChildAttached(child));
+            self.count_child();
+        }
+    }
+
+    /// Records a child joining the group, and suspends the whole group if
+    /// children join faster than the fork guard allows.
+    fn count_child(&mut self) {
+        let Some(fork_guard) = self.fork_guard else {
+            return;
+        };
+        let now = Instant::now();
+        self.recent_children.push_back(now);
+        while self
+            .recent_children
+            .front()
+            .is_some_and(|&joined| now - joined > fork_guard.window)
+        {
+            self.recent_children.pop_front();
+        }
+
+        if self.fork_bomb || self.recent_children.len() <= fork_guard.max_children {
+            return;
+        }
+        self.fork_bomb = true;
+        let _ = self.suspend();
+        self.observers.notify(Event::ForkBombDetected {
+            children: self.children.len(),
+        });
+    }
+
+    /// Samples the resident memory of the group, and applies the action of the
+    /// memory guard once it exceeds the threshold.
+    fn check_memory(&mut self) {
+        let Some(memory_guard) = self.memory_guard else {
+            return;
+        };
+        let rss: u64 = self.members().filter_map(|pid| pid.get_rss()).sum();
+        if rss <= memory_guard.max_rss {
+            if memory_guard.action == MemoryAction::Notify {
+                self.memory_exceeded = false;
+            }
+            return;
+        }
+        if self.memory_exceeded {
+            return;
+        }
+
+        self.memory_exceeded = true;
+        match memory_guard.action {
+            MemoryAction::Notify => {}
+            MemoryAction::Suspend => {
+                let _ = self.suspend();
+            }
+            MemoryAction::Terminate => {
+                let _ = self.terminate();
+            }
+        }
+        self.observers.notify(Event::MemoryExceeded { rss });
+    }
+
+    /// Removes `child` from the group, resuming it if the group suspended it.
+    fn detach_child(&mut self, child: Pid) {
+        self.children.remove(&child);
+        // a reparented or excluded child must not stay suspended
+        if self.stopped.lock().remove(&child) {
+            let _ = self.signal_process(child, &Signal::SIGCONT);
+        }
+        self.detached.push(child);
+        self.observers.notify(Event::ChildDetached(child));
+    }
+
+    /// Indicates whether `pid` must be left out of the group, even as a child of a target.
+    fn is_excluded(&self, pid: Pid) -> bool {
+        self.excluded_pids.contains(&pid)
+            || self
+                .excluded_names
+                .iter()
+                .any(|name| target::has_name(pid, name))
+    }
+
+    /// Lists the descendants of the targets, the targets themselves excluded.
+    ///
+    /// Walks the whole of `/proc` only if the kernel doesn't list the children
+    /// of each process. Returns `None` if `/proc` can't be read.
+    fn descendants(&self) -> Option<HashSet<Pid>> {
+        if self.backend.is_some() {
+            return self.scan_descendants();
+        }
+
+        let mut descendants = HashSet::new();
+        for target in &self.targets {
+            match target.descendants() {
+                Some(found) => descendants.extend(found),
+                None => return self.scan_descendants(),
+            }
+        }
+        descendants.retain(|pid| !self.targets.contains(pid));
+        Some(descendants)
+    }
+
+    /// Lists the descendants of the targets by chasing the parent of every process.
+    fn scan_descendants(&self) -> Option<HashSet<Pid>> {
+        let processes: Vec<Pid> = match &self.backend {
+            Some(backend) => backend.pids().ok()?,
+            None => ProcessIterator::new().ok()?.collect(),
+        };
+        Some(
+            processes
+                .into_iter()
+                .filter(|process| {
+                    !self.targets.contains(process)
+                        && self
+                            .targets
+                            .iter()
+                            .any(|&target| self.is_descendant(*process, target))
+                })
+                .collect(),
+        )
+    }
+
+    /// Indicates whether `pid` descends from `ancestor`.
+    fn is_descendant(&self, mut pid: Pid, ancestor: Pid) -> bool {
+        let Some(backend) = &self.backend else {
+            return pid.is_child_of(ancestor);
+        };
+        while let Some(parent) = backend.parent(pid) {
+            if parent == ancestor {
+                return true;
+            }
+            pid = parent;
+        }
+        false
+    }
+
+    /// Indicates whether `pid` exists.
+    ///
+    /// A member with a pidfd is dead as soon as it exits, even before it is reaped.
+    fn alive(&self, pid: Pid) -> bool {
+        match (&self.backend, self.pidfds.get(&pid)) {
+            (Some(backend), _) => backend.alive(pid),
+            (None, Some(pidfd)) => {
+                !pidfd.wait(Some(Duration::ZERO)).unwrap_or(false) && pid.alive()
+            }
+            (None, None) => pid.alive(),
+        }
+    }
+
+    /// Indicates whether `pid` is a kernel thread, never with a custom backend.
+    fn is_kernel_thread(&self, pid: Pid) -> bool {
+        self.backend.is_none() && pid.is_kernel_thread()
+    }
+
+    /// Indicates whether `pid` is currently stopped.
+    fn is_stopped(&self, pid: Pid) -> bool {
+        match &self.backend {
+            Some(backend) => backend.is_stopped(pid),
+            None => pid.is_stopped(),
+        }
+    }
+
+    /// Indicates whether the processes are read from procfs, rather than from a custom backend.
+    pub(crate) fn uses_procfs(&self) -> bool {
+        self.backend.is_none()
+    }
+
+    /// Parses the stat file of `pid` with `parse`, opening it on first use.
+    ///
+    /// The file of an exited process can't be read anymore, it is opened again
+    /// once in case the PID was reused. Returns `None` if it can't be read.
+    fn read_stat<T>(
+        &mut self,
+        pid: Pid,
+        parse: impl Fn(StatFileIter) -> io::Result<T>,
+    ) -> Option<io::Result<T>> {
+        for _ in 0..2 {
+            let stat = match self.stat_files.entry(pid) {
+                Entry::Occupied(entry) => entry.into_mut(),
+                Entry::Vacant(entry) => entry.insert(StatReader::open(pid).ok()?),
+            };
+            match stat.read() {
+                Ok(data) => return Some(parse(StatFileIter::from(data))),
+                Err(_) => {
+                    self.stat_files.remove(&pid);
+                }
+            }
+        }
+        None
+    }
+
+    /// Retrieves the CPU time of the thread `tid` of `pid`, from its stat file kept open.
+    ///
+    /// Zero if the file can't be read, the thread exited meanwhile.
+    fn read_thread_cputime(&mut self, pid: Pid, tid: Pid) -> Result<Duration> {
+        if self.thread_stat.is_none() {
+            self.thread_stat = StatReader::open_thread(pid, tid).ok();
+        }
+        match self.thread_stat.as_mut().map(StatReader::read) {
+            Some(Ok(data)) => {
+                pid::cputime(&StatFileIter::from(data)).map_err(|err| Error::InvalidStat(tid, err))
+            }
+            _ => {
+                self.thread_stat = None;
+                Ok(Duration::default())
+            }
+        }
+    }
+
+    /// Lists the processes of the targets again, resuming the ones that left the group.
+    fn refresh_targets(&mut self) -> Result<()> {
+        let mut targets = Vec::new();
+        for source in &self.sources {
+            for pid in source.resolve().map_err(|_| Error::DeadTarget)? {
+                if !targets.contains(&pid) {
+                    targets.push(pid);
+                }
+            }
+        }
+
+        for &gone in self.targets.iter().filter(|pid| !targets.contains(pid)) {
+            if !self.alive(gone) {
+                self.observers.notify(Event::TargetDied(gone));
+            }
+            let _ = self.resume_process(gone);
+        }
+        self.targets = targets;
+        Ok(())
+    }
+
+    /// Adds `child` to the group if it was forked by one of its members.
+    ///
+    /// The child is suspended right away if the rest of the group is.
+    pub(crate) fn adopt(&mut self, parent: Pid, child: Pid) {
+        if !self.includes_children() || !self.members().any(|member| member == parent) {
+            return;
+        }
+
+        if self.targets.contains(&child) || self.is_excluded(child) || !self.children.insert(child)
+        {
+            return;
+        }
+
+        if self.suspended.load(Ordering::Relaxed) {
+            let _ = self.suspend_process(child);
+        }
+        self.attached.push(child);
+        self.observers.notify(Event::ChildAttached(child));
+        self.count_child();
+    }
+
+    /// Leaves the children with one of the given PIDs or command names out of the group.
+    ///
+    /// They are never suspended, except by the cgroup freezer. Children already
+    /// in the group are detached right away.
+    pub(crate) fn exclude(&mut self, pids: &[Pid], names: &[String]) {
+        self.excluded_pids.extend(pids);
+        self.excluded_names.extend_from_slice(names);
+
+        let excluded: Vec<Pid> = self
+            .children
+            .iter()
+            .copied()
+            .filter(|&child| self.is_excluded(child))
+            .collect();
+        for child in excluded {
+            self.detach_child(child);
+        }
+    }
+
+    /// Sets how to handle the processes stopped by someone else.
+    pub(crate) fn set_stopped_policy(&mut self, stopped_policy: StoppedPolicy) {
+        self.stopped_policy = stopped_policy;
+    }
+
+    /// Sets how the processes of the group are suspended.
+    ///
+    /// The freezer requires a group of processes given by PID, it fails with
+    /// [`Error::Cgroup`] if the members can't be moved to a dedicated cgroup.
+    pub(crate) fn set_suspend_mode(&mut self, suspend_mode: SuspendMode) -> Result<()> {
+        if let Some(freezer) = self.freezer.take() {
+            freezer.release();
         }
 
+        if suspend_mode == SuspendMode::Freezer {
+            if self.thread.is_some()
+                || self.backend.is_some()
+                || self.sources.iter().any(Target::is_dynamic)
+            {
+                return Err(Error::Cgroup(io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    "only whole real processes given by PID can be frozen",
+                )));
+            }
+            let members: Vec<Pid> = self.members().collect();
+            self.freezer = Some(Cgroup::create_freezer(&members).map_err(Error::Cgroup)?);
+        }
+        self.suspend_mode = suspend_mode;
         Ok(())
     }
 
+    /// Sets the signal suspending the processes.
+    pub(crate) fn set_stop_signal(&mut self, stop_signal: StopSignal) {
+        self.stop_signal = stop_signal;
+    }
+
+    /// Sets the check deciding which processes are left running rather than suspended.
+    pub(crate) fn set_inhibit(&mut self, inhibit: Option<Arc<dyn InhibitCheck>>) {
+        self.inhibit = inhibit;
+    }
+
+    /// Sets whether the CPU time of the exited children the members waited for
+    /// is counted against the limit and the budget.
+    ///
+    /// Otherwise a process forking short-lived workers escapes the limit when
+    /// the children aren't included. Only read from procfs, and never for a
+    /// single thread.
+    pub(crate) fn set_include_reaped(&mut self, include_reaped: bool) {
+        if include_reaped == self.include_reaped || self.thread.is_some() || self.backend.is_some()
+        {
+            return;
+        }
+        self.include_reaped = include_reaped;
+
+        // the time reaped so far is not consumed at the next update
+        for stats in self.processes.values_mut() {
+            let reaped = stats.pid.get_reaped_cputime();
+            stats.cpu_time = if include_reaped {
+                stats.cpu_time + reaped
+            } else {
+                stats.cpu_time.saturating_sub(reaped)
+            };
+        }
+        self.total_time = self.processes.values().map(|stats| stats.cpu_time).sum();
+    }
+
+    /// Sets how fast children may join the group before it is suspended, if at all.
+    pub(crate) fn set_fork_guard(&mut self, fork_guard: Option<ForkGuard>) {
+        self.fork_guard = fork_guard;
+        self.rearm_guards();
+    }
+
+    /// Sets how much memory the group may use, and what happens beyond, if watched at all.
+    pub(crate) fn set_memory_guard(&mut self, memory_guard: Option<MemoryGuard>) {
+        self.memory_guard = memory_guard;
+        self.rearm_guards();
+    }
+
+    /// Indicates whether a guard suspended the group since they were last rearmed.
+    pub(crate) fn guard_suspended(&self) -> bool {
+        self.fork_bomb
+            || (self.memory_exceeded
+                && self
+                    .memory_guard
+                    .is_some_and(|guard| guard.action == MemoryAction::Suspend))
+    }
+
+    /// Counts the children joining the group from zero again, and watches the
+    /// memory again, once the group is resumed after a guard tripped.
+    pub(crate) fn rearm_guards(&mut self) {
+        self.fork_bomb = false;
+        self.recent_children.clear();
+        self.memory_exceeded = false;
+    }
+
+    /// Sets the weight of the previous measurements in the CPU usage.
+    pub(crate) fn set_smoothing(&mut self, smoothing: f64) {
+        self.smoothing = smoothing;
+    }
+
+    /// Averages the CPU usage over the last `window` rather than smoothing it, if any.
+    pub(crate) fn set_usage_window(&mut self, usage_window: Option<Duration>) {
+        self.usage_window = usage_window;
+        self.usage_samples.clear();
+    }
+
+    /// Starts or stops tracking the children of the targets.
+    ///
+    /// Excluded children are resumed, in case the group was suspended.
+    /// Groups limiting a single thread never include children.
+    pub(crate) fn set_children_mode(&mut self, children_mode: ChildrenMode) {
+        if self.thread.is_some() {
+            return;
+        }
+        self.children_mode = children_mode;
+
+        if let ChildrenMode::Exclude = children_mode {
+            for child in std::mem::take(&mut self.children) {
+                let _ = self.resume_process(child);
+                self.detached.push(child);
+                self.observers.notify(Event::ChildDetached(child));
+            }
+        }
+    }
+
+    /// Replaces the target of the group, which exited, by `pid`.
+    ///
+    /// The children of the previous target leave the group.
+    pub(crate) fn reattach(&mut self, pid: Pid) -> Result<()> {
+        if let Some(freezer) = &mut self.freezer {
+            freezer.attach(pid).map_err(Error::Cgroup)?;
+        }
+        for child in std::mem::take(&mut self.children) {
+            let _ = self.resume_process(child);
+            self.detached.push(child);
+            self.observers.notify(Event::ChildDetached(child));
+        }
+
+        self.sources = vec![Target::Pid(pid)];
+        self.targets = vec![pid];
+        self.start_times = pid
+            .get_start_time()
+            .map(|start_time| (pid, start_time))
+            .into_iter()
+            .collect();
+        self.processes.clear();
+        self.stat_files.clear();
+        self.pidfds.clear();
+        self.update()
+    }
+
+    /// Indicates whether the children of the targets belong to the group.
+    #[inline]
+    pub fn includes_children(&self) -> bool {
+        matches!(self.children_mode, ChildrenMode::Include)
+    }
+
+    /// Retrieves the subscribers to the events of the group.
+    pub(crate) fn observers(&self) -> &Observers {
+        &self.observers
+    }
+
+    /// Lists the targets of the group, its children left out.
+    pub(crate) fn targets(&self) -> &[Pid] {
+        &self.targets
+    }
+
+    /// Iterates over the processes of the group (targets and tracked children).
+    pub fn members(&self) -> impl Iterator<Item = Pid> + '_ {
+        self.targets.iter().chain(self.children.iter()).copied()
+    }
+
     /// Retrieves the previously computed CPU usage.
     #[inline]
     pub fn cpu_usage(&self) -> f64 {
         self.cpu_usage
     }
 
+    /// Indicates whether a process of the group was in uninterruptible sleep
+    /// (`D` state) at the last update.
+    ///
+    /// Such a process can't be stopped until it wakes up, and its CPU usage
+    /// often comes from the kernel on its behalf.
+    pub(crate) fn uninterruptible(&self) -> bool {
+        self.processes
+            .values()
+            .any(|stats| stats.state == Some('D'))
+    }
+
     /// Retrieves the total amount of CPU time used.
     pub fn total_cpu_time(&self) -> Duration {
         self.total_time
     }
 
-    /// Sends a signal to the target process and its children if needed.
-    fn kill(&self, signal: &Signal) {
-        let _ = self.target.kill(signal);
-        if let ChildrenMode::Include = self.children_mode {
-            for child in &self.children {
-                let _ = child.kill(signal);
+    /// Records a time slice of the limiter, `throttled` if the group was suspended during it.
+    pub(crate) fn record_cycle(&mut self, working_rate: f64, throttled: bool) {
+        self.working_rate = working_rate;
+        let now = Instant::now();
+        self.working_rates.push_back((now, working_rate));
+        while self
+            .working_rates
+            .front()
+            .is_some_and(|&(time, _)| now - time > DUTY_CYCLE_WINDOW)
+        {
+            self.working_rates.pop_front();
+        }
+        if throttled {
+            self.throttle_cycles += 1;
+        }
+        if let Some(recorder) = &mut self.recorder {
+            recorder.record(TimelineSample {
+                time: SystemTime::now(),
+                usage: self.cpu_usage,
+                limit: self.limit,
+                working_rate,
+            });
+        }
+    }
+
+    /// Records the last `capacity` time slices, see [`Timeline`].
+    pub(crate) fn set_timeline(&mut self, capacity: Option<usize>) {
+        self.recorder = capacity.map(Recorder::new);
+    }
+
+    /// Lists the members in `journal` from now on, or stops listing them and
+    /// removes the previous journal if `None`.
+    pub(crate) fn set_journal(&mut self, journal: Option<Journal>) {
+        self.journal = journal;
+        self.record_members();
+    }
+
+    /// Opens a pidfd for the members which joined since the last update, and
+    /// closes those of the members which left.
+    ///
+    /// Members are signaled by PID when the kernel doesn't support pidfds, or
+    /// when the group signals a thread or goes through a backend.
+    fn open_pidfds(&mut self) {
+        if self.thread.is_some() || self.backend.is_some() {
+            return;
+        }
+        let members: HashSet<Pid> = self.members().collect();
+        self.pidfds.retain(|pid, _| members.contains(pid));
+        for pid in members {
+            if let Entry::Vacant(entry) = self.pidfds.entry(pid) {
+                if let Ok(pidfd) = PidFd::open(pid) {
+                    entry.insert(pidfd);
+                }
             }
         }
     }
 
-    /// Suspends the execution of the group.
+    /// Lists the current members in the journal, if any.
+    fn record_members(&mut self) {
+        let members = self.members().collect();
+        if let Some(journal) = &mut self.journal {
+            if let Err(err) = journal.record(members) {
+                warn!(error = %err, "couldn't record the members for recovery");
+            }
+        }
+    }
+
+    /// Retrieves the recorded time slices, none unless enabled with
+    /// [`CpuLimitBuilder::timeline`](crate::CpuLimitBuilder::timeline).
+    pub fn timeline(&self) -> Timeline {
+        self.recorder
+            .as_ref()
+            .map_or_else(Timeline::default, Recorder::timeline)
+    }
+
+    /// Retrieves the working rate of the last time slice, and its range and average
+    /// over the last 10 seconds.
+    ///
+    /// Only tracked by [`Backend::Signal`](crate::Backend::Signal): the working rate stays 1 otherwise.
+    pub fn duty_cycle(&self) -> DutyCycle {
+        let rates = self.working_rates.iter().map(|&(_, rate)| rate);
+        if self.working_rates.is_empty() {
+            return DutyCycle {
+                current: self.working_rate,
+                min: self.working_rate,
+                max: self.working_rate,
+                average: self.working_rate,
+            };
+        }
+
+        DutyCycle {
+            current: self.working_rate,
+            min: rates.clone().fold(f64::INFINITY, f64::min),
+            max: rates.clone().fold(f64::NEG_INFINITY, f64::max),
+            average: rates.sum::<f64>() / self.working_rates.len() as f64,
+        }
+    }
+
+    /// Records the limit in effect, as a percentage.
+    pub(crate) fn set_limit(&mut self, limit: f64) {
+        self.limit = limit;
+    }
+
+    /// Retrieves the limit in effect, as a percentage.
+    #[cfg(feature = "metrics")]
     #[inline]
-    pub fn suspend(&self) {
-        self.kill(&Signal::SIGSTOP);
+    pub fn limit(&self) -> f64 {
+        self.limit
     }
 
-    /// Resumes the execution of the group.
+    /// Retrieves the number of children currently tracked.
+    #[cfg(feature = "metrics")]
     #[inline]
-    pub fn resume(&self) {
-        self.kill(&Signal::SIGCONT);
+    pub fn children_count(&self) -> usize {
+        self.children.len()
+    }
+
+    /// Retrieves the number of `SIGSTOP` and `SIGCONT` successfully sent.
+    #[cfg(feature = "metrics")]
+    pub fn signals_sent(&self) -> (u64, u64) {
+        (
+            self.sigstop_count.load(Ordering::Relaxed),
+            self.sigcont_count.load(Ordering::Relaxed),
+        )
+    }
+
+    /// Retrieves the CPU usage of every process and the state of the throttling.
+    pub fn stats(&self) -> GroupStats {
+        let mut processes: Vec<ProcessStats> = self.processes.values().copied().collect();
+        processes.sort_by_key(|stats| stats.pid);
+
+        GroupStats {
+            processes,
+            children: self.children.len(),
+            attached: self.attached.clone(),
+            detached: self.detached.clone(),
+            working_rate: self.working_rate,
+            throttle_cycles: self.throttle_cycles,
+            limit: self.limit,
+        }
+    }
+
+    /// Retrieves the CPU time consumed by the group since it was created.
+    pub fn consumed_cpu_time(&self) -> Duration {
+        self.consumed.unwrap_or_default()
+    }
+
+    /// Sends a signal to the target processes and their children if needed.
+    ///
+    /// Every process is signaled, the first failure is reported.
+    fn kill(&self, signal: &Signal) -> Result<()> {
+        if self.thread.is_some() {
+            return self.signal_process(self.targets[0], signal);
+        }
+
+        let mut result = Ok(());
+        for member in self.members() {
+            result = result.and(self.signal_process(member, signal));
+        }
+        result
+    }
+
+    /// Sends `signal` to `pid`, or to the limited thread of it.
+    ///
+    /// Processes that exited in the meantime are not an error.
+    fn signal_process(&self, pid: Pid, signal: &Signal) -> Result<()> {
+        let result = match (self.thread, &self.backend) {
+            (Some(tid), _) => pid.tgkill(tid, signal),
+            (None, Some(backend)) => backend.signal(pid, *signal),
+            (None, None) => match self.pidfds.get(&pid) {
+                Some(pidfd) => pidfd.send_signal(signal),
+                None => pid.kill(signal),
+            },
+        };
+
+        if result.is_ok() {
+            match signal {
+                Signal::SIGSTOP | Signal::SIGTSTP => {
+                    self.sigstop_count.fetch_add(1, Ordering::Relaxed)
+                }
+                Signal::SIGCONT => self.sigcont_count.fetch_add(1, Ordering::Relaxed),
+                _ => 0,
+            };
+        }
+
+        match result {
+            Err(Error::SignalFailed {
+                errno: libc::ESRCH, ..
+            }) => Ok(()),
+            Err(err) => {
+                warn!(pid = %pid, ?signal, error = %err, "couldn't signal a process");
+                Err(err)
+            }
+            result => result,
+        }
+    }
+
+    /// Makes sure every process of the group can be signaled.
+    ///
+    /// A `SIGCONT` is attempted too, except on stopped processes which must be left alone.
+    pub(crate) fn check_permission(&self) -> Result<()> {
+        for member in self.members() {
+            let result = self
+                .signal_process(member, &Signal::SIGNULL)
+                .and_then(|()| {
+                    if self.is_stopped(member) {
+                        Ok(())
+                    } else {
+                        self.signal_process(member, &Signal::SIGCONT)
+                    }
+                });
+
+            match result {
+                Err(Error::SignalFailed {
+                    errno: libc::EPERM, ..
+                }) => return Err(Error::PermissionDenied(member)),
+                result => result?,
+            }
+        }
+        Ok(())
+    }
+
+    /// Stops `pid`, unless the policy says to leave it alone.
+    pub(crate) fn suspend_process(&self, pid: Pid) -> Result<()> {
+        let mut stopped = self.stopped.lock();
+        if self.stopped_policy == StoppedPolicy::Skip
+            && !stopped.contains(&pid)
+            && self.is_stopped(pid)
+        {
+            // stopped by someone else
+            return Ok(());
+        }
+        if self
+            .inhibit
+            .as_ref()
+            .is_some_and(|check| check.inhibits(pid))
+        {
+            debug!(pid = %pid, "suspension inhibited");
+            return Ok(());
+        }
+
+        self.signal_process(pid, &self.stop_signal.signal())?;
+        stopped.insert(pid);
+        Ok(())
+    }
+
+    /// Continues `pid`, unless the policy says it was stopped by someone else.
+    fn resume_process(&self, pid: Pid) -> Result<()> {
+        let stopped = self.stopped.lock().remove(&pid);
+        if stopped || self.stopped_policy == StoppedPolicy::Override {
+            self.signal_process(pid, &Signal::SIGCONT)?;
+        }
+        Ok(())
+    }
+
+    /// Sends `signal` at once to the process groups of the targets, in
+    /// [`SuspendMode::ProcessGroup`].
+    ///
+    /// Returns the process groups signaled, and the first failure.
+    fn signal_process_groups(&self, signal: &Signal) -> (HashSet<Pid>, Result<()>) {
+        let mut groups = HashSet::new();
+        let mut result = Ok(());
+        // an inhibit check must be asked about each process
+        if self.suspend_mode != SuspendMode::ProcessGroup
+            || self.thread.is_some()
+            || self.backend.is_some()
+            || self.inhibit.is_some()
+        {
+            return (groups, result);
+        }
+
+        for pgid in self
+            .targets
+            .iter()
+            .filter_map(|target| target.process_group())
+        {
+            if !groups.insert(pgid) {
+                continue;
+            }
+            result = result.and(match pgid.kill_group(signal) {
+                Err(Error::SignalFailed {
+                    errno: libc::ESRCH, ..
+                }) => Ok(()),
+                result => result,
+            });
+        }
+        (groups, result)
+    }
+
+    /// Suspends the execution of the group.
+    ///
+    /// Every process is suspended, the first failure is reported.
+    pub fn suspend(&self) -> Result<()> {
+        self.suspended.store(true, Ordering::Relaxed);
+        if let Some(freezer) = &self.freezer {
+            return freezer.freeze(true).map_err(Error::Cgroup);
+        }
+
+        let (groups, mut result) = self.signal_process_groups(&self.stop_signal.signal());
+        for member in self.members() {
+            if member
+                .process_group()
+                .is_some_and(|pgid| groups.contains(&pgid))
+            {
+                // stopped along with its process group
+                self.stopped.lock().insert(member);
+                continue;
+            }
+            result = result.and(self.suspend_process(member));
+        }
+        result
+    }
+
+    /// Asks the processes of the group to terminate.
+    ///
+    /// The group is resumed, otherwise suspended processes could not handle the signal.
+    pub fn terminate(&self) -> Result<()> {
+        self.signal_all(&Signal::SIGTERM)
+    }
+
+    /// Sends `signal` to the processes of the group, and resumes them to handle it.
+    pub(crate) fn signal_all(&self, signal: &Signal) -> Result<()> {
+        let result = self.kill(signal);
+        result.and(self.resume())
+    }
+
+    /// Resumes the execution of the group.
+    ///
+    /// Processes that left the group since it was suspended are resumed too.
+    /// Every process is resumed, the first failure is reported.
+    pub fn resume(&self) -> Result<()> {
+        self.suspended.store(false, Ordering::Relaxed);
+        if let Some(freezer) = &self.freezer {
+            return freezer.freeze(false).map_err(Error::Cgroup);
+        }
+
+        let (groups, mut result) = self.signal_process_groups(&Signal::SIGCONT);
+        let members: HashSet<Pid> = self.members().collect();
+        let left: Vec<Pid> = self
+            .stopped
+            .lock()
+            .iter()
+            .copied()
+            .filter(|pid| !members.contains(pid))
+            .collect();
+
+        for pid in members.into_iter().chain(left) {
+            if pid
+                .process_group()
+                .is_some_and(|pgid| groups.contains(&pgid))
+            {
+                // resumed along with its process group
+                self.stopped.lock().remove(&pid);
+                continue;
+            }
+            result = result.and(self.resume_process(pid));
+        }
+        result
+    }
+}
+
+impl Drop for ProcessGroup {
+    fn drop(&mut self) {
+        // thaws the members and moves them back where they were
+        if let Some(freezer) = self.freezer.take() {
+            freezer.release();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::os::unix::process::CommandExt;
+    use std::process::Command;
+    use std::thread;
+
+    use super::*;
+    use crate::backend::MockBackend;
+
+    /// Creates a group of the target `100` and its children `101` and `102`, all simulated.
+    fn simulated_group() -> (Arc<MockBackend>, ProcessGroup) {
+        let mock = Arc::new(MockBackend::new());
+        mock.spawn(Pid::from(100), None);
+        mock.spawn(Pid::from(101), Some(Pid::from(100)));
+        mock.spawn(Pid::from(102), Some(Pid::from(100)));
+        let group =
+            ProcessGroup::with_backend(&[Pid::from(100)], ChildrenMode::Include, mock.clone())
+                .unwrap();
+        (mock, group)
+    }
+
+    /// Retrieves the number of `SIGSTOP` and `SIGCONT` sent, whatever the features.
+    fn signals_sent(group: &ProcessGroup) -> (u64, u64) {
+        (
+            group.sigstop_count.load(Ordering::Relaxed),
+            group.sigcont_count.load(Ordering::Relaxed),
+        )
+    }
+
+    #[test]
+    fn stopped_policy() {
+        let (mock, mut group) = simulated_group();
+        let (target, stopped) = (Pid::from(100), Pid::from(101));
+        // by someone else
+        mock.signal(stopped, Signal::SIGSTOP).unwrap();
+
+        group.suspend().unwrap();
+        group.resume().unwrap();
+        assert!(!mock.is_stopped(target));
+        assert!(mock.is_stopped(stopped));
+        assert_eq!(signals_sent(&group), (2, 2));
+
+        group.set_stopped_policy(StoppedPolicy::Override);
+        group.suspend().unwrap();
+        group.resume().unwrap();
+        assert!(!mock.is_stopped(stopped));
+    }
+
+    #[test]
+    fn traced_target() {
+        let mut command = Command::new("sleep");
+        command.arg("10");
+        // SAFETY: Only the async-signal-safe `ptrace` is called between `fork` and `exec`.
+        unsafe {
+            command.pre_exec(|| {
+                libc::ptrace(libc::PTRACE_TRACEME, 0, 0, 0);
+                Ok(())
+            });
+        }
+        // stops on `exec`, until the test process lets it go
+        let mut child = command.spawn().unwrap();
+        let pid = Pid::from(child.id());
+        let start = Instant::now();
+        while !pid.is_stopped() && start.elapsed() < Duration::from_secs(5) {
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        let group = ProcessGroup::new(&[Target::Pid(pid)], ChildrenMode::Exclude).unwrap();
+        group.suspend().unwrap();
+        group.resume().unwrap();
+        let signals = signals_sent(&group);
+
+        child.kill().unwrap();
+        child.wait().unwrap();
+        assert_eq!(signals, (0, 0));
+    }
+
+    #[test]
+    fn suspend_modes() {
+        let (mock, mut group) = simulated_group();
+        let members = [100, 101, 102].map(Pid::from);
+
+        // the process groups can't be signaled through a backend
+        group.set_suspend_mode(SuspendMode::ProcessGroup).unwrap();
+        group.set_stop_signal(StopSignal::Tstp);
+        group.suspend().unwrap();
+        assert!(members.iter().all(|&pid| mock.is_stopped(pid)));
+        group.resume().unwrap();
+        assert!(members.iter().all(|&pid| !mock.is_stopped(pid)));
+        assert_eq!(signals_sent(&group), (3, 3));
+
+        assert!(matches!(
+            group.set_suspend_mode(SuspendMode::Freezer),
+            Err(Error::Cgroup(_))
+        ));
+    }
+
+    #[test]
+    fn exclusions() {
+        let (mock, mut group) = simulated_group();
+        // names are read from procfs, even with a backend
+        let own = Pid::from(std::process::id());
+        mock.spawn(own, Some(Pid::from(100)));
+        group.update().unwrap();
+        assert_eq!(group.stats().children, 3);
+
+        group.suspend().unwrap();
+        group.exclude(
+            &[Pid::from(101), Pid::from(103)],
+            &[own.get_name().unwrap()],
+        );
+        // the excluded children are resumed as they leave
+        assert!(!mock.is_stopped(Pid::from(101)));
+        assert!(!mock.is_stopped(own));
+        group.resume().unwrap();
+
+        mock.spawn(Pid::from(103), Some(Pid::from(102)));
+        mock.spawn(Pid::from(104), Some(Pid::from(103)));
+        group.update().unwrap();
+        let mut members: Vec<Pid> = group.members().collect();
+        members.sort();
+        // the children of an excluded process are still included
+        assert_eq!(members, [100, 102, 104].map(Pid::from));
+    }
+
+    #[test]
+    fn pid_reused() {
+        let mut child = Command::new("sleep").arg("10").spawn().unwrap();
+        let pid = Pid::from(child.id());
+        let mut group = ProcessGroup::new(&[Target::Pid(pid)], ChildrenMode::Exclude).unwrap();
+        // as if the target exited and another process got its PID
+        *group.start_times.get_mut(&pid).unwrap() += 1;
+
+        let result = group.update();
+        let signals = signals_sent(&group);
+
+        child.kill().unwrap();
+        child.wait().unwrap();
+        assert!(matches!(result, Err(Error::PidReused(reused)) if reused == pid));
+        assert!(group.targets().is_empty());
+        assert_eq!(signals, (0, 0));
+    }
+
+    #[test]
+    fn usage_window() {
+        let mut child = Command::new("sh")
+            .args(["-c", "while :; do :; done"])
+            .spawn()
+            .unwrap();
+        let pid = Pid::from(child.id());
+        let mut group = ProcessGroup::new(&[Target::Pid(pid)], ChildrenMode::Exclude).unwrap();
+        group.set_usage_window(Some(Duration::from_secs(1)));
+
+        for _ in 0..10 {
+            thread::sleep(Duration::from_millis(100));
+            group.update().unwrap();
+        }
+        // measured over the last 100 ms, not since the group was created
+        let usage = group.stats().processes[0].cpu_usage;
+
+        child.kill().unwrap();
+        child.wait().unwrap();
+        assert!(usage > 0.25, "usage {usage}");
     }
 }
@@ -0,0 +1,134 @@
+//! A typed view of the `/proc/<pid>/stat` file of a process.
+//!
+//! See `man proc` for the meaning of each field.
+
+use std::io;
+use std::time::Duration;
+
+use crate::clock::SystemClock;
+use crate::pid::{self, Pid};
+use crate::stat_iterator::{StatField, StatFileIter};
+
+/// The details of a process, as read at once from its stat file, see [`Pid::info`].
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct ProcessInfo {
+    pid: Pid,
+    comm: String,
+    state: char,
+    ppid: Pid,
+    pgrp: Pid,
+    nice: i64,
+    num_threads: u64,
+    starttime: u64,
+    utime: u64,
+    stime: u64,
+    rss: u64,
+}
+
+impl ProcessInfo {
+    /// Parses the content of a stat file, such as one read from another procfs.
+    ///
+    /// Fails with [`io::ErrorKind::InvalidData`] if the file is malformed.
+    pub fn from_stat(data: &[u8]) -> io::Result<Self> {
+        Self::parse(StatFileIter::from(data))
+    }
+
+    /// Parses the fields of a stat file.
+    pub(crate) fn parse(stat: StatFileIter) -> io::Result<Self> {
+        Ok(Self {
+            pid: stat.field(StatField::Pid)?,
+            comm: String::from_utf8_lossy(stat.raw_field(StatField::Comm)?).into_owned(),
+            state: stat.field(StatField::State)?,
+            ppid: stat.field(StatField::Ppid)?,
+            pgrp: stat.field(StatField::Pgrp)?,
+            nice: stat.field(StatField::Nice)?,
+            num_threads: stat.field(StatField::NumThreads)?,
+            starttime: stat.field(StatField::Starttime)?,
+            utime: stat.field(StatField::Utime)?,
+            stime: stat.field(StatField::Stime)?,
+            rss: stat.field(StatField::Rss)?,
+        })
+    }
+
+    /// Retrieves the process identifier.
+    pub fn pid(&self) -> Pid {
+        self.pid
+    }
+
+    /// Retrieves the command name, truncated to 15 bytes by the kernel.
+    ///
+    /// Bytes that aren't UTF-8 are replaced with `U+FFFD`.
+    pub fn comm(&self) -> &str {
+        &self.comm
+    }
+
+    /// Retrieves the state, such as `R` (running), `S` (sleeping), `D`
+    /// (uninterruptible sleep) or `T` (stopped).
+    pub fn state(&self) -> char {
+        self.state
+    }
+
+    /// Retrieves the parent process identifier.
+    pub fn ppid(&self) -> Pid {
+        self.ppid
+    }
+
+    /// Retrieves the process group identifier.
+    pub fn pgrp(&self) -> Pid {
+        self.pgrp
+    }
+
+    /// Retrieves the nice value, from 19 (lowest priority) to -20 (highest).
+    pub fn nice(&self) -> i64 {
+        self.nice
+    }
+
+    /// Retrieves the number of threads.
+    pub fn num_threads(&self) -> u64 {
+        self.num_threads
+    }
+
+    /// Retrieves the time the process started after system boot, in clock ticks.
+    pub fn starttime(&self) -> u64 {
+        self.starttime
+    }
+
+    /// Retrieves the CPU time spent in user mode.
+    pub fn utime(&self) -> Duration {
+        pid::ticks_to_duration(self.utime)
+    }
+
+    /// Retrieves the CPU time spent in kernel mode.
+    pub fn stime(&self) -> Duration {
+        pid::ticks_to_duration(self.stime)
+    }
+
+    /// Retrieves the resident set size, in bytes.
+    pub fn rss(&self) -> u64 {
+        SystemClock::current().pages_to_bytes(self.rss)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_stat() {
+        let stat = "128377 (cat) R 127912 128377 127912 34817 128377 4194304 90 0 0 0 7 3 0 0 25 5 1 0 7545849 18751488 252 18446744073709551615 94742542643200 94742542658614 140726597052192 0 0 0 0 0 0 0 0 0 17 0 0 0 0 0 0 94742542670560 94742542671976 94742570721280 140726597055035 140726597055055 140726597055055 140726597058539 0\n";
+        let info = ProcessInfo::parse(StatFileIter::from(stat)).unwrap();
+
+        assert_eq!(info.pid(), Pid::from(128377));
+        assert_eq!(info.comm(), "cat");
+        assert_eq!(info.state(), 'R');
+        assert_eq!(info.ppid(), Pid::from(127912));
+        assert_eq!(info.pgrp(), Pid::from(128377));
+        assert_eq!(info.nice(), 5);
+        assert_eq!(info.num_threads(), 1);
+        assert_eq!(info.starttime(), 7545849);
+        assert_eq!(info.utime(), pid::ticks_to_duration(7));
+        assert_eq!(info.rss(), SystemClock::current().pages_to_bytes(252));
+
+        assert!(ProcessInfo::from_stat(b"12 (sh) S 1 12\n").is_err());
+    }
+}
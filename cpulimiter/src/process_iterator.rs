@@ -1,23 +1,138 @@
 //! Parse the `/proc` directory to extract PIDs.
+//!
+//! On macOS, the processes are listed with `proc_listallpids` instead,
+//! and with the `kern.proc.proc` sysctl on FreeBSD.
 
+#[cfg(target_os = "linux")]
 use std::fs;
+#[cfg(target_os = "linux")]
 use std::fs::ReadDir;
 
+#[cfg(feature = "regex")]
+use regex::Regex;
+
 use crate::pid::Pid;
+#[cfg(target_os = "linux")]
+use crate::process_info::ProcessInfo;
+#[cfg(target_os = "linux")]
+use crate::procfs;
+#[cfg(target_os = "freebsd")]
+use crate::stat_iterator::ProcInfo;
+#[cfg(target_os = "linux")]
+use crate::target;
+
+/// An iterator over existing processes, optionally filtered.
+///
+/// ```no_run
+/// use cpulimiter::{Pid, ProcessIterator};
+///
+/// // the processes of the user 1000 named `make`, and their details
+/// for info in ProcessIterator::new().unwrap().by_uid(1000).by_name("make").infos() {
+///     println!("{} {:?}", info.pid(), info.utime());
+/// }
+/// ```
+#[cfg(target_os = "linux")]
+pub struct ProcessIterator {
+    proc: ReadDir,
+    filters: Vec<Filter>,
+}
+
+/// A condition the processes yielded by a [`ProcessIterator`] must meet.
+#[cfg(target_os = "linux")]
+enum Filter {
+    Name(String),
+    Uid(u32),
+    ChildOf(Pid),
+    #[cfg(feature = "regex")]
+    Matching(Regex),
+}
+
+#[cfg(target_os = "linux")]
+impl Filter {
+    fn accepts(&self, pid: Pid) -> bool {
+        match self {
+            Filter::Name(name) => target::has_name(pid, name),
+            Filter::Uid(uid) => pid.get_uid() == Some(*uid),
+            Filter::ChildOf(parent) => pid != *parent && pid.is_child_of(*parent),
+            #[cfg(feature = "regex")]
+            Filter::Matching(regex) => command_line(pid).is_some_and(|line| regex.is_match(&line)),
+        }
+    }
+}
+
+/// Retrieves the arguments of `pid` separated by spaces, or its command name
+/// if it has none, as kernel threads.
+#[cfg(feature = "regex")]
+fn command_line(pid: Pid) -> Option<String> {
+    let cmdline = fs::read(procfs::path(format!("{pid}/cmdline"))).ok()?;
+    if cmdline.is_empty() {
+        return pid.get_name();
+    }
+    let args: Vec<_> = cmdline
+        .split(|&byte| byte == 0)
+        .filter(|arg| !arg.is_empty())
+        .map(String::from_utf8_lossy)
+        .collect();
+    Some(args.join(" "))
+}
 
 /// An iterator over existing processes.
+#[cfg(any(target_os = "macos", target_os = "freebsd"))]
 pub(crate) struct ProcessIterator {
-    proc: ReadDir,
+    pids: std::vec::IntoIter<Pid>,
 }
 
+#[cfg(target_os = "linux")]
 impl ProcessIterator {
     /// Instantiates a `ProcessIterator` (open the `/proc` directory).
     pub fn new() -> std::io::Result<Self> {
-        let proc = fs::read_dir("/proc")?;
-        Ok(Self { proc })
+        let proc = fs::read_dir(procfs::path(""))?;
+        Ok(Self {
+            proc,
+            filters: Vec::new(),
+        })
+    }
+
+    /// Only yields the processes with the command name `name`, compared as for
+    /// [`Target::Name`](crate::Target::Name).
+    #[must_use]
+    pub fn by_name(mut self, name: impl Into<String>) -> Self {
+        self.filters.push(Filter::Name(name.into()));
+        self
+    }
+
+    /// Only yields the processes owned by the real user `uid`.
+    #[must_use]
+    pub fn by_uid(mut self, uid: u32) -> Self {
+        self.filters.push(Filter::Uid(uid));
+        self
+    }
+
+    /// Only yields the descendants of `pid`, not `pid` itself.
+    #[must_use]
+    pub fn children_of(mut self, pid: Pid) -> Self {
+        self.filters.push(Filter::ChildOf(pid));
+        self
+    }
+
+    /// Only yields the processes whose command line, arguments separated by
+    /// spaces, matches `regex`.
+    ///
+    /// Kernel threads have no arguments: their command name is matched instead.
+    #[cfg(feature = "regex")]
+    #[must_use]
+    pub fn matching(mut self, regex: Regex) -> Self {
+        self.filters.push(Filter::Matching(regex));
+        self
+    }
+
+    /// Reads the details of each process yielded, skipping the ones exiting meanwhile.
+    pub fn infos(self) -> impl Iterator<Item = ProcessInfo> {
+        self.filter_map(|pid| pid.info().ok())
     }
 }
 
+#[cfg(target_os = "linux")]
 impl Iterator for ProcessIterator {
     type Item = Pid;
 
@@ -35,9 +150,100 @@ impl Iterator for ProcessIterator {
 
             if let Some(pid) = next.file_name().to_str() {
                 if let Ok(pid) = pid.parse::<u32>() {
-                    return Some(Pid::from(pid));
+                    let pid = Pid::from(pid);
+                    if self.filters.iter().all(|filter| filter.accepts(pid)) {
+                        return Some(pid);
+                    }
                 }
             }
         }
     }
 }
+
+#[cfg(target_os = "macos")]
+impl ProcessIterator {
+    /// Instantiates a `ProcessIterator` (take a snapshot of the process list).
+    pub fn new() -> std::io::Result<Self> {
+        // SAFETY: Inherently unsafe as a syscall, a null buffer only queries the count.
+        let count = unsafe { libc::proc_listallpids(std::ptr::null_mut(), 0) };
+        if count < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+
+        // leave room for the processes created in the meantime
+        let mut pids = vec![0 as libc::pid_t; count as usize + 32];
+        let size = (pids.len() * std::mem::size_of::<libc::pid_t>()) as libc::c_int;
+        // SAFETY: Inherently unsafe as a syscall, but the buffer is valid for `size` bytes.
+        let count = unsafe { libc::proc_listallpids(pids.as_mut_ptr().cast(), size) };
+        if count < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+
+        pids.truncate(count as usize);
+        let pids: Vec<Pid> = pids.into_iter().map(|pid| Pid::from(pid as u32)).collect();
+        Ok(Self {
+            pids: pids.into_iter(),
+        })
+    }
+}
+
+#[cfg(target_os = "freebsd")]
+impl ProcessIterator {
+    /// Instantiates a `ProcessIterator` (take a snapshot of the process list).
+    pub fn new() -> std::io::Result<Self> {
+        let pids: Vec<Pid> = ProcInfo::all()?.iter().map(ProcInfo::pid).collect();
+        Ok(Self {
+            pids: pids.into_iter(),
+        })
+    }
+}
+
+#[cfg(any(target_os = "macos", target_os = "freebsd"))]
+impl Iterator for ProcessIterator {
+    type Item = Pid;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.pids.next()
+    }
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod test {
+    use std::process::Command;
+    use std::thread;
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn filters() {
+        let current = Pid::from(std::process::id());
+        let mut child = Command::new("sleep").arg("10").spawn().unwrap();
+        let pid = Pid::from(child.id());
+        // until the child executes `sleep`, it has the name and arguments of the test
+        thread::sleep(Duration::from_millis(100));
+
+        let children: Vec<Pid> = ProcessIterator::new()
+            .unwrap()
+            .children_of(current)
+            .collect();
+        assert!(children.contains(&pid) && !children.contains(&current));
+
+        let sleeping: Vec<Pid> = ProcessIterator::new()
+            .unwrap()
+            .children_of(current)
+            .by_name("sleep")
+            .by_uid(current.get_uid().unwrap())
+            .collect();
+        assert_eq!(sleeping, vec![pid]);
+
+        #[cfg(feature = "regex")]
+        assert!(ProcessIterator::new()
+            .unwrap()
+            .matching(Regex::new("^sleep 10$").unwrap())
+            .any(|process| process == pid));
+
+        child.kill().unwrap();
+        child.wait().unwrap();
+    }
+}
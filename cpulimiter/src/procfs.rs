@@ -0,0 +1,63 @@
+//! Locate the procfs the processes are read from, `/proc` unless configured otherwise.
+//!
+//! Inside a container, the procfs of the host may be bind-mounted elsewhere,
+//! and tests may provide a fake tree. The root is read from the
+//! `CPULIMIT_PROCFS` environment variable at first use, or set with
+//! [`ProcfsRoot::install`].
+
+use std::env;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use parking_lot::RwLock;
+
+/// The environment variable overriding the default root.
+const ENV_VAR: &str = "CPULIMIT_PROCFS";
+
+/// The root in use by the whole library.
+static ROOT: OnceLock<RwLock<ProcfsRoot>> = OnceLock::new();
+
+fn root() -> &'static RwLock<ProcfsRoot> {
+    ROOT.get_or_init(|| {
+        RwLock::new(env::var_os(ENV_VAR).map_or_else(ProcfsRoot::default, ProcfsRoot::new))
+    })
+}
+
+/// Where procfs is mounted.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct ProcfsRoot(PathBuf);
+
+impl ProcfsRoot {
+    /// Designates a procfs mounted at `path`.
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self(path.into())
+    }
+
+    /// Retrieves the root currently in use.
+    pub fn current() -> Self {
+        root().read().clone()
+    }
+
+    /// Reads the processes from this root from now on, in the whole process.
+    ///
+    /// Takes precedence over the `CPULIMIT_PROCFS` environment variable.
+    pub fn install(self) {
+        *root().write() = self;
+    }
+
+    /// Retrieves the path of the mount point.
+    pub fn path(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Default for ProcfsRoot {
+    fn default() -> Self {
+        Self::new("/proc")
+    }
+}
+
+/// Retrieves the path of `relative` in the procfs in use, such as `1/stat`.
+pub(crate) fn path(relative: impl AsRef<Path>) -> PathBuf {
+    root().read().0.join(relative)
+}
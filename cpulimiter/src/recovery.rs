@@ -0,0 +1,180 @@
+//! Resume the processes left suspended by a limiter that was killed.
+//!
+//! A limiter given a recovery directory lists its members in a file of its
+//! own there, removed once it stops. [`recover`] resumes the stopped members
+//! listed by the limiters which are gone, for instance after a `SIGKILL`.
+//!
+//! The first line of each file is the PID and the start time of the process
+//! running the limiter, the following lines those of the members. The start
+//! times tell apart the processes which got the same PID since.
+
+use std::fs::{self, File};
+use std::io;
+use std::os::unix::fs::FileExt;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::pid::{Pid, Signal};
+
+/// Tells apart the files of the limiters of a same process.
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+/// The file listing the members of a limiter, removed when dropped.
+///
+/// The file is kept open: it is still written once the process dropped the
+/// privileges it was created with.
+pub(crate) struct Journal {
+    path: PathBuf,
+    file: File,
+    /// The members listed in the file, sorted.
+    members: Vec<Pid>,
+}
+
+impl Journal {
+    /// Creates the file of a new limiter in `dir`, and `dir` if needed.
+    pub fn create(dir: &Path) -> io::Result<Self> {
+        fs::create_dir_all(dir)?;
+        let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+        let path = dir.join(format!("{}-{id}", process::id()));
+        // never seen empty by `recover`
+        let temporary = path.with_extension("tmp");
+        let journal = Self {
+            file: File::create(&temporary)?,
+            path,
+            members: Vec::new(),
+        };
+        if let Err(err) = journal
+            .write()
+            .and_then(|()| fs::rename(&temporary, &journal.path))
+        {
+            let _ = fs::remove_file(&temporary);
+            return Err(err);
+        }
+        Ok(journal)
+    }
+
+    /// Lists `members` in the file, unless they didn't change.
+    pub fn record(&mut self, mut members: Vec<Pid>) -> io::Result<()> {
+        members.sort();
+        if members == self.members {
+            return Ok(());
+        }
+        self.members = members;
+        self.write()
+    }
+
+    /// Rewrites the file in place.
+    ///
+    /// The first line never changes, and [`recover`] leaves the file alone
+    /// while this process runs: it never reads a partial list.
+    fn write(&self) -> io::Result<()> {
+        let owner = Pid::from(process::id());
+        let mut content = String::new();
+        for pid in std::iter::once(owner).chain(self.members.iter().copied()) {
+            if let Some(start_time) = pid.get_start_time() {
+                content.push_str(&format!("{pid} {start_time}\n"));
+            }
+        }
+
+        self.file.write_all_at(content.as_bytes(), 0)?;
+        self.file.set_len(content.len() as u64)
+    }
+}
+
+impl Drop for Journal {
+    fn drop(&mut self) {
+        // emptied for `recover` to remove it, if this process can't anymore
+        if fs::remove_file(&self.path).is_err() {
+            let _ = self.file.set_len(0);
+        }
+    }
+}
+
+/// Sends `SIGCONT` to the stopped processes listed in `dir` by the limiters
+/// which are gone, and removes their files.
+///
+/// Returns the processes resumed. The files of the running limiters are left alone.
+pub fn recover(dir: impl AsRef<Path>) -> io::Result<Vec<Pid>> {
+    let mut resumed = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().is_some_and(|extension| extension == "tmp") {
+            continue;
+        }
+        let Ok(content) = fs::read_to_string(&path) else {
+            continue;
+        };
+
+        let mut processes = content.lines().filter_map(|line| {
+            let (pid, start_time) = line.split_once(' ')?;
+            Some((pid.parse::<Pid>().ok()?, start_time.parse::<u64>().ok()?))
+        });
+        let Some(owner) = processes.next() else {
+            // left by a limiter which couldn't remove it
+            if content.is_empty() {
+                fs::remove_file(path)?;
+            }
+            continue;
+        };
+        if is_running(owner) {
+            continue;
+        }
+
+        for (pid, start_time) in processes {
+            if is_running((pid, start_time))
+                && pid.is_stopped()
+                && pid.kill(&Signal::SIGCONT).is_ok()
+            {
+                resumed.push(pid);
+            }
+        }
+        fs::remove_file(path)?;
+    }
+    Ok(resumed)
+}
+
+/// Indicates whether the process `pid` started at `start_time` still exists.
+fn is_running((pid, start_time): (Pid, u64)) -> bool {
+    pid.get_start_time() == Some(start_time)
+}
+
+#[cfg(test)]
+mod test {
+    use std::process::Command;
+    use std::thread;
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn recover_stopped() {
+        let dir = std::env::temp_dir().join(format!("cpulimit-recovery-{}", process::id()));
+        let mut child = Command::new("sleep").arg("10").spawn().unwrap();
+        let pid = Pid::from(child.id());
+
+        let mut journal = Journal::create(&dir).unwrap();
+        journal.record(vec![pid]).unwrap();
+        pid.kill(&Signal::SIGSTOP).unwrap();
+        while !pid.is_stopped() {
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        // left alone while the limiter runs
+        assert_eq!(recover(&dir).unwrap(), vec![]);
+        assert!(pid.is_stopped());
+
+        // as if this process had been replaced by another one
+        let content = fs::read_to_string(&journal.path).unwrap();
+        let (_, members) = content.split_once('\n').unwrap();
+        fs::write(&journal.path, format!("{} 0\n{members}", process::id())).unwrap();
+        assert_eq!(recover(&dir).unwrap(), vec![pid]);
+        assert!(!pid.is_stopped());
+        assert!(!journal.path.exists());
+
+        drop(journal);
+        fs::remove_dir(dir).unwrap();
+        child.kill().unwrap();
+        child.wait().unwrap();
+    }
+}
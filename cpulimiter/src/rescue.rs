@@ -0,0 +1,144 @@
+//! Resume the suspended processes when this process dies abruptly.
+//!
+//! The processes stopped by the groups are listed in a fixed table of atomics,
+//! the only kind of state a signal handler may read. On a fatal signal, the
+//! handler sends `SIGCONT` to each of them, then lets the previous handler or
+//! the default action take place. It runs on the alternate signal stack of the
+//! thread, if any, to handle stack overflows too.
+//!
+//! The termination signals are only handled when nothing else handles them, so
+//! that a program stopping its limiters gracefully on `SIGTERM` still can.
+
+use std::ffi::c_void;
+use std::mem;
+use std::ptr;
+use std::sync::atomic::{AtomicI32, Ordering};
+use std::sync::OnceLock;
+
+use crate::pid::Pid;
+
+/// How many suspended processes can be tracked at once, the others are not resumed.
+const CAPACITY: usize = 1024;
+
+/// The signals terminating the process after a bug, always handled.
+const FAULTS: [libc::c_int; 5] = [
+    libc::SIGABRT,
+    libc::SIGBUS,
+    libc::SIGFPE,
+    libc::SIGILL,
+    libc::SIGSEGV,
+];
+
+/// The signals asking the process to terminate, handled unless already handled.
+const TERMINATIONS: [libc::c_int; 4] = [libc::SIGHUP, libc::SIGINT, libc::SIGQUIT, libc::SIGTERM];
+
+/// The suspended processes, `0` in the free slots.
+static SUSPENDED: [AtomicI32; CAPACITY] = [const { AtomicI32::new(0) }; CAPACITY];
+
+/// The actions replaced by the handler, by signal number.
+static PREVIOUS: OnceLock<Vec<(libc::c_int, libc::sigaction)>> = OnceLock::new();
+
+/// Records that `pid` was suspended, for the handler to resume it.
+pub(crate) fn track(pid: Pid) {
+    let pid = u32::from(pid) as libc::pid_t;
+    if SUSPENDED
+        .iter()
+        .any(|slot| slot.load(Ordering::Relaxed) == pid)
+    {
+        return;
+    }
+    for slot in &SUSPENDED {
+        if slot
+            .compare_exchange(0, pid, Ordering::Relaxed, Ordering::Relaxed)
+            .is_ok()
+        {
+            return;
+        }
+    }
+}
+
+/// Records that `pid` was resumed.
+pub(crate) fn untrack(pid: Pid) {
+    let pid = u32::from(pid) as libc::pid_t;
+    for slot in &SUSPENDED {
+        let _ = slot.compare_exchange(pid, 0, Ordering::Relaxed, Ordering::Relaxed);
+    }
+}
+
+/// Installs the handler of the fatal signals, once for the whole process.
+pub(crate) fn install() {
+    if PREVIOUS.get().is_some() {
+        return;
+    }
+
+    let mut previous = Vec::new();
+    for signal in FAULTS.into_iter().chain(TERMINATIONS) {
+        // SAFETY: an all-zero `sigaction` is a valid value, overwritten by the call.
+        let mut current: libc::sigaction = unsafe { mem::zeroed() };
+        // SAFETY: `sigaction` only writes the current action to `current`.
+        if unsafe { libc::sigaction(signal, ptr::null(), &mut current) } != 0 {
+            continue;
+        }
+        let handled = current.sa_sigaction != libc::SIG_DFL;
+        if current.sa_sigaction == libc::SIG_IGN || (handled && TERMINATIONS.contains(&signal)) {
+            continue;
+        }
+        previous.push((signal, current));
+    }
+
+    // the actions are known before the handler may look them up
+    if PREVIOUS.set(previous).is_err() {
+        return;
+    }
+    // SAFETY: an all-zero `sigaction` is an empty mask and no flags.
+    let mut action: libc::sigaction = unsafe { mem::zeroed() };
+    action.sa_sigaction = handler as Handler as libc::sighandler_t;
+    action.sa_flags = libc::SA_SIGINFO | libc::SA_ONSTACK;
+    for (signal, _) in PREVIOUS.get().into_iter().flatten() {
+        // SAFETY: the handler only calls async-signal-safe functions.
+        unsafe { libc::sigaction(*signal, &action, ptr::null_mut()) };
+    }
+}
+
+/// A handler receiving the details of the signal, with `SA_SIGINFO`.
+type Handler = extern "C" fn(libc::c_int, *mut libc::siginfo_t, *mut c_void);
+
+/// Resumes the suspended processes, then calls the previous handler, or
+/// delivers `signal` again for the default action to take place.
+extern "C" fn handler(signal: libc::c_int, info: *mut libc::siginfo_t, context: *mut c_void) {
+    for slot in &SUSPENDED {
+        let pid = slot.swap(0, Ordering::Relaxed);
+        if pid != 0 {
+            // SAFETY: `kill` is async-signal-safe.
+            unsafe { libc::kill(pid, libc::SIGCONT) };
+        }
+    }
+
+    let previous = PREVIOUS.get().and_then(|previous| {
+        previous
+            .iter()
+            .find(|(number, _)| *number == signal)
+            .map(|(_, action)| action)
+    });
+    let Some(action) = previous else {
+        return;
+    };
+    if action.sa_sigaction == libc::SIG_DFL {
+        // SAFETY: `sigaction` and `raise` are async-signal-safe, and `action`
+        // was returned by `sigaction`.
+        unsafe {
+            libc::sigaction(signal, action, ptr::null_mut());
+            libc::raise(signal);
+        }
+    } else if action.sa_flags & libc::SA_SIGINFO != 0 {
+        // SAFETY: the previous handler was installed with `SA_SIGINFO`, and
+        // gets the details of the signal as the kernel gave them, such as the
+        // faulting address the stack overflow handler of std looks at.
+        let previous: Handler = unsafe { mem::transmute(action.sa_sigaction) };
+        previous(signal, info, context);
+    } else {
+        // SAFETY: the previous handler was installed without `SA_SIGINFO`.
+        let previous: extern "C" fn(libc::c_int) = unsafe { mem::transmute(action.sa_sigaction) };
+        previous(signal);
+    }
+}
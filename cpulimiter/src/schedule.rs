@@ -0,0 +1,184 @@
+//! Change the limit over time, with daily schedules and gradual ramps.
+
+use std::fmt::Display;
+use std::time::{Duration, Instant};
+
+/// A time of the day, in local time.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub struct TimeOfDay {
+    hour: u8,
+    minute: u8,
+}
+
+impl TimeOfDay {
+    /// Creates a time of the day, `None` if `hour` or `minute` is out of range.
+    pub fn new(hour: u8, minute: u8) -> Option<Self> {
+        (hour < 24 && minute < 60).then_some(Self { hour, minute })
+    }
+
+    /// Retrieves the current local time.
+    pub fn now() -> Self {
+        // SAFETY: Inherently unsafe as a syscall, but a null pointer is allowed.
+        let now = unsafe { libc::time(std::ptr::null_mut()) };
+        // SAFETY: `tm` is a plain C struct that can be zeroed.
+        let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+        // SAFETY: Inherently unsafe as a syscall, but both pointers are valid.
+        unsafe { libc::localtime_r(&now, &mut tm) };
+
+        Self {
+            hour: tm.tm_hour as u8,
+            minute: tm.tm_min as u8,
+        }
+    }
+}
+
+impl Display for TimeOfDay {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:02}:{:02}", self.hour, self.minute)
+    }
+}
+
+/// A daily time window, from `start` (included) to `end` (excluded).
+///
+/// The window spans midnight when `end` is before `start`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct TimeRange {
+    pub start: TimeOfDay,
+    pub end: TimeOfDay,
+}
+
+impl TimeRange {
+    /// Creates a time window from `start` to `end`.
+    pub fn new(start: TimeOfDay, end: TimeOfDay) -> Self {
+        Self { start, end }
+    }
+
+    /// Indicates whether `time` is in the window.
+    pub fn contains(&self, time: TimeOfDay) -> bool {
+        if self.start <= self.end {
+            self.start <= time && time < self.end
+        } else {
+            self.start <= time || time < self.end
+        }
+    }
+}
+
+/// A gradual change of the limit.
+struct Ramp {
+    from: f64,
+    to: f64,
+    start: Instant,
+    duration: Duration,
+}
+
+impl Ramp {
+    /// Interpolates the limit at the current time, `None` once the ramp is over.
+    fn limit(&self) -> Option<f64> {
+        let progress = self.start.elapsed().as_secs_f64() / self.duration.as_secs_f64();
+        (progress < 1_f64).then_some(self.from + (self.to - self.from) * progress)
+    }
+}
+
+/// The limit requested by the user, possibly overridden by a schedule.
+pub(crate) struct ActiveLimit {
+    base: f64,
+    /// The change of `base` in progress, if any.
+    ramp: Option<Ramp>,
+    /// The first window containing the current time wins.
+    schedule: Vec<(TimeRange, f64)>,
+    current: f64,
+}
+
+impl ActiveLimit {
+    /// Starts with `base` as the active limit.
+    pub fn new(base: f64) -> Self {
+        Self {
+            base,
+            ramp: None,
+            schedule: Vec::new(),
+            current: base,
+        }
+    }
+
+    /// Sets the limit applied outside the windows of the schedule.
+    ///
+    /// Cancels the ramp in progress, if any.
+    pub fn set_base(&mut self, limit: f64) {
+        self.base = limit;
+        self.ramp = None;
+    }
+
+    /// Moves the base limit from the limit in effect to `limit` over `duration`.
+    pub fn ramp_to(&mut self, limit: f64, duration: Duration) {
+        self.base = self.current;
+        self.ramp = Some(Ramp {
+            from: self.current,
+            to: limit,
+            start: Instant::now(),
+            duration,
+        });
+    }
+
+    /// Replaces the schedule.
+    pub fn set_schedule(&mut self, schedule: Vec<(TimeRange, f64)>) {
+        self.schedule = schedule;
+    }
+
+    /// Retrieves the limit currently in effect.
+    #[inline]
+    pub fn get(&self) -> f64 {
+        self.current
+    }
+
+    /// Selects the limit in effect now, returning it if it changed.
+    pub fn refresh(&mut self) -> Option<f64> {
+        if let Some(ramp) = &self.ramp {
+            match ramp.limit() {
+                Some(limit) => self.base = limit,
+                None => {
+                    self.base = ramp.to;
+                    self.ramp = None;
+                }
+            }
+        }
+
+        let now = TimeOfDay::now();
+        let limit = self
+            .schedule
+            .iter()
+            .find(|(range, _)| range.contains(now))
+            .map_or(self.base, |&(_, limit)| limit);
+
+        (limit != self.current).then(|| {
+            self.current = limit;
+            limit
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{TimeOfDay, TimeRange};
+
+    fn at(hour: u8, minute: u8) -> TimeOfDay {
+        TimeOfDay::new(hour, minute).unwrap()
+    }
+
+    #[test]
+    fn daytime_range() {
+        let range = TimeRange::new(at(9, 0), at(18, 30));
+        assert!(range.contains(at(9, 0)));
+        assert!(range.contains(at(18, 29)));
+        assert!(!range.contains(at(18, 30)));
+        assert!(!range.contains(at(2, 0)));
+    }
+
+    #[test]
+    fn overnight_range() {
+        let range = TimeRange::new(at(22, 0), at(6, 0));
+        assert!(range.contains(at(23, 59)));
+        assert!(range.contains(at(0, 0)));
+        assert!(!range.contains(at(6, 0)));
+        assert!(!range.contains(at(12, 0)));
+    }
+}
@@ -0,0 +1,107 @@
+//! Launch a command that stays suspended until it is attached to a limiter.
+//!
+//! The child process stops itself right after the `fork`, before calling
+//! `exec`: the program it runs never executes a single instruction unthrottled.
+
+use std::fs::File;
+use std::io::{self, Read};
+use std::mem::size_of;
+use std::os::unix::io::{FromRawFd, OwnedFd};
+use std::os::unix::process::CommandExt;
+use std::process::{Child, Command};
+use std::thread::{self, JoinHandle};
+
+use crate::pid::{Pid, Signal};
+
+/// A command that was forked but waits for a `SIGCONT` to call `exec`.
+pub(crate) struct StoppedChild {
+    pub pid: Pid,
+    /// The thread waiting for [`Command::spawn`] to return.
+    ///
+    /// The standard library only returns once `exec` succeeded or failed.
+    spawner: JoinHandle<io::Result<Child>>,
+}
+
+impl StoppedChild {
+    /// Forks a child process for `command` and waits until it stopped itself.
+    pub fn spawn(mut command: Command) -> io::Result<Self> {
+        let mut fds = [0; 2];
+        // SAFETY: Inherently unsafe as a syscall, but `fds` can hold two file descriptors.
+        if unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        // SAFETY: The file descriptors were just created and are owned by nobody else.
+        let (reader, writer) =
+            unsafe { (OwnedFd::from_raw_fd(fds[0]), OwnedFd::from_raw_fd(fds[1])) };
+
+        let write_fd = fds[1];
+        // SAFETY: Only async-signal-safe functions are called between `fork` and `exec`.
+        unsafe {
+            command.pre_exec(move || {
+                let pid = libc::getpid();
+                libc::write(
+                    write_fd,
+                    (&pid as *const libc::pid_t).cast(),
+                    size_of::<libc::pid_t>(),
+                );
+                libc::raise(libc::SIGSTOP);
+                Ok(())
+            });
+        }
+
+        let spawner = thread::Builder::new().spawn(move || {
+            let child = command.spawn();
+            // closes the pipe if the `fork` failed
+            drop(writer);
+            child
+        })?;
+
+        let mut buf = [0; size_of::<libc::pid_t>()];
+        if let Err(err) = File::from(reader).read_exact(&mut buf) {
+            // the child was never created, the spawning thread knows why
+            return match spawner.join() {
+                Ok(Err(spawn_err)) => Err(spawn_err),
+                _ => Err(err),
+            };
+        }
+        let pid = libc::pid_t::from_ne_bytes(buf);
+
+        // wait until the child actually stopped, without reaping it.
+        // SAFETY: `siginfo_t` is a plain C struct that can be zeroed.
+        let mut info: libc::siginfo_t = unsafe { std::mem::zeroed() };
+        // SAFETY: Inherently unsafe as a syscall, but the PID is a child of this process.
+        let res = unsafe {
+            libc::waitid(
+                libc::P_PID,
+                pid as libc::id_t,
+                &mut info,
+                libc::WSTOPPED | libc::WNOWAIT,
+            )
+        };
+        if res != 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(Self {
+            pid: Pid::from(pid as u32),
+            spawner,
+        })
+    }
+
+    /// Resumes the child and waits for it to call `exec`.
+    pub fn exec(self) -> io::Result<Child> {
+        // fails if the child was killed in the meantime, `spawn` reports it
+        let _ = self.pid.kill(&Signal::SIGCONT);
+        self.spawner
+            .join()
+            .map_err(|_| io::Error::other("the spawning thread panicked"))?
+    }
+
+    /// Kills the child instead of letting it run its program.
+    pub fn abort(self) {
+        let _ = self.pid.kill(&Signal::SIGKILL);
+        if let Ok(mut child) = self.exec() {
+            let _ = child.wait();
+        }
+    }
+}
@@ -1,40 +1,112 @@
 //! An iterator over the fields of `/proc/<pid>/stat` files.
 //!
+//! Threads have their own `/proc/<pid>/task/<tid>/stat` file with the same format.
+//!
 //! The second field of stat files (`comm`) is an arbitrary string
-//! that might contain whitespace, making the straightforward
-//! [`str::split_whitespace`] parsing impossible.
+//! that might contain whitespace, newlines, parentheses or bytes that aren't
+//! UTF-8, making the straightforward [`str::split_whitespace`] parsing
+//! impossible. The files are thus parsed as bytes.
 //!
 //! See `man proc` for a list of the fields in the file.
+//!
+//! FreeBSD has no procfs by default: the same information is read from the
+//! `kinfo_proc` structures returned by the `kern.proc` sysctl.
 
-use std::{fs, io};
+#[cfg(target_os = "linux")]
+use std::fs::{self, File};
+use std::io;
+#[cfg(target_os = "freebsd")]
+use std::mem::size_of;
+#[cfg(target_os = "linux")]
+use std::os::unix::fs::FileExt;
+#[cfg(target_os = "linux")]
+use std::path::PathBuf;
+#[cfg(target_os = "linux")]
+use std::str::FromStr;
+#[cfg(target_os = "freebsd")]
+use std::time::Duration;
 
 use crate::pid::Pid;
+#[cfg(target_os = "linux")]
+use crate::procfs;
 
 /// The content of a `/proc/<pid>/stat` file.
-pub struct StatFile(String);
+#[cfg(target_os = "linux")]
+pub struct StatFile(Vec<u8>);
+
+/// A `/proc/<pid>/stat` file kept open, to be read again without reallocating.
+///
+/// Once the process exits, reading fails even if its PID is reused.
+#[cfg(target_os = "linux")]
+pub struct StatReader {
+    file: File,
+    buffer: Vec<u8>,
+}
 
-/// An iterator over the fields of a [`StatFile`].
+/// An iterator over the raw fields of a [`StatFile`].
+///
+/// A malformed or truncated file yields an [`io::ErrorKind::InvalidData`]
+/// error, after which the iteration stops.
+#[cfg(target_os = "linux")]
+#[derive(Clone)]
 pub struct StatFileIter<'s> {
-    data: &'s str,
+    data: &'s [u8],
     idx: usize,
     state: State,
 }
 
+/// The fields of a stat file read by the crate, numbered from 0 in their order in the file.
+#[cfg(target_os = "linux")]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum StatField {
+    Pid = 0,
+    Comm = 1,
+    State = 2,
+    Ppid = 3,
+    Pgrp = 4,
+    /// The kernel flags of the process, the `PF_*` constants.
+    Flags = 8,
+    /// The CPU time spent in user mode, in clock ticks.
+    Utime = 13,
+    /// The CPU time spent in kernel mode, in clock ticks.
+    Stime = 14,
+    /// The CPU time spent in user mode by the children waited for, in clock ticks.
+    Cutime = 15,
+    /// The CPU time spent in kernel mode by the children waited for, in clock ticks.
+    Cstime = 16,
+    Nice = 18,
+    NumThreads = 19,
+    /// The time the process started after system boot, in clock ticks.
+    Starttime = 21,
+    /// The resident set size, in pages.
+    Rss = 23,
+}
+
 /// The state of a `StatFileIter`.
-#[derive(PartialEq)]
+#[cfg(target_os = "linux")]
+#[derive(Clone, Copy, PartialEq)]
 enum State {
     /// Just instantiated, the next field is the first (PID).
     Pid,
     /// The next field to yield is the command name.
     Command,
-    /// The remaining fields are simply separated by whitespace.
+    /// The remaining fields are simply separated by spaces.
     Normal,
+    /// The end of the file or an error was reached.
+    Done,
 }
 
+#[cfg(target_os = "linux")]
 impl StatFile {
     /// Opens the `/proc/<pid>/stat` file.
     pub fn open(pid: Pid) -> io::Result<Self> {
-        let stat = fs::read_to_string(format!("/proc/{pid}/stat"))?;
+        let stat = fs::read(procfs::path(format!("{pid}/stat")))?;
+        Ok(Self(stat))
+    }
+
+    /// Opens the `/proc/<pid>/task/<tid>/stat` file of a thread.
+    pub fn open_thread(pid: Pid, tid: Pid) -> io::Result<Self> {
+        let stat = fs::read(procfs::path(format!("{pid}/task/{tid}/stat")))?;
         Ok(Self(stat))
     }
 
@@ -42,10 +114,58 @@ impl StatFile {
     pub fn iter(&self) -> StatFileIter<'_> {
         self.0[..].into()
     }
+
+    /// Parses a field of the file, see [`StatFileIter::field`].
+    pub fn field<T: FromStr>(&self, field: StatField) -> io::Result<T> {
+        self.iter().field(field)
+    }
 }
 
-impl<'a> From<&'a str> for StatFileIter<'a> {
-    fn from(data: &'a str) -> Self {
+#[cfg(target_os = "linux")]
+impl StatReader {
+    /// Opens the `/proc/<pid>/stat` file.
+    pub fn open(pid: Pid) -> io::Result<Self> {
+        Self::with_path(procfs::path(format!("{pid}/stat")))
+    }
+
+    /// Opens the `/proc/<pid>/task/<tid>/stat` file of a thread.
+    pub fn open_thread(pid: Pid, tid: Pid) -> io::Result<Self> {
+        Self::with_path(procfs::path(format!("{pid}/task/{tid}/stat")))
+    }
+
+    fn with_path(path: PathBuf) -> io::Result<Self> {
+        Ok(Self {
+            file: File::open(path)?,
+            // large enough for most stat files
+            buffer: vec![0; 512],
+        })
+    }
+
+    /// Reads the current content of the file, from the start.
+    pub fn read(&mut self) -> io::Result<&[u8]> {
+        let mut filled = 0;
+        loop {
+            if filled == self.buffer.len() {
+                self.buffer.resize(filled * 2, 0);
+            }
+
+            let read = self
+                .file
+                .read_at(&mut self.buffer[filled..], filled as u64)?;
+            filled += read;
+            // the whole file is generated at once, a short read means the end
+            if read == 0 || filled < self.buffer.len() {
+                break;
+            }
+        }
+
+        Ok(&self.buffer[..filled])
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl<'a> From<&'a [u8]> for StatFileIter<'a> {
+    fn from(data: &'a [u8]) -> Self {
         Self {
             data,
             idx: 0,
@@ -54,68 +174,287 @@ impl<'a> From<&'a str> for StatFileIter<'a> {
     }
 }
 
+#[cfg(target_os = "linux")]
+impl<'a> From<&'a str> for StatFileIter<'a> {
+    fn from(data: &'a str) -> Self {
+        data.as_bytes().into()
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl<'a> StatFileIter<'a> {
+    /// Parses a field of the file, wherever the iterator currently is.
+    ///
+    /// Fails with [`io::ErrorKind::InvalidData`] if the field is missing or malformed.
+    pub fn field<T: FromStr>(&self, field: StatField) -> io::Result<T> {
+        let value = self.raw_field(field)?;
+        std::str::from_utf8(value)
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .ok_or_else(|| {
+                invalid_data(format!(
+                    "invalid stat field {field:?}: `{}`",
+                    String::from_utf8_lossy(value)
+                ))
+            })
+    }
+
+    /// Retrieves the bytes of a field of the file, wherever the iterator currently is.
+    ///
+    /// Fails with [`io::ErrorKind::InvalidData`] if the field is missing or a
+    /// previous field is malformed.
+    pub fn raw_field(&self, field: StatField) -> io::Result<&'a [u8]> {
+        let mut fields = StatFileIter::from(self.data);
+        for _ in 0..field as usize {
+            fields.next().transpose()?;
+        }
+        fields
+            .next()
+            .unwrap_or_else(|| Err(invalid_data(format!("missing stat field {field:?}"))))
+    }
+
+    /// Stops the iteration on a malformed file.
+    fn fail(&mut self, reason: &str) -> io::Result<&'a [u8]> {
+        self.state = State::Done;
+        Err(invalid_data(format!(
+            "{reason} at byte {} of stat file",
+            self.idx
+        )))
+    }
+}
+
+#[cfg(target_os = "linux")]
 impl<'a> Iterator for StatFileIter<'a> {
-    type Item = &'a str;
+    type Item = io::Result<&'a [u8]>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        if let State::Command = self.state {
-            // find the last parenthesis as it marks the end of the command name
-            let idx = self.data.rfind(')')?;
-            self.idx += 1; // skip first parenthesis
-            let res = &self.data[self.idx..idx];
-
-            // the following fields are all whitespace-separated
-            self.state = State::Normal;
-            self.idx = idx + 2; // place idx on the next field
-
-            Some(res)
-        } else {
-            if self.state == State::Pid {
+        let rest = &self.data[self.idx..];
+        match self.state {
+            State::Pid => {
+                // the PID is followed by the command name, in parentheses
+                let Some(end) = rest.windows(2).position(|pair| pair == b" (") else {
+                    return Some(self.fail("missing command name"));
+                };
                 self.state = State::Command;
+                self.idx += end + 1;
+                Some(Ok(&rest[..end]))
             }
-
-            // yield the next whitespace-separated field
-            let idx = self.idx + self.data[self.idx..].find(char::is_whitespace)?;
-            let res = &self.data[self.idx..idx];
-            self.idx = idx + 1;
-            Some(res)
+            State::Command => {
+                // find the last parenthesis as it marks the end of the command name
+                let Some(end) = rest.iter().rposition(|&byte| byte == b')') else {
+                    return Some(self.fail("missing closing parenthesis"));
+                };
+                self.state = State::Normal;
+                self.idx += end + 1;
+                Some(Ok(&rest[1..end]))
+            }
+            State::Normal => {
+                // each field is preceded by a space, the file ends with a newline
+                let field = match rest {
+                    [] | [b'\n'] => {
+                        self.state = State::Done;
+                        return None;
+                    }
+                    [b' ', field @ ..] => field,
+                    _ => return Some(self.fail("missing field separator")),
+                };
+                let len = field
+                    .iter()
+                    .position(|&byte| matches!(byte, b' ' | b'\n'))
+                    .unwrap_or(field.len());
+                if len == 0 {
+                    return Some(self.fail("empty field"));
+                }
+                self.idx += 1 + len;
+                Some(Ok(&field[..len]))
+            }
+            State::Done => None,
         }
     }
 }
 
-#[cfg(test)]
+/// Builds an error reporting a malformed stat file.
+#[cfg(target_os = "linux")]
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// The `kinfo_proc` structure of a process, the FreeBSD counterpart of a stat file.
+#[cfg(target_os = "freebsd")]
+pub struct ProcInfo(libc::kinfo_proc);
+
+#[cfg(target_os = "freebsd")]
+impl ProcInfo {
+    /// Queries the information of the process `pid` (`kern.proc.pid.<pid>`).
+    pub fn query(pid: libc::pid_t) -> io::Result<Self> {
+        let mib = [libc::CTL_KERN, libc::KERN_PROC, libc::KERN_PROC_PID, pid];
+        sysctl_procs(&mib)?
+            .pop()
+            .map(Self)
+            .ok_or_else(|| io::Error::from(io::ErrorKind::NotFound))
+    }
+
+    /// Queries the information of every process on the system (`kern.proc.proc`).
+    pub fn all() -> io::Result<Vec<Self>> {
+        let mib = [libc::CTL_KERN, libc::KERN_PROC, libc::KERN_PROC_PROC];
+        Ok(sysctl_procs(&mib)?.into_iter().map(Self).collect())
+    }
+
+    /// Retrieves the process identifier.
+    pub fn pid(&self) -> Pid {
+        Pid::from(self.0.ki_pid as u32)
+    }
+
+    /// Retrieves the parent process identifier.
+    pub fn ppid(&self) -> Pid {
+        Pid::from(self.0.ki_ppid as u32)
+    }
+
+    /// Retrieves the CPU time, user mode and kernel mode included.
+    pub fn cputime(&self) -> Duration {
+        // unit: microseconds
+        Duration::from_micros(self.0.ki_runtime)
+    }
+}
+
+/// Retrieves the `kinfo_proc` structures matching a `kern.proc` sysctl name.
+#[cfg(target_os = "freebsd")]
+fn sysctl_procs(mib: &[libc::c_int]) -> io::Result<Vec<libc::kinfo_proc>> {
+    let mut size = 0;
+    // SAFETY: Inherently unsafe as a syscall, a null buffer only queries the size.
+    let res = unsafe {
+        libc::sysctl(
+            mib.as_ptr(),
+            mib.len() as libc::c_uint,
+            std::ptr::null_mut(),
+            &mut size,
+            std::ptr::null(),
+            0,
+        )
+    };
+    if res != 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    // leave room for the processes created in the meantime
+    let capacity = size / size_of::<libc::kinfo_proc>() + 32;
+    let mut procs = Vec::<libc::kinfo_proc>::with_capacity(capacity);
+    let mut size = capacity * size_of::<libc::kinfo_proc>();
+    // SAFETY: Inherently unsafe as a syscall, but the buffer is valid for `size` bytes.
+    let res = unsafe {
+        libc::sysctl(
+            mib.as_ptr(),
+            mib.len() as libc::c_uint,
+            procs.as_mut_ptr().cast(),
+            &mut size,
+            std::ptr::null(),
+            0,
+        )
+    };
+    if res != 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    // SAFETY: The kernel filled `size` bytes with complete structures.
+    unsafe { procs.set_len(size / size_of::<libc::kinfo_proc>()) };
+    Ok(procs)
+}
+
+#[cfg(all(test, target_os = "linux"))]
 mod test {
-    use super::{StatFile, StatFileIter};
+    use super::{StatField, StatFile, StatFileIter, StatReader};
+
+    const STAT: &str = "128377 (cat) R 127912 128377 127912 34817 128377 4194304 90 0 0 0 0 0 0 0 25 5 1 0 7545849 18751488 252 18446744073709551615 94742542643200 94742542658614 140726597052192 0 0 0 0 0 0 0 0 0 17 0 0 0 0 0 0 94742542670560 94742542671976 94742570721280 140726597055035 140726597055055 140726597055055 140726597058539 0\n";
+
+    /// Collects the fields, or the first error.
+    fn fields(stat: &[u8]) -> std::io::Result<Vec<&[u8]>> {
+        StatFileIter::from(stat).collect()
+    }
 
     #[test]
     fn standard_stat() {
-        let stat = "128377 (cat) R 127912 128377 127912 34817 128377 4194304 90 0 0 0 0 0 0 0 25 5 1 0 7545849 18751488 252 18446744073709551615 94742542643200 94742542658614 140726597052192 0 0 0 0 0 0 0 0 0 17 0 0 0 0 0 0 94742542670560 94742542671976 94742570721280 140726597055035 140726597055055 140726597055055 140726597058539 0\n";
-        let mut stat = StatFileIter::from(stat);
+        let mut stat = StatFileIter::from(STAT);
 
-        assert_eq!(stat.next(), Some("128377"));
-        assert_eq!(stat.next(), Some("cat"));
-        assert_eq!(stat.next(), Some("R"));
-        assert_eq!(stat.next(), Some("127912"));
-        assert_eq!(stat.nth(52 - 4 - 1), Some("0"));
+        assert_eq!(stat.next().unwrap().unwrap(), b"128377");
+        assert_eq!(stat.next().unwrap().unwrap(), b"cat");
+        assert_eq!(stat.next().unwrap().unwrap(), b"R");
+        assert_eq!(stat.next().unwrap().unwrap(), b"127912");
+        assert_eq!(stat.nth(52 - 4 - 1).unwrap().unwrap(), b"0");
+        assert!(stat.next().is_none());
     }
 
     #[test]
     fn evil_program_name() {
-        let stat = "144650 (evil program x) name!) S 120869 144650 120869 34819 144650 4194304 94 0 0 0 0 0 0 0 15 -5 1 0 8684651 18751488 274 18446744073709551615 94787199291392 94787199306806 140721558631744 0 0 0 0 0 0 0 0 0 17 3 0 0 0 0 0 94787199318752 94787199320168 94787216977920 140721558639669 140721558639689 140721558639689 140721558642667 42\n";
-        let mut stat = StatFileIter::from(stat);
+        let stat = b"144650 (evil pro\ngram x) name!\xff) S 120869 144650 120869 34819 144650 4194304 94 0 0 0 0 0 0 0 15 -5 1 0 8684651 18751488 274 18446744073709551615 94787199291392 94787199306806 140721558631744 0 0 0 0 0 0 0 0 0 17 3 0 0 0 0 0 94787199318752 94787199320168 94787216977920 140721558639669 140721558639689 140721558639689 140721558642667 42\n";
+        let mut stat = StatFileIter::from(&stat[..]);
 
-        assert_eq!(stat.next(), Some("144650"));
-        assert_eq!(stat.next(), Some("evil program x) name!"));
-        assert_eq!(stat.next(), Some("S"));
-        assert_eq!(stat.next(), Some("120869"));
-        assert_eq!(stat.nth(52 - 4 - 1), Some("42"));
+        assert_eq!(stat.next().unwrap().unwrap(), b"144650");
+        assert_eq!(
+            stat.next().unwrap().unwrap(),
+            b"evil pro\ngram x) name!\xff"
+        );
+        assert_eq!(stat.next().unwrap().unwrap(), b"S");
+        assert_eq!(stat.next().unwrap().unwrap(), b"120869");
+        assert_eq!(stat.nth(52 - 4 - 1).unwrap().unwrap(), b"42");
+        assert_eq!(stat.field::<i64>(StatField::Nice).unwrap(), -5);
+        assert!(stat.field::<String>(StatField::Comm).is_err());
+    }
+
+    #[test]
+    fn malformed_stat() {
+        assert!(fields(b"").is_err());
+        assert!(fields(b"12 sh S 1\n").is_err());
+        assert!(fields(b"12 (sh S 1\n").is_err());
+        assert!(fields(b"12 (sh) S  1\n").is_err());
+        assert!(fields(b"12 (sh) S 1\n2\n").is_err());
+        assert_eq!(fields(b"12 (sh) S 1").unwrap().len(), 4);
+
+        let mut stat = StatFileIter::from("12 (sh) S 1 x\n");
+        assert!(stat.nth(4).unwrap().is_ok());
+        assert!(stat.next().is_none());
+        assert!(stat.field::<u64>(StatField::Pgrp).is_err());
+
+        // any truncation either fails or yields the fields read so far
+        let complete = fields(STAT.as_bytes()).unwrap();
+        for len in 0..STAT.len() {
+            if let Ok(truncated) = fields(&STAT.as_bytes()[..len]) {
+                let (last, read) = truncated.split_last().unwrap();
+                assert_eq!(read, &complete[..read.len()]);
+                assert!(complete[read.len()].starts_with(last));
+            }
+        }
     }
 
     #[test]
     fn parse_real_file() {
         let pid = std::process::id();
         let stat = StatFile::open(pid.into()).unwrap();
+        assert_eq!(stat.field::<u32>(StatField::Pid).unwrap(), pid);
         let mut stat = stat.iter();
-        assert_eq!(stat.next(), Some(&pid.to_string()[..]));
+        assert_eq!(stat.next().unwrap().unwrap(), pid.to_string().as_bytes());
+    }
+
+    #[test]
+    fn typed_fields() {
+        let mut stat =
+            StatFileIter::from("42 (a b) S 1 42 42 0 -1 4194560 7 0 0 0 12 34 0 0 20 -5 3\n");
+        stat.next();
+
+        assert_eq!(stat.field::<String>(StatField::Comm).unwrap(), "a b");
+        assert_eq!(stat.field::<u64>(StatField::Utime).unwrap(), 12);
+        assert_eq!(stat.field::<i64>(StatField::Nice).unwrap(), -5);
+        assert!(stat.field::<u64>(StatField::Comm).is_err());
+        assert!(stat.field::<u64>(StatField::Starttime).is_err());
+    }
+
+    #[test]
+    fn read_again() {
+        let pid = std::process::id();
+        let mut stat = StatReader::open(pid.into()).unwrap();
+        for _ in 0..2 {
+            let mut fields = StatFileIter::from(stat.read().unwrap());
+            assert_eq!(fields.next().unwrap().unwrap(), pid.to_string().as_bytes());
+            assert!(fields.nth(50).unwrap().is_ok());
+        }
     }
 }
@@ -0,0 +1,87 @@
+//! Query the system: its number of CPUs, and its CPU utilization from `/proc/stat`.
+//!
+//! The first line of the file sums the time spent by all the CPUs in each
+//! state, in clock ticks. See `man proc` for the list of the states.
+
+use std::fs;
+use std::io;
+use std::sync::OnceLock;
+
+use crate::clock::SystemClock;
+use crate::procfs;
+
+/// Retrieves the number of processors online, at least 1.
+///
+/// It is queried once: a limit of 100% of the whole machine keeps the same
+/// meaning for the lifetime of the process.
+pub fn num_cpus() -> usize {
+    static NUM_CPUS: OnceLock<usize> = OnceLock::new();
+    *NUM_CPUS.get_or_init(|| {
+        // SAFETY: Inherently unsafe as a syscall, but the parameter is valid.
+        let online = unsafe { libc::sysconf(libc::_SC_NPROCESSORS_ONLN) };
+        usize::try_from(online).unwrap_or(0).max(1)
+    })
+}
+
+/// Retrieves the number of clock ticks per second, the unit of the times in procfs.
+///
+/// See [`SystemClock`] to override it.
+pub fn clock_ticks() -> u64 {
+    SystemClock::current().ticks_per_second()
+}
+
+/// The CPU utilization of the system between two samples.
+pub(crate) struct SystemUsage {
+    /// The busy and total times of the previous sample.
+    last: Option<(u64, u64)>,
+    utilization: f64,
+}
+
+impl SystemUsage {
+    /// Starts without any sample: the first update reports an idle system.
+    pub fn new() -> Self {
+        Self {
+            last: None,
+            utilization: 0_f64,
+        }
+    }
+
+    /// Samples `/proc/stat` and retrieves the fraction of time the CPUs were
+    /// busy since the previous sample, between 0 and 1.
+    ///
+    /// The previous value is kept if the file can't be read.
+    pub fn update(&mut self) -> f64 {
+        if let Ok((busy, total)) = read_times() {
+            if let Some((last_busy, last_total)) = self.last {
+                let elapsed = total.saturating_sub(last_total);
+                if elapsed > 0 {
+                    self.utilization = busy.saturating_sub(last_busy) as f64 / elapsed as f64;
+                }
+            }
+            self.last = Some((busy, total));
+        }
+        self.utilization
+    }
+}
+
+/// Reads the busy and total times of all the CPUs.
+fn read_times() -> io::Result<(u64, u64)> {
+    let stat = fs::read_to_string(procfs::path("stat"))?;
+    let line = stat
+        .lines()
+        .find(|line| line.starts_with("cpu "))
+        .ok_or_else(|| io::Error::from(io::ErrorKind::InvalidData))?;
+
+    // user, nice, system, idle, iowait, irq, softirq, steal (guests are included in user)
+    let times: Vec<u64> = line
+        .split_whitespace()
+        .skip(1)
+        .take(8)
+        .map(|time| time.parse().unwrap_or_default())
+        .collect();
+
+    let total = times.iter().sum();
+    let idle =
+        times.get(3).copied().unwrap_or_default() + times.get(4).copied().unwrap_or_default();
+    Ok((total - idle, total))
+}
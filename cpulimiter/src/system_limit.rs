@@ -0,0 +1,94 @@
+//! Keep the utilization of the whole machine under a ceiling.
+
+use crate::builder::CpuLimitBuilder;
+use crate::error::Result;
+use crate::limiter::{CpuLimit, LimitMode};
+use crate::process_group::ChildrenMode;
+use crate::target::Target;
+
+/// Throttles a set of managed processes so that the utilization of the whole
+/// machine, read from `/proc/stat`, stays under a ceiling.
+///
+/// The managed processes share whatever the other processes leave below the
+/// ceiling, and are never throttled for their own sake: on a shared
+/// workstation, a ceiling of 80% keeps 20% of headroom for interactive use.
+///
+/// # Example
+///
+/// ```no_run
+/// use cpulimiter::{Pid, SystemLimit, Target};
+///
+/// let limiter = SystemLimit::new(80.0)
+///     .manage(Pid::from(1048))
+///     .manage(Target::Name("make".to_string()))
+///     .start()
+///     .unwrap();
+/// ```
+#[derive(Debug)]
+pub struct SystemLimit {
+    ceiling: f64,
+    managed: Vec<Target>,
+    children_mode: ChildrenMode,
+}
+
+impl SystemLimit {
+    /// Keeps the machine at most `total_percent` busy, 100% being all its cores.
+    pub fn new(total_percent: f64) -> Self {
+        Self {
+            ceiling: total_percent,
+            managed: Vec::new(),
+            children_mode: ChildrenMode::default(),
+        }
+    }
+
+    /// Adds a process, or a set of processes, to the managed ones.
+    #[must_use]
+    pub fn manage(mut self, target: impl Into<Target>) -> Self {
+        self.managed.push(target.into());
+        self
+    }
+
+    /// Sets whether the children of the managed processes are managed too.
+    #[must_use]
+    pub fn children(mut self, children_mode: ChildrenMode) -> Self {
+        self.children_mode = children_mode;
+        self
+    }
+
+    /// Starts throttling the managed processes.
+    ///
+    /// Fails with [`Error::NoTarget`](crate::Error::NoTarget) if none was
+    /// given, and with [`Error::Unsupported`](crate::Error::Unsupported)
+    /// unless the ceiling is a positive percentage, at most 100%.
+    pub fn start(self) -> Result<CpuLimit> {
+        self.managed
+            .into_iter()
+            .fold(CpuLimitBuilder::new(100_f64), CpuLimitBuilder::target)
+            .mode(LimitMode::TotalSystem)
+            .children(self.children_mode)
+            .system_ceiling(self.ceiling)
+            .build()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::error::Error;
+    use crate::pid::Pid;
+
+    #[test]
+    fn invalid() {
+        let current = Pid::from(std::process::id());
+        assert!(matches!(
+            SystemLimit::new(80_f64).start(),
+            Err(Error::NoTarget)
+        ));
+        for ceiling in [0_f64, 120_f64, f64::NAN] {
+            assert!(matches!(
+                SystemLimit::new(ceiling).manage(current).start(),
+                Err(Error::Unsupported(_))
+            ));
+        }
+    }
+}
@@ -0,0 +1,155 @@
+//! Designate the processes to limit.
+
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+use crate::cgroup;
+use crate::pid::Pid;
+use crate::process_iterator::ProcessIterator;
+
+/// The maximum length of a command name, see `TASK_COMM_LEN` in the kernel.
+const MAX_NAME_LEN: usize = 15;
+
+/// A set of processes sharing a single CPU budget.
+#[derive(Clone, PartialEq, Eq, Debug)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum Target {
+    /// A single process.
+    Pid(Pid),
+    /// Every process listed in the `cgroup.procs` file of a cgroup v2.
+    ///
+    /// Relative paths start at the mount point of the hierarchy (usually `/sys/fs/cgroup`).
+    /// The membership is refreshed at every update, the cgroup is not modified.
+    Cgroup(PathBuf),
+    /// Every process owned by the user with the given UID (real user ID).
+    ///
+    /// New processes of the user join the group at the next update.
+    /// The current process is never part of the group.
+    User(u32),
+    /// Every process running the executable at the given path.
+    ///
+    /// New processes running it join the group at the next update.
+    Exe(PathBuf),
+    /// Every process with the given command name (`comm`).
+    ///
+    /// Names are truncated to 15 bytes by the kernel, so only the start of
+    /// longer names is compared. New processes join the group at the next update.
+    Name(String),
+    /// Every process of the Docker, containerd or Podman container with the given ID.
+    ///
+    /// The processes are found by their cgroup, so the full ID or a prefix of
+    /// it is expected, not the name of the container. Unlike with the cgroup
+    /// backend, the cgroup of the container isn't modified: only signals are sent.
+    /// New processes of the container join the group at the next update.
+    Container(String),
+}
+
+impl From<Pid> for Target {
+    fn from(pid: Pid) -> Self {
+        Self::Pid(pid)
+    }
+}
+
+impl Target {
+    /// Indicates whether the processes of the target change over time.
+    pub(crate) fn is_dynamic(&self) -> bool {
+        !matches!(self, Target::Pid(_))
+    }
+
+    /// Lists the processes currently designated by the target.
+    ///
+    /// Fails if the target itself disappeared (but not if it is merely empty).
+    pub fn resolve(&self) -> io::Result<Vec<Pid>> {
+        match self {
+            Target::Pid(pid) => Ok(if pid.alive() { vec![*pid] } else { vec![] }),
+            Target::Cgroup(path) => {
+                let path = if path.is_absolute() {
+                    path.clone()
+                } else {
+                    cgroup::mount_point()?.join(path)
+                };
+
+                let procs = fs::read_to_string(path.join("cgroup.procs"))?;
+                Ok(procs.lines().filter_map(|pid| pid.parse().ok()).collect())
+            }
+            Target::User(uid) => Self::find(|pid| pid.get_uid() == Some(*uid)),
+            Target::Exe(path) => Self::find(|pid| pid.get_exe().as_ref() == Some(path)),
+            Target::Name(name) => Self::find(|pid| has_name(pid, name)),
+            Target::Container(id) => Self::find(|pid| in_container(pid, id)),
+        }
+    }
+
+    /// Lists the processes matching `predicate`, except the current one and the kernel threads.
+    fn find(predicate: impl Fn(Pid) -> bool) -> io::Result<Vec<Pid>> {
+        // suspending itself would stop the limiter
+        let current = Pid::from(std::process::id());
+        Ok(ProcessIterator::new()?
+            .filter(|&pid| pid != current && predicate(pid) && !pid.is_kernel_thread())
+            .collect())
+    }
+}
+
+/// Indicates whether the command name of `pid` is `name`, compared as for [`Target::Name`].
+pub(crate) fn has_name(pid: Pid, name: &str) -> bool {
+    pid.get_name().as_deref() == Some(truncate(name, MAX_NAME_LEN))
+}
+
+/// Indicates whether `pid` belongs to the container `id`, compared as for [`Target::Container`].
+fn in_container(pid: Pid, id: &str) -> bool {
+    if id.is_empty() || !id.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+        return false;
+    }
+
+    pid.get_cgroups().is_some_and(|paths| {
+        paths.iter().any(|path| {
+            path.iter()
+                .filter_map(|component| component.to_str())
+                .any(|component| container_id(component).starts_with(id))
+        })
+    })
+}
+
+/// Extracts the container ID from a component of a cgroup path, such as
+/// `docker-<id>.scope` with systemd, or `<id>` with the cgroupfs driver.
+fn container_id(component: &str) -> &str {
+    let scope = component.strip_suffix(".scope").unwrap_or(component);
+    scope.rsplit('-').next().unwrap_or(scope)
+}
+
+/// Truncates `name` to at most `len` bytes, on a character boundary.
+fn truncate(name: &str, len: usize) -> &str {
+    if name.len() <= len {
+        return name;
+    }
+
+    let mut end = len;
+    while !name.is_char_boundary(end) {
+        end -= 1;
+    }
+    &name[..end]
+}
+
+#[cfg(test)]
+mod test {
+    use super::{container_id, truncate};
+
+    #[test]
+    fn truncate_name() {
+        assert_eq!(truncate("ffmpeg", 15), "ffmpeg");
+        assert_eq!(truncate("systemd-journald", 15), "systemd-journal");
+        assert_eq!(truncate("ééééééééé", 15), "ééééééé");
+    }
+
+    #[test]
+    fn parse_container_id() {
+        assert_eq!(container_id("docker-3f4e2a.scope"), "3f4e2a");
+        assert_eq!(container_id("cri-containerd-3f4e2a.scope"), "3f4e2a");
+        assert_eq!(container_id("libpod-3f4e2a.scope"), "3f4e2a");
+        assert_eq!(container_id("3f4e2a"), "3f4e2a");
+    }
+}
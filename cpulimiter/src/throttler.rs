@@ -0,0 +1,74 @@
+//! Plug custom enforcement strategies into the signal limiter.
+//!
+//! The limiting thread decides when the group runs; a [`Throttler`] decides how
+//! it is stopped and let run again. By default, the group sends `SIGSTOP` and
+//! `SIGCONT` according to its [`SuspendMode`](crate::SuspendMode).
+
+use std::fmt;
+
+use crate::error::Result;
+use crate::process_group::ProcessGroup;
+
+/// The way a limiter stops and resumes the processes of a group.
+///
+/// # Example
+///
+/// ```no_run
+/// use std::sync::Arc;
+/// use cpulimiter::{CpuLimit, Error, Pid, ProcessGroup, Throttler};
+///
+/// /// Only logs the decisions of the limiter.
+/// struct DryRun;
+///
+/// impl Throttler for DryRun {
+///     fn suspend(&self, group: &ProcessGroup) -> Result<(), Error> {
+///         println!("suspend {:?}", group.members().collect::<Vec<_>>());
+///         Ok(())
+///     }
+///
+///     fn resume(&self, group: &ProcessGroup) -> Result<(), Error> {
+///         println!("resume {:?}", group.members().collect::<Vec<_>>());
+///         Ok(())
+///     }
+/// }
+///
+/// let handle = CpuLimit::builder(25.0)
+///     .target(Pid::from(1048))
+///     .throttler(Arc::new(DryRun))
+///     .build()
+///     .unwrap();
+/// ```
+pub trait Throttler: Send + Sync {
+    /// Stops the processes of `group` until [`resume`](Self::resume) is called.
+    fn suspend(&self, group: &ProcessGroup) -> Result<()>;
+
+    /// Lets the processes of `group` run.
+    fn resume(&self, group: &ProcessGroup) -> Result<()>;
+
+    /// Called when the limiter starts and whenever the limit changes, with the
+    /// number of cores the group may use. Does nothing by default.
+    fn apply(&self, cores: f64) -> Result<()> {
+        let _ = cores;
+        Ok(())
+    }
+}
+
+impl fmt::Debug for dyn Throttler {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Throttler")
+    }
+}
+
+/// The default throttler, sending signals as configured by the group.
+#[derive(Clone, Copy, Default, Debug)]
+pub struct SignalThrottler;
+
+impl Throttler for SignalThrottler {
+    fn suspend(&self, group: &ProcessGroup) -> Result<()> {
+        group.suspend()
+    }
+
+    fn resume(&self, group: &ProcessGroup) -> Result<()> {
+        group.resume()
+    }
+}
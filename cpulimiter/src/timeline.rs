@@ -0,0 +1,153 @@
+//! Record the CPU usage, the limit and the working rate of every time slice,
+//! to analyze how a group was throttled afterwards.
+
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// The state of a group during a time slice, see [`Timeline`].
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct TimelineSample {
+    /// When the slice ended.
+    pub time: SystemTime,
+    /// The CPU usage of the group, in cores.
+    pub usage: f64,
+    /// The limit in effect, as a percentage, schedules and ramps included.
+    pub limit: f64,
+    /// The fraction of the slice the group was allowed to run, between 0 and 1.
+    pub working_rate: f64,
+}
+
+/// The recent time slices of a limiter, oldest first, see [`CpuLimit::timeline`](crate::CpuLimit::timeline).
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct Timeline {
+    samples: Vec<TimelineSample>,
+}
+
+impl Timeline {
+    /// Retrieves the samples, oldest first.
+    pub fn samples(&self) -> &[TimelineSample] {
+        &self.samples
+    }
+
+    /// Writes the samples to `path` as CSV, with a header line.
+    ///
+    /// The time is in seconds since the Unix epoch.
+    pub fn export_csv(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let mut file = BufWriter::new(File::create(path)?);
+        writeln!(file, "time,usage,limit,working_rate")?;
+        for sample in &self.samples {
+            writeln!(
+                file,
+                "{},{},{},{}",
+                unix_time(sample.time),
+                sample.usage,
+                sample.limit,
+                sample.working_rate
+            )?;
+        }
+        file.flush()
+    }
+
+    /// Writes the samples to `path` as a JSON array of objects.
+    ///
+    /// The time is in seconds since the Unix epoch.
+    pub fn export_json(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let mut file = BufWriter::new(File::create(path)?);
+        write!(file, "[")?;
+        for (i, sample) in self.samples.iter().enumerate() {
+            if i > 0 {
+                write!(file, ",")?;
+            }
+            write!(
+                file,
+                r#"{{"time":{},"usage":{},"limit":{},"working_rate":{}}}"#,
+                unix_time(sample.time),
+                sample.usage,
+                sample.limit,
+                sample.working_rate
+            )?;
+        }
+        writeln!(file, "]")?;
+        file.flush()
+    }
+}
+
+/// Keeps the last samples of a group, up to a capacity.
+#[derive(Debug)]
+pub(crate) struct Recorder {
+    capacity: usize,
+    samples: VecDeque<TimelineSample>,
+}
+
+impl Recorder {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            samples: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    /// Adds `sample`, dropping the oldest one if the recorder is full.
+    pub fn record(&mut self, sample: TimelineSample) {
+        if self.samples.len() == self.capacity {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(sample);
+    }
+
+    pub fn timeline(&self) -> Timeline {
+        Timeline {
+            samples: self.samples.iter().copied().collect(),
+        }
+    }
+}
+
+/// Converts `time` to seconds since the Unix epoch.
+fn unix_time(time: SystemTime) -> f64 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs_f64()
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn ring_buffer_and_export() {
+        let mut recorder = Recorder::new(2);
+        for i in 0..3 {
+            recorder.record(TimelineSample {
+                time: UNIX_EPOCH + Duration::from_secs(i),
+                usage: 0.5,
+                limit: 50_f64,
+                working_rate: 0.25,
+            });
+        }
+        let timeline = recorder.timeline();
+        assert_eq!(timeline.samples().len(), 2);
+        assert_eq!(
+            timeline.samples()[0].time,
+            UNIX_EPOCH + Duration::from_secs(1)
+        );
+
+        let path = std::env::temp_dir().join(format!("cpulimit-timeline-{}", std::process::id()));
+        timeline.export_csv(&path).unwrap();
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "time,usage,limit,working_rate\n1,0.5,50,0.25\n2,0.5,50,0.25\n"
+        );
+        timeline.export_json(&path).unwrap();
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "[{\"time\":1,\"usage\":0.5,\"limit\":50,\"working_rate\":0.25},{\"time\":2,\"usage\":0.5,\"limit\":50,\"working_rate\":0.25}]\n"
+        );
+        fs::remove_file(path).unwrap();
+    }
+}
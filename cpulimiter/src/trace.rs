@@ -0,0 +1,43 @@
+//! Report what the limiters do with [`tracing`](https://docs.rs/tracing).
+//!
+//! Each limiting thread runs in a `limiter` span, with debug events at every
+//! slice and warnings when a process can't be signaled. Without the `tracing`
+//! feature, the macros expand to nothing and the spans are empty.
+
+#[cfg(feature = "tracing")]
+pub(crate) use tracing::{debug, info_span, warn};
+
+#[cfg(not(feature = "tracing"))]
+macro_rules! discard {
+    ($($arg:tt)*) => {{}};
+}
+
+#[cfg(not(feature = "tracing"))]
+macro_rules! empty_span {
+    ($($arg:tt)*) => {
+        $crate::trace::Span
+    };
+}
+
+#[cfg(not(feature = "tracing"))]
+pub(crate) use {discard as debug, discard as warn, empty_span as info_span};
+
+/// A span doing nothing, in place of the one of `tracing`.
+#[cfg(not(feature = "tracing"))]
+pub(crate) struct Span;
+
+#[cfg(not(feature = "tracing"))]
+impl Span {
+    /// Runs `f`.
+    pub fn in_scope<T>(&self, f: impl FnOnce() -> T) -> T {
+        f()
+    }
+}
+
+/// Something worth reporting to the subscriber of `tracing`.
+pub(crate) trait Trace {
+    /// Emits the event describing `self`, at the level it deserves.
+    ///
+    /// Does nothing by default, nor without the `tracing` feature.
+    fn trace(&self) {}
+}
@@ -0,0 +1,123 @@
+//! Check how closely the limiter holds a busy loop to its limit.
+//!
+//! Each test spawns a shell spinning on one core, limits it, and measures the
+//! CPU time it actually uses from its stat file. Run them with
+//! `cargo test --test accuracy -- --nocapture` to print the measurements,
+//! for instance while tuning the controller.
+#![cfg(target_os = "linux")]
+
+use std::process::{Child, Command};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use cpulimiter::{AdaptiveLimit, CpuLimit, CpuLimitBuilder, Pid};
+
+/// The time the limiter may take to converge, not measured.
+const WARMUP: Duration = Duration::from_secs(1);
+
+/// The time the usage is averaged over.
+const MEASUREMENT: Duration = Duration::from_secs(4);
+
+/// A process spinning on one core, killed when dropped, even by a failed assertion.
+struct BusyLoop(Child);
+
+impl BusyLoop {
+    fn spawn() -> Self {
+        let child = Command::new("sh")
+            .args(["-c", "while :; do :; done"])
+            .spawn()
+            .unwrap();
+        Self(child)
+    }
+
+    fn pid(&self) -> Pid {
+        Pid::from(self.0.id())
+    }
+}
+
+impl Drop for BusyLoop {
+    fn drop(&mut self) {
+        let _ = self.0.kill();
+        let _ = self.0.wait();
+    }
+}
+
+/// Limits a busy loop with `builder` and measures its average usage, as a percentage of one core.
+fn measure(builder: CpuLimitBuilder) -> f64 {
+    let busy_loop = BusyLoop::spawn();
+    let pid = busy_loop.pid();
+    let limiter: CpuLimit = builder.target(pid).build().unwrap();
+
+    thread::sleep(WARMUP);
+    let (start, cputime) = (Instant::now(), pid.get_cputime());
+    thread::sleep(MEASUREMENT);
+    let usage = (pid.get_cputime() - cputime).as_secs_f64() / start.elapsed().as_secs_f64();
+
+    limiter.stop().unwrap();
+    usage * 100_f64
+}
+
+/// Asserts that the usage under `limit` is within `tolerance` percentage points of it.
+fn assert_accuracy(limit: f64, tolerance: f64) {
+    let usage = measure(CpuLimit::builder(limit));
+    println!("limit {limit}%: usage {usage:.2}%");
+    assert!(
+        (usage - limit).abs() <= tolerance,
+        "usage {usage:.2}% is off the limit {limit}% by more than {tolerance} points"
+    );
+}
+
+#[test]
+fn relative() {
+    let reference = BusyLoop::spawn();
+    let reference_pid = reference.pid();
+    let reference_limiter = CpuLimit::new(reference_pid, 40_f64).unwrap();
+    let busy_loop = BusyLoop::spawn();
+    let pid = busy_loop.pid();
+    let limiter = CpuLimit::new_relative(pid, reference_pid, 0.5).unwrap();
+
+    thread::sleep(WARMUP);
+    let start = Instant::now();
+    let cputimes = (pid.get_cputime(), reference_pid.get_cputime());
+    thread::sleep(MEASUREMENT);
+    let elapsed = start.elapsed().as_secs_f64();
+    let usage = (pid.get_cputime() - cputimes.0).as_secs_f64() / elapsed * 100_f64;
+    let reference_usage =
+        (reference_pid.get_cputime() - cputimes.1).as_secs_f64() / elapsed * 100_f64;
+    println!("half of {reference_usage:.2}%: usage {usage:.2}%");
+    limiter.stop().unwrap();
+    reference_limiter.stop().unwrap();
+    assert!(
+        (usage - reference_usage / 2_f64).abs() <= 5_f64,
+        "usage {usage:.2}% is off half the reference {reference_usage:.2}% by more than 5 points"
+    );
+}
+
+#[test]
+fn adaptive_under_ceiling() {
+    // never relaxed, and the ceiling leaves room for the limit
+    let builder = CpuLimit::builder(50_f64)
+        .adaptive(AdaptiveLimit { threshold: 0_f64 })
+        .system_ceiling(75_f64);
+    let usage = measure(builder);
+    println!("limit 50% under a 75% ceiling: usage {usage:.2}%");
+    assert!(
+        (usage - 50_f64).abs() <= 10_f64,
+        "usage {usage:.2}% is off the limit 50% by more than 10 points"
+    );
+}
+
+#[test]
+fn limit_5() {
+    assert_accuracy(5_f64, 3_f64);
+}
+
+#[test]
+fn limit_25() {
+    assert_accuracy(25_f64, 5_f64);
+}
+
+#[test]
+fn limit_75() {
+    assert_accuracy(75_f64, 10_f64);
+}